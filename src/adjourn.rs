@@ -0,0 +1,70 @@
+use crate::network::{format_move, parse_move};
+use chess_networking::{Move, Start};
+use std::path::PathBuf;
+
+/// A game put aside to be finished later.
+pub struct Adjourned {
+    pub seed: u64,
+    /// `Start::is_white` of the side that saved it, which has to take the same seat to resume.
+    pub is_white: bool,
+    /// Where the game started, if not from the usual position.
+    pub fen: Option<String>,
+    pub history: Vec<Move>,
+}
+
+/// Both sides share the seed, so it doubles as an id they agree on without having to send one.
+pub fn game_id(seed: u64) -> String {
+    format!("{:016x}", seed)
+}
+
+fn path(id: &str) -> PathBuf {
+    PathBuf::from(format!("adjourned-{}.txt", id))
+}
+
+fn invalid(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Writes the game to `adjourned-<id>.txt` in the working directory and returns its id.
+pub fn save(seed: u64, start: &Start, history: &[Move]) -> std::io::Result<String> {
+    let id = game_id(seed);
+    let moves: Vec<String> = history.iter().map(format_move).collect();
+    let mut contents = format!("seed {}\nwhite {}\n", seed, start.is_white);
+    if let Some(fen) = &start.fen {
+        contents.push_str(&format!("fen {}\n", fen));
+    }
+    contents.push_str(&format!("moves {}\n", moves.join(" ")));
+
+    std::fs::write(path(&id), contents)?;
+    Ok(id)
+}
+
+pub fn load(id: &str) -> std::io::Result<Adjourned> {
+    let contents = std::fs::read_to_string(path(id))?;
+
+    let mut seed = None;
+    let mut is_white = None;
+    let mut fen = None;
+    let mut history = Vec::new();
+    for line in contents.lines() {
+        let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+        match key {
+            "seed" => seed = value.parse().ok(),
+            "white" => is_white = value.parse().ok(),
+            "fen" => fen = Some(value.to_string()),
+            "moves" => {
+                for m in value.split_whitespace() {
+                    history.push(parse_move(m).ok_or_else(|| invalid("unreadable move"))?);
+                }
+            }
+            _ => return Err(invalid("unknown line in adjourned game")),
+        }
+    }
+
+    Ok(Adjourned {
+        seed: seed.ok_or_else(|| invalid("adjourned game has no seed"))?,
+        is_white: is_white.ok_or_else(|| invalid("adjourned game has no color"))?,
+        fen,
+        history,
+    })
+}
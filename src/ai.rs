@@ -0,0 +1,352 @@
+use crate::crazyhouse;
+use crate::eval::{self, Position};
+use crate::log;
+use crate::rng::Rng;
+use crate::{BoardExtensions, ChessColor};
+use chess::ChessBoard;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+
+/// How well the computer plays, from 1 (a beginner can beat it) to 5 (plays its best move).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Difficulty(u8);
+
+impl Difficulty {
+    pub const EASIEST: Difficulty = Difficulty(1);
+    pub const HARDEST: Difficulty = Difficulty(5);
+    pub const DEFAULT: Difficulty = Difficulty(3);
+
+    pub fn from_level(level: u8) -> Option<Difficulty> {
+        (Self::EASIEST.0..=Self::HARDEST.0)
+            .contains(&level)
+            .then_some(Difficulty(level))
+    }
+
+    pub fn level(self) -> u8 {
+        self.0
+    }
+
+    /// Plies to search ahead.
+    pub fn depth(self) -> u32 {
+        [1, 2, 2, 3, 4][self.0 as usize - 1]
+    }
+
+    /// How many of the best moves a regular pick chooses between.
+    fn candidates(self) -> usize {
+        [4, 3, 2, 2, 1][self.0 as usize - 1]
+    }
+
+    /// Centipawns a candidate may be worse than the best move, so regular picks stay sensible.
+    fn tolerance(self) -> i32 {
+        [150, 80, 40, 15, 0][self.0 as usize - 1]
+    }
+
+    /// Chance in percent of playing any legal move at all instead.
+    fn blunder_chance(self) -> u64 {
+        [20, 10, 5, 0, 0][self.0 as usize - 1]
+    }
+}
+
+/// Picks the move to play from the search results, each scored from the mover's point of view.
+/// Higher difficulties stick to the best move, lower ones wander off it more and more.
+pub fn choose<'a>(
+    scored: &'a [(String, i32)],
+    difficulty: Difficulty,
+    rng: &mut Rng,
+) -> Option<&'a str> {
+    let mut ranked: Vec<&(String, i32)> = scored.iter().collect();
+    // Stable, so equally good moves keep the order the search found them in.
+    ranked.sort_by_key(|&(_, score)| std::cmp::Reverse(*score));
+
+    let &(_, best) = *ranked.first()?;
+    if rng.below(100) < difficulty.blunder_chance() {
+        let pick = rng.below(ranked.len() as u64) as usize;
+        return Some(&ranked[pick].0);
+    }
+
+    let candidates = ranked
+        .iter()
+        .take(difficulty.candidates())
+        .take_while(|(_, score)| best - score <= difficulty.tolerance())
+        .count();
+    let pick = rng.below(candidates as u64) as usize;
+
+    Some(&ranked[pick].0)
+}
+
+/// Scores well past anything material can add up to, so mates always come first.
+pub const MATE: i32 = 100_000;
+const INFINITY: i32 = 1_000_000;
+
+/// Every legal move in the current position of `board` with its score from the mover's point of
+/// view, searched `depth` plies deep. `None` once `stop` is set, which the search keeps checking.
+///
+/// Each move gets a full window rather than sharing alpha-beta bounds with its siblings, since
+/// `choose` needs to know how much worse than the best move the others are.
+pub fn search(board: &mut ChessBoard, depth: u32, stop: &AtomicBool) -> Option<Vec<(String, i32)>> {
+    let mut scored = Vec::new();
+    for m in crazyhouse::legal_moves(board, None) {
+        crazyhouse::make_move(board, m.clone());
+        let score = -negamax(board, depth.saturating_sub(1), -INFINITY, INFINITY, stop);
+        board.undo_move();
+        if stop.load(Ordering::Relaxed) {
+            return None;
+        }
+        scored.push((m, score));
+    }
+    Some(scored)
+}
+
+/// The score of `board` for the side to move, or anything at all once `stop` is set.
+fn negamax(
+    board: &mut ChessBoard,
+    depth: u32,
+    mut alpha: i32,
+    beta: i32,
+    stop: &AtomicBool,
+) -> i32 {
+    if stop.load(Ordering::Relaxed) {
+        return 0;
+    }
+    let moves = crazyhouse::legal_moves(board, None);
+    if moves.is_empty() {
+        // Mates with more depth left are closer, and so better for the side giving them.
+        return if board.is_in_check() {
+            -MATE - depth as i32
+        } else {
+            0
+        };
+    }
+
+    if depth == 0 {
+        let score = eval::evaluate(board.position());
+        return match board.current_side() {
+            ChessColor::White => score,
+            ChessColor::Black => -score,
+        };
+    }
+
+    for m in moves {
+        crazyhouse::make_move(board, m);
+        let score = -negamax(board, depth - 1, -beta, -alpha, stop);
+        board.undo_move();
+
+        if score >= beta {
+            return beta;
+        }
+        alpha = alpha.max(score);
+    }
+
+    alpha
+}
+
+/// A position for `Searcher`'s thread to search, with where to send the result.
+struct Job {
+    history: Vec<Position>,
+    white_move: bool,
+    depth: u32,
+    stop: Arc<AtomicBool>,
+    results: Sender<Vec<(String, i32)>>,
+}
+
+/// One thread searching a position at a time, so the window keeps responding meanwhile. Starting
+/// on another position stops the search still going on, which nobody wants anymore.
+pub struct Searcher {
+    /// Started the first time there's something to search.
+    jobs: Option<Sender<Job>>,
+    stop: Arc<AtomicBool>,
+    results: Option<Receiver<Vec<(String, i32)>>>,
+}
+
+impl Searcher {
+    pub fn new() -> Searcher {
+        Searcher {
+            jobs: None,
+            stop: Arc::new(AtomicBool::new(false)),
+            results: None,
+        }
+    }
+
+    /// Searches the current position of `board` `depth` plies deep, see `search`.
+    pub fn start(&mut self, board: &ChessBoard, depth: u32) {
+        self.cancel();
+        self.stop = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = mpsc::channel();
+        // Only the position history crosses over, the search gets a board of its own.
+        let job = Job {
+            history: board.board.clone(),
+            white_move: board.white_move,
+            depth,
+            stop: Arc::clone(&self.stop),
+            results: sender,
+        };
+        let jobs = self.jobs.get_or_insert_with(|| {
+            let (jobs, queue) = mpsc::channel();
+            std::thread::spawn(move || work(queue));
+            jobs
+        });
+        if jobs.send(job).is_err() {
+            log::error!("the search thread is gone");
+            return;
+        }
+        self.results = Some(receiver);
+    }
+
+    /// The result of the search `start` began, once it's done.
+    pub fn poll(&mut self) -> Option<Vec<(String, i32)>> {
+        let scored = match self.results.as_ref()?.try_recv() {
+            Ok(scored) => Some(scored),
+            Err(TryRecvError::Empty) => return None,
+            Err(TryRecvError::Disconnected) => {
+                log::error!("the search stopped without a result");
+                None
+            }
+        };
+        self.results = None;
+        scored
+    }
+
+    /// Whether a search was started and hasn't been polled for or cancelled yet.
+    pub fn busy(&self) -> bool {
+        self.results.is_some()
+    }
+
+    /// Stops the search going on, if any.
+    pub fn cancel(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.results = None;
+    }
+}
+
+impl Drop for Searcher {
+    /// The thread finishes once the search it's on stops and no more jobs can come.
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// `Searcher`'s thread, searching each job that isn't stopped by the time it gets to it.
+fn work(queue: Receiver<Job>) {
+    for job in queue {
+        if job.stop.load(Ordering::Relaxed) {
+            continue;
+        }
+        let mut board = ChessBoard::new();
+        board.board = job.history;
+        board.white_move = job.white_move;
+        if let Some(scored) = search(&mut board, job.depth, &job.stop) {
+            // Nobody listens anymore when the search was cancelled right as it finished.
+            let _ = job.results.send(scored);
+        }
+    }
+}
+
+/// Something that picks moves for the computer, our own `Engine` or an external one.
+pub trait Player {
+    /// Starts looking for a move in the current position of `board`, unless already busy.
+    fn think(&mut self, board: &ChessBoard);
+    /// The move to play once one was found, for the same position `think` was given.
+    fn poll(&mut self, board: &mut ChessBoard, rng: &mut Rng) -> Option<String>;
+    /// Forgets about the search going on, for when the position changed underneath it.
+    fn cancel(&mut self);
+    /// How good the position given to `think` is for the side to move in centipawns, as far as
+    /// the search got. Mates are scored around `MATE`.
+    fn score(&self) -> Option<i32> {
+        None
+    }
+}
+
+/// The built-in computer opponent, searching on a `Searcher`'s thread.
+pub struct Engine {
+    difficulty: Difficulty,
+    depth: u32,
+    searcher: Searcher,
+    score: Option<i32>,
+}
+
+impl Engine {
+    /// `depth` overrides how far the difficulty would have it search.
+    pub fn new(difficulty: Difficulty, depth: Option<u32>) -> Engine {
+        Engine {
+            difficulty,
+            depth: depth.unwrap_or(difficulty.depth()),
+            searcher: Searcher::new(),
+            score: None,
+        }
+    }
+}
+
+impl Player for Engine {
+    fn think(&mut self, board: &ChessBoard) {
+        if self.searcher.busy() {
+            return;
+        }
+        self.score = None;
+        self.searcher.start(board, self.depth);
+    }
+
+    fn poll(&mut self, _board: &mut ChessBoard, rng: &mut Rng) -> Option<String> {
+        let scored = self.searcher.poll()?;
+        self.score = scored.iter().map(|&(_, score)| score).max();
+        choose(&scored, self.difficulty, rng).map(|m| m.to_string())
+    }
+
+    fn cancel(&mut self) {
+        self.searcher.cancel();
+    }
+
+    fn score(&self) -> Option<i32> {
+        self.score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_same_moves_from_the_same_seed() {
+        let scored: Vec<(String, i32)> = ["e2e4", "d2d4", "g1f3", "c2c4", "b1c3", "a2a3"]
+            .iter()
+            .zip([30, 25, 20, 10, -40, -90])
+            .map(|(m, score)| (m.to_string(), score))
+            .collect();
+        let picks = |seed: u64, difficulty: Difficulty| {
+            let mut rng = Rng::new(seed);
+            (0..100)
+                .map(|_| choose(&scored, difficulty, &mut rng).unwrap().to_string())
+                .collect::<Vec<String>>()
+        };
+
+        for level in 1..=5 {
+            let difficulty = Difficulty::from_level(level).unwrap();
+            assert_eq!(picks(7, difficulty), picks(7, difficulty));
+        }
+        // The easiest level wanders off the best move, so another seed shows in the picks.
+        assert_ne!(picks(7, Difficulty::EASIEST), picks(8, Difficulty::EASIEST));
+        assert!(picks(7, Difficulty::HARDEST).iter().all(|m| m == "e2e4"));
+    }
+
+    #[test]
+    fn stops_searching_when_asked() {
+        let mut board = crate::new_board(None);
+        assert_eq!(search(&mut board, 4, &AtomicBool::new(true)), None);
+        let scored = search(&mut board, 1, &AtomicBool::new(false)).unwrap();
+        assert_eq!(scored.len(), 20);
+
+        // Starting over drops the search still going, only the last one answers.
+        let mut searcher = Searcher::new();
+        searcher.start(&board, 4);
+        searcher.start(&board, 1);
+        let scored = loop {
+            if let Some(scored) = searcher.poll() {
+                break scored;
+            }
+            assert!(searcher.busy(), "the search stopped without a result");
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        };
+        assert_eq!(scored.len(), 20);
+        assert!(!searcher.busy());
+    }
+}
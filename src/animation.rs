@@ -0,0 +1,224 @@
+use crate::eval::Position;
+use crate::move_kind::MoveKind;
+use crate::{coords, draw_piece_centered, Piece, RECT_WIDTH};
+use chess::ChessBoard;
+use raylib::prelude::*;
+
+/// Seconds a piece takes to slide over to its new square.
+const DURATION: f32 = 0.15;
+
+/// A piece on its way from one square to another, as board characters and square indices.
+struct Slide {
+    piece: char,
+    from: u32,
+    to: u32,
+    /// What stood on `captured_on` before, left in place until the piece gets there. That's
+    /// `to` except for en passant.
+    captured: char,
+    captured_on: u32,
+    /// Share of the animation gone before the piece sets off.
+    delay: f32,
+}
+
+/// Slides the pieces of each new move from where they stood to where they went, instead of
+/// having them jump there. The board already holds the new position, the destination squares
+/// are just drawn as before the move until the slide is over.
+///
+/// Castling moves the king first and the rook after it, and a pawn taken en passant fades out
+/// as the other one passes it, so the opponent's special moves are easy to follow.
+pub struct Animation {
+    /// Position last seen, and how many snapshots the board had then.
+    last: Option<(Position, usize)>,
+    slides: Vec<Slide>,
+    elapsed: f32,
+    /// Seconds the whole move takes, longer for castling's two slides one after the other.
+    duration: f32,
+    /// Whether the captured pieces fade out rather than wait to be covered.
+    fading: bool,
+}
+
+impl Animation {
+    pub fn new() -> Animation {
+        Animation {
+            last: None,
+            slides: Vec::new(),
+            elapsed: DURATION,
+            duration: DURATION,
+            fading: false,
+        }
+    }
+
+    /// Starts sliding when a move was made since the last call. Anything else changing the
+    /// board, like a takeback or a new game, shows up straight away. `instant` skips the slide
+    /// for a piece that was dragged onto its square.
+    pub fn update(&mut self, rl: &RaylibHandle, board: &ChessBoard, instant: bool) {
+        self.elapsed += rl.get_frame_time();
+        if self.elapsed >= self.duration {
+            self.slides.clear();
+        }
+
+        let position = board.board[board.board.len() - 1];
+        let plies = board.board.len();
+        let Some((last, last_plies)) = self.last.replace((position, plies)) else {
+            return;
+        };
+        if last == position {
+            return;
+        }
+
+        self.slides.clear();
+        if plies == last_plies + 1 && !instant {
+            self.slides = slides(&last, &position);
+            self.elapsed = 0.0;
+            self.duration = DURATION;
+            self.fading = false;
+
+            let white = !board.white_move;
+            match MoveKind::between(&last, &position, white) {
+                MoveKind::Castle => {
+                    self.duration = 2.0 * DURATION;
+                    for slide in &mut self.slides {
+                        if slide.piece.eq_ignore_ascii_case(&'r') {
+                            slide.delay = 0.5;
+                        }
+                    }
+                }
+                // The pawn taken en passant stands beside the square the other one goes to.
+                MoveKind::EnPassant => {
+                    if let Some(slide) = self.slides.first_mut() {
+                        slide.captured_on = slide.from / 8 * 8 + slide.to % 8;
+                        slide.captured = last[slide.from as usize / 8][slide.to as usize % 8];
+                    }
+                    self.fading = true;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Squares the slides are drawn on, to leave out when drawing the position.
+    pub fn squares(&self) -> Vec<u32> {
+        self.slides
+            .iter()
+            .flat_map(|slide| [slide.to, slide.captured_on])
+            .collect()
+    }
+
+    /// Draws whatever is being captured and the sliding pieces on top of it.
+    pub fn draw(&self, d: &mut impl RaylibDraw, textures: &[Texture2D], flipped: bool) {
+        let elapsed = (self.elapsed / self.duration).min(1.0);
+        let progress = |slide: &Slide| {
+            let t = ((elapsed - slide.delay) / (1.0 - slide.delay)).clamp(0.0, 1.0);
+            // Eases out, fast off the square and slowing down towards the end.
+            1.0 - (1.0 - t) * (1.0 - t)
+        };
+
+        let center = |square: u32| {
+            let (x, y) = coords::square_position(square, flipped);
+            let half = RECT_WIDTH as f32 / 2.0;
+            Vector2::new(x as f32 + half, y as f32 + half)
+        };
+        for slide in &self.slides {
+            if let Ok(Some(piece)) = Piece::from(slide.captured) {
+                let alpha = if self.fading {
+                    1.0 - progress(slide)
+                } else {
+                    1.0
+                };
+                let tint = Color::WHITE.fade(alpha);
+                draw_piece_centered(d, textures, piece, center(slide.captured_on), tint);
+            }
+        }
+        for slide in &self.slides {
+            if let Ok(Some(piece)) = Piece::from(slide.piece) {
+                let from = center(slide.from);
+                let position = from + (center(slide.to) - from) * progress(slide);
+                draw_piece_centered(d, textures, piece, position, Color::WHITE);
+            }
+        }
+    }
+}
+
+/// Pairs the squares a move emptied with the ones it filled, which covers castling's rook as
+/// well. A promoted piece comes from its pawn. In Chess960 the king or the rook can land where
+/// the other one stood, so that square counts as emptied too.
+fn slides(before: &Position, after: &Position) -> Vec<Slide> {
+    let squares = (0..64u32).map(|s| (s, s as usize / 8, s as usize % 8));
+    let mut emptied: Vec<u32> = squares
+        .clone()
+        .filter(|&(_, y, x)| before[y][x] != '.' && after[y][x] != before[y][x])
+        .map(|(s, _, _)| s)
+        .collect();
+    let at = |position: &Position, s: u32| position[s as usize / 8][s as usize % 8];
+
+    let mut slides = Vec::new();
+    for (to, y, x) in squares {
+        let piece = after[y][x];
+        if piece == '.' || piece == before[y][x] {
+            continue;
+        }
+        let white = piece.is_ascii_uppercase();
+        let pawn = if white { 'P' } else { 'p' };
+        let Some(idx) = emptied
+            .iter()
+            .position(|&s| at(before, s) == piece)
+            .or_else(|| emptied.iter().position(|&s| at(before, s) == pawn))
+        else {
+            continue;
+        };
+        // Only the other side's pieces are captured, castling can land on our own.
+        let taken = before[y][x];
+        let captured = if taken != '.' && taken.is_ascii_uppercase() != white {
+            taken
+        } else {
+            '.'
+        };
+
+        slides.push(Slide {
+            piece,
+            from: emptied.remove(idx),
+            to,
+            captured,
+            captured_on: to,
+            delay: 0.0,
+        });
+    }
+
+    slides
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen;
+
+    /// The slides between two positions given by their piece placement, as the piece and its
+    /// squares, and what it captures where.
+    fn moves(before: &str, after: &str) -> Vec<(char, u32, u32, char, u32)> {
+        let (before, _) = fen::parse(before).unwrap();
+        let (after, _) = fen::parse(after).unwrap();
+        slides(&before, &after)
+            .iter()
+            .map(|s| (s.piece, s.from, s.to, s.captured, s.captured_on))
+            .collect()
+    }
+
+    #[test]
+    fn slides_the_rook_when_castling() {
+        let slides = moves("4k3/8/8/8/8/8/8/4K2R", "4k3/8/8/8/8/8/8/5RK1");
+        assert_eq!(slides, [('R', 63, 61, '.', 61), ('K', 60, 62, '.', 62)]);
+    }
+
+    #[test]
+    fn slides_a_chess960_king_onto_its_rooks_square() {
+        // The king from f1 takes the rook's place on g1 and the rook lands where it stood.
+        let slides = moves("4k3/8/8/8/8/8/8/5K1R", "4k3/8/8/8/8/8/8/5RK1");
+        assert_eq!(slides, [('R', 63, 61, '.', 61), ('K', 61, 62, '.', 62)]);
+    }
+
+    #[test]
+    fn keeps_what_is_taken_on_the_square() {
+        let slides = moves("4k3/8/8/3p4/4P3/8/8/4K3", "4k3/8/8/3P4/8/8/8/4K3");
+        assert_eq!(slides, [('P', 36, 27, 'p', 27)]);
+    }
+}
@@ -0,0 +1,84 @@
+use crate::{coords, draw_arrow, RECT_WIDTH};
+use raylib::prelude::*;
+
+const COLOR_ARROW: u32 = 0xf0a01eb3;
+const COLOR_MARK: u32 = 0xe0461e80;
+
+/// Arrows and marked squares drawn with the right mouse button, for pointing things out while
+/// looking at a position. Dragging draws an arrow, a click without moving marks the square, and
+/// doing the same again removes it. Any left click clears them all.
+pub struct Annotations {
+    arrows: Vec<(u32, u32)>,
+    marks: Vec<u32>,
+    /// Square the right button went down on.
+    drag_from: Option<u32>,
+}
+
+impl Annotations {
+    pub fn new() -> Annotations {
+        Annotations {
+            arrows: Vec::new(),
+            marks: Vec::new(),
+            drag_from: None,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.arrows.clear();
+        self.marks.clear();
+        self.drag_from = None;
+    }
+
+    pub fn update(&mut self, rl: &RaylibHandle, flipped: bool) {
+        if rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT) {
+            self.clear();
+            return;
+        }
+
+        let square = coords::square_at(coords::mouse_position(rl), flipped);
+        if rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_RIGHT) {
+            self.drag_from = square;
+        }
+        if !rl.is_mouse_button_released(MouseButton::MOUSE_BUTTON_RIGHT) {
+            return;
+        }
+
+        // Letting go off the board drops whatever was being drawn.
+        let (Some(from), Some(to)) = (self.drag_from.take(), square) else {
+            return;
+        };
+        if from == to {
+            toggle(&mut self.marks, from);
+        } else {
+            toggle(&mut self.arrows, (from, to));
+        }
+    }
+
+    /// Draws the marks and arrows, meant to go on top of the pieces.
+    pub fn draw(&self, d: &mut impl RaylibDraw, flipped: bool) {
+        for &square in &self.marks {
+            let (x, y) = coords::square_position(square, flipped);
+            let half = RECT_WIDTH as f32 / 2.0;
+            d.draw_circle(
+                x + RECT_WIDTH / 2,
+                y + RECT_WIDTH / 2,
+                half * 0.9,
+                Color::get_color(COLOR_MARK),
+            );
+        }
+
+        for &(from, to) in &self.arrows {
+            draw_arrow(d, from, to, flipped, Color::get_color(COLOR_ARROW));
+        }
+    }
+}
+
+/// Adds `item` if it isn't there yet and removes it otherwise.
+fn toggle<T: PartialEq>(items: &mut Vec<T>, item: T) {
+    match items.iter().position(|i| *i == item) {
+        Some(idx) => {
+            items.remove(idx);
+        }
+        None => items.push(item),
+    }
+}
@@ -0,0 +1,303 @@
+use crate::eval::Position;
+use crate::{coords, fen, rules, ChessColor, Piece, COLOR_WHITE_SELECTED};
+use crate::{WINDOW_HEIGHT, WINDOW_WIDTH};
+use raylib::prelude::*;
+
+/// How a position set up in the editor gets played.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EditorChoice {
+    Local,
+    Computer,
+    Host,
+    /// Exploring the position, moving for either side.
+    Analysis,
+    /// Back to the main menu without playing.
+    Back,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Control {
+    Erase,
+    SideToMove,
+    Clear,
+    StartingPosition,
+    Play(EditorChoice),
+}
+
+/// Sets up a position to start a game from. A piece picked from the palette next to the board
+/// goes wherever the board is clicked or dragged over, the right button clears squares. The side
+/// to move is a button below the palette, and the position is checked before any game starts
+/// from it. Castling is open to every king and rook still on their starting squares, since the
+/// board only knows a position's history and not its castling field.
+pub struct BoardEditor {
+    position: Position,
+    white_move: bool,
+    /// What clicking a square puts there, `'.'` to clear it.
+    brush: char,
+    /// Why the position can't be played, shown until something changes.
+    message: Option<&'static str>,
+}
+
+impl BoardEditor {
+    pub const WIDTH: i32 = 280;
+    const PAD: f32 = 16.0;
+    const ICON_SIZE: f32 = 80.0;
+    const BUTTON_HEIGHT: f32 = 44.0;
+    const BUTTON_PAD: f32 = 10.0;
+    const FONT_SIZE: i32 = 24;
+    /// The palette rows, white's pieces on the left and black's on the right.
+    const PIECES: [char; 6] = ['k', 'q', 'r', 'b', 'n', 'p'];
+    const ROWS: [&'static [Control]; 9] = [
+        &[Control::Erase],
+        &[Control::SideToMove],
+        &[Control::Clear],
+        &[Control::StartingPosition],
+        &[Control::Play(EditorChoice::Local)],
+        &[Control::Play(EditorChoice::Computer)],
+        &[Control::Play(EditorChoice::Host)],
+        &[Control::Play(EditorChoice::Analysis)],
+        &[Control::Play(EditorChoice::Back)],
+    ];
+    /// The row each castling right needs the king on the e-file of, and the rook's column.
+    const CASTLING: [(char, usize, usize); 4] =
+        [('K', 7, 7), ('Q', 7, 0), ('k', 0, 7), ('q', 0, 0)];
+
+    /// Starts out from `fen`, or the standard starting position without one.
+    pub fn new(fen: Option<&str>) -> BoardEditor {
+        let fen = fen.unwrap_or(fen::STARTING_POSITION);
+        let (position, white_move) = fen::parse(fen)
+            .or_else(|_| fen::parse(fen::STARTING_POSITION))
+            .unwrap();
+        BoardEditor {
+            position,
+            white_move,
+            brush: 'Q',
+            message: None,
+        }
+    }
+
+    pub fn position(&self) -> &Position {
+        &self.position
+    }
+
+    fn left() -> f32 {
+        (WINDOW_WIDTH + coords::board_margin()) as f32
+    }
+
+    fn palette(piece: char) -> Rectangle {
+        let row = Self::PIECES
+            .iter()
+            .position(|&p| p == piece.to_ascii_lowercase())
+            .unwrap_or(0);
+        let column = if piece.is_ascii_uppercase() { 0 } else { 1 };
+        Rectangle::new(
+            Self::left() + Self::PAD + column as f32 * (Self::ICON_SIZE + Self::PAD),
+            Self::PAD + row as f32 * Self::ICON_SIZE,
+            Self::ICON_SIZE,
+            Self::ICON_SIZE,
+        )
+    }
+
+    fn control(row: usize, i: usize) -> Rectangle {
+        let count = Self::ROWS[row].len() as f32;
+        let full = Self::WIDTH as f32 - 2.0 * Self::PAD;
+        let width = (full - (count - 1.0) * Self::BUTTON_PAD) / count;
+        let top = 2.0 * Self::PAD + Self::PIECES.len() as f32 * Self::ICON_SIZE;
+        Rectangle::new(
+            Self::left() + Self::PAD + i as f32 * (width + Self::BUTTON_PAD),
+            top + row as f32 * (Self::BUTTON_HEIGHT + Self::BUTTON_PAD),
+            width,
+            Self::BUTTON_HEIGHT,
+        )
+    }
+
+    fn controls() -> impl Iterator<Item = (Control, Rectangle)> {
+        Self::ROWS.iter().enumerate().flat_map(|(row, controls)| {
+            controls
+                .iter()
+                .enumerate()
+                .map(move |(i, &control)| (control, Self::control(row, i)))
+        })
+    }
+
+    fn label(&self, control: Control) -> &'static str {
+        match control {
+            Control::Erase => "Erase",
+            Control::SideToMove if self.white_move => "White to move",
+            Control::SideToMove => "Black to move",
+            Control::Clear => "Clear board",
+            Control::StartingPosition => "Starting position",
+            Control::Play(EditorChoice::Local) => "Local game",
+            Control::Play(EditorChoice::Computer) => "vs AI",
+            Control::Play(EditorChoice::Host) => "Host game",
+            Control::Play(EditorChoice::Analysis) => "Analyse",
+            Control::Play(EditorChoice::Back) => "Back",
+        }
+    }
+
+    /// Whether a button that's either on or off is on.
+    fn is_on(&self, control: Control) -> bool {
+        match control {
+            Control::Erase => self.brush == '.',
+            _ => false,
+        }
+    }
+
+    /// Handles painting the board and the clicks on the panel, returning how to play once a
+    /// button for that is clicked. Starting a game only happens with a position `fen` takes.
+    pub fn update(&mut self, rl: &RaylibHandle) -> Option<EditorChoice> {
+        let mouse = coords::mouse_position(rl);
+        if let Some(square) = coords::square_at(mouse, false) {
+            let (y, x) = (square as usize / 8, square as usize % 8);
+            let before = self.position[y][x];
+            if rl.is_mouse_button_down(MouseButton::MOUSE_BUTTON_LEFT) {
+                self.position[y][x] = self.brush;
+            } else if rl.is_mouse_button_down(MouseButton::MOUSE_BUTTON_RIGHT) {
+                self.position[y][x] = '.';
+            }
+            if self.position[y][x] != before {
+                self.message = None;
+            }
+            return None;
+        }
+
+        if !rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT) {
+            return None;
+        }
+        let colors = [ChessColor::White, ChessColor::Black];
+        let pieces = colors.into_iter().flat_map(|side| {
+            Self::PIECES.map(|p| match side {
+                ChessColor::White => p.to_ascii_uppercase(),
+                ChessColor::Black => p,
+            })
+        });
+        for piece in pieces {
+            if Self::palette(piece).check_collision_point_rec(mouse) {
+                self.brush = piece;
+                return None;
+            }
+        }
+
+        let (control, _) =
+            Self::controls().find(|(_, rect)| rect.check_collision_point_rec(mouse))?;
+        self.message = None;
+        match control {
+            Control::Erase => self.brush = '.',
+            Control::SideToMove => self.white_move = !self.white_move,
+            Control::Clear => self.position = [['.'; 8]; 8],
+            Control::StartingPosition => *self = BoardEditor::new(None),
+            Control::Play(EditorChoice::Back) => return Some(EditorChoice::Back),
+            Control::Play(choice) => match self.fen() {
+                Ok(_) => return Some(choice),
+                Err(e) => self.message = Some(e),
+            },
+        }
+        None
+    }
+
+    /// The position as FEN if a game can start from it, or what's wrong with it.
+    pub fn fen(&self) -> Result<String, &'static str> {
+        let squares = self.position.iter().flatten();
+        for king in ['K', 'k'] {
+            if squares.clone().filter(|&&c| c == king).count() != 1 {
+                return Err("Each side needs exactly one king");
+            }
+        }
+        let back_ranks = [self.position[0], self.position[7]];
+        if back_ranks
+            .iter()
+            .flatten()
+            .any(|c| c.eq_ignore_ascii_case(&'p'))
+        {
+            return Err("Pawns can't stand on the first or last rank");
+        }
+        let waiting = if self.white_move {
+            ChessColor::Black
+        } else {
+            ChessColor::White
+        };
+        if rules::in_check(&self.position, waiting) {
+            return Err("The side that isn't to move is in check");
+        }
+
+        let mut castling = String::new();
+        for (flag, row, rook) in Self::CASTLING {
+            let (king_piece, rook_piece) = if flag.is_ascii_uppercase() {
+                ('K', 'R')
+            } else {
+                ('k', 'r')
+            };
+            if self.position[row][4] == king_piece && self.position[row][rook] == rook_piece {
+                castling.push(flag);
+            }
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+
+        let side = if self.white_move { "w" } else { "b" };
+        let placement = fen::placement(&self.position);
+        let fen = format!("{} {} {} - 0 1", placement, side, castling);
+        fen::parse(&fen).map_err(|_| "The position can't be written as FEN")?;
+        Ok(fen)
+    }
+
+    pub fn draw(&self, d: &mut RaylibDrawHandle, textures: &[Texture2D]) {
+        d.draw_rectangle(
+            Self::left() as i32,
+            0,
+            Self::WIDTH,
+            WINDOW_HEIGHT,
+            Color::get_color(0x00_00_00_55),
+        );
+
+        for side in [ChessColor::White, ChessColor::Black] {
+            for piece in Self::PIECES {
+                let piece = match side {
+                    ChessColor::White => piece.to_ascii_uppercase(),
+                    ChessColor::Black => piece,
+                };
+                let rect = Self::palette(piece);
+                if piece == self.brush {
+                    d.draw_rectangle_rec(rect, Color::get_color(COLOR_WHITE_SELECTED));
+                }
+                let Ok(Some(piece)) = Piece::from(piece) else {
+                    continue;
+                };
+                let texture = &textures[piece.texture_index()];
+                d.draw_texture_pro(
+                    texture,
+                    Rectangle::new(0.0, 0.0, texture.width() as f32, texture.height() as f32),
+                    rect,
+                    Vector2::zero(),
+                    0.0,
+                    Color::WHITE,
+                );
+            }
+        }
+
+        for (control, rect) in Self::controls() {
+            let background = if self.is_on(control) {
+                Color::get_color(COLOR_WHITE_SELECTED)
+            } else {
+                Color::RAYWHITE
+            };
+            d.draw_rectangle_rounded(rect, 0.3, 8, background);
+
+            let label = self.label(control);
+            let length = d.measure_text(label, Self::FONT_SIZE);
+            d.draw_text(
+                label,
+                (rect.x + (rect.width - length as f32) / 2.0) as i32,
+                (rect.y + (rect.height - Self::FONT_SIZE as f32) / 2.0) as i32,
+                Self::FONT_SIZE,
+                Color::BLACK,
+            );
+        }
+    }
+
+    /// Why the position can't be played, if a game was just refused for it.
+    pub fn message(&self) -> Option<&'static str> {
+        self.message
+    }
+}
@@ -0,0 +1,93 @@
+use crate::ai::{self, Searcher};
+use crate::eval::Position;
+use crate::{coords, draw_arrow, move_squares, ANALYSIS_DEPTH};
+use chess::ChessBoard;
+use raylib::prelude::*;
+
+/// The engine's best few moves in the position on the board, as arrows with their scores, for
+/// following a game without playing in it: spectating, replaying, or looking back over one that
+/// is over. Searching runs on its own thread like the computer opponent's.
+pub struct Candidates {
+    /// The position being analysed and whether white is to move in it.
+    position: Option<(Position, bool)>,
+    searcher: Searcher,
+    /// The best moves first, scored from white's point of view.
+    best: Vec<(String, i32)>,
+}
+
+impl Candidates {
+    const COUNT: usize = 3;
+    const FONT_SIZE: i32 = 20;
+    const PAD: i32 = 4;
+    /// The best move's arrow, the others fade out from it.
+    const COLOR: u32 = 0x8c50dcff;
+
+    pub fn new() -> Candidates {
+        Candidates {
+            position: None,
+            searcher: Searcher::new(),
+            best: Vec::new(),
+        }
+    }
+
+    /// Analyses the position after `ply` plies of `board`, starting over whenever that changes.
+    pub fn update(&mut self, board: &ChessBoard, ply: usize) {
+        let latest = board.board.len() - 1;
+        let white_move = board.white_move != ((latest - ply) % 2 == 1);
+        let position = (board.board[ply], white_move);
+
+        if self.position != Some(position) {
+            self.position = Some(position);
+            self.best.clear();
+
+            // The earlier positions count for repetitions, so the search gets those as well.
+            let mut position = ChessBoard::new();
+            position.board = board.board[..=ply].to_vec();
+            position.white_move = white_move;
+            self.searcher.start(&position, ANALYSIS_DEPTH);
+        }
+
+        if let Some(mut scored) = self.searcher.poll() {
+            scored.sort_by_key(|&(_, score)| std::cmp::Reverse(score));
+            scored.truncate(Self::COUNT);
+            let side = if white_move { 1 } else { -1 };
+            self.best = scored
+                .into_iter()
+                .map(|(m, score)| (m, side * score))
+                .collect();
+        }
+    }
+
+    /// Draws the worst of the moves first, so the best one ends up on top where they cross.
+    pub fn draw(&self, d: &mut RaylibDrawHandle, flipped: bool) {
+        for (i, (m, score)) in self.best.iter().enumerate().rev() {
+            let alpha = 1.0 - 0.3 * i as f32;
+            let color = Color::get_color(Self::COLOR).fade(alpha * 0.75);
+            let (from, to) = move_squares(m);
+            draw_arrow(d, from, to, flipped, color);
+
+            let text = if score.abs() >= ai::MATE - 1000 {
+                let sign = if *score > 0 { "+" } else { "-" };
+                format!("{}M", sign)
+            } else {
+                format!("{:+.1}", *score as f32 / 100.0)
+            };
+            let (x, y) = coords::square_position(to, flipped);
+            let length = d.measure_text(&text, Self::FONT_SIZE);
+            d.draw_rectangle(
+                x,
+                y,
+                length + 2 * Self::PAD,
+                Self::FONT_SIZE + 2 * Self::PAD,
+                Color::get_color(Self::COLOR).fade(alpha),
+            );
+            d.draw_text(
+                &text,
+                x + Self::PAD,
+                y + Self::PAD,
+                Self::FONT_SIZE,
+                Color::RAYWHITE,
+            );
+        }
+    }
+}
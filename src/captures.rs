@@ -0,0 +1,152 @@
+use crate::eval::Position;
+use crate::{ChessColor, Piece, WINDOW_HEIGHT};
+use raylib::prelude::*;
+
+/// Pieces each side has taken along with who's ahead on material, in a column next to the board
+/// with each side's at its end, across from the player's name plate.
+pub struct CapturesPanel;
+
+impl CapturesPanel {
+    pub const WIDTH: i32 = 160;
+    const PAD: i32 = 16;
+    const FONT_SIZE: i32 = 24;
+    const ICON_SIZE: i32 = 32;
+    const ICONS_PER_ROW: usize = 4;
+
+    /// `history` are the positions of the game up to the one shown.
+    pub fn draw(
+        d: &mut RaylibDrawHandle,
+        x: i32,
+        history: &[Position],
+        textures: &[Texture2D],
+        flipped: bool,
+    ) {
+        d.draw_rectangle(
+            x,
+            0,
+            Self::WIDTH,
+            WINDOW_HEIGHT,
+            Color::get_color(0x00_00_00_55),
+        );
+
+        let taken = captured(history);
+        let advantage = history.last().map_or(0, material);
+        for side in [ChessColor::White, ChessColor::Black] {
+            let lead = match side {
+                ChessColor::White => advantage,
+                ChessColor::Black => -advantage,
+            };
+            let pieces = &taken[side as usize];
+            let rows = pieces.len().div_ceil(Self::ICONS_PER_ROW) as i32;
+
+            // The captures start at the outer ends, with the lead towards the middle.
+            let at_bottom = (side == ChessColor::White) != flipped;
+            let icons_y = if at_bottom {
+                WINDOW_HEIGHT - Self::PAD - rows * Self::ICON_SIZE
+            } else {
+                Self::PAD
+            };
+
+            for (i, &c) in pieces.iter().enumerate() {
+                let Ok(Some(piece)) = Piece::from(c) else {
+                    continue;
+                };
+                let texture = &textures[piece.texture_index()];
+                let column = (i % Self::ICONS_PER_ROW) as i32;
+                let row = (i / Self::ICONS_PER_ROW) as i32;
+                d.draw_texture_pro(
+                    texture,
+                    Rectangle::new(0.0, 0.0, texture.width() as f32, texture.height() as f32),
+                    Rectangle::new(
+                        (x + Self::PAD + column * Self::ICON_SIZE) as f32,
+                        (icons_y + row * Self::ICON_SIZE) as f32,
+                        Self::ICON_SIZE as f32,
+                        Self::ICON_SIZE as f32,
+                    ),
+                    Vector2::zero(),
+                    0.0,
+                    Color::WHITE,
+                );
+            }
+
+            if lead > 0 {
+                let lead_y = if at_bottom {
+                    icons_y - Self::FONT_SIZE
+                } else {
+                    icons_y + rows * Self::ICON_SIZE
+                };
+                d.draw_text(
+                    &format!("+{}", lead),
+                    x + Self::PAD,
+                    lead_y,
+                    Self::FONT_SIZE,
+                    Color::RAYWHITE,
+                );
+            }
+        }
+    }
+}
+
+/// Points for a board character in the usual 1, 3, 3, 5, 9 scale, the king counts for nothing.
+fn value(c: char) -> i32 {
+    match c.to_ascii_lowercase() {
+        'p' => 1,
+        'n' | 'b' => 3,
+        'r' => 5,
+        'q' => 9,
+        _ => 0,
+    }
+}
+
+/// White's material minus black's in points.
+fn material(position: &Position) -> i32 {
+    position
+        .iter()
+        .flatten()
+        .map(|&c| {
+            if c.is_ascii_uppercase() {
+                value(c)
+            } else {
+                -value(c)
+            }
+        })
+        .sum()
+}
+
+/// The opponent's pieces each side took over the course of `history`, indexed by `ChessColor`
+/// and cheapest first. Whatever the other side lost on a move was captured, the mover's own
+/// pawn going missing is a promotion.
+fn captured(history: &[Position]) -> [Vec<char>; 2] {
+    let mut taken = [Vec::new(), Vec::new()];
+    for pair in history.windows(2) {
+        let [before, after] = pair else {
+            continue;
+        };
+        // Only the mover's pieces turn up on squares they weren't on.
+        let arrived = before
+            .iter()
+            .flatten()
+            .zip(after.iter().flatten())
+            .find(|(b, a)| b != a && **a != '.');
+        let Some((_, &moved)) = arrived else {
+            continue;
+        };
+        let (mover, opponent_pieces) = if moved.is_ascii_uppercase() {
+            (ChessColor::White, "pnbrq")
+        } else {
+            (ChessColor::Black, "PNBRQ")
+        };
+
+        for piece in opponent_pieces.chars() {
+            let count =
+                |position: &Position| position.iter().flatten().filter(|&&c| c == piece).count();
+            let lost = count(before).saturating_sub(count(after));
+            taken[mover as usize].extend(std::iter::repeat_n(piece, lost));
+        }
+    }
+
+    for pieces in &mut taken {
+        pieces.sort_by_key(|&c| value(c));
+    }
+    taken
+}
@@ -0,0 +1,118 @@
+use crate::name_plates::NamePlates;
+use crate::network::MAX_CHAT_LEN;
+use crate::RECT_WIDTH;
+use raylib::prelude::*;
+use std::collections::VecDeque;
+
+/// The last few chat messages over the top of the board, below the opponent's name, with a line
+/// to type ours into. C opens it and Enter sends what was typed, backspacing past the start
+/// closes it again.
+pub struct ChatBox {
+    /// Who said what, oldest first.
+    messages: VecDeque<(String, String)>,
+    /// What's been typed so far, `None` while the box is closed.
+    input: Option<String>,
+}
+
+impl ChatBox {
+    /// Messages kept and shown, older ones scroll off.
+    const SHOWN: usize = 6;
+    const FONT_SIZE: i32 = 24;
+    const PAD: i32 = 8;
+
+    pub fn new() -> ChatBox {
+        ChatBox {
+            messages: VecDeque::new(),
+            input: None,
+        }
+    }
+
+    /// Whether keys go into the box rather than being shortcuts.
+    pub fn is_open(&self) -> bool {
+        self.input.is_some()
+    }
+
+    /// Handles typing, returning a message once Enter is pressed on it. `typing` is whether
+    /// letters already go somewhere else, which keeps C from opening the box. So does Ctrl,
+    /// Ctrl+C copies the position.
+    pub fn update(&mut self, rl: &mut RaylibHandle, typing: bool) -> Option<String> {
+        let Some(input) = &mut self.input else {
+            let ctrl = rl.is_key_down(KeyboardKey::KEY_LEFT_CONTROL)
+                || rl.is_key_down(KeyboardKey::KEY_RIGHT_CONTROL);
+            if !typing && !ctrl && rl.is_key_pressed(KeyboardKey::KEY_C) {
+                // The C that opened the box is still queued up.
+                while rl.get_char_pressed().is_some() {}
+                self.input = Some(String::new());
+            }
+            return None;
+        };
+
+        while let Some(c) = rl.get_char_pressed() {
+            if !c.is_control() && input.len() + c.len_utf8() <= MAX_CHAT_LEN {
+                input.push(c);
+            }
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_BACKSPACE) && input.pop().is_none() {
+            self.input = None;
+            return None;
+        }
+
+        if !rl.is_key_pressed(KeyboardKey::KEY_ENTER) {
+            return None;
+        }
+        // Enter on an empty box just closes it.
+        self.input
+            .take()
+            .map(|text| text.trim().to_string())
+            .filter(|text| !text.is_empty())
+    }
+
+    /// Adds a message from `name` to the bottom of the ones shown.
+    pub fn push(&mut self, name: &str, text: String) {
+        self.messages.push_back((name.to_string(), text));
+        if self.messages.len() > Self::SHOWN {
+            self.messages.pop_front();
+        }
+    }
+
+    pub fn draw(&self, d: &mut impl RaylibDraw) {
+        let lines = self.messages.len() + usize::from(self.is_open());
+        if lines == 0 {
+            return;
+        }
+
+        let line_height = Self::FONT_SIZE + Self::PAD;
+        let width = 6 * RECT_WIDTH;
+        let (x, y) = (Self::PAD, NamePlates::BOTTOM);
+        d.draw_rectangle(
+            x,
+            y,
+            width,
+            lines as i32 * line_height + Self::PAD,
+            Color::get_color(0x00_00_00_99),
+        );
+
+        let mut line_y = y + Self::PAD;
+        for (name, text) in &self.messages {
+            let line = format!("{}: {}", name, text);
+            d.draw_text(
+                &line,
+                x + Self::PAD,
+                line_y,
+                Self::FONT_SIZE,
+                Color::RAYWHITE,
+            );
+            line_y += line_height;
+        }
+        if let Some(input) = &self.input {
+            let line = format!("Say: {}_", input);
+            d.draw_text(
+                &line,
+                x + Self::PAD,
+                line_y,
+                Self::FONT_SIZE,
+                Color::get_color(0xf5f580ff),
+            );
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use crate::eval::Position;
+use crate::pgn::square_name;
+use crate::{move_squares, rules, BoardExtensions, ChessColor};
+use chess::ChessBoard;
+
+/// Where the king and the two rooks of a Chess960 game started, as the king's file and then the
+/// queenside and kingside rook's. `None` unless `start` is a Chess960 starting position other
+/// than the standard one, which our chess library castles in by itself.
+pub fn home_files(start: &Position) -> Option<(usize, [usize; 2])> {
+    let white = start[7];
+    let mirrored = white
+        .iter()
+        .zip(start[0])
+        .all(|(&w, b)| w.to_ascii_lowercase() == b);
+    let pawns = start[1] == ['p'; 8] && start[6] == ['P'; 8];
+    let file = |piece: char| white.iter().position(|&c| c == piece);
+    let rooks: Vec<usize> = (0..8).filter(|&f| white[f] == 'R').collect();
+    let (Some(king), &[queenside, kingside]) = (file('K'), &rooks[..]) else {
+        return None;
+    };
+    let standard = king == 4 && queenside == 0 && kingside == 7;
+    (mirrored && pawns && queenside < king && king < kingside && !standard)
+        .then_some((king, [queenside, kingside]))
+}
+
+/// The castling moves the side to move in `board` has that our chess library doesn't know about,
+/// written as the king moving onto its own rook. Like in standard chess, the king and that rook
+/// can't have moved, every square either of them crosses or lands on has to be empty but for
+/// the two of them, and the king can't be in check, pass an attacked square or end up in check.
+pub fn castling_moves(board: &ChessBoard) -> Vec<String> {
+    let Some((king, rooks)) = board.board.first().and_then(home_files) else {
+        return Vec::new();
+    };
+    let side = board.current_side();
+    let (row, king_piece, rook_piece) = match side {
+        ChessColor::White => (7, 'K', 'R'),
+        ChessColor::Black => (0, 'k', 'r'),
+    };
+    let never_moved = |file: usize, piece: char| {
+        board
+            .board
+            .iter()
+            .all(|position| position[row][file] == piece)
+    };
+    if !never_moved(king, king_piece) {
+        return Vec::new();
+    }
+
+    let position = board.position();
+    let mut moves = Vec::new();
+    for rook in rooks {
+        if !never_moved(rook, rook_piece) {
+            continue;
+        }
+        // The library already castles a king from the e-file with a rook from the corner.
+        if king == 4 && (rook == 0 || rook == 7) {
+            continue;
+        }
+        let (king_to, rook_to) = destinations(king, rook);
+        let files = [king, rook, king_to, rook_to];
+        let (lowest, highest) = (*files.iter().min().unwrap(), *files.iter().max().unwrap());
+        let clear = (lowest..=highest).all(|f| f == king || f == rook || position[row][f] == '.');
+        if !clear {
+            continue;
+        }
+
+        // Without the two of them on the board neither blocks an attack on the king's path.
+        let mut bare = *position;
+        bare[row][king] = '.';
+        bare[row][rook] = '.';
+        let path = king.min(king_to)..=king.max(king_to);
+        let attacked = path
+            .into_iter()
+            .any(|f| rules::is_attacked(&bare, f as i32, row as i32, side.opposite()));
+        if attacked || rules::in_check(&castled(position, row, king, rook), side) {
+            continue;
+        }
+
+        let square = |file: usize| (row * 8 + file) as u32;
+        moves.push(format!(
+            "{}{}",
+            square_name(square(king)),
+            square_name(square(rook))
+        ));
+    }
+    moves
+}
+
+/// Whether `m` is one of the castling moves `castling_moves` writes for `position`.
+pub fn is_castling(position: &Position, m: &str) -> bool {
+    let (from, to) = move_squares(m);
+    let at = |s: u32| position[s as usize / 8][s as usize % 8];
+    let (king, rook) = (at(from), at(to));
+    m.len() == 4
+        && king.eq_ignore_ascii_case(&'k')
+        && rook.eq_ignore_ascii_case(&'r')
+        && king.is_ascii_uppercase() == rook.is_ascii_uppercase()
+}
+
+/// Castles with `m` on `board` if it's one of the moves of `castling_moves`, returning whether it
+/// was. The library only goes by the positions it has been through, so this is one more of
+/// those with the other side to move.
+pub fn castle(board: &mut ChessBoard, m: &str) -> bool {
+    if board.board.first().and_then(home_files).is_none() || !is_castling(board.position(), m) {
+        return false;
+    }
+    let (from, to) = move_squares(m);
+    let row = from as usize / 8;
+    let position = castled(board.position(), row, from as usize % 8, to as usize % 8);
+    board.board.push(position);
+    board.white_move = !board.white_move;
+    true
+}
+
+/// The files the king on `king` and the rook on `rook` end up on, the g- and f-file when the
+/// rook is on the king's right and the c- and d-file when it's on the left.
+fn destinations(king: usize, rook: usize) -> (usize, usize) {
+    if rook > king {
+        (6, 5)
+    } else {
+        (2, 3)
+    }
+}
+
+/// `position` after the king on `king` castles with the rook on `rook`, both on row `row`.
+fn castled(position: &Position, row: usize, king: usize, rook: usize) -> Position {
+    let (king_to, rook_to) = destinations(king, rook);
+    let mut after = *position;
+    let (king_piece, rook_piece) = (after[row][king], after[row][rook]);
+    after[row][king] = '.';
+    after[row][rook] = '.';
+    after[row][king_to] = king_piece;
+    after[row][rook_to] = rook_piece;
+    after
+}
@@ -0,0 +1,195 @@
+use crate::{BoardExtensions, ChessColor, WINDOW_HEIGHT, WINDOW_WIDTH};
+use chess::ChessBoard;
+use raylib::prelude::*;
+
+/// A chess clock, each side's remaining time counting down while it's their move and the
+/// increment added once they've made it.
+pub struct Clock {
+    /// Seconds left, indexed by `ChessColor`.
+    remaining: [f32; 2],
+    increment: f32,
+    /// Length of the board history when last updated, to notice moves being made.
+    ply: Option<usize>,
+    flagged: Option<ChessColor>,
+    /// The only side whose time runs out by this clock, `None` for either.
+    judged: Option<ChessColor>,
+    /// The side whose time ran out in the last update.
+    ran_out: Option<ChessColor>,
+    /// Seconds left below which a side's time is running low.
+    low_time: f32,
+    /// Which sides were already warned about running low, indexed like `remaining`.
+    warned: [bool; 2],
+    /// The side whose clock passed a whole second while running low in the last update.
+    ticked: Option<ChessColor>,
+}
+
+impl Clock {
+    const FONT_SIZE: i32 = 32;
+    const PAD: i32 = 10;
+    /// How many times a second a clock running low flashes.
+    const FLASH_RATE: f64 = 2.0;
+
+    /// `time` and `increment` in seconds, like in `Start`.
+    pub fn new(time: u64, increment: u64) -> Clock {
+        Clock {
+            remaining: [time as f32; 2],
+            increment: increment as f32,
+            ply: None,
+            flagged: None,
+            judged: None,
+            ran_out: None,
+            low_time: 10.0,
+            warned: [false; 2],
+            ticked: None,
+        }
+    }
+
+    /// Sets how many seconds left count as running low, see `Config::low_time`.
+    pub fn with_low_time(self, seconds: f32) -> Clock {
+        Clock {
+            low_time: seconds,
+            ..self
+        }
+    }
+
+    /// Only lets `side`'s time run out, the other side's clock stopping at zero until `flag` is
+    /// called for it. In a network game each player's own clock is the one that counts for
+    /// their time, so both sides agree on who lost on time.
+    pub fn judging(self, side: ChessColor) -> Clock {
+        Clock {
+            judged: Some(side),
+            ..self
+        }
+    }
+
+    /// Carries on a saved clock with `remaining` seconds left for white and black.
+    pub fn resume(remaining: [f32; 2], increment: u64) -> Clock {
+        Clock {
+            remaining,
+            ..Clock::new(0, increment)
+        }
+    }
+
+    /// Seconds left for white and black.
+    pub fn remaining(&self) -> [f32; 2] {
+        self.remaining
+    }
+
+    /// Parses `minutes+increment`, e.g. `5+3`, into seconds for `new`.
+    pub fn parse(s: &str) -> Option<(u64, u64)> {
+        let (minutes, increment) = s.split_once('+').unwrap_or((s, "0"));
+        let minutes: f32 = minutes.parse().ok()?;
+        let time = (minutes * 60.0) as u64;
+
+        (time > 0).then_some((time, increment.parse().ok()?))
+    }
+
+    /// The side whose time ran out, if one did. The clock stops once that happens.
+    pub fn flagged(&self) -> Option<ChessColor> {
+        self.flagged
+    }
+
+    /// The side whose time ran out in the last update, for telling the opponent.
+    pub fn ran_out(&self) -> Option<ChessColor> {
+        self.ran_out
+    }
+
+    /// Ends `side`'s time, for when the opponent's own clock said it ran out.
+    pub fn flag(&mut self, side: ChessColor) {
+        self.remaining[side as usize] = 0.0;
+        self.flagged = Some(side);
+    }
+
+    /// The side whose time just started running low, once per side.
+    pub fn running_low(&mut self) -> Option<ChessColor> {
+        let side = [ChessColor::White, ChessColor::Black]
+            .into_iter()
+            .find(|&side| {
+                !self.warned[side as usize] && self.remaining[side as usize] < self.low_time
+            })?;
+        self.warned[side as usize] = true;
+        Some(side)
+    }
+
+    /// The side whose clock, running low, passed a whole second in the last update.
+    pub fn ticked(&self) -> Option<ChessColor> {
+        self.ticked
+    }
+
+    pub fn update(&mut self, rl: &RaylibHandle, board: &ChessBoard, paused: bool) {
+        // A move adds the increment for the side that made it, a takeback doesn't.
+        let ply = board.board.len();
+        if self.ply.is_some_and(|last| ply > last) {
+            self.remaining[board.current_side().opposite() as usize] += self.increment;
+        }
+        self.ply = Some(ply);
+        self.ticked = None;
+        self.ran_out = None;
+
+        if paused || self.flagged.is_some() {
+            return;
+        }
+
+        let side = board.current_side();
+        let remaining = &mut self.remaining[side as usize];
+        let before = *remaining;
+        *remaining = (*remaining - rl.get_frame_time()).max(0.0);
+        if *remaining < self.low_time && remaining.ceil() != before.ceil() {
+            self.ticked = Some(side);
+        }
+        if *remaining == 0.0 && self.judged.is_none_or(|judged| judged == side) {
+            self.flagged = Some(side);
+            self.ran_out = Some(side);
+        }
+    }
+
+    /// Both clocks along the right edge, each on its side of the board. One running low is
+    /// drawn in red, flashing while it runs.
+    pub fn draw(&self, d: &mut RaylibDrawHandle, to_move: ChessColor, flipped: bool) {
+        let flash_on = (d.get_time() * Self::FLASH_RATE).fract() < 0.5;
+        for side in [ChessColor::White, ChessColor::Black] {
+            let seconds = self.remaining[side as usize];
+            let low = seconds < self.low_time;
+            let text = if low {
+                format!("{:.1}", seconds)
+            } else {
+                let seconds = seconds.ceil() as u32;
+                format!("{}:{:02}", seconds / 60, seconds % 60)
+            };
+
+            let length = d.measure_text(&text, Self::FONT_SIZE);
+            let width = length + 2 * Self::PAD;
+            let height = Self::FONT_SIZE + 2 * Self::PAD;
+            let x = WINDOW_WIDTH - width - Self::PAD;
+            let at_bottom = (side == ChessColor::White) != flipped;
+            let y = if at_bottom {
+                WINDOW_HEIGHT - height - Self::PAD
+            } else {
+                Self::PAD
+            };
+
+            // Only the running clock stands out.
+            let running = side == to_move && self.flagged.is_none();
+            let background = if running && low && flash_on {
+                Color::get_color(0xb4_1e_1e_cc)
+            } else if side == to_move {
+                Color::get_color(0x00_00_00_cc)
+            } else {
+                Color::get_color(0x00_00_00_66)
+            };
+            let color = match (low, running && flash_on) {
+                (true, true) => Color::RAYWHITE,
+                (true, false) => Color::RED,
+                (false, _) => Color::RAYWHITE,
+            };
+
+            d.draw_rectangle_rounded(
+                Rectangle::new(x as f32, y as f32, width as f32, height as f32),
+                0.3,
+                8,
+                background,
+            );
+            d.draw_text(&text, x + Self::PAD, y + Self::PAD, Self::FONT_SIZE, color);
+        }
+    }
+}
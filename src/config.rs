@@ -0,0 +1,384 @@
+use crate::log;
+use crate::sound::Effect;
+use crate::BOARD_BORDER;
+use std::path::{Path, PathBuf};
+
+/// Where the settings live, next to the adjourned games in the working directory.
+pub const PATH: &str = "chess-gui.toml";
+
+/// The per-user directory for what shouldn't sit in whatever directory the game was started
+/// from, like the TLS key: `$XDG_CONFIG_HOME/chess-gui`, `~/.config/chess-gui`, or
+/// `%APPDATA%\chess-gui` on Windows. It's the working directory when none of those are set.
+pub fn dir() -> PathBuf {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from));
+    base.map_or_else(PathBuf::new, |base| base.join("chess-gui"))
+}
+
+/// Board color presets the settings menu cycles through, light squares first.
+pub const THEMES: [(&str, u32, u32); 4] = [
+    ("Green", 0xebecd0ff, 0x779556ff),
+    ("Brown", 0xf0d9b5ff, 0xb58863ff),
+    ("Blue", 0xdee3e6ff, 0x8ca2adff),
+    ("Gray", 0xe0e0e0ff, 0x8a8a8aff),
+];
+
+/// Window sizes the settings menu cycles through, as a factor of the full-size layout.
+pub const SCALES: [f32; 4] = [0.5, 0.75, 1.0, 1.25];
+
+/// Master volumes the settings menu cycles through.
+pub const VOLUMES: [f32; 5] = [0.2, 0.4, 0.6, 0.8, 1.0];
+
+/// Volumes of a single sound effect the settings menu cycles through, which can be off.
+pub const EFFECT_VOLUMES: [f32; 6] = [0.0, 0.2, 0.4, 0.6, 0.8, 1.0];
+
+/// Preferences kept between runs. Command line flags still win over them.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Config {
+    /// Square colors as `0xRRGGBBAA`.
+    pub light_square: u32,
+    pub dark_square: u32,
+    /// Piece set under `assets/pieces`, `None` for the default one.
+    pub piece_set: Option<String>,
+    /// File letters and rank numbers along the edges of the board.
+    pub coordinates: bool,
+    pub sound: bool,
+    /// Master volume from 0 to 1, applied on top of the per-effect ones.
+    pub volume: f32,
+    /// Volume of each sound effect from 0 to 1, in the order of `Effect::ALL`.
+    pub effect_volumes: [f32; Effect::ALL.len()],
+    /// Where to connect when joining a game without giving an address.
+    pub address: Option<String>,
+    pub name: Option<String>,
+    /// Size of the window relative to the full-size layout, 1.0 has the board 1024 pixels wide.
+    pub window_scale: f32,
+    /// Seconds left on a clock below which it flashes and ticks.
+    pub low_time: f32,
+    /// Thickness of the frame around the board in full-size pixels, 0 for none.
+    pub board_border: i32,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            light_square: THEMES[0].1,
+            dark_square: THEMES[0].2,
+            piece_set: None,
+            coordinates: true,
+            sound: true,
+            volume: 1.0,
+            effect_volumes: [1.0; Effect::ALL.len()],
+            address: None,
+            name: None,
+            window_scale: 1.0,
+            low_time: 10.0,
+            board_border: BOARD_BORDER,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the settings file, falling back to the defaults for anything missing from it or
+    /// unreadable. No file at all just means nothing was changed yet.
+    pub fn load() -> Config {
+        let contents = match std::fs::read_to_string(PATH) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Config::default(),
+            Err(e) => {
+                log::error!("could not read {}: {}, using the default settings", PATH, e);
+                return Config::default();
+            }
+        };
+        Config::parse(&contents)
+    }
+
+    /// The settings in `contents`, logging each line that isn't understood.
+    fn parse(contents: &str) -> Config {
+        let mut config = Config::default();
+        for (number, line) in contents.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let parsed = line
+                .split_once('=')
+                .map(|(key, value)| (key.trim(), value.trim()))
+                .and_then(|(key, value)| config.set(key, value));
+            if parsed.is_none() {
+                log::error!("{} line {}: ignoring {}", PATH, number + 1, line);
+            }
+        }
+
+        config
+    }
+
+    /// Applies one `key = value` line, `None` if either isn't understood.
+    fn set(&mut self, key: &str, value: &str) -> Option<()> {
+        match key {
+            "light_square" => self.light_square = parse_color(&parse_string(value)?)?,
+            "dark_square" => self.dark_square = parse_color(&parse_string(value)?)?,
+            "piece_set" => self.piece_set = Some(parse_string(value)?),
+            "coordinates" => self.coordinates = value.parse().ok()?,
+            "sound" => self.sound = value.parse().ok()?,
+            "volume" => self.volume = parse_volume(value)?,
+            "address" => self.address = Some(parse_string(value)?),
+            "name" => self.name = Some(parse_string(value)?),
+            "window_scale" => {
+                self.window_scale = value.parse().ok().filter(|s| (0.25..=4.0).contains(s))?
+            }
+            "low_time" => self.low_time = value.parse().ok().filter(|&s: &f32| s >= 0.0)?,
+            "board_border" => {
+                self.board_border = value.parse().ok().filter(|b| (0..=64).contains(b))?
+            }
+            _ => {
+                // One `<effect>_volume` key per sound effect.
+                let name = key.strip_suffix("_volume")?;
+                let effect = Effect::ALL.iter().position(|e| e.name() == name)?;
+                self.effect_volumes[effect] = parse_volume(value)?;
+            }
+        }
+        Some(())
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        std::fs::write(Path::new(PATH), self.contents())
+    }
+
+    /// The settings file for these settings, which `parse` reads back the same.
+    fn contents(&self) -> String {
+        let mut contents = String::from("# chess-gui settings\n");
+        contents.push_str(&format!(
+            "light_square = \"#{:06x}\"\n",
+            self.light_square >> 8
+        ));
+        contents.push_str(&format!(
+            "dark_square = \"#{:06x}\"\n",
+            self.dark_square >> 8
+        ));
+        if let Some(set) = &self.piece_set {
+            contents.push_str(&format!("piece_set = {}\n", quote(set)));
+        }
+        contents.push_str(&format!("coordinates = {}\n", self.coordinates));
+        contents.push_str(&format!("sound = {}\n", self.sound));
+        contents.push_str(&format!("volume = {}\n", self.volume));
+        for (effect, volume) in Effect::ALL.iter().zip(self.effect_volumes) {
+            contents.push_str(&format!("{}_volume = {}\n", effect.name(), volume));
+        }
+        if let Some(address) = &self.address {
+            contents.push_str(&format!("address = {}\n", quote(address)));
+        }
+        if let Some(name) = &self.name {
+            contents.push_str(&format!("name = {}\n", quote(name)));
+        }
+        contents.push_str(&format!("window_scale = {}\n", self.window_scale));
+        contents.push_str(&format!("low_time = {}\n", self.low_time));
+        contents.push_str(&format!("board_border = {}\n", self.board_border));
+        contents
+    }
+
+    /// Index into `THEMES` of the current board colors, if they're one of the presets.
+    pub fn theme(&self) -> Option<usize> {
+        THEMES
+            .iter()
+            .position(|&(_, light, dark)| light == self.light_square && dark == self.dark_square)
+    }
+}
+
+/// The line up to a `#` that isn't inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '#' if !in_string => return &line[..i],
+            '"' if !escaped => in_string = !in_string,
+            _ => {}
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    line
+}
+
+/// A TOML basic string, without the escapes beyond `\"` and `\\`.
+fn parse_string(value: &str) -> Option<String> {
+    let inner = value.strip_prefix('"')?.strip_suffix('"')?;
+
+    let mut s = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => s.push(chars.next().filter(|c| matches!(c, '"' | '\\'))?),
+            '"' => return None,
+            _ => s.push(c),
+        }
+    }
+    Some(s)
+}
+
+fn parse_volume(value: &str) -> Option<f32> {
+    value.parse().ok().filter(|v| (0.0..=1.0).contains(v))
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// `#rrggbb` to `0xRRGGBBff`.
+fn parse_color(s: &str) -> Option<u32> {
+    let hex = s.strip_prefix('#').filter(|hex| hex.len() == 6)?;
+    u32::from_str_radix(hex, 16).ok().map(|rgb| rgb << 8 | 0xff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_comments_outside_strings() {
+        assert_eq!(strip_comment("sound = true # on"), "sound = true ");
+        assert_eq!(strip_comment("# all of it"), "");
+        assert_eq!(strip_comment(r##"name = "#1" # x"##), r##"name = "#1" "##);
+        // An escaped quote doesn't end the string, an escaped backslash before one does.
+        assert_eq!(strip_comment(r##"name = "a\"#""##), r##"name = "a\"#""##);
+        assert_eq!(strip_comment(r#"name = "a\\" # x"#), r#"name = "a\\" "#);
+    }
+
+    #[test]
+    fn parses_basic_strings() {
+        assert_eq!(parse_string("\"plain\"").as_deref(), Some("plain"));
+        assert_eq!(parse_string("\"\"").as_deref(), Some(""));
+        assert_eq!(
+            parse_string(r#""say \"hi\"""#).as_deref(),
+            Some("say \"hi\"")
+        );
+        assert_eq!(
+            parse_string(r#""back\\slash""#).as_deref(),
+            Some("back\\slash")
+        );
+        assert_eq!(parse_string("bare"), None);
+        assert_eq!(parse_string("\"open"), None);
+        assert_eq!(parse_string(r#""a"b""#), None);
+        assert_eq!(parse_string(r#""tab\t""#), None);
+    }
+
+    #[test]
+    fn quoting_reads_back() {
+        for s in [
+            "plain",
+            "",
+            "say \"hi\"",
+            "back\\slash",
+            "ends with \\",
+            "#hash",
+        ] {
+            assert_eq!(parse_string(&quote(s)).as_deref(), Some(s));
+            let line = format!("name = {}", quote(s));
+            assert_eq!(strip_comment(&line), line);
+        }
+    }
+
+    #[test]
+    fn parses_volumes_and_colors() {
+        assert_eq!(parse_volume("0"), Some(0.0));
+        assert_eq!(parse_volume("0.5"), Some(0.5));
+        assert_eq!(parse_volume("1.0"), Some(1.0));
+        assert_eq!(parse_volume("1.5"), None);
+        assert_eq!(parse_volume("-0.1"), None);
+        assert_eq!(parse_volume("loud"), None);
+
+        assert_eq!(parse_color("#f0d9b5"), Some(0xf0d9b5ff));
+        assert_eq!(parse_color("#F0D9B5"), Some(0xf0d9b5ff));
+        assert_eq!(parse_color("f0d9b5"), None);
+        assert_eq!(parse_color("#f0d9b"), None);
+        assert_eq!(parse_color("#f0d9b5ff"), None);
+        assert_eq!(parse_color("#g0d9b5"), None);
+    }
+
+    #[test]
+    fn sets_each_key() {
+        let mut config = Config::default();
+        assert_eq!(config.set("light_square", "\"#112233\""), Some(()));
+        assert_eq!(config.set("dark_square", "\"#445566\""), Some(()));
+        assert_eq!(config.set("piece_set", "\"wood\""), Some(()));
+        assert_eq!(config.set("coordinates", "false"), Some(()));
+        assert_eq!(config.set("sound", "false"), Some(()));
+        assert_eq!(config.set("volume", "0.4"), Some(()));
+        assert_eq!(config.set("capture_volume", "0.2"), Some(()));
+        assert_eq!(config.set("address", "\"example.org:8080\""), Some(()));
+        assert_eq!(config.set("name", "\"Ada\""), Some(()));
+        assert_eq!(config.set("window_scale", "0.75"), Some(()));
+        assert_eq!(config.set("low_time", "30"), Some(()));
+        assert_eq!(config.set("board_border", "0"), Some(()));
+
+        let capture = Effect::ALL
+            .iter()
+            .position(|&e| e == Effect::Capture)
+            .unwrap();
+        assert_eq!(config.light_square, 0x112233ff);
+        assert_eq!(config.dark_square, 0x445566ff);
+        assert_eq!(config.piece_set.as_deref(), Some("wood"));
+        assert!(!config.coordinates && !config.sound);
+        assert_eq!(config.volume, 0.4);
+        assert_eq!(config.effect_volumes[capture], 0.2);
+        assert_eq!(config.address.as_deref(), Some("example.org:8080"));
+        assert_eq!(config.name.as_deref(), Some("Ada"));
+        assert_eq!(config.window_scale, 0.75);
+        assert_eq!(config.low_time, 30.0);
+        assert_eq!(config.board_border, 0);
+    }
+
+    #[test]
+    fn refuses_bad_keys_and_values() {
+        let mut config = Config::default();
+        for (key, value) in [
+            ("light_square", "#112233"),
+            ("piece_set", "wood"),
+            ("coordinates", "yes"),
+            ("volume", "2"),
+            ("window_scale", "8"),
+            ("low_time", "-1"),
+            ("board_border", "100"),
+            ("siren_volume", "0.5"),
+            ("capture_volume", "loud"),
+            ("colour", "\"#112233\""),
+        ] {
+            assert_eq!(config.set(key, value), None, "{} = {}", key, value);
+        }
+        assert_eq!(config, Config::default());
+    }
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let config = Config {
+            light_square: THEMES[1].1,
+            dark_square: THEMES[1].2,
+            piece_set: Some("wood".to_string()),
+            coordinates: false,
+            sound: false,
+            volume: 0.6,
+            effect_volumes: [0.4; Effect::ALL.len()],
+            address: Some("host:4000".to_string()),
+            name: Some("a \"quoted\" # name".to_string()),
+            window_scale: 1.25,
+            low_time: 5.0,
+            board_border: 20,
+        };
+        assert_eq!(Config::parse(&config.contents()), config);
+        assert_eq!(
+            Config::parse(&Config::default().contents()),
+            Config::default()
+        );
+    }
+
+    #[test]
+    fn skips_lines_it_does_not_understand() {
+        let contents = "# settings\n\nsound = false # quiet\nvolume = 9\nnonsense\nname = \"Bo\"\n";
+        let config = Config::parse(contents);
+        assert!(!config.sound);
+        assert_eq!(config.volume, Config::default().volume);
+        assert_eq!(config.name.as_deref(), Some("Bo"));
+    }
+}
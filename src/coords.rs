@@ -0,0 +1,168 @@
+use crate::{
+    BOARD_BORDER, BOARD_SHADOW, BOARD_SHADOW_OFFSET, RECT_WIDTH, WINDOW_HEIGHT, WINDOW_WIDTH,
+};
+use raylib::prelude::*;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+// Everything is laid out in window (logical) pixels. On high-DPI displays the framebuffer can be
+// larger than the window, so rendering is zoomed by the content scale and mouse input is mapped
+// back through the same camera. That way the board fills the window and clicks land where the
+// squares are drawn regardless of the display. The camera also moves the board in from the
+// window edges, so layout coordinates have the board's top left corner at the origin.
+//
+// The window can be resized freely, the whole layout is scaled to fit it and centered in
+// whatever room is left over.
+
+/// Ratio between framebuffer pixels and logical window pixels, 1.0 on regular displays.
+pub fn content_scale(rl: &RaylibHandle) -> f32 {
+    let screen_width = rl.get_screen_width();
+    if screen_width <= 0 {
+        return 1.0;
+    }
+
+    let scale = rl.get_render_width() as f32 / screen_width as f32;
+    if scale > 0.0 {
+        scale
+    } else {
+        1.0
+    }
+}
+
+/// Thickness of the frame around the board, from the settings.
+static BORDER: AtomicI32 = AtomicI32::new(BOARD_BORDER);
+/// Full size of everything that's laid out, the margins around the board included.
+static LAYOUT_WIDTH: AtomicI32 = AtomicI32::new(WINDOW_WIDTH + 2 * margin(BOARD_BORDER));
+static LAYOUT_HEIGHT: AtomicI32 = AtomicI32::new(WINDOW_HEIGHT + 2 * margin(BOARD_BORDER));
+
+/// Space between the board and the window edges with a frame `border` thick, room for the frame
+/// and its shadow.
+const fn margin(border: i32) -> i32 {
+    border + BOARD_SHADOW + BOARD_SHADOW_OFFSET
+}
+
+/// Sets the thickness of the frame around the board, which the margins around it grow with.
+pub fn set_board_border(border: i32) {
+    BORDER.store(border, Ordering::Relaxed);
+    reset_layout_size();
+}
+
+pub fn board_border() -> i32 {
+    BORDER.load(Ordering::Relaxed)
+}
+
+/// Space between the board and the window edges for the current frame.
+pub fn board_margin() -> i32 {
+    margin(board_border())
+}
+
+/// Sets how much room the layout needs at full size, which is what gets fit into the window.
+pub fn set_layout_size(width: i32, height: i32) {
+    LAYOUT_WIDTH.store(width, Ordering::Relaxed);
+    LAYOUT_HEIGHT.store(height, Ordering::Relaxed);
+}
+
+/// Goes back to the layout of just the board, as on the menus.
+pub fn reset_layout_size() {
+    set_layout_size(
+        WINDOW_WIDTH + 2 * board_margin(),
+        WINDOW_HEIGHT + 2 * board_margin(),
+    );
+}
+
+fn layout_size() -> (f32, f32) {
+    (
+        LAYOUT_WIDTH.load(Ordering::Relaxed) as f32,
+        LAYOUT_HEIGHT.load(Ordering::Relaxed) as f32,
+    )
+}
+
+/// The window's size for the layout at `scale` times its full size, the window size setting.
+/// That's only where the window starts out, it can be resized freely from there.
+pub fn window_size(scale: f32) -> (i32, i32) {
+    let (width, height) = layout_size();
+    ((width * scale) as i32, (height * scale) as i32)
+}
+
+/// Resizes the window to `window_size`, for when the layout or the setting changed.
+pub fn fit_window(rl: &mut RaylibHandle, scale: f32) {
+    let (width, height) = window_size(scale);
+    rl.set_window_size(width, height);
+}
+
+/// Window pixels per layout pixel, as large as it gets with the whole layout still in view.
+fn fit(rl: &RaylibHandle) -> f32 {
+    let (width, height) = layout_size();
+    let scale = (rl.get_screen_width() as f32 / width).min(rl.get_screen_height() as f32 / height);
+    if scale > 0.0 {
+        scale
+    } else {
+        1.0
+    }
+}
+
+/// Framebuffer pixels per layout pixel, fitting the window on top of the content scale.
+pub fn zoom(rl: &RaylibHandle) -> f32 {
+    content_scale(rl) * fit(rl)
+}
+
+/// Camera that all drawing should happen under.
+pub fn camera(rl: &RaylibHandle) -> Camera2D {
+    let scale = zoom(rl);
+    let content = content_scale(rl);
+    let (width, height) = layout_size();
+
+    // Whatever the layout doesn't fill is split evenly on both sides.
+    let left = (rl.get_screen_width() as f32 * content - width * scale) / 2.0;
+    let top = (rl.get_screen_height() as f32 * content - height * scale) / 2.0;
+    let margin = board_margin() as f32 * scale;
+
+    Camera2D {
+        offset: Vector2::new(left + margin, top + margin),
+        target: Vector2::zero(),
+        rotation: 0.0,
+        zoom: scale,
+    }
+}
+
+/// Mouse position in layout coordinates, i.e. the same space the board is drawn in.
+pub fn mouse_position(rl: &RaylibHandle) -> Vector2 {
+    let scale = content_scale(rl);
+    let mouse = rl.get_mouse_position();
+
+    rl.get_screen_to_world2D(Vector2::new(mouse.x * scale, mouse.y * scale), camera(rl))
+}
+
+/// The board's area in layout coordinates, which menus and overlays center themselves on.
+pub fn board_area() -> Rectangle {
+    Rectangle::new(0.0, 0.0, WINDOW_WIDTH as f32, WINDOW_HEIGHT as f32)
+}
+
+/// Everything that's laid out, the board with its margins and the panels next to it.
+pub fn layout_area() -> Rectangle {
+    let (width, height) = layout_size();
+    let margin = board_margin() as f32;
+    Rectangle::new(-margin, -margin, width, height)
+}
+
+/// The square under a point in layout coordinates, or `None` if it's outside the board. A
+/// `flipped` board is seen from Black's side, which simply mirrors the square index.
+pub fn square_at(pos: Vector2, flipped: bool) -> Option<u32> {
+    if pos.x < 0.0 || pos.y < 0.0 || pos.x >= WINDOW_WIDTH as f32 || pos.y >= WINDOW_HEIGHT as f32 {
+        return None;
+    }
+
+    let x = pos.x as u32 / RECT_WIDTH as u32;
+    let y = pos.y as u32 / RECT_WIDTH as u32;
+
+    let square = y * 8 + x;
+    Some(if flipped { 63 - square } else { square })
+}
+
+/// Top left corner of a square in layout coordinates.
+pub fn square_position(square: u32, flipped: bool) -> (i32, i32) {
+    let square = if flipped { 63 - square } else { square };
+    let x = (square & 7) as i32;
+    let y = (square / 8) as i32;
+
+    (x * RECT_WIDTH, y * RECT_WIDTH)
+}
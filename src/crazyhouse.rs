@@ -0,0 +1,288 @@
+use crate::eval::Position;
+use crate::pgn::square_name;
+use crate::{
+    chess960, fen, move_squares, rules, BoardExtensions, ChessColor, Piece, WINDOW_HEIGHT,
+};
+use chess::ChessBoard;
+use raylib::prelude::*;
+
+/// The pieces that can be dropped, in the order the pockets list them.
+const PIECES: [char; 5] = ['p', 'n', 'b', 'r', 'q'];
+
+/// The pieces each side took in Crazyhouse and can put back on the board as their own, counted
+/// by `ChessColor` and then by `PIECES`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Pockets([[u32; 5]; 2]);
+
+impl Pockets {
+    /// The pockets a game starts with, `None` for a FEN without any since that's not Crazyhouse.
+    pub fn from_fen(fen: &str) -> Option<Pockets> {
+        let mut pockets = Pockets([[0; 5]; 2]);
+        for c in fen::pockets(fen)?.chars() {
+            let side = if c.is_ascii_uppercase() {
+                ChessColor::White
+            } else {
+                ChessColor::Black
+            };
+            let piece = PIECES.iter().position(|&p| p == c.to_ascii_lowercase())?;
+            pockets.0[side as usize][piece] += 1;
+        }
+        Some(pockets)
+    }
+
+    /// The pieces in the pockets the way FEN lists them between the brackets, white's first.
+    pub fn fen(&self) -> String {
+        let mut pieces = String::new();
+        for side in [ChessColor::White, ChessColor::Black] {
+            for (i, &piece) in PIECES.iter().enumerate() {
+                let count = self.0[side as usize][i] as usize;
+                pieces.extend(std::iter::repeat_n(placed(piece, side), count));
+            }
+        }
+        pieces
+    }
+
+    /// How many of `piece`, a lowercase board character, `side` has to drop.
+    pub fn count(&self, side: ChessColor, piece: char) -> u32 {
+        PIECES
+            .iter()
+            .position(|&p| p == piece)
+            .map_or(0, |i| self.0[side as usize][i])
+    }
+
+    fn add(&mut self, side: ChessColor, piece: char, amount: i32) {
+        if let Some(i) = PIECES.iter().position(|&p| p == piece) {
+            let count = &mut self.0[side as usize][i];
+            *count = count.saturating_add_signed(amount);
+        }
+    }
+
+    /// The pockets after the moves between the positions of `history`, these being the ones the
+    /// game started with. Whatever the mover's opponent lost goes into the mover's pocket, and
+    /// a piece of theirs turning up without any leaving a square came out of it.
+    pub fn after(&self, history: &[Position]) -> Pockets {
+        let mut pockets = self.clone();
+        // A promoted piece goes back to being a pawn once it's taken, so it's followed around.
+        let mut promoted = [[false; 8]; 8];
+        let squares = || (0..8).flat_map(|y| (0..8).map(move |x| (y, x)));
+
+        for pair in history.windows(2) {
+            let [before, after] = pair else {
+                continue;
+            };
+            // Only the mover's pieces turn up on squares they weren't on.
+            let arrived: Vec<(usize, usize)> = squares()
+                .filter(|&(y, x)| before[y][x] != after[y][x] && after[y][x] != '.')
+                .collect();
+            let Some(&(y, x)) = arrived.first() else {
+                continue;
+            };
+            let white = after[y][x].is_ascii_uppercase();
+            let mover = if white {
+                ChessColor::White
+            } else {
+                ChessColor::Black
+            };
+            let ours = |c: char| c != '.' && c.is_ascii_uppercase() == white;
+
+            for (y, x) in squares() {
+                let piece = before[y][x];
+                if piece != '.' && !ours(piece) && after[y][x] != piece {
+                    let piece = if promoted[y][x] { 'p' } else { piece };
+                    pockets.add(mover, piece.to_ascii_lowercase(), 1);
+                    promoted[y][x] = false;
+                }
+            }
+
+            let left: Vec<(usize, usize)> = squares()
+                .filter(|&(y, x)| ours(before[y][x]) && after[y][x] != before[y][x])
+                .collect();
+            match (&left[..], &arrived[..]) {
+                (&[], &[(y, x)]) => pockets.add(mover, after[y][x].to_ascii_lowercase(), -1),
+                (&[(from_y, from_x)], &[(y, x)]) => {
+                    let promotes = before[from_y][from_x].eq_ignore_ascii_case(&'p')
+                        && !after[y][x].eq_ignore_ascii_case(&'p');
+                    promoted[y][x] = promoted[from_y][from_x] || promotes;
+                    promoted[from_y][from_x] = false;
+                }
+                // Castling, and neither the king nor a rook that can still castle was promoted.
+                _ => {}
+            }
+        }
+
+        pockets
+    }
+}
+
+/// The move string for dropping `piece`, a lowercase board character, on `square`. Our chess
+/// library has nothing like it, so it's the square twice with the piece after, as in `e4e4n`.
+pub fn drop_move(square: u32, piece: char) -> String {
+    let name = square_name(square);
+    format!("{}{}{}", name, name, piece)
+}
+
+/// The piece move string `m` drops, if it's a drop.
+pub fn dropped_piece(m: &str) -> Option<char> {
+    let piece = m.chars().nth(4)?;
+    let bytes = m.as_bytes();
+    (bytes.len() == 5 && bytes[0..2] == bytes[2..4] && PIECES.contains(&piece)).then_some(piece)
+}
+
+pub fn is_drop(m: &str) -> bool {
+    dropped_piece(m).is_some()
+}
+
+/// Every drop the side to move in `board` has out of `pockets`. Pawns can't go on the first or
+/// last rank, and like any move a drop can't leave the king in check.
+fn drops(board: &ChessBoard, pockets: &Pockets) -> Vec<String> {
+    let side = board.current_side();
+    let position = board.position();
+    let mut drops = Vec::new();
+    for piece in PIECES {
+        if pockets.count(side, piece) == 0 {
+            continue;
+        }
+        for square in 0..64u32 {
+            let (y, x) = (square as usize / 8, square as usize % 8);
+            if position[y][x] != '.' || (piece == 'p' && (y == 0 || y == 7)) {
+                continue;
+            }
+            let mut after = *position;
+            after[y][x] = placed(piece, side);
+            if !rules::in_check(&after, side) {
+                drops.push(drop_move(square, piece));
+            }
+        }
+    }
+    drops
+}
+
+/// The legal moves in `board`, along with the drops when playing Crazyhouse, `start` being the
+/// pockets the game started with, and the castling our chess library misses in Chess960.
+pub fn legal_moves(board: &mut ChessBoard, start: Option<&Pockets>) -> Vec<String> {
+    let mut moves = board.get_moves();
+    moves.extend(chess960::castling_moves(board));
+    if let Some(start) = start {
+        moves.extend(drops(board, &start.after(&board.board)));
+    }
+    moves
+}
+
+/// Makes move `m` on `board`, which may be a drop or Chess960 castling. Our chess library only
+/// goes by the positions it has been through, so a drop is one more of those with the other side
+/// to move, and undoing it is the same as for any other move.
+pub fn make_move(board: &mut ChessBoard, m: String) {
+    if chess960::castle(board, &m) {
+        return;
+    }
+    let Some(piece) = dropped_piece(&m) else {
+        board.make_move(m);
+        return;
+    };
+
+    let (square, _) = move_squares(&m);
+    let mut position = *board.position();
+    position[square as usize / 8][square as usize % 8] = placed(piece, board.current_side());
+    board.board.push(position);
+    board.white_move = !board.white_move;
+}
+
+/// The board character of `piece` once `side` drops it.
+pub fn placed(piece: char, side: ChessColor) -> char {
+    match side {
+        ChessColor::White => piece.to_ascii_uppercase(),
+        ChessColor::Black => piece,
+    }
+}
+
+/// Each player's pocket in a column next to the board, at their end of it, a piece they have
+/// none of faded out. Pieces are dragged out of it onto the board.
+pub struct PocketsPanel;
+
+impl PocketsPanel {
+    pub const WIDTH: i32 = 160;
+    const PAD: i32 = 16;
+    const ICON_SIZE: i32 = 80;
+    const FONT_SIZE: i32 = 32;
+
+    /// Where `side`'s `piece` sits in the panel starting at `x`.
+    fn slot(x: i32, side: ChessColor, piece: usize, flipped: bool) -> Rectangle {
+        let at_bottom = (side == ChessColor::White) != flipped;
+        let offset = piece as i32 * Self::ICON_SIZE;
+        let y = if at_bottom {
+            WINDOW_HEIGHT - Self::PAD - Self::ICON_SIZE - offset
+        } else {
+            Self::PAD + offset
+        };
+        Rectangle::new(
+            (x + Self::PAD) as f32,
+            y as f32,
+            Self::ICON_SIZE as f32,
+            Self::ICON_SIZE as f32,
+        )
+    }
+
+    /// The piece of `side`'s under `mouse` in the panel starting at `x`, if they have one.
+    pub fn piece_at(
+        x: i32,
+        pockets: &Pockets,
+        side: ChessColor,
+        mouse: Vector2,
+        flipped: bool,
+    ) -> Option<char> {
+        PIECES.into_iter().enumerate().find_map(|(i, piece)| {
+            let slot = Self::slot(x, side, i, flipped);
+            let hit = slot.check_collision_point_rec(mouse) && pockets.count(side, piece) > 0;
+            hit.then_some(piece)
+        })
+    }
+
+    pub fn draw(
+        d: &mut RaylibDrawHandle,
+        x: i32,
+        pockets: &Pockets,
+        textures: &[Texture2D],
+        flipped: bool,
+    ) {
+        d.draw_rectangle(
+            x,
+            0,
+            Self::WIDTH,
+            WINDOW_HEIGHT,
+            Color::get_color(0x00_00_00_55),
+        );
+
+        for side in [ChessColor::White, ChessColor::Black] {
+            for (i, piece) in PIECES.into_iter().enumerate() {
+                let Ok(Some(p)) = Piece::from(placed(piece, side)) else {
+                    continue;
+                };
+                let count = pockets.count(side, piece);
+                let slot = Self::slot(x, side, i, flipped);
+                let tint = if count > 0 {
+                    Color::WHITE
+                } else {
+                    Color::WHITE.fade(0.25)
+                };
+                let texture = &textures[p.texture_index()];
+                d.draw_texture_pro(
+                    texture,
+                    Rectangle::new(0.0, 0.0, texture.width() as f32, texture.height() as f32),
+                    slot,
+                    Vector2::zero(),
+                    0.0,
+                    tint,
+                );
+                if count > 1 {
+                    d.draw_text(
+                        &format!("x{}", count),
+                        (slot.x + slot.width) as i32,
+                        (slot.y + slot.height) as i32 - Self::FONT_SIZE,
+                        Self::FONT_SIZE,
+                        Color::RAYWHITE,
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use crate::{ChessColor, Piece, PieceType};
+
+pub type Position = [[char; 8]; 8];
+
+/// Piece values in centipawns, indexed by `PieceType`.
+const PIECE_VALUES: [i32; 6] = [100, 320, 330, 500, 900, 0];
+
+// Piece-square tables from White's point of view, first row is the 8th rank, same as `Position`.
+#[rustfmt::skip]
+const PAWN_TABLE: [[i32; 8]; 8] = [
+    [  0,   0,   0,   0,   0,   0,   0,   0],
+    [ 50,  50,  50,  50,  50,  50,  50,  50],
+    [ 10,  10,  20,  30,  30,  20,  10,  10],
+    [  5,   5,  10,  25,  25,  10,   5,   5],
+    [  0,   0,   0,  20,  20,   0,   0,   0],
+    [  5,  -5, -10,   0,   0, -10,  -5,   5],
+    [  5,  10,  10, -20, -20,  10,  10,   5],
+    [  0,   0,   0,   0,   0,   0,   0,   0],
+];
+
+#[rustfmt::skip]
+const KNIGHT_TABLE: [[i32; 8]; 8] = [
+    [-50, -40, -30, -30, -30, -30, -40, -50],
+    [-40, -20,   0,   0,   0,   0, -20, -40],
+    [-30,   0,  10,  15,  15,  10,   0, -30],
+    [-30,   5,  15,  20,  20,  15,   5, -30],
+    [-30,   0,  15,  20,  20,  15,   0, -30],
+    [-30,   5,  10,  15,  15,  10,   5, -30],
+    [-40, -20,   0,   5,   5,   0, -20, -40],
+    [-50, -40, -30, -30, -30, -30, -40, -50],
+];
+
+#[rustfmt::skip]
+const BISHOP_TABLE: [[i32; 8]; 8] = [
+    [-20, -10, -10, -10, -10, -10, -10, -20],
+    [-10,   0,   0,   0,   0,   0,   0, -10],
+    [-10,   0,   5,  10,  10,   5,   0, -10],
+    [-10,   5,   5,  10,  10,   5,   5, -10],
+    [-10,   0,  10,  10,  10,  10,   0, -10],
+    [-10,  10,  10,  10,  10,  10,  10, -10],
+    [-10,   5,   0,   0,   0,   0,   5, -10],
+    [-20, -10, -10, -10, -10, -10, -10, -20],
+];
+
+#[rustfmt::skip]
+const ROOK_TABLE: [[i32; 8]; 8] = [
+    [  0,   0,   0,   0,   0,   0,   0,   0],
+    [  5,  10,  10,  10,  10,  10,  10,   5],
+    [ -5,   0,   0,   0,   0,   0,   0,  -5],
+    [ -5,   0,   0,   0,   0,   0,   0,  -5],
+    [ -5,   0,   0,   0,   0,   0,   0,  -5],
+    [ -5,   0,   0,   0,   0,   0,   0,  -5],
+    [ -5,   0,   0,   0,   0,   0,   0,  -5],
+    [  0,   0,   0,   5,   5,   0,   0,   0],
+];
+
+#[rustfmt::skip]
+const QUEEN_TABLE: [[i32; 8]; 8] = [
+    [-20, -10, -10,  -5,  -5, -10, -10, -20],
+    [-10,   0,   0,   0,   0,   0,   0, -10],
+    [-10,   0,   5,   5,   5,   5,   0, -10],
+    [ -5,   0,   5,   5,   5,   5,   0,  -5],
+    [  0,   0,   5,   5,   5,   5,   0,  -5],
+    [-10,   5,   5,   5,   5,   5,   0, -10],
+    [-10,   0,   5,   0,   0,   0,   0, -10],
+    [-20, -10, -10,  -5,  -5, -10, -10, -20],
+];
+
+#[rustfmt::skip]
+const KING_TABLE: [[i32; 8]; 8] = [
+    [-30, -40, -40, -50, -50, -40, -40, -30],
+    [-30, -40, -40, -50, -50, -40, -40, -30],
+    [-30, -40, -40, -50, -50, -40, -40, -30],
+    [-30, -40, -40, -50, -50, -40, -40, -30],
+    [-20, -30, -30, -40, -40, -30, -30, -20],
+    [-10, -20, -20, -20, -20, -20, -20, -10],
+    [ 20,  20,   0,   0,   0,   0,  20,  20],
+    [ 20,  30,  10,   0,   0,  10,  30,  20],
+];
+
+fn piece_square_table(t: PieceType) -> &'static [[i32; 8]; 8] {
+    match t {
+        PieceType::Pawn => &PAWN_TABLE,
+        PieceType::Knight => &KNIGHT_TABLE,
+        PieceType::Bishop => &BISHOP_TABLE,
+        PieceType::Rook => &ROOK_TABLE,
+        PieceType::Queen => &QUEEN_TABLE,
+        PieceType::King => &KING_TABLE,
+    }
+}
+
+/// Static evaluation in centipawns (material plus piece-square tables), positive means White is
+/// better.
+pub fn evaluate(position: &Position) -> i32 {
+    let mut score = 0;
+
+    for (y, row) in position.iter().enumerate() {
+        for (x, &c) in row.iter().enumerate() {
+            if let Ok(Some(piece)) = Piece::from(c) {
+                // The tables are written for White, so mirror the rank for Black.
+                let rank = match piece.color {
+                    ChessColor::White => y,
+                    ChessColor::Black => 7 - y,
+                };
+
+                let value = PIECE_VALUES[piece.t as usize] + piece_square_table(piece.t)[rank][x];
+                score += sign(piece.color) * value;
+            }
+        }
+    }
+
+    score
+}
+
+fn sign(color: ChessColor) -> i32 {
+    match color {
+        ChessColor::White => 1,
+        ChessColor::Black => -1,
+    }
+}
@@ -0,0 +1,327 @@
+use crate::chess960;
+use crate::crazyhouse::Pockets;
+use crate::eval::Position;
+use chess::ChessBoard;
+
+pub const STARTING_POSITION: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct InvalidFen(&'static str);
+
+impl std::fmt::Display for InvalidFen {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid FEN: {}", self.0)
+    }
+}
+
+/// The piece placement and side to move of a FEN string. Crazyhouse pockets after the placement
+/// are checked, `pockets` is what gets them.
+///
+/// The rest of the fields are checked but otherwise ignored, our chess library works out
+/// castling and en passant from the position history and has nowhere to put them.
+pub fn parse(fen: &str) -> Result<(Position, bool), InvalidFen> {
+    let mut fields = fen.split_whitespace();
+    let placement = fields.next().ok_or(InvalidFen("empty"))?;
+    let placement = match placement.split_once('[') {
+        Some((placement, _)) => {
+            let pieces = pockets(fen).ok_or(InvalidFen("pockets without a closing bracket"))?;
+            if !pieces.chars().all(|c| "pnbrqPNBRQ".contains(c)) {
+                return Err(InvalidFen("unknown piece in the pockets"));
+            }
+            placement
+        }
+        None => placement,
+    };
+
+    let mut position = [['.'; 8]; 8];
+    let ranks: Vec<&str> = placement.split('/').collect();
+    if ranks.len() != 8 {
+        return Err(InvalidFen("expected 8 ranks"));
+    }
+
+    for (row, rank) in ranks.iter().enumerate() {
+        let mut file = 0;
+        for c in rank.chars() {
+            if let Some(empty) = c.to_digit(10).filter(|d| (1..=8).contains(d)) {
+                file += empty as usize;
+            } else if "pnbrqkPNBRQK".contains(c) {
+                if file < 8 {
+                    position[row][file] = c;
+                }
+                file += 1;
+            } else {
+                return Err(InvalidFen("unknown piece"));
+            }
+
+            if file > 8 {
+                return Err(InvalidFen("rank with more than 8 squares"));
+            }
+        }
+
+        if file != 8 {
+            return Err(InvalidFen("rank with less than 8 squares"));
+        }
+    }
+
+    for king in ['K', 'k'] {
+        if position.iter().flatten().filter(|&&c| c == king).count() != 1 {
+            return Err(InvalidFen("each side needs exactly one king"));
+        }
+    }
+
+    let white_move = match fields.next() {
+        Some("w") | None => true,
+        Some("b") => false,
+        Some(_) => return Err(InvalidFen("side to move must be w or b")),
+    };
+
+    if let Some(castling) = fields.next() {
+        if castling != "-" && !castling.chars().all(|c| "KQkq".contains(c)) {
+            return Err(InvalidFen("bad castling rights"));
+        }
+    }
+    if let Some(en_passant) = fields.next() {
+        let bytes = en_passant.as_bytes();
+        let is_square = bytes.len() == 2
+            && (b'a'..=b'h').contains(&bytes[0])
+            && matches!(bytes[1], b'3' | b'6');
+        if en_passant != "-" && !is_square {
+            return Err(InvalidFen("bad en passant square"));
+        }
+    }
+    for _ in 0..2 {
+        if let Some(counter) = fields.next() {
+            counter
+                .parse::<u32>()
+                .map_err(|_| InvalidFen("bad move counter"))?;
+        }
+    }
+
+    Ok((position, white_move))
+}
+
+/// The pieces in hand of a Crazyhouse FEN, which come in brackets right after the piece
+/// placement as in `RNBQKBNR[Qp] w`. `None` without the brackets, as in every other FEN.
+pub fn pockets(fen: &str) -> Option<&str> {
+    let placement = fen.split_whitespace().next()?;
+    placement.split_once('[')?.1.strip_suffix(']')
+}
+
+/// The current position of `board` as FEN. Castling rights, en passant and the move counters
+/// are worked out from the position history, which only goes back to where the game started.
+/// In crazyhouse `pockets` are the ones the game started with, and what's in them now goes after
+/// the placement.
+pub fn to_fen(board: &ChessBoard, pockets: Option<&Pockets>) -> String {
+    let history = &board.board;
+    let mut fen = placement(&history[history.len() - 1]);
+    if let Some(pockets) = pockets {
+        fen.push_str(&format!("[{}]", pockets.after(history).fen()));
+    }
+
+    fen.push_str(if board.white_move { " w " } else { " b " });
+
+    // A side can still castle as long as its king and that rook never left their squares, which
+    // are the ones the game started with in Chess960.
+    let never_moved = |row: usize, file: usize, piece: char| {
+        history.iter().all(|position| position[row][file] == piece)
+    };
+    let (king_file, [queenside, kingside]) =
+        chess960::home_files(&history[0]).unwrap_or((4, [0, 7]));
+    let mut castling = String::new();
+    for (flag, row, king, rook, rook_file) in [
+        ('K', 7, 'K', 'R', kingside),
+        ('Q', 7, 'K', 'R', queenside),
+        ('k', 0, 'k', 'r', kingside),
+        ('q', 0, 'k', 'r', queenside),
+    ] {
+        if never_moved(row, king_file, king) && never_moved(row, rook_file, rook) {
+            castling.push(flag);
+        }
+    }
+    fen.push_str(if castling.is_empty() { "-" } else { &castling });
+
+    fen.push(' ');
+    fen.push_str(&en_passant_target(history).unwrap_or_else(|| "-".to_string()));
+
+    let halfmoves = crate::rules::halfmove_clock(history);
+    let fullmoves = (history.len() - 1) / 2 + 1;
+    fen.push_str(&format!(" {} {}", halfmoves, fullmoves));
+
+    fen
+}
+
+/// The piece placement field of FEN for `position`, rank 8 first.
+pub fn placement(position: &Position) -> String {
+    let mut placement = String::new();
+    for (row, rank) in position.iter().enumerate() {
+        let mut empty = 0;
+        for &c in rank {
+            if c == '.' {
+                empty += 1;
+                continue;
+            }
+            if empty > 0 {
+                placement.push_str(&empty.to_string());
+                empty = 0;
+            }
+            placement.push(c);
+        }
+        if empty > 0 {
+            placement.push_str(&empty.to_string());
+        }
+        if row < 7 {
+            placement.push('/');
+        }
+    }
+    placement
+}
+
+/// Chess960 starting position `number` out of 0 to 959 in Scharnagl's numbering, where 518 is
+/// the standard one. The bishops end up on opposite colors and the king between the rooks, and
+/// both sides can castle either way as the rooks are the outermost ones, like `KQkq` says.
+pub fn chess960(number: u64) -> String {
+    // Pairs of the five squares left after the bishops and the queen, for the knights.
+    const KNIGHTS: [(usize, usize); 10] = [
+        (0, 1),
+        (0, 2),
+        (0, 3),
+        (0, 4),
+        (1, 2),
+        (1, 3),
+        (1, 4),
+        (2, 3),
+        (2, 4),
+        (3, 4),
+    ];
+
+    let mut n = number as usize % 960;
+    let mut rank = ['.'; 8];
+    rank[2 * (n % 4) + 1] = 'B';
+    n /= 4;
+    rank[2 * (n % 4)] = 'B';
+    n /= 4;
+
+    let empty = |rank: &[char; 8]| -> Vec<usize> { (0..8).filter(|&f| rank[f] == '.').collect() };
+    rank[empty(&rank)[n % 6]] = 'Q';
+    n /= 6;
+    let (first, second) = KNIGHTS[n];
+    let squares = empty(&rank);
+    rank[squares[first]] = 'N';
+    rank[squares[second]] = 'N';
+    for (file, piece) in empty(&rank).into_iter().zip(['R', 'K', 'R']) {
+        rank[file] = piece;
+    }
+
+    let white: String = rank.iter().collect();
+    let black = white.to_lowercase();
+    format!("{}/pppppppp/8/8/8/8/PPPPPPPP/{} w KQkq - 0 1", black, white)
+}
+
+/// Position `fen` for playing Crazyhouse, with empty pockets unless it has some already.
+pub fn crazyhouse(fen: &str) -> String {
+    if pockets(fen).is_some() {
+        return fen.to_string();
+    }
+    match fen.trim().split_once(' ') {
+        Some((placement, rest)) => format!("{}[] {}", placement, rest),
+        None => format!("{}[]", fen.trim()),
+    }
+}
+
+/// The square a pawn skipped over with a double step on the last move, if it just made one.
+fn en_passant_target(history: &[Position]) -> Option<String> {
+    let [.., before, after] = history else {
+        return None;
+    };
+
+    for file in 0..8 {
+        // White pawns start on row 6 and land on row 4, black ones go from 1 to 3.
+        for (pawn, from, to, skipped) in [('P', 6, 4, '3'), ('p', 1, 3, '6')] {
+            let left = before[from][file] == pawn && after[from][file] == '.';
+            let arrived = before[to][file] == '.' && after[to][file] == pawn;
+            if left && arrived {
+                return Some(format!("{}{}", (b'a' + file as u8) as char, skipped));
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::variant::Variant;
+
+    #[test]
+    fn starting_position_round_trips() {
+        let board = crate::new_board(Some(STARTING_POSITION));
+        assert_eq!(to_fen(&board, None), STARTING_POSITION);
+    }
+
+    #[test]
+    fn rejects_each_kind_of_bad_fen() {
+        let cases = [
+            ("", "empty"),
+            ("8/8/8/8/8/8/8/K6k[Q w", "pockets without a closing bracket"),
+            ("8/8/8/8/8/8/8/K6k[K] w", "unknown piece in the pockets"),
+            ("8/8/8/8/8/8/K6k w", "expected 8 ranks"),
+            ("8/8/8/8/8/8/8/K5xk w", "unknown piece"),
+            ("8/8/8/8/8/8/8/K7k w", "rank with more than 8 squares"),
+            ("8/8/8/8/8/8/8/K5k w", "rank with less than 8 squares"),
+            ("8/8/8/8/8/8/8/K7 w", "each side needs exactly one king"),
+            ("8/8/8/8/8/8/8/K6k x", "side to move must be w or b"),
+            ("8/8/8/8/8/8/8/K6k w KQx", "bad castling rights"),
+            ("8/8/8/8/8/8/8/K6k w - e4", "bad en passant square"),
+            ("8/8/8/8/8/8/8/K6k w - - x 1", "bad move counter"),
+        ];
+        for (fen, reason) in cases {
+            assert_eq!(parse(fen), Err(InvalidFen(reason)), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn reads_pockets() {
+        let fen = "4k3/8/8/8/8/8/8/4K3[Qpp] b - - 0 1";
+        let (position, white_move) = parse(fen).unwrap();
+        assert_eq!(position[7][4], 'K');
+        assert!(!white_move);
+        assert_eq!(pockets(fen), Some("Qpp"));
+        assert_eq!(pockets(STARTING_POSITION), None);
+        assert_eq!(crazyhouse(fen), fen);
+        assert_eq!(
+            crazyhouse(STARTING_POSITION),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1"
+        );
+    }
+
+    #[test]
+    fn writes_what_was_taken_into_the_pockets() {
+        let start = "4k3/8/8/3p4/4P3/8/8/4K3[Qn] w - - 0 1";
+        let pockets = Pockets::from_fen(start);
+        let mut board = crate::new_board(Some(start));
+        assert_eq!(to_fen(&board, pockets.as_ref()), start);
+
+        let (mut position, _) = parse(start).unwrap();
+        position[4][4] = '.';
+        position[3][3] = 'P';
+        board.board.push(position);
+        board.white_move = false;
+        assert_eq!(
+            to_fen(&board, pockets.as_ref()),
+            "4k3/8/8/3P4/8/8/8/4K3[PQn] b - - 0 1"
+        );
+    }
+
+    #[test]
+    fn keeps_the_variant_after_the_move_counters() {
+        let fen = Variant::ThreeCheck.tag("4k3/8/8/8/8/8/8/4K3 w");
+        assert_eq!(fen, "4k3/8/8/8/8/8/8/4K3 w - - 0 1 three-check");
+        assert!(parse(&fen).is_ok());
+        assert_eq!(Variant::from_fen(Some(&fen)), Variant::ThreeCheck);
+        assert_eq!(
+            Variant::from_fen(Some(STARTING_POSITION)),
+            Variant::Standard
+        );
+    }
+}
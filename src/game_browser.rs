@@ -0,0 +1,159 @@
+use crate::coords;
+use crate::matchmaking::OpenGame;
+use raylib::prelude::*;
+
+/// What was picked in the game browser.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Choice {
+    /// Play the open game at this index of the list.
+    Join(usize),
+    Host,
+    Refresh,
+    Back,
+}
+
+/// The games open at a lobby, a row each to click on to join, with buttons below to host one
+/// instead or ask the lobby again.
+pub struct GameBrowser {
+    pub games: Vec<OpenGame>,
+    /// Why the list is empty or the last choice didn't work out, shown below the buttons.
+    pub message: Option<String>,
+}
+
+impl GameBrowser {
+    const BUTTONS: [(Choice, &'static str); 3] = [
+        (Choice::Host, "Host game"),
+        (Choice::Refresh, "Refresh"),
+        (Choice::Back, "Back"),
+    ];
+    /// Rows of open games shown, the lobby is asked for all of them but only this many fit.
+    const ROWS: usize = 8;
+    const WIDTH: f32 = 640.0;
+    const ROW_HEIGHT: f32 = 48.0;
+    const BUTTON_HEIGHT: f32 = 56.0;
+    const PAD: f32 = 12.0;
+    const TOP: f32 = 140.0;
+    const FONT_SIZE: i32 = 28;
+
+    pub fn new() -> GameBrowser {
+        GameBrowser {
+            games: Vec::new(),
+            message: None,
+        }
+    }
+
+    fn left() -> f32 {
+        let area = coords::board_area();
+        area.x + (area.width - Self::WIDTH) / 2.0
+    }
+
+    fn row(i: usize) -> Rectangle {
+        Rectangle::new(
+            Self::left(),
+            Self::TOP + i as f32 * (Self::ROW_HEIGHT + Self::PAD),
+            Self::WIDTH,
+            Self::ROW_HEIGHT,
+        )
+    }
+
+    fn button(i: usize) -> Rectangle {
+        let top = Self::TOP + Self::ROWS as f32 * (Self::ROW_HEIGHT + Self::PAD);
+        Rectangle::new(
+            Self::left(),
+            top + Self::PAD + i as f32 * (Self::BUTTON_HEIGHT + Self::PAD),
+            Self::WIDTH,
+            Self::BUTTON_HEIGHT,
+        )
+    }
+
+    /// Handles clicks, returning what was clicked if anything.
+    pub fn update(&mut self, rl: &RaylibHandle) -> Option<Choice> {
+        if rl.is_key_pressed(KeyboardKey::KEY_F5) {
+            return Some(Choice::Refresh);
+        }
+        if !rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT) {
+            return None;
+        }
+
+        let mouse = coords::mouse_position(rl);
+        let rows = self.games.len().min(Self::ROWS);
+        (0..rows)
+            .find(|&i| Self::row(i).check_collision_point_rec(mouse))
+            .map(Choice::Join)
+            .or_else(|| {
+                (0..Self::BUTTONS.len())
+                    .find(|&i| Self::button(i).check_collision_point_rec(mouse))
+                    .map(|i| Self::BUTTONS[i].0)
+            })
+    }
+
+    /// `lobby` is the address the games come from, shown in the title.
+    pub fn draw(&self, d: &mut RaylibDrawHandle, lobby: &str) {
+        let area = coords::board_area();
+        let centered = |d: &RaylibDrawHandle, text: &str, size: i32| {
+            area.x + (area.width - d.measure_text(text, size) as f32) / 2.0
+        };
+        let title = "Open games";
+        d.draw_text(
+            title,
+            centered(d, title, 72) as i32,
+            24,
+            72,
+            Color::RAYWHITE,
+        );
+        let subtitle = format!("at {}", lobby);
+        d.draw_text(
+            &subtitle,
+            centered(d, &subtitle, Self::FONT_SIZE) as i32,
+            96,
+            Self::FONT_SIZE,
+            Color::LIGHTGRAY,
+        );
+
+        let text_y =
+            |rect: Rectangle, height: f32| rect.y + (height - Self::FONT_SIZE as f32) / 2.0;
+        for (i, game) in self.games.iter().take(Self::ROWS).enumerate() {
+            let rect = Self::row(i);
+            d.draw_rectangle_rounded(rect, 0.3, 8, Color::get_color(0xc8c8c8ff));
+            d.draw_text(
+                &game.name,
+                (rect.x + Self::PAD) as i32,
+                text_y(rect, Self::ROW_HEIGHT) as i32,
+                Self::FONT_SIZE,
+                Color::BLACK,
+            );
+            let length = d.measure_text(&game.address, Self::FONT_SIZE);
+            d.draw_text(
+                &game.address,
+                (rect.x + rect.width - Self::PAD) as i32 - length,
+                text_y(rect, Self::ROW_HEIGHT) as i32,
+                Self::FONT_SIZE,
+                Color::DARKGRAY,
+            );
+        }
+
+        for (i, (_, label)) in Self::BUTTONS.iter().enumerate() {
+            let rect = Self::button(i);
+            d.draw_rectangle_rounded(rect, 0.3, 8, Color::RAYWHITE);
+            d.draw_text(
+                label,
+                (rect.x + (rect.width - d.measure_text(label, Self::FONT_SIZE) as f32) / 2.0)
+                    as i32,
+                text_y(rect, Self::BUTTON_HEIGHT) as i32,
+                Self::FONT_SIZE,
+                Color::BLACK,
+            );
+        }
+
+        if let Some(message) = &self.message {
+            let rect = Self::button(Self::BUTTONS.len());
+            d.draw_text(
+                message,
+                centered(d, message, Self::FONT_SIZE) as i32,
+                text_y(rect, Self::BUTTON_HEIGHT) as i32,
+                Self::FONT_SIZE,
+                Color::get_color(0xe02828ff),
+            );
+        }
+    }
+}
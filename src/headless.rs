@@ -0,0 +1,331 @@
+use crate::config::Config;
+use crate::crazyhouse::{self, Pockets};
+use crate::network::{format_move, ChessProtocol, Client, Packet, Server};
+use crate::rng::Rng;
+use crate::variant::Variant;
+use crate::{
+    command, current_game_state, draw_reason, end_state, exchange_checksum, fen, flag_value,
+    game_address, game_mode, log, move_string, new_board, outgoing_move, pgn, rules, send_forfeit,
+    tls, transport, variant_notice, variant_start, win_reason, BoardExtensions, GameMode,
+};
+use chess::{ChessBoard, GameState};
+use chess_networking::{Ack, Start};
+use std::io::BufRead;
+use std::time::Duration;
+
+/// How long to wait between looking for the opponent's next packet.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Plays without a window, for testing the protocol and scripting bot matches. Our moves are
+/// read from stdin one per line in SAN or coordinates, `resign` gives up, and the board is
+/// printed as text after every move. Only network and local games can be played this way.
+///
+/// Returns the exit code, 0 once the game is over.
+pub fn run(args: &[String], config: &Config) -> i32 {
+    let command = command(args);
+    let is_server = command == Some("server") || args.iter().any(|a| a == "--listen");
+    let mut network: Option<Box<dyn ChessProtocol>> = match game_mode(args) {
+        Some(GameMode::Network) if command == Some("stdio") => {
+            eprintln!("--headless takes our moves from stdin, stdio games need it for theirs");
+            return 2;
+        }
+        Some(GameMode::Network) => {
+            let address = game_address(args, config, is_server);
+            let transport = transport(args);
+            let connection = if is_server {
+                log::info!("waiting for an opponent on {}", address);
+                tls(args, is_server)
+                    .and_then(|tls| Server::new(&address, transport, tls))
+                    .map(|s| Box::new(s) as Box<dyn ChessProtocol>)
+            } else {
+                log::info!("connecting to {}", address);
+                tls(args, is_server)
+                    .and_then(|tls| Client::new(&address, transport, tls))
+                    .map(|c| Box::new(c) as Box<dyn ChessProtocol>)
+            };
+            match connection {
+                Ok(connection) => Some(connection),
+                Err(e) => {
+                    log::error!("could not connect over {}: {}", address, e);
+                    return 1;
+                }
+            }
+        }
+        Some(GameMode::Local) => None,
+        _ => {
+            eprintln!("--headless only plays network and local games");
+            return 2;
+        }
+    };
+
+    let fen = flag_value(args, "--fen").filter(|fen| fen::parse(fen).is_ok());
+    let variant_fen = flag_value(args, "--variant")
+        .and_then(|name| variant_start(name, fen, &mut Rng::from_entropy()));
+    let desired_start = Start {
+        is_white: is_server,
+        name: flag_value(args, "--name")
+            .map(|name| name.to_string())
+            .or_else(|| config.name.clone()),
+        fen: variant_fen.or(fen.map(|fen| fen.to_string())),
+        time: None,
+        inc: None,
+    };
+    let start = match network.as_mut() {
+        Some(network) => match setup(network.as_mut(), desired_start) {
+            Ok(start) => start,
+            Err(e) => {
+                log::error!("setting up the game failed: {}", e);
+                return 1;
+            }
+        },
+        None => desired_start,
+    };
+
+    let mut board = new_board(start.fen.as_deref());
+    let variant = Variant::from_fen(start.fen.as_deref());
+    // Crazyhouse drops are typed as `N@f3` or `f3f3n`.
+    let pockets = start.fen.as_deref().and_then(Pockets::from_fen);
+    let notice = network
+        .is_some()
+        .then(|| variant_notice(flag_value(args, "--variant"), start.fen.as_deref()))
+        .flatten();
+    if let Some(notice) = notice {
+        eprintln!("{}", notice);
+    }
+    let mut lines = std::io::stdin().lock().lines();
+    print_board(&board, pockets.as_ref());
+    loop {
+        let moves = crazyhouse::legal_moves(&mut board, pockets.as_ref());
+        match current_game_state(&mut board, &moves, variant) {
+            GameState::InProgress => {}
+            GameState::Checkmate => {
+                let result = if board.white_move { "0-1" } else { "1-0" };
+                let reason = win_reason(&board, variant).describe().to_lowercase();
+                return finish(network, &format!("{} {}", result, reason));
+            }
+            GameState::Draw => {
+                let reason = draw_reason(&board, &moves).describe().to_lowercase();
+                return finish(network, &format!("1/2-1/2 {}", reason));
+            }
+        }
+
+        // The client moves for white, see `our_turn` in `play`.
+        let our_turn = network.is_none() || board.white_move != start.is_white;
+        let result = if our_turn {
+            let Some(m) = read_move(&mut lines, &mut board, pockets.as_ref()) else {
+                if let Some(network) = network.as_mut() {
+                    send_forfeit(network.as_mut());
+                }
+                return finish(network, "resigned");
+            };
+            match network.as_mut() {
+                Some(network) => send_move(network.as_mut(), &mut board, m),
+                None => {
+                    crazyhouse::make_move(&mut board, m);
+                    Ok(true)
+                }
+            }
+        } else {
+            match network.as_mut() {
+                Some(network) => {
+                    receive_move(network.as_mut(), &mut board, pockets.as_ref(), variant)
+                }
+                None => unreachable!("without a network every move is ours"),
+            }
+        };
+
+        match result {
+            Ok(true) => print_board(&board, pockets.as_ref()),
+            Ok(false) => return finish(network, "the game is over"),
+            Err(e) => {
+                log::error!("connection lost: {}", e);
+                return 1;
+            }
+        }
+    }
+}
+
+/// The setup, seed, match and checksum exchange the windowed game does before the first move.
+fn setup(network: &mut dyn ChessProtocol, desired_start: Start) -> std::io::Result<Start> {
+    let start = network.handle_setup(desired_start)?;
+    // Nothing random happens without a window, but the peer still expects the seed.
+    network.share_seed(Rng::from_entropy().seed())?;
+    let games = network.share_match(1)?;
+    if games > 1 {
+        log::info!(
+            "the host wants a match of {} games, only the first is played",
+            games
+        );
+    }
+
+    if network.exchanges_checksums() {
+        let board = new_board(start.fen.as_deref());
+        if exchange_checksum(network, board.checksum())? != board.checksum() {
+            log::error!("the opponent started from another position");
+        }
+    }
+
+    network.set_blocking(false)?;
+    log::info!(
+        "game started, playing as {}",
+        if start.is_white { "black" } else { "white" }
+    );
+    Ok(start)
+}
+
+/// Prints how the game ended and closes the connection.
+fn finish(network: Option<Box<dyn ChessProtocol>>, result: &str) -> i32 {
+    println!("{}", result);
+    if let Some(mut network) = network {
+        if let Err(e) = network.close() {
+            log::error!("could not close the connection: {}", e);
+        }
+    }
+    0
+}
+
+/// The next legal move typed on stdin, `None` on `resign` or once stdin runs out.
+fn read_move(
+    lines: &mut impl Iterator<Item = std::io::Result<String>>,
+    board: &mut ChessBoard,
+    pockets: Option<&Pockets>,
+) -> Option<String> {
+    loop {
+        let line = lines.next()?.ok()?;
+        let line = line.trim();
+        match line {
+            "" => continue,
+            "resign" => return None,
+            _ => {}
+        }
+        match pgn::find_move(board, line, pockets) {
+            Some(m) => return Some(m),
+            None => println!("illegal {}", line),
+        }
+    }
+}
+
+/// Sends our move and plays it once the opponent accepts it. Returns whether the game goes on.
+fn send_move(
+    network: &mut dyn ChessProtocol,
+    board: &mut ChessBoard,
+    m: String,
+) -> std::io::Result<bool> {
+    network.send(Packet::Move(outgoing_move(&m)))?;
+    let ack = loop {
+        match next_packet(network)? {
+            Packet::Ack(ack) => break ack,
+            // Sent while we were picking the move, which turns it down.
+            Packet::DrawOffer => log::info!("the opponent's draw offer lapsed"),
+            packet => log::error!("ignoring an unexpected {} from the opponent", packet.name()),
+        }
+    };
+    if !ack.ok {
+        println!("rejected {}", m);
+        return Ok(true);
+    }
+
+    crazyhouse::make_move(board, m);
+    if network.exchanges_checksums() {
+        let checksum = loop {
+            match next_packet(network)? {
+                Packet::Checksum(checksum) => break checksum,
+                packet => log::error!("ignoring an unexpected {} from the opponent", packet.name()),
+            }
+        };
+        if checksum != board.checksum() {
+            log::error!("our board no longer matches the opponent's");
+        }
+    }
+    Ok(true)
+}
+
+/// Waits for the opponent's move and plays it if it's legal, turning down anything they ask
+/// for. Returns whether the game goes on.
+fn receive_move(
+    network: &mut dyn ChessProtocol,
+    board: &mut ChessBoard,
+    pockets: Option<&Pockets>,
+    variant: Variant,
+) -> std::io::Result<bool> {
+    let m = loop {
+        match next_packet(network)? {
+            Packet::Move(m) => break m,
+            Packet::TakebackRequest | Packet::AdjournRequest | Packet::RematchRequest => {
+                log::info!("declined the opponent's request");
+                network.send(Packet::Ack(Ack {
+                    ok: false,
+                    end_state: None,
+                }))?;
+            }
+            Packet::Resign => {
+                println!("opponent resigned");
+                return Ok(false);
+            }
+            Packet::DrawOffer => {
+                // A draw that can be claimed ends the game, an offer is turned down.
+                let claimed = rules::draw_claim(&board.board).is_some();
+                println!(
+                    "opponent {} a draw",
+                    if claimed { "claimed" } else { "offered" }
+                );
+                network.send(Packet::Ack(Ack {
+                    ok: claimed,
+                    end_state: claimed.then_some(chess_networking::GameState::Draw),
+                }))?;
+                if claimed {
+                    return Ok(false);
+                }
+            }
+            packet => log::error!("ignoring an unexpected {} from the opponent", packet.name()),
+        }
+    };
+
+    let move_str = move_string(board, &m);
+    let legal = crazyhouse::legal_moves(board, pockets).contains(&move_str);
+    if legal {
+        println!("opponent played {}", format_move(&m));
+        crazyhouse::make_move(board, move_str);
+    } else {
+        println!("opponent sent an illegal move {}", format_move(&m));
+    }
+
+    let moves = crazyhouse::legal_moves(board, pockets);
+    let state = current_game_state(board, &moves, variant);
+    network.send(Packet::Ack(Ack {
+        ok: legal,
+        end_state: end_state(&state),
+    }))?;
+    if legal && network.exchanges_checksums() {
+        network.send(Packet::Checksum(board.checksum()))?;
+    }
+    Ok(true)
+}
+
+/// Waits for the opponent's next packet, printing any chat that comes first.
+fn next_packet(network: &mut dyn ChessProtocol) -> std::io::Result<Packet> {
+    loop {
+        match network.poll()? {
+            Some(Packet::Chat(text)) => println!("opponent says {}", text),
+            Some(packet) => return Ok(packet),
+            None => std::thread::sleep(POLL_INTERVAL),
+        }
+    }
+}
+
+/// The board as text from White's side, rank 8 on top and `.` for empty squares, and what's in
+/// the crazyhouse `pockets` the game started with by now.
+fn print_board(board: &ChessBoard, pockets: Option<&Pockets>) {
+    for (row, rank) in board.position().iter().enumerate() {
+        let squares: Vec<String> = rank.iter().map(|c| c.to_string()).collect();
+        println!("{} {}", 8 - row, squares.join(" "));
+    }
+    println!("  a b c d e f g h");
+    if let Some(pockets) = pockets {
+        println!("pockets [{}]", pockets.after(&board.board).fen());
+    }
+    println!(
+        "{} to move",
+        if board.white_move { "white" } else { "black" }
+    );
+}
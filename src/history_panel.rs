@@ -0,0 +1,150 @@
+use crate::network::format_move;
+use crate::{coords, new_board, openings, pgn, WINDOW_HEIGHT};
+use chess_networking::Move;
+use raylib::prelude::*;
+
+/// The moves of the game so far in SAN, one row per move number, scrolled with the mouse wheel.
+/// The opening being played is named at the bottom.
+pub struct HistoryPanel {
+    /// First visible row.
+    scroll: usize,
+    /// Keeps the newest move in view until the user scrolls away from it.
+    follow: bool,
+    /// The moves `san` was worked out for, in coordinate form to tell when the game changed.
+    moves: Vec<String>,
+    san: Vec<String>,
+    /// Whether black made the first move, which leaves white's half of the first row empty.
+    black_first: bool,
+    /// ECO code and name of the opening, only looked up for games from the usual position.
+    opening: Option<(&'static str, &'static str)>,
+}
+
+impl HistoryPanel {
+    pub const WIDTH: i32 = 220;
+    const FONT_SIZE: i32 = 20;
+    const ROW_HEIGHT: i32 = 28;
+    const PAD: i32 = 10;
+    /// Rows kept free at the bottom for the opening, its code and up to two lines of name.
+    const OPENING_ROWS: i32 = 3;
+
+    pub fn new() -> HistoryPanel {
+        HistoryPanel {
+            scroll: 0,
+            follow: true,
+            moves: Vec::new(),
+            san: Vec::new(),
+            black_first: false,
+            opening: None,
+        }
+    }
+
+    pub fn area(x: i32) -> Rectangle {
+        Rectangle::new(x as f32, 0.0, Self::WIDTH as f32, WINDOW_HEIGHT as f32)
+    }
+
+    fn visible_rows() -> usize {
+        ((WINDOW_HEIGHT - 2 * Self::PAD) / Self::ROW_HEIGHT - Self::OPENING_ROWS) as usize
+    }
+
+    /// `fen` is where the game started, if not from the usual position.
+    pub fn update(
+        &mut self,
+        rl: &RaylibHandle,
+        area: Rectangle,
+        history: &[Move],
+        fen: Option<&str>,
+    ) {
+        // SAN needs the position each move was played in, so it's only redone when the game
+        // changed rather than every frame.
+        let moves: Vec<String> = history.iter().map(format_move).collect();
+        if moves != self.moves {
+            self.san = pgn::san_moves(history, fen);
+            self.black_first = !new_board(fen).white_move;
+            self.opening = fen.is_none().then(|| openings::lookup(&self.san)).flatten();
+            self.moves = moves;
+        }
+
+        let rows = (self.san.len() + usize::from(self.black_first)).div_ceil(2);
+        let max_scroll = rows.saturating_sub(Self::visible_rows());
+
+        let wheel = rl.get_mouse_wheel_move();
+        if wheel != 0.0 && area.check_collision_point_rec(coords::mouse_position(rl)) {
+            // Wheel up moves towards the start of the game.
+            let rows = wheel.abs().ceil() as usize;
+            self.scroll = if wheel > 0.0 {
+                self.scroll.saturating_sub(rows)
+            } else {
+                self.scroll + rows
+            };
+            self.follow = self.scroll >= max_scroll;
+        }
+
+        if self.follow {
+            self.scroll = max_scroll;
+        }
+        self.scroll = self.scroll.min(max_scroll);
+    }
+
+    /// The latest move numbered like in the list, as in `12... Nf6`, for reading it out when the
+    /// pieces aren't shown.
+    pub fn last_move(&self) -> Option<String> {
+        let san = self.san.last()?;
+        let ply = self.san.len() - 1 + usize::from(self.black_first);
+        let dots = if ply % 2 == 1 { "..." } else { "." };
+        Some(format!("{}{} {}", ply / 2 + 1, dots, san))
+    }
+
+    pub fn draw(&self, d: &mut RaylibDrawHandle, area: Rectangle) {
+        d.draw_rectangle_rec(area, Color::get_color(0x00_00_00_55));
+
+        let padding = self.black_first.then(|| "...".to_string());
+        let plies: Vec<&String> = padding.iter().chain(&self.san).collect();
+
+        let x = area.x as i32 + Self::PAD;
+        let rows = plies.chunks(2).enumerate();
+        for (row, (number, moves)) in rows
+            .skip(self.scroll)
+            .take(Self::visible_rows())
+            .enumerate()
+        {
+            let y = area.y as i32 + Self::PAD + row as i32 * Self::ROW_HEIGHT;
+            let text = match moves {
+                [white, black] => format!("{}. {} {}", number + 1, white, black),
+                [white] => format!("{}. {}", number + 1, white),
+                _ => unreachable!(),
+            };
+
+            d.draw_text(&text, x, y, Self::FONT_SIZE, Color::RAYWHITE);
+        }
+
+        let Some((eco, name)) = self.opening else {
+            return;
+        };
+        let bottom = area.y as i32 + area.height as i32 - Self::PAD;
+        let top = bottom - Self::OPENING_ROWS * Self::ROW_HEIGHT;
+        let color = Color::get_color(0xc8c8c8ff);
+        d.draw_text(eco, x, top, Self::FONT_SIZE, color);
+
+        // Words go on the first line while they fit, the rest on the second.
+        let width = Self::WIDTH - 2 * Self::PAD;
+        let mut lines = [String::new(), String::new()];
+        let mut line = 0;
+        for word in name.split_whitespace() {
+            let candidate = if lines[line].is_empty() {
+                word.to_string()
+            } else {
+                format!("{} {}", lines[line], word)
+            };
+            if line == 0 && d.measure_text(&candidate, Self::FONT_SIZE) > width {
+                line = 1;
+                lines[line] = word.to_string();
+            } else {
+                lines[line] = candidate;
+            }
+        }
+        for (i, text) in lines.iter().enumerate() {
+            let y = top + (i as i32 + 1) * Self::ROW_HEIGHT;
+            d.draw_text(text, x, y, Self::FONT_SIZE, color);
+        }
+    }
+}
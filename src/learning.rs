@@ -0,0 +1,225 @@
+use crate::eval::Position;
+use crate::move_kind::MoveKind;
+use crate::{coords, draw_announcement, move_squares, rules, ChessColor, RECT_WIDTH};
+use raylib::prelude::*;
+
+/// Squares the selected piece looks like it could go to but can't.
+const COLOR_BLOCKED: u32 = 0xe0282866;
+const COLOR_LABEL: u32 = 0x3c8ce0e6;
+const FONT_SIZE: i32 = 20;
+const PAD: i32 = 4;
+
+/// Why a square the selected piece moves like it could reach isn't one of its moves.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Blocked {
+    /// The king would be attacked on that square.
+    KingAttacked,
+    /// Moving away opens a line to the piece's own king.
+    Pinned,
+    /// The king is in check and the move doesn't do anything about it.
+    StillInCheck,
+    CastlingOutOfCheck,
+    CastlingThroughCheck,
+    /// The king or the rook has moved before.
+    CastlingRightsLost,
+}
+
+impl Blocked {
+    fn describe(self) -> &'static str {
+        match self {
+            Blocked::KingAttacked => "The king would be in check there",
+            Blocked::Pinned => "Pinned: moving it would leave the king in check",
+            Blocked::StillInCheck => "That doesn't get the king out of check",
+            Blocked::CastlingOutOfCheck => "Castling isn't allowed while in check",
+            Blocked::CastlingThroughCheck => "The king can't castle through an attacked square",
+            Blocked::CastlingRightsLost => "The king or that rook has already moved",
+        }
+    }
+}
+
+/// Every square the piece on `from` moves like it reaches, whether or not that leaves its king
+/// in check, and castling whenever the squares between the king and a rook are empty.
+fn reachable(position: &Position, from: u32) -> Vec<u32> {
+    let (y, x) = ((from / 8) as i32, (from % 8) as i32);
+    let piece = position[y as usize][x as usize];
+    let white = piece.is_ascii_uppercase();
+    let at = |y: i32, x: i32| {
+        ((0..8).contains(&y) && (0..8).contains(&x)).then(|| position[y as usize][x as usize])
+    };
+    let enemy = |c: char| c != '.' && c.is_ascii_uppercase() != white;
+    let mut squares = Vec::new();
+    let mut add = |y: i32, x: i32| squares.push((y * 8 + x) as u32);
+
+    let steps = |directions: &[(i32, i32)], slides: bool, add: &mut dyn FnMut(i32, i32)| {
+        for &(dy, dx) in directions {
+            let (mut ty, mut tx) = (y + dy, x + dx);
+            while let Some(c) = at(ty, tx) {
+                if c == '.' || enemy(c) {
+                    add(ty, tx);
+                }
+                if c != '.' || !slides {
+                    break;
+                }
+                ty += dy;
+                tx += dx;
+            }
+        }
+    };
+    let straight = [(0, 1), (0, -1), (1, 0), (-1, 0)];
+    let diagonal = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+    let all = [straight, diagonal].concat();
+
+    match piece.to_ascii_lowercase() {
+        'p' => {
+            let (forward, start) = if white { (-1, 6) } else { (1, 1) };
+            if at(y + forward, x) == Some('.') {
+                add(y + forward, x);
+                if y == start && at(y + 2 * forward, x) == Some('.') {
+                    add(y + 2 * forward, x);
+                }
+            }
+            for dx in [-1, 1] {
+                if at(y + forward, x + dx).is_some_and(enemy) {
+                    add(y + forward, x + dx);
+                }
+            }
+        }
+        'n' => {
+            let jumps = [
+                (1, 2),
+                (2, 1),
+                (-1, 2),
+                (-2, 1),
+                (1, -2),
+                (2, -1),
+                (-1, -2),
+                (-2, -1),
+            ];
+            steps(&jumps, false, &mut add);
+        }
+        'b' => steps(&diagonal, true, &mut add),
+        'r' => steps(&straight, true, &mut add),
+        'q' => steps(&all, true, &mut add),
+        'k' => {
+            steps(&all, false, &mut add);
+            let (home, rook) = if white { (7, 'R') } else { (0, 'r') };
+            if (y, x) == (home, 4) {
+                let empty = |xs: &[i32]| xs.iter().all(|&x| at(home, x) == Some('.'));
+                if at(home, 7) == Some(rook) && empty(&[5, 6]) {
+                    add(home, 6);
+                }
+                if at(home, 0) == Some(rook) && empty(&[1, 2, 3]) {
+                    add(home, 2);
+                }
+            }
+        }
+        _ => {}
+    }
+    squares
+}
+
+/// Why the piece on `from` can't go to `to`, which it moves like it reaches, if the rules give a
+/// reason a beginner would miss.
+fn blocked(position: &Position, from: u32, to: u32) -> Option<Blocked> {
+    let (y, x) = (from as usize / 8, from as usize % 8);
+    let piece = position[y][x];
+    let side = if piece.is_ascii_uppercase() {
+        ChessColor::White
+    } else {
+        ChessColor::Black
+    };
+    let in_check = rules::in_check(position, side);
+    let is_king = piece.eq_ignore_ascii_case(&'k');
+
+    if MoveKind::special(position, from, to) == Some(MoveKind::Castle) {
+        let passed = (x as i32 + (to as i32 % 8 - x as i32).signum()) as u32 + y as u32 * 8;
+        let attacked = [passed, to].iter().any(|&s| {
+            rules::is_attacked(position, (s % 8) as i32, (s / 8) as i32, side.opposite())
+        });
+        return Some(if in_check {
+            Blocked::CastlingOutOfCheck
+        } else if attacked {
+            Blocked::CastlingThroughCheck
+        } else {
+            Blocked::CastlingRightsLost
+        });
+    }
+
+    let mut after = *position;
+    after[y][x] = '.';
+    after[to as usize / 8][to as usize % 8] = piece;
+    if !rules::in_check(&after, side) {
+        return None;
+    }
+    Some(if is_king {
+        Blocked::KingAttacked
+    } else if in_check {
+        Blocked::StillInCheck
+    } else {
+        Blocked::Pinned
+    })
+}
+
+/// Learning mode's extra marks for the piece on `selected`: the squares it seems to reach but
+/// can't, with the reason shown while the mouse is over one, and labels on castling and en
+/// passant among its `moves`.
+pub fn draw(
+    d: &mut RaylibDrawHandle,
+    position: &Position,
+    moves: &[String],
+    selected: u32,
+    mouse: Vector2,
+    flipped: bool,
+) {
+    let targets: Vec<u32> = moves
+        .iter()
+        .map(|m| move_squares(m))
+        .filter(|&(from, to)| from == selected && from != to)
+        .map(|(_, to)| to)
+        .collect();
+    let hovered = coords::square_at(mouse, flipped);
+    let mut reason = None;
+
+    for to in reachable(position, selected) {
+        if targets.contains(&to) {
+            continue;
+        }
+        let Some(why) = blocked(position, selected, to) else {
+            continue;
+        };
+        let (x, y) = coords::square_position(to, flipped);
+        d.draw_rectangle(
+            x,
+            y,
+            RECT_WIDTH,
+            RECT_WIDTH,
+            Color::get_color(COLOR_BLOCKED),
+        );
+        if hovered == Some(to) {
+            reason = Some(why);
+        }
+    }
+
+    for to in targets {
+        let label = match MoveKind::special(position, selected, to) {
+            Some(MoveKind::Castle) if to % 8 > selected % 8 => "O-O",
+            Some(MoveKind::Castle) => "O-O-O",
+            Some(MoveKind::EnPassant) => "e.p.",
+            _ => continue,
+        };
+        let (x, y) = coords::square_position(to, flipped);
+        let length = d.measure_text(label, FONT_SIZE);
+        d.draw_rectangle(
+            x,
+            y,
+            length + 2 * PAD,
+            FONT_SIZE + 2 * PAD,
+            Color::get_color(COLOR_LABEL),
+        );
+        d.draw_text(label, x + PAD, y + PAD, FONT_SIZE, Color::RAYWHITE);
+    }
+
+    if let Some(reason) = reason {
+        draw_announcement(d, reason.describe());
+    }
+}
@@ -0,0 +1,76 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
+pub enum Level {
+    Error = 0,
+    Info = 1,
+    Debug = 2,
+}
+
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(Level::Error as u8);
+
+impl Level {
+    fn parse(s: &str) -> Option<Level> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "error" => Some(Level::Error),
+            "info" => Some(Level::Info),
+            "debug" | "trace" => Some(Level::Debug),
+            _ => None,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+        }
+    }
+}
+
+/// Picks the log level from `CHESS_LOG` (or `RUST_LOG`), with `--verbose` forcing debug output.
+/// Only errors are printed by default.
+pub fn init(verbose: bool) {
+    let from_env = std::env::var("CHESS_LOG")
+        .or_else(|_| std::env::var("RUST_LOG"))
+        .ok()
+        .and_then(|s| Level::parse(&s));
+
+    let level = if verbose {
+        Level::Debug
+    } else {
+        from_env.unwrap_or(Level::Error)
+    };
+
+    MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn enabled(level: Level) -> bool {
+    level as u8 <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+pub fn write(level: Level, args: std::fmt::Arguments) {
+    if enabled(level) {
+        eprintln!("[{}] {}", level.label(), args);
+    }
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::log::write($crate::log::Level::Error, format_args!($($arg)*))
+    };
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::log::write($crate::log::Level::Info, format_args!($($arg)*))
+    };
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        $crate::log::write($crate::log::Level::Debug, format_args!($($arg)*))
+    };
+}
+
+pub(crate) use {debug, error, info};
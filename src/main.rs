@@ -1,324 +1,3663 @@
+use ai::{Difficulty, Engine, Player};
+use animation::Animation;
+use annotations::Annotations;
+use board_editor::{BoardEditor, EditorChoice};
+use candidates::Candidates;
+use captures::CapturesPanel;
+use chat::ChatBox;
 use chess::*;
 use chess_networking::{Ack, Move, PromotionPiece, Start};
+use clock::Clock;
+use config::Config;
+use crazyhouse::{Pockets, PocketsPanel};
+use game_browser::{Choice as BrowserChoice, GameBrowser};
+use history_panel::HistoryPanel;
+use main_menu::{Choice, MainMenu};
+use match_score::MatchScore;
+use move_entry::MoveEntry;
+use move_kind::MoveKind;
+use move_log::MoveLog;
+use name_plates::NamePlates;
 use network::*;
+use pause_menu::{PauseChoice, PauseMenu};
 use raylib::prelude::*;
+use requests::{Request, Requests};
+use rng::Rng;
+use settings_menu::SettingsMenu;
+use sound::{Effect, Sounds};
+use std::io::Write;
+use stdio::Stdio;
+use theme::Theme;
+use tls::Tls;
+use variant::Variant;
 
+mod adjourn;
+mod ai;
+mod animation;
+mod annotations;
+mod board_editor;
+mod candidates;
+mod captures;
+mod chat;
+mod chess960;
+mod clock;
+mod config;
+mod coords;
+mod crazyhouse;
+mod eval;
+mod fen;
+mod game_browser;
+mod headless;
+mod history_panel;
+mod learning;
+mod log;
+mod main_menu;
+mod match_score;
+mod matchmaking;
+mod move_entry;
+mod move_kind;
+mod move_log;
+mod name_plates;
 mod network;
+mod openings;
+mod pause_menu;
+mod pgn;
+mod puzzles;
+mod requests;
+mod rng;
+mod rules;
+mod savegame;
+mod settings_menu;
+mod sound;
+mod stdio;
+mod theme;
+mod tls;
+mod uci;
+mod variant;
+mod websocket;
 
 const WINDOW_WIDTH: i32 = 1024;
 const WINDOW_HEIGHT: i32 = 1024;
 const RECT_WIDTH: i32 = WINDOW_WIDTH / 8;
 
-const COLOR_EVEN: u32 = 0xebecd0ff;
-const COLOR_ODD: u32 = 0x779556ff;
 const COLOR_MOVABLE: u32 = 0xcdcdb4ff;
 const COLOR_WHITE_SELECTED: u32 = 0xf5f580ff;
 const COLOR_BLACK_SELECTED: u32 = 0xb9ca42ff;
+const COLOR_EVAL_WHITE: u32 = 0xf0f0f0ff;
+const COLOR_EVAL_BLACK: u32 = 0x403d39ff;
+const COLOR_BACKGROUND: u32 = 0x302e2bff;
+const COLOR_REJECTED: u32 = 0xe0282899;
+const COLOR_THREAT: u32 = 0xe0282840;
+const COLOR_IN_FLIGHT: u32 = 0x3c8ce080;
+const COLOR_ENGINE_HINT: u32 = 0x3cc85a99;
+const COLOR_LAST_MOVE: u32 = 0xf7ec5a73;
+const COLOR_CHECK: u32 = 0xeb3c3cb3;
+const COLOR_REVIEW_FRAME: u32 = 0xf0a830ff;
+
+/// Thickness of the frame marking an earlier position being looked at rather than the game.
+const REVIEW_FRAME_WIDTH: f32 = 6.0;
+
+/// Thickness of the frame around the board unless the settings say otherwise.
+const BOARD_BORDER: i32 = 12;
+/// How far the drop shadow spreads out from the frame, and how far down and right it falls.
+const BOARD_SHADOW: i32 = 8;
+const BOARD_SHADOW_OFFSET: i32 = 4;
+
+/// Smallest the window can be resized to, in either direction.
+const MIN_WINDOW_SIZE: i32 = 320;
+
+/// Plies the built-in engine looks ahead for hints and the evaluation bar.
+const ANALYSIS_DEPTH: u32 = 3;
+
+/// Seconds between attempts to get a dropped connection back.
+const RECONNECT_INTERVAL: f32 = 2.0;
+/// Seconds to keep trying to get a dropped connection back before the game is over.
+const RECONNECT_TIMEOUT: f32 = 120.0;
+/// Seconds a move rejected by the opponent stays highlighted.
+const REJECTED_FLASH_TIME: f32 = 1.0;
+/// Seconds the board takes to fade back in after turning around.
+const FLIP_FADE_TIME: f32 = 0.3;
+
+const DEFAULT_PORT: u16 = 8384;
+const USAGE: &str = "\
+usage: chess-gui [options]                        pick what to play from a menu
+       chess-gui --listen [address] [options]    host a game
+       chess-gui --connect <address> [options]   join a hosted game
+       chess-gui server|client [address] [options]
+       chess-gui local|analysis|stdio [options]
+       chess-gui replay <game.pgn> [options]      step through a saved game
+       chess-gui spectate [address] [options]     watch a hosted game
+       chess-gui browse [lobby] [options]         join or host a game listed at a lobby
+       chess-gui lobby [address]                  run a lobby for others to list games at
+       chess-gui puzzles <puzzles.csv> [options]  solve tactics puzzles one by one
+       chess-gui vs-ai [--difficulty 1-5] [--depth n] [options]
+       chess-gui vs-ai|analysis --engine <path> [--movetime ms] [options]
+
+options: --name <name>, --seed <n>, --resume <id>, --load <file>,
+         --pieces <set> (a piece set under assets/pieces, like wood),
+         --variant chess960|crazyhouse|king-of-the-hill|three-check, --pgn-archive <path>,
+         --captures, --transport tcp|ws, --lobby <address> (list a hosted game there),
+         --move-log <dir>,
+         --tls [--tls-fingerprint <sha256>] (encrypt the connection, checking the host's key),
+         --match <games> (a best-of match with colors alternating, the host's count counts),
+         --headless (server, client and local games, moves typed on stdin), ...";
+/// Seconds a sent move is drawn as in flight before we stop expecting an ack to come soon.
+const IN_FLIGHT_TIMEOUT: f32 = 5.0;
+/// Seconds the scoreboard is shown between the games of a match.
+const MATCH_BREAK: f32 = 5.0;
+/// Seconds to wait for the opponent to acknowledge our move before giving up on the connection.
+const ACK_TIMEOUT: f32 = 15.0;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum GameMode {
+    Network,
+    /// Free play for exploring positions, any legal move for either side without a peer. It
+    /// starts from `--fen`, and a FEN pasted with Ctrl+V sets the board up again.
+    Analysis,
+    /// Two players taking turns at the same machine.
+    Local,
+    /// Stepping through a game loaded from PGN with the arrow keys, no moves can be made.
+    Replay,
+    /// Playing white against the built-in engine, or an external one given with `--engine`.
+    Computer,
+    /// Watching a game hosted by someone else, no moves can be made.
+    Spectate,
+}
+
+/// What the window is showing, each scene running until it hands over to the next one.
+enum Scene {
+    MainMenu,
+    /// Playing with the given command line, the real one or one the main menu put together.
+    Game(Vec<String>),
+    /// Picking a game from the ones open at the lobby with this address.
+    Browse(String),
+    /// Solving the puzzles in the file at this path.
+    Puzzles(String),
+    /// Setting up a position to play from, starting with this FEN if given.
+    Editor(Option<String>),
+    Quit,
+}
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    log::init(args.iter().any(|a| a == "--verbose" || a == "-v"));
+    let mut config = Config::load();
+    coords::set_board_border(config.board_border);
+
+    if args.iter().any(|a| a == "--headless") {
+        std::process::exit(headless::run(&args, &config));
+    }
+    if command(&args) == Some("lobby") {
+        std::process::exit(run_lobby(&args));
+    }
+
+    // Without a mode on the command line it's picked from the main menu instead.
+    let mut scene = match game_mode(&args) {
+        Some(_) => Scene::Game(args.clone()),
+        None if command(&args) == Some("browse") => {
+            let lobby = args.get(2).filter(|a| !a.starts_with('-'));
+            Scene::Browse(lobby.map_or_else(
+                || format!("127.0.0.1:{}", matchmaking::DEFAULT_LOBBY_PORT),
+                |lobby| with_port(lobby, matchmaking::DEFAULT_LOBBY_PORT),
+            ))
+        }
+        None if command(&args) == Some("puzzles") => match args.get(2) {
+            Some(path) if !path.starts_with('-') => Scene::Puzzles(path.clone()),
+            _ => {
+                eprintln!("{}", USAGE);
+                std::process::exit(2);
+            }
+        },
+        None if command(&args).is_none()
+            && !args.iter().any(|a| a == "--listen" || a == "--connect") =>
+        {
+            Scene::MainMenu
+        }
+        None => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    // The game resizes the window to fit whatever it shows next to the board.
+    let (width, height) = coords::window_size(config.window_scale);
     let (mut rl, thread) = raylib::init()
-        .size(WINDOW_WIDTH, WINDOW_HEIGHT)
+        .size(width, height)
         .title("Chess")
+        .resizable()
         .msaa_4x()
         .log_level(TraceLogLevel::LOG_ERROR)
         .build();
+    rl.set_window_min_size(MIN_WINDOW_SIZE, MIN_WINDOW_SIZE);
 
     let audio = match RaylibAudio::init_audio_device() {
-        Ok(audio) => audio,
-        Err(e) => panic!("{}", e),
+        Ok(audio) => Some(audio),
+        Err(e) => {
+            log::error!("no audio: {}", e);
+            None
+        }
     };
 
-    let move_sound = audio.new_sound("assets/move-self.mp3").unwrap();
-    let capture_sound = audio.new_sound("assets/capture.mp3").unwrap();
-    let promote_sound = audio.new_sound("assets/move-check.mp3").unwrap();
+    loop {
+        scene = match scene {
+            Scene::MainMenu => run_main_menu(&mut rl, &thread, &mut config, &args),
+            Scene::Browse(lobby) => run_game_browser(&mut rl, &thread, &config, &args, &lobby),
+            Scene::Editor(fen) => {
+                run_board_editor(&mut rl, &thread, &config, &args, fen.as_deref())
+            }
+            Scene::Puzzles(path) => {
+                run_puzzles(&mut rl, &thread, audio.as_ref(), &config, &args, &path)
+            }
+            Scene::Game(args) => play(&mut rl, &thread, audio.as_ref(), &mut config, &args),
+            Scene::Quit => break,
+        };
+    }
+}
 
-    let textures = load_textures(&mut rl, &thread);
-    let args: Vec<String> = std::env::args().collect();
+/// The first argument, which names the mode unless it's already a flag.
+fn command(args: &[String]) -> Option<&str> {
+    args.get(1)
+        .map(|a| a.as_str())
+        .filter(|a| !a.starts_with('-'))
+}
+
+/// The mode the command line asks for, `None` if it doesn't name one or names one that doesn't
+/// exist.
+fn game_mode(args: &[String]) -> Option<GameMode> {
+    let listening = args.iter().any(|a| a == "--listen");
+    let connecting = args.iter().any(|a| a == "--connect");
+    match command(args) {
+        Some("analysis") => Some(GameMode::Analysis),
+        Some("local") => Some(GameMode::Local),
+        Some("replay") => Some(GameMode::Replay),
+        Some("vs-ai") => Some(GameMode::Computer),
+        Some("spectate") => Some(GameMode::Spectate),
+        Some("server" | "client" | "stdio") => Some(GameMode::Network),
+        None if listening != connecting => Some(GameMode::Network),
+        _ => None,
+    }
+}
 
-    let is_server = args[1] == "server";
-    let address = &args[2];
+/// Shows the main menu until something to play is picked. Flags from the real command line
+/// carry over to the game.
+fn run_main_menu(
+    rl: &mut RaylibHandle,
+    thread: &RaylibThread,
+    config: &mut Config,
+    args: &[String],
+) -> Scene {
+    let mut menu = MainMenu::new(config);
+    let mut settings_menu = SettingsMenu::new();
+    coords::reset_layout_size();
+    coords::fit_window(rl, config.window_scale);
 
-    let mut network: Box<dyn ChessProtocol> = if is_server {
-        Box::new(Server::new(address).unwrap())
-    } else {
-        Box::new(Client::new(address).unwrap())
+    while !rl.window_should_close() {
+        let scale = config.window_scale;
+        if settings_menu.open {
+            if settings_menu.update(rl, config) {
+                if config.window_scale != scale {
+                    coords::fit_window(rl, config.window_scale);
+                }
+                // The name and address can be changed there as well.
+                menu.name = config.name.clone().unwrap_or_default();
+                menu.address = config.address.clone().unwrap_or_default();
+            }
+        } else if let Some(choice) = menu.update(rl) {
+            let mode = match choice {
+                Choice::Host => Some("server"),
+                Choice::Join => Some("client"),
+                Choice::Local => Some("local"),
+                Choice::Computer => Some("vs-ai"),
+                Choice::Replay => Some("replay"),
+                // Picked from the saved game below, it's only a placeholder until then.
+                Choice::Load => Some("local"),
+                Choice::Editor => {
+                    menu.remember(config);
+                    return Scene::Editor(menu.position.clone());
+                }
+                Choice::Settings => {
+                    // The settings show the name and address typed in so far.
+                    menu.remember(config);
+                    settings_menu.open = true;
+                    None
+                }
+                Choice::Quit => return Scene::Quit,
+            };
+
+            let mut game: Vec<String> = args.iter().take(1).cloned().collect();
+            game.extend(mode.map(|mode| mode.to_string()));
+            match choice {
+                Choice::Join if !menu.address.trim().is_empty() => {
+                    game.push(menu.address.trim().to_string())
+                }
+                Choice::Replay => {
+                    let path = menu.game_file.trim();
+                    if path.is_empty() {
+                        menu.message = Some("Type in the game file to replay".to_string());
+                        continue;
+                    }
+                    if !std::path::Path::new(path).is_file() {
+                        menu.message = Some(format!("There's no game file at {}", path));
+                        continue;
+                    }
+                    game.push(path.to_string());
+                }
+                Choice::Load => {
+                    let path = menu.game_file.trim();
+                    if path.is_empty() {
+                        menu.message = Some("Type in the saved game to load".to_string());
+                        continue;
+                    }
+                    let saved = match savegame::load(std::path::Path::new(path)) {
+                        Ok(saved) => saved,
+                        Err(e) => {
+                            menu.message = Some(format!("Could not load {}: {}", path, e));
+                            continue;
+                        }
+                    };
+                    // A network game goes on at this machine, hosting it again takes
+                    // `server --load`.
+                    if saved.mode == "vs-ai" || saved.mode == "analysis" {
+                        game.pop();
+                        game.push(saved.mode);
+                    }
+                    game.extend(["--load".to_string(), path.to_string()]);
+                }
+                _ => {}
+            }
+            if let (Choice::Host | Choice::Local | Choice::Computer, Some(fen)) =
+                (choice, &menu.position)
+            {
+                game.extend(["--fen".to_string(), fen.clone()]);
+            }
+
+            if mode.is_some() {
+                menu.remember(config);
+                if let Some(name) = &config.name {
+                    game.extend(["--name".to_string(), name.clone()]);
+                }
+                game.extend(args.iter().skip(1).cloned());
+                return Scene::Game(game);
+            }
+        }
+
+        let camera = coords::camera(rl);
+        let mut d = rl.begin_drawing(thread);
+        d.clear_background(Color::get_color(COLOR_BACKGROUND));
+        let mut d = d.begin_mode2D(camera);
+        menu.draw(&mut d);
+        settings_menu.draw(&mut d, config);
+    }
+
+    Scene::Quit
+}
+
+/// Shows the board editor until a game is started from the position set up in it, with the
+/// options on `args` carried over like from the main menu.
+fn run_board_editor(
+    rl: &mut RaylibHandle,
+    thread: &RaylibThread,
+    config: &Config,
+    args: &[String],
+    fen: Option<&str>,
+) -> Scene {
+    coords::set_layout_size(
+        WINDOW_WIDTH + 2 * coords::board_margin() + BoardEditor::WIDTH,
+        WINDOW_HEIGHT + 2 * coords::board_margin(),
+    );
+    coords::fit_window(rl, config.window_scale);
+    let board_shadow = !args.iter().any(|a| a == "--no-shadow");
+    let mut theme = Theme::load(rl, thread, config, flag_value(args, "--pieces"));
+    let mut editor = BoardEditor::new(fen);
+
+    while !rl.window_should_close() {
+        let mode = match editor.update(rl) {
+            Some(EditorChoice::Local) => "local",
+            Some(EditorChoice::Computer) => "vs-ai",
+            Some(EditorChoice::Host) => "server",
+            Some(EditorChoice::Analysis) => "analysis",
+            Some(EditorChoice::Back) => return Scene::MainMenu,
+            None => "",
+        };
+        // Only a position that checks out gets a game started from it.
+        if let (false, Ok(fen)) = (mode.is_empty(), editor.fen()) {
+            let mut game: Vec<String> = args.iter().take(1).cloned().collect();
+            game.extend([mode.to_string(), "--fen".to_string(), fen]);
+            if let Some(name) = &config.name {
+                game.extend(["--name".to_string(), name.clone()]);
+            }
+            game.extend(args.iter().skip(1).cloned());
+            return Scene::Game(game);
+        }
+
+        let square_pixels = RECT_WIDTH as f32 * coords::zoom(rl);
+        theme.update(rl, thread, square_pixels);
+        let textures = theme.pieces();
+
+        let camera = coords::camera(rl);
+        let mut d = rl.begin_drawing(thread);
+        d.clear_background(Color::get_color(COLOR_BACKGROUND));
+        let mut d = d.begin_mode2D(camera);
+        draw_board(&mut d, board_shadow, &theme, config, false);
+        draw_pieces(&mut d, editor.position(), textures, false, &[]);
+        editor.draw(&mut d, textures);
+        if let Some(message) = editor.message() {
+            draw_announcement(&mut d, message);
+        }
+    }
+
+    Scene::Quit
+}
+
+/// Shows the games open at `lobby` until one is picked to join, or hosting one instead. The
+/// options on `args` carry over to the game.
+fn run_game_browser(
+    rl: &mut RaylibHandle,
+    thread: &RaylibThread,
+    config: &Config,
+    args: &[String],
+    lobby: &str,
+) -> Scene {
+    let fetch = |lobby: String| {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let _ = sender.send(matchmaking::list(&lobby));
+        });
+        receiver
     };
 
-    let desired_start = Start {
-        is_white: is_server,
-        name: None,
-        fen: None,
-        time: None,
-        inc: None,
-    };
-
-    let start = network.handle_setup(desired_start).unwrap();
-    let mut our_turn = start.is_white == false;
-    network.set_blocking(false).unwrap();
-
-    let initial_board: [[char; 8]; 8] = [
-        ['r', 'n', 'b', 'q', 'k', 'b', 'n', 'r'],
-        ['p', 'p', 'p', 'p', 'p', 'p', 'p', 'p'],
-        ['.', '.', '.', '.', '.', '.', '.', '.'],
-        ['.', '.', '.', '.', '.', '.', '.', '.'],
-        ['.', '.', '.', '.', '.', '.', '.', '.'],
-        ['.', '.', '.', '.', '.', '.', '.', '.'],
-        ['P', 'P', 'P', 'P', 'P', 'P', 'P', 'P'],
-        ['R', 'N', 'B', 'Q', 'K', 'B', 'N', 'R'],
-    ];
+    let mut browser = GameBrowser::new();
+    browser.message = Some("Asking the lobby...".to_string());
+    let mut fetching = Some(fetch(lobby.to_string()));
+    while !rl.window_should_close() {
+        if let Some(Ok(result)) = fetching.as_ref().map(|receiver| receiver.try_recv()) {
+            fetching = None;
+            browser.message = None;
+            match result {
+                Ok(games) => {
+                    if games.is_empty() {
+                        browser.message = Some("No open games, host one".to_string());
+                    }
+                    browser.games = games;
+                }
+                Err(e) => {
+                    log::error!("could not list the games at {}: {}", lobby, e);
+                    browser.message = Some(format!("Could not reach the lobby at {}", lobby));
+                }
+            }
+        }
 
-    let mut board = ChessBoard::new();
-    board.board = vec![initial_board];
+        // The options go after the mode, the command line without its own mode and lobby.
+        let mut game: Vec<String> = args.iter().take(1).cloned().collect();
+        match browser.update(rl) {
+            Some(BrowserChoice::Join(i)) => {
+                let open = &browser.games[i];
+                log::info!("joining game {} hosted by {}", open.id, open.name);
+                game.extend(["client".to_string(), open.address.clone()]);
+            }
+            Some(BrowserChoice::Host) => {
+                game.extend([
+                    "server".to_string(),
+                    "--lobby".to_string(),
+                    lobby.to_string(),
+                ]);
+            }
+            Some(BrowserChoice::Refresh) if fetching.is_none() => {
+                browser.message = Some("Asking the lobby...".to_string());
+                fetching = Some(fetch(lobby.to_string()));
+            }
+            Some(BrowserChoice::Back) => return Scene::MainMenu,
+            Some(BrowserChoice::Refresh) | None => {}
+        }
+        if game.len() > 1 {
+            if let Some(name) = &config.name {
+                game.extend(["--name".to_string(), name.clone()]);
+            }
+            game.extend(args.iter().skip_while(|a| !a.starts_with('-')).cloned());
+            return Scene::Game(game);
+        }
+
+        let camera = coords::camera(rl);
+        let mut d = rl.begin_drawing(thread);
+        d.clear_background(Color::get_color(COLOR_BACKGROUND));
+        let mut d = d.begin_mode2D(camera);
+        browser.draw(&mut d, lobby);
+    }
+
+    Scene::Quit
+}
+
+/// Goes through the tactics puzzles in the file at `path` one by one. Each of our moves is
+/// checked against the solution, with the opponent's replies played for us, until the line runs
+/// out or a move misses it. Any move that mates solves the puzzle too.
+fn run_puzzles(
+    rl: &mut RaylibHandle,
+    thread: &RaylibThread,
+    audio: Option<&RaylibAudio>,
+    config: &Config,
+    args: &[String],
+    path: &str,
+) -> Scene {
+    /// Seconds before the opponent's reply, so it can be followed.
+    const REPLY_DELAY: f32 = 0.5;
+
+    coords::reset_layout_size();
+    coords::fit_window(rl, config.window_scale);
+    let board_shadow = !args.iter().any(|a| a == "--no-shadow");
+    let mut theme = Theme::load(rl, thread, config, flag_value(args, "--pieces"));
+    let sounds = Sounds::load(audio, config, args.iter().any(|a| a == "--no-sound"));
+
+    let puzzles = match puzzles::load(std::path::Path::new(path)) {
+        Ok(puzzles) => puzzles,
+        Err(e) => {
+            log::error!("could not load puzzles from {}: {}", path, e);
+            let text = format!("Could not load {}", path);
+            show_error(rl, thread, config, &theme, board_shadow, &text);
+            return Scene::Quit;
+        }
+    };
+    log::info!("loaded {} puzzles", puzzles.len());
+
+    // The board for a puzzle, whether it's seen from Black's side and how long until the
+    // opponent's move leading into it.
+    let begin = |puzzle: &puzzles::Puzzle| {
+        let mut board = new_board(Some(&puzzle.fen));
+        let moves = board.get_moves();
+        let flipped = puzzle.setup.is_some() == board.white_move;
+        (
+            board,
+            moves,
+            flipped,
+            puzzle.setup.as_ref().map(|_| REPLY_DELAY),
+        )
+    };
 
+    let mut score = puzzles::Score::default();
+    let mut index = 0;
+    let (mut board, moves, mut flipped, mut reply_in) = begin(&puzzles[0]);
+    let mut last_move: Option<String> = None;
+    // How many moves of the solution have been played.
+    let mut progress = 0;
+    // Whether the current puzzle was solved once it's over, and the move that was missed if it
+    // wasn't.
+    let mut outcome: Option<(bool, String)> = None;
     let mut move_selector = MoveSelector {
-        moves: board.get_moves(),
+        moves,
         selected_square: None,
         promotion_prompt: None,
         promotion_move: None,
+        auto_queen: args.iter().any(|a| a == "--auto-queen"),
+        dragging: false,
+        dropping: None,
     };
 
-    let mut awaiting_ack = false;
-
     while !rl.window_should_close() {
-        let mut game_state = board.current_gamestate();
+        let puzzle = puzzles.get(index);
+        let next_pressed = rl.is_key_pressed(KeyboardKey::KEY_ENTER)
+            || rl.is_key_pressed(KeyboardKey::KEY_SPACE)
+            || rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT);
 
-        if awaiting_ack {
-            if let Some(ack) = network.receive_ack().unwrap() {
-                println!("{ack:?}");
-                awaiting_ack = false;
+        match puzzle {
+            None => {
+                if next_pressed {
+                    return Scene::Quit;
+                }
+            }
+            Some(_) if outcome.is_some() => {
+                if next_pressed {
+                    index += 1;
+                    if let Some(puzzle) = puzzles.get(index) {
+                        (board, move_selector.moves, flipped, reply_in) = begin(puzzle);
+                        last_move = None;
+                        progress = 0;
+                        outcome = None;
+                        move_selector.selected_square = None;
+                    }
+                }
+            }
+            Some(puzzle) if reply_in.is_some() => {
+                let left = reply_in.unwrap() - rl.get_frame_time();
+                reply_in = Some(left).filter(|&left| left > 0.0);
+                if reply_in.is_none() {
+                    let reply = match &puzzle.setup {
+                        Some(setup) if last_move.is_none() => setup,
+                        _ => {
+                            progress += 1;
+                            &puzzle.solution[progress - 1]
+                        }
+                    };
+                    // Every move of the line was checked to be legal while loading.
+                    let m = move_string(&board, &parse_move(reply).unwrap());
+                    play_move_sounds(&sounds, &mut board, &m, Effect::OpponentMove);
+                    board.make_move(m.clone());
+                    last_move = Some(m);
+                    move_selector.moves = board.get_moves();
+                    if progress == puzzle.solution.len() {
+                        log::info!("solved puzzle {}", puzzle.id);
+                        score.record(true);
+                        outcome = Some((true, String::new()));
+                        move_selector.moves.clear();
+                    }
+                }
+            }
+            Some(puzzle) => {
+                if let Some(m) = move_selector.on_update(rl, flipped, None) {
+                    let expected = &puzzle.solution[progress];
+                    let played = format_move(&outgoing_move(&m));
+                    play_move_sounds(&sounds, &mut board, &m, Effect::Move);
+                    board.make_move(m.clone());
+                    let moves = board.get_moves();
+                    let mates = current_game_state(&mut board, &moves, Variant::Standard)
+                        == GameState::Checkmate;
 
-                if !ack.ok {
-                    board.undo_move();
+                    if played == *expected || mates {
+                        last_move = Some(m);
+                        progress += 1;
+                        if progress == puzzle.solution.len() || mates {
+                            log::info!("solved puzzle {}", puzzle.id);
+                            score.record(true);
+                            outcome = Some((true, String::new()));
+                        } else {
+                            reply_in = Some(REPLY_DELAY);
+                        }
+                    } else {
+                        // The board shows the move that was missed instead of ours.
+                        board.undo_move();
+                        let answer = move_string(&board, &parse_move(expected).unwrap());
+                        let san = pgn::san(&mut board, &answer, None);
+                        board.make_move(answer.clone());
+                        last_move = Some(answer);
+                        log::info!("missed puzzle {}, played {} for {}", puzzle.id, played, san);
+                        score.record(false);
+                        outcome = Some((false, san));
+                    }
                     move_selector.moves = board.get_moves();
-                    our_turn = !our_turn;
+                    if outcome.is_some() {
+                        move_selector.moves.clear();
+                    }
                 }
             }
+        }
+
+        let square_pixels = RECT_WIDTH as f32 * coords::zoom(rl);
+        theme.update(rl, thread, square_pixels);
+        let textures = theme.pieces();
+        let dragged = move_selector.dragged(rl);
+        let mouse = coords::mouse_position(rl);
+
+        let camera = coords::camera(rl);
+        let mut d = rl.begin_drawing(thread);
+        d.clear_background(Color::get_color(COLOR_BACKGROUND));
+        let mut d = d.begin_mode2D(camera);
+
+        draw_board(&mut d, board_shadow, &theme, config, flipped);
+        if let Some(m) = &last_move {
+            let (from, to) = move_squares(m);
+            for square in [from, to] {
+                let (x, y) = coords::square_position(square, flipped);
+                d.draw_rectangle(
+                    x,
+                    y,
+                    RECT_WIDTH,
+                    RECT_WIDTH,
+                    Color::get_color(COLOR_LAST_MOVE),
+                );
+            }
+        }
+        if let Some(s) = move_selector.selected_square {
+            hightlight_current_piece(&mut d, &board, s, flipped);
+        }
+        let hidden: Vec<u32> = dragged.into_iter().collect();
+        draw_pieces(&mut d, board.position(), textures, flipped, &hidden);
+        if let Some(s) = move_selector.selected_square {
+            highlight_movable_squares(&mut d, &move_selector.moves, s, flipped);
+        }
+        if let Some(piece) = dragged.and_then(|s| board.piece_on(s)) {
+            draw_piece_centered(&mut d, textures, piece, mouse, Color::WHITE);
+        }
+        if let Some(p) = &move_selector.promotion_prompt {
+            p.draw(&mut d, textures, board.current_side());
+        }
+
+        match &outcome {
+            _ if puzzle.is_none() => {
+                let text = format!(
+                    "Solved {} of {}, best streak {}",
+                    score.solved, score.attempted, score.best_streak
+                );
+                draw_overlay(&mut d, &text);
+            }
+            Some((true, _)) => draw_announcement(&mut d, "Solved, click for the next puzzle"),
+            Some((false, answer)) => {
+                let text = format!("The answer was {}, click for the next one", answer);
+                draw_announcement(&mut d, &text);
+            }
+            None => {
+                let text = format!(
+                    "Puzzle {} of {}, {} to move, streak {}",
+                    index + 1,
+                    puzzles.len(),
+                    if flipped { "black" } else { "white" },
+                    score.streak
+                );
+                draw_announcement(&mut d, &text);
+            }
+        }
+    }
+
+    Scene::Quit
+}
+
+/// Runs a game with the mode and options on `args` until the window is closed.
+fn play(
+    rl: &mut RaylibHandle,
+    thread: &RaylibThread,
+    audio: Option<&RaylibAudio>,
+    config: &mut Config,
+    args: &[String],
+) -> Scene {
+    let command = command(args);
+    let listening = args.iter().any(|a| a == "--listen");
+    let mode = game_mode(args).expect("the mode is checked before starting a game");
+    let is_server = command == Some("server") || listening;
+
+    let address = game_address(args, config, is_server);
+
+    let mut eval_bar = if args.iter().any(|a| a == "--eval-bar") {
+        Some(EvalBar::new())
+    } else {
+        None
+    };
+    // Spectators have nothing to do but follow the game, so they always get the move list, and
+    // playing blindfolded it's where the moves are read from.
+    let list_wanted = ["--move-list", "--blindfold"];
+    let mut move_list =
+        if mode == GameMode::Spectate || args.iter().any(|a| list_wanted.contains(&a.as_str())) {
+            Some(HistoryPanel::new())
         } else {
-            if let Some(m) = network.receive_move().unwrap() {
-                println!("{m:?}");
-                let mut move_str = String::new();
+            None
+        };
+    let show_captures = args.iter().any(|a| a == "--captures");
+    let eval_bar_width = if eval_bar.is_some() {
+        EvalBar::WIDTH
+    } else {
+        0
+    };
+    let captures_width = if show_captures {
+        CapturesPanel::WIDTH
+    } else {
+        0
+    };
+    let move_list_width = if move_list.is_some() {
+        HistoryPanel::WIDTH
+    } else {
+        0
+    };
+    let extra_width = eval_bar_width + captures_width + move_list_width;
+    let toolbar_height = if !Toolbar::actions(mode, false, false).is_empty() {
+        Toolbar::HEIGHT
+    } else {
+        0
+    };
 
-                move_str.push(('a' as u8 + m.from.0 as u8) as char);
-                move_str.push(('1' as u8 + m.from.1 as u8) as char);
-                move_str.push(('a' as u8 + m.to.0 as u8) as char);
-                move_str.push(('1' as u8 + m.to.1 as u8) as char);
+    // The layout is always laid out full size and zoomed to fit the window, the window size
+    // setting is only where the window starts out.
+    let layout_width = WINDOW_WIDTH + 2 * coords::board_margin() + extra_width;
+    let layout_height = WINDOW_HEIGHT + 2 * coords::board_margin() + toolbar_height;
+    coords::set_layout_size(layout_width, layout_height);
+    coords::fit_window(rl, config.window_scale);
 
-                if let Some(promotion_piece) = m.promotion {
-                    move_str.push(match promotion_piece {
-                        PromotionPiece::Queen => 'q',
-                        PromotionPiece::Rook => 'r',
-                        PromotionPiece::Bishop => 'b',
-                        PromotionPiece::Knight => 'n',
-                    });
-                }
+    // Opponent moves can sound a bit different from ours, which helps when not looking.
+    let opponent_move_effect = if args.iter().any(|a| a == "--opponent-sound") {
+        Effect::OpponentMove
+    } else {
+        Effect::Move
+    };
+    let no_sound = args.iter().any(|a| a == "--no-sound");
+    let mut sounds = Sounds::load(audio, config, no_sound);
 
-                // Our chess library needs a 'e' appended if the move is en passant.
-                let from_square = ((7 - m.from.1) * 8 + m.from.0) as u32;
-                let to_squqare = ((7 - m.to.1) * 8 + m.to.0) as u32;
-                let moving_piece = board.piece_on(from_square).unwrap(); // Why no implicit upcasting rust?
-                let target_piece = board.piece_on(to_squqare);
+    // A game saved with Ctrl+S, carried on from where it was saved.
+    let load_path = flag_value(args, "--load");
+    let loaded = match load_path {
+        Some(_) if mode == GameMode::Replay || mode == GameMode::Spectate => {
+            log::error!("--load needs a game to play, ignoring it");
+            None
+        }
+        Some(path) => match savegame::load(std::path::Path::new(path)) {
+            Ok(game) => Some(game),
+            Err(e) => {
+                log::error!("could not load {}: {}, starting a new game", path, e);
+                None
+            }
+        },
+        None => None,
+    };
+    // We're the white player unless `is_white` is set, see `our_turn`.
+    let saved_name = loaded.as_ref().map(|game| match game.is_white {
+        true => game.black.clone(),
+        false => game.white.clone(),
+    });
 
-                if moving_piece.t == PieceType::Pawn && target_piece.is_none() {
-                    let diff = (from_square as i32 - to_squqare as i32).abs();
-                    if diff != 8 && diff != 16 {
-                        move_str.push('e');
-                    }
-                }
+    let name = flag_value(args, "--name")
+        .map(|name| name.to_string())
+        .or(saved_name.filter(|name| name != "?"))
+        .or_else(|| config.name.clone());
+    let piece_set = flag_value(args, "--pieces");
+    let board_shadow = !args.iter().any(|a| a == "--no-shadow");
+    let pgn_archive = flag_value(args, "--pgn-archive");
+    let match_games = match flag_value(args, "--match").map(|games| games.parse()) {
+        Some(Ok(games)) if games > 0 => games,
+        Some(_) => {
+            log::error!("--match expects a positive number of games, playing a single game");
+            1
+        }
+        None => 1,
+    };
+    let mut theme = Theme::load(rl, thread, config, piece_set);
 
-                let is_legal_move = move_selector.moves.iter().any(|s| *s == move_str);
+    // Kept for showing the fingerprints, the connection has its own copy.
+    let mut tls_in_use: Option<Tls> = None;
+    let mut network: Option<Box<dyn ChessProtocol>> = match mode {
+        GameMode::Network if command == Some("stdio") => Some(Box::new(Stdio::new())),
+        GameMode::Network => {
+            let target = address.clone();
+            let transport = transport(args);
+            let tls = tls(args, is_server);
+            tls_in_use = tls.as_ref().ok().cloned().flatten();
+            let connection = if is_server {
+                log::info!("waiting for an opponent on {}", address);
+                let mut text = format!("Waiting for an opponent on {}...", address);
+                // Whoever joins can only check our key if they know its fingerprint.
+                if let Some(status) = tls_in_use.as_ref().and_then(Tls::status) {
+                    text = format!("{}\n{}", text, status);
+                }
+                // Listed at the lobby for anyone to join until someone does.
+                let listed = flag_value(args, "--lobby").map(|lobby| {
+                    let port = address
+                        .rsplit_once(':')
+                        .and_then(|(_, port)| port.parse().ok())
+                        .unwrap_or(DEFAULT_PORT);
+                    let name = flag_value(args, "--name")
+                        .or(config.name.as_deref())
+                        .unwrap_or("?");
+                    (
+                        with_port(lobby, matchmaking::DEFAULT_LOBBY_PORT),
+                        port,
+                        name.to_string(),
+                    )
+                });
+                lobby(rl, thread, config, &theme, board_shadow, &text, move || {
+                    let _listing = match &listed {
+                        Some((lobby, port, name)) => {
+                            let listing = matchmaking::host(lobby, *port, name)?;
+                            log::info!("listed as game {} at {}", listing.id, lobby);
+                            Some(listing)
+                        }
+                        None => None,
+                    };
+                    Server::new(&target, transport, tls?)
+                        .map(|s| Box::new(s) as Box<dyn ChessProtocol + Send>)
+                })
+            } else {
+                log::info!("connecting to {}", address);
+                let text = format!("Connecting to {}...", address);
+                lobby(rl, thread, config, &theme, board_shadow, &text, move || {
+                    Client::new(&target, transport, tls?)
+                        .map(|c| Box::new(c) as Box<dyn ChessProtocol + Send>)
+                })
+            };
 
-                if is_legal_move {
-                    board.make_move(move_str);
-                    game_state = board.current_gamestate();
-                    move_selector.moves = board.get_moves();
-                    our_turn = !our_turn;
+            match connection {
+                Some(Ok(connection)) => Some(connection as Box<dyn ChessProtocol>),
+                Some(Err(e)) => {
+                    log::error!("could not connect over {}: {}", address, e);
+                    let text = NetworkError::from(e).to_string();
+                    show_error(rl, thread, config, &theme, board_shadow, &text);
+                    return after_game(rl);
                 }
+                // The window was closed before anyone showed up.
+                None => return Scene::Quit,
+            }
+        }
+        GameMode::Analysis
+        | GameMode::Local
+        | GameMode::Replay
+        | GameMode::Computer
+        | GameMode::Spectate => None,
+    };
+    let (mut spectator, spectated) = if mode == GameMode::Spectate {
+        log::info!("connecting to {} to spectate", address);
+        let target = address.clone();
+        let transport = transport(args);
+        let tls = tls(args, false);
+        tls_in_use = tls.as_ref().ok().cloned().flatten();
+        let text = format!("Connecting to {}...", address);
+        match lobby(rl, thread, config, &theme, board_shadow, &text, move || {
+            Spectator::connect(&target, transport, tls?.as_ref())
+        }) {
+            Some(Ok((spectator, start))) => (Some(spectator), Some(start)),
+            Some(Err(e)) => {
+                log::error!("could not spectate {}: {}", address, e);
+                let text = NetworkError::from(e).to_string();
+                show_error(rl, thread, config, &theme, board_shadow, &text);
+                return after_game(rl);
+            }
+            None => return Scene::Quit,
+        }
+    } else {
+        (None, None)
+    };
+
+    // Time control as `minutes+increment`, without one there's no clock.
+    let time_control = flag_value(args, "--clock").and_then(|s| {
+        let parsed = Clock::parse(s);
+        if parsed.is_none() {
+            log::error!("--clock expects minutes+increment like 5+3, playing without a clock");
+        }
+        parsed
+    });
+
+    let start_position = flag_value(args, "--fen").filter(|fen| match fen::parse(fen) {
+        Ok(_) => true,
+        Err(e) => {
+            log::error!("--fen: {}, using the standard starting position", e);
+            false
+        }
+    });
+
+    let mut desired_start = Start {
+        is_white: is_server,
+        name: name.clone(),
+        fen: start_position.map(|fen| fen.to_string()),
+        time: time_control.map(|(time, _)| time),
+        inc: time_control.map(|(_, inc)| inc),
+    };
+
+    let mut rng = match args.iter().position(|a| a == "--seed") {
+        Some(idx) => match args.get(idx + 1).map(|s| s.parse::<u64>()) {
+            Some(Ok(seed)) => Rng::new(seed),
+            _ => {
+                log::error!("--seed expects a number, picking a random seed instead");
+                Rng::from_entropy()
+            }
+        },
+        None => Rng::from_entropy(),
+    };
 
-                network
-                    .send_ack(Ack {
-                        ok: is_legal_move,
-                        end_state: match game_state {
-                            GameState::Draw => Some(chess_networking::GameState::Draw),
-                            GameState::Checkmate => Some(chess_networking::GameState::CheckMate),
-                            GameState::InProgress => None,
-                        },
-                    })
-                    .unwrap();
+    // The server's position wins over the client's in the setup, so both end up with the same
+    // one.
+    match flag_value(args, "--variant") {
+        Some("crazyhouse") if mode == GameMode::Computer => {
+            log::error!("the computer doesn't play crazyhouse, playing standard chess")
+        }
+        Some(name) => {
+            if let Some(fen) = variant_start(name, start_position, &mut rng) {
+                desired_start.fen = Some(fen);
             }
         }
+        None => {}
+    }
 
-        if our_turn {
-            if let Some(m) = move_selector.on_update(&mut rl) {
-                let (from, to) = move_squares(&m);
-                let is_capture = board.piece_on(to).is_some();
-                let is_promotion = is_promotion(&m);
-                let is_quiet = !is_capture && !is_promotion;
-
-                if is_capture {
-                    capture_sound.play();
-                }
-                if is_promotion {
-                    promote_sound.play();
-                }
-                if is_quiet {
-                    move_sound.play();
-                }
-
-                network
-                    .send_move(Move {
-                        from: (from as u8 & 7, 7 - from as u8 / 8),
-                        to: (to as u8 & 7, 7 - to as u8 / 8),
-                        promotion: if is_promotion {
-                            Some(match m.chars().nth(4).unwrap() {
-                                'q' => PromotionPiece::Queen,
-                                'r' => PromotionPiece::Rook,
-                                'b' => PromotionPiece::Bishop,
-                                'n' => PromotionPiece::Knight,
-                                _ => unreachable!(),
-                            })
-                        } else {
-                            None
-                        },
-                        forfeit: false,
-                        offer_draw: false,
-                    })
-                    .unwrap();
-
-                board.make_move(m);
-                awaiting_ack = true;
-                move_selector.moves = board.get_moves();
-                our_turn = !our_turn;
-            }
-
-            if game_state == GameState::Checkmate || game_state == GameState::Draw {
-                if let Some(restart) = Menu::update(&mut rl) {
-                    if restart {
-                        board = ChessBoard::new();
-                        board.board = vec![initial_board];
-                        move_selector.moves = board.get_moves();
-                    } else {
-                        break;
+    // A game adjourned earlier, picked up again where it was left.
+    let resumed = flag_value(args, "--resume").and_then(|id| match adjourn::load(id) {
+        Ok(game) => Some(game),
+        Err(e) => {
+            log::error!("could not resume game {}: {}, starting a new one", id, e);
+            None
+        }
+    });
+
+    let replayed = (mode == GameMode::Replay).then(|| {
+        let Some(path) = args.get(2) else {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        };
+        let game = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|pgn| pgn::parse(&pgn).map_err(|e| e.to_string()));
+        match game {
+            Ok(game) => game,
+            Err(e) => {
+                log::error!("could not load {}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    });
+    if let Some(game) = &replayed {
+        desired_start.fen = game.fen.clone();
+    }
+
+    if let Some(game) = &loaded {
+        rng = Rng::new(game.seed);
+        if let Some((time, inc, _)) = game.clock {
+            desired_start.time = Some(time);
+            desired_start.inc = Some(inc);
+        }
+        // The peer can't know the moves that led here, so a network game starts over from the
+        // saved position and the setup tells them what it is.
+        if mode == GameMode::Network {
+            desired_start.fen = Some(game.position.clone());
+        } else {
+            desired_start.is_white = game.is_white;
+            desired_start.fen = game.fen.clone();
+        }
+    }
+
+    let mut desynced = false;
+    // The opponent sent something outside the protocol, which a reconnect wouldn't fix.
+    let mut broken: Option<NetworkError> = None;
+    if let Some(game) = &resumed {
+        // The seed is what identifies the game, so it carries over as well.
+        rng = Rng::new(game.seed);
+        desired_start.fen = game.fen.clone();
+    }
+
+    let mut match_score: Option<MatchScore> = None;
+    let mut start = match network.as_mut() {
+        Some(network) => {
+            let setup = network.handle_setup(desired_start).and_then(|start| {
+                let seed = network.share_seed(rng.seed())?;
+                Ok((start, seed, network.share_match(match_games)?))
+            });
+            match setup {
+                Ok((start, seed, games)) => {
+                    rng = Rng::new(seed);
+                    if games > 1 {
+                        log::info!("playing a match of {} games", games);
+                        match_score = Some(MatchScore::new(games));
                     }
+                    start
+                }
+                Err(e) => {
+                    log::error!("setting up the game failed: {}", e);
+                    let text = NetworkError::from(e).to_string();
+                    show_error(rl, thread, config, &theme, board_shadow, &text);
+                    return after_game(rl);
                 }
             }
         }
+        None => match spectated {
+            Some(start) => start,
+            // Playing both sides starts out the same as being the client, we make the first
+            // move.
+            None => desired_start,
+        },
+    };
 
-        let mut d = rl.begin_drawing(&thread);
+    let opponent = network
+        .as_ref()
+        .and_then(|network| network.opponent_name())
+        .map(str::to_string);
+    let mut board = new_board(start.fen.as_deref());
+    let mut history: Vec<Move> = Vec::new();
+    // What each side had in hand at the start, when playing crazyhouse. The pockets go next to
+    // everything else, the window just fits the wider layout.
+    let pockets = start.fen.as_deref().and_then(Pockets::from_fen);
+    let variant = Variant::from_fen(start.fen.as_deref());
+    // Shown until the first move, the joiner might not know the host picked something else.
+    let variant_notice = network
+        .is_some()
+        .then(|| variant_notice(flag_value(args, "--variant"), start.fen.as_deref()))
+        .flatten();
+    if let Some(notice) = &variant_notice {
+        log::info!("{}", notice);
+    }
+    let pockets_x = WINDOW_WIDTH + coords::board_margin() + extra_width;
+    if pockets.is_some() {
+        coords::set_layout_size(layout_width + PocketsPanel::WIDTH, layout_height);
+        coords::fit_window(rl, config.window_scale);
+    }
+    if let Some(game) = &resumed {
+        for m in &game.history {
+            let move_str = move_string(&board, m);
+            if !crazyhouse::legal_moves(&mut board, pockets.as_ref()).contains(&move_str) {
+                log::error!("adjourned game has an illegal move {}", move_str);
+                break;
+            }
+            crazyhouse::make_move(&mut board, move_str);
+            history.push(m.clone());
+        }
+        log::info!(
+            "resumed game {} after {} moves",
+            adjourn::game_id(game.seed),
+            history.len()
+        );
+    }
 
-        draw_board(&mut d);
-        match game_state {
-            GameState::InProgress => {
-                if let Some(s) = move_selector.selected_square {
-                    hightlight_current_piece(&mut d, &board, s);
+    if let Some(game) = &loaded {
+        if mode != GameMode::Network {
+            for m in &game.history {
+                let move_str = move_string(&board, m);
+                if !crazyhouse::legal_moves(&mut board, pockets.as_ref()).contains(&move_str) {
+                    log::error!("saved game has an illegal move {}", move_str);
+                    break;
                 }
-                draw_pieces(&mut d, &board, &textures);
+                crazyhouse::make_move(&mut board, move_str);
+                history.push(m.clone());
+            }
+        } else if start.fen.as_ref() != Some(&game.position) {
+            // The server's starting position wins when both sides asked for one.
+            log::error!("the opponent picked another starting position, the saved game is left");
+        }
+        log::info!("loaded game after {} moves", game.history.len());
+    }
 
-                if let Some(s) = move_selector.selected_square {
-                    highlight_movable_squares(&mut d, &move_selector.moves, s);
-                }
+    if let Some(game) = &replayed {
+        // Every move was checked to be legal while parsing.
+        for m in &game.history {
+            let m = move_string(&board, m);
+            crazyhouse::make_move(&mut board, m);
+        }
+        history = game.history.clone();
+    }
 
-                if let Some(p) = &move_selector.promotion_prompt {
-                    p.draw(&mut d, &textures, board.current_side());
+    if let Some(network) = network.as_mut() {
+        // Only differs when just one side resumed an adjourned game, or they resumed different
+        // ones.
+        let theirs = if network.exchanges_checksums() {
+            exchange_checksum(network.as_mut(), board.checksum()).map(Some)
+        } else {
+            Ok(None)
+        };
+        match theirs.and_then(|theirs| network.set_blocking(false).map(|_| theirs)) {
+            Ok(Some(theirs)) => {
+                let same_seat = resumed
+                    .as_ref()
+                    .is_none_or(|g| g.is_white == start.is_white);
+                if theirs != board.checksum() || !same_seat {
+                    log::error!("the opponent isn't resuming the same game as us");
+                    desynced = true;
                 }
             }
-            GameState::Checkmate => Menu::draw(&mut d, &board, &textures, "Checkmate"),
-            GameState::Draw => Menu::draw(&mut d, &board, &textures, "Draw"),
+            Ok(None) => {}
+            Err(e) => {
+                log::error!("setting up the game failed: {}", e);
+                let text = NetworkError::from(e).to_string();
+                show_error(rl, thread, config, &theme, board_shadow, &text);
+                return after_game(rl);
+            }
+        }
+    }
+    log::info!(
+        "game started, playing as {}",
+        if start.is_white { "white" } else { "black" }
+    );
+    // Passing this to `--seed` reproduces the game's random choices.
+    log::info!("seed {}", rng.seed());
+    // A replayed game was played long ago, there's nothing to log.
+    let move_log_directory = flag_value(args, "--move-log").filter(|_| mode != GameMode::Replay);
+    let new_move_log = |start: &Start, history: &[Move]| {
+        let directory = move_log_directory?;
+        let (white, black) = player_names(mode, name.as_deref(), opponent.as_deref(), start);
+        MoveLog::create(directory, start.fen.as_deref(), history, white, black)
+            .map_err(|e| log::error!("could not start a move log in {}: {}", directory, e))
+            .ok()
+    };
+    let mut move_log = new_move_log(&start, &history);
+    // The client moves for white, and without a network both sides are ours.
+    let mut our_turn = mode != GameMode::Replay
+        && mode != GameMode::Spectate
+        && (network.is_none() || board.white_move != start.is_white);
+    sounds.play(Effect::GameStart);
+
+    let mut move_selector = MoveSelector {
+        moves: crazyhouse::legal_moves(&mut board, pockets.as_ref()),
+        selected_square: None,
+        promotion_prompt: None,
+        promotion_move: None,
+        auto_queen: args.iter().any(|a| a == "--auto-queen"),
+        dragging: false,
+        dropping: None,
+    };
+
+    // Our move stays on the board while the opponent checks it, and is taken back if they
+    // reject it. Not hearing back at all means starting over through a reconnect, after which
+    // the server's move history is what counts.
+    let mut awaiting_ack = false;
+    let mut ack_wait = 0.0;
+    // An accepted move is followed by the opponent's checksum of the resulting position.
+    let mut awaiting_checksum = false;
+    // Squares of a move the opponent rejected and how much longer to flash them.
+    let mut rejected_flash: Option<((u32, u32), f32)> = None;
+    // Squares of our move that hasn't been acked yet and how much longer to draw it.
+    let mut in_flight: Option<((u32, u32), f32)> = None;
+    let mut move_timer = MoveTimer::new();
+    let low_time = config.low_time;
+    let new_clock = |start: &Start| {
+        let clock = Clock::new(start.time?, start.inc.unwrap_or(0)).with_low_time(low_time);
+        // An engine over stdio keeps no time, so our clock counts for both sides.
+        Some(match mode {
+            GameMode::Network if command != Some("stdio") => clock.judging(our_color(start)),
+            _ => clock,
+        })
+    };
+    let mut clock = new_clock(&start);
+    // Only an offline game has the same moves to count the saved time against.
+    if let Some((_, inc, remaining)) = loaded
+        .as_ref()
+        .filter(|_| mode != GameMode::Network)
+        .and_then(|game| game.clock)
+    {
+        clock = Some(Clock::resume(remaining, inc).with_low_time(low_time));
+    }
+    let mut opponent_left = false;
+    // A packet from the opponent that the game isn't ready for yet, say an answer to a request
+    // still coming in after our own move. It waits here until the part expecting it takes it.
+    let mut incoming: Option<Packet> = None;
+    let mut blindfold = args.iter().any(|a| a == "--blindfold");
+    let mut last_opponent_move: Option<String> = None;
+    // The last move the opponent tried that we rejected, shown until they send a legal one.
+    let mut illegal_move_received: Option<String> = None;
+    // The host closed the game we were watching.
+    let mut spectating_ended = false;
+    let mut show_debug = false;
+    let mut disconnected = false;
+    let mut reconnect_cooldown = 0.0;
+    // How long the connection has been gone, and whether we gave up on getting it back.
+    let mut disconnected_time = 0.0;
+    let mut abandoned = false;
+    // A replayed game ended long ago, there's nothing to announce or archive.
+    let mut game_over_handled = mode == GameMode::Replay;
+    // How the game ended, as long as `game_over_handled` says it's still over.
+    let mut game_result = None;
+    let mut requests = Requests::default();
+    let mut adjourned = false;
+    // Seconds until the next game of a match is asked for, counting down once a game is over.
+    let mut match_break: Option<f32> = None;
+    let mut draw_agreed = false;
+    // A repetition or fifty-move draw one of the players claimed.
+    let mut draw_claimed: Option<rules::DrawClaim> = None;
+    let mut resigned = false;
+    // Index into `board.board` of the position being looked at, `None` follows the live game.
+    let mut review: Option<usize> = replayed.as_ref().map(|_| 0);
+    // Whether clicking the board while reviewing jumps back to the game or does nothing.
+    let review_snaps_back = !args.iter().any(|a| a == "--review-ignore-clicks");
+    // Lets us look at the opponent's legal moves while they think, off by default since that's
+    // help you wouldn't get over a real board.
+    let preview_opponent = args.iter().any(|a| a == "--preview-opponent");
+    // Draws the board from Black's side, with the 8th rank at the bottom. Over the network our
+    // own pieces start out at the bottom, and we play black when `start.is_white` is set.
+    let mut flipped = network.is_some() && start.is_white;
+    let mut flip_fade = 0.0;
+    let mut animation = Animation::new();
+    let mut move_entry = MoveEntry::new();
+    // Only a connection to another copy of this game can carry chat.
+    let mut chat = (network.is_some() && command != Some("stdio")).then(ChatBox::new);
+    // With both players at one screen, turn the board towards whoever is to move. Turning it by
+    // hand stops that.
+    let mut auto_flip = mode != GameMode::Network
+        && mode != GameMode::Computer
+        && args.iter().any(|a| a == "--auto-flip");
+    let mut show_threats = args.iter().any(|a| a == "--threats");
+    // Explains why the selected piece can't go where it looks like it could, and labels castling
+    // and en passant, toggled with L.
+    let mut learning = args.iter().any(|a| a == "--learning");
+    // The engine's top moves for whoever isn't playing the game, toggled with Tab.
+    let mut show_candidates = false;
+    let mut candidates = Candidates::new();
+    let mut annotations = Annotations::new();
+    let mut settings_menu = SettingsMenu::new();
+    let mut pause_menu = PauseMenu::new();
+
+    // An external UCI engine plays the computer's side, or suggests moves while analysing.
+    let uci_engine = uci_engine(args, mode);
+    let built_in =
+        (mode == GameMode::Computer && uci_engine.is_none()).then(|| built_in_engine(args));
+    let (uci_engine, uci_analyser) = if mode == GameMode::Analysis {
+        (None, uci_engine)
+    } else {
+        (uci_engine, None)
+    };
+    let mut engine: Option<Box<dyn Player>> = match (uci_engine, built_in) {
+        (Some(engine), _) => Some(Box::new(engine)),
+        (None, Some(engine)) => Some(Box::new(engine)),
+        (None, None) => None,
+    };
+    // Suggests moves and feeds the evaluation bar, an external engine while analysing with one
+    // and the built-in one at its strongest otherwise. Hints would be cheating against a person.
+    let hints_allowed = mode != GameMode::Network && mode != GameMode::Spectate;
+    let mut show_hint = uci_analyser.is_some();
+    let mut analyser: Option<Box<dyn Player>> = match uci_analyser {
+        Some(engine) => Some(Box::new(engine)),
+        None if hints_allowed || eval_bar.is_some() => Some(Box::new(Engine::new(
+            Difficulty::HARDEST,
+            Some(ANALYSIS_DEPTH),
+        ))),
+        None => None,
+    };
+    // The analyser's best move, and the position it was asked about.
+    let mut engine_hint: Option<(u32, u32)> = None;
+    let mut analysed: Option<u64> = None;
+
+    // At a shared board Esc pauses the game rather than closing the window.
+    if mode == GameMode::Local {
+        rl.set_exit_key(None);
+    }
+
+    while !rl.window_should_close() {
+        let mut game_state = current_game_state(&mut board, &move_selector.moves, variant);
+        let timed_out = clock.as_ref().and_then(|clock| clock.flagged());
+        // However a game of a match ends, the connection stays up for the next one.
+        let match_goes_on = match_score.as_ref().is_some_and(|score| !score.decided());
+        let mut restart = false;
+        // Both sides agreed to play again, which restarts the game with the colors swapped.
+        let mut rematch = false;
+        // A chat message to send and a move typed into the move box, and whether letters are
+        // going into either instead of being shortcuts. An open chat box gets the keys first.
+        // Typing into the settings comes before both.
+        let in_settings = settings_menu.is_typing();
+        let chatting = chat.as_ref().is_some_and(ChatBox::is_open);
+        let said = chat
+            .as_mut()
+            .filter(|_| !in_settings)
+            .and_then(|chat| chat.update(rl, move_entry.is_open()));
+        let mut typed = if chatting || in_settings {
+            None
+        } else {
+            move_entry.update(rl)
         };
+        let typing =
+            in_settings || move_entry.is_open() || chat.as_ref().is_some_and(ChatBox::is_open);
+
+        // Once the game is over the computer's turn counts as ours too, so the menu still works.
+        if mode == GameMode::Computer {
+            our_turn = board.white_move || game_state != GameState::InProgress;
+        }
+
+        if let Some(network) = network.as_mut() {
+            if !disconnected && broken.is_none() {
+                let their_name = opponent.as_deref().unwrap_or("Opponent");
+                if let (Some(text), Some(chat)) = (said, chat.as_mut()) {
+                    match network.send(Packet::Chat(text.clone())) {
+                        Ok(()) => chat.push(name.as_deref().unwrap_or("You"), text),
+                        Err(e) => connection_lost(e, &mut disconnected, &mut broken),
+                    }
+                }
+                // Chat is shown as soon as it comes in, anything else is handled below.
+                while incoming.is_none() && !disconnected {
+                    match network.poll() {
+                        Ok(Some(Packet::Chat(text))) => {
+                            if let Some(chat) = chat.as_mut() {
+                                chat.push(their_name, text);
+                            }
+                        }
+                        Ok(Some(packet)) => incoming = Some(packet),
+                        Ok(None) => break,
+                        Err(e) => {
+                            connection_lost(e, &mut disconnected, &mut broken);
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(network) = network.as_mut() {
+            let finished = resigned || draw_agreed || draw_claimed.is_some();
+            if adjourned || (finished && !match_goes_on) || abandoned || broken.is_some() {
+                // Nothing more goes over the wire, the game is over or put aside.
+                incoming = None;
+            } else if disconnected {
+                reconnect_cooldown -= rl.get_frame_time();
+                disconnected_time += rl.get_frame_time();
+                if disconnected_time > RECONNECT_TIMEOUT {
+                    log::error!(
+                        "no connection for {} seconds, ending the game",
+                        RECONNECT_TIMEOUT
+                    );
+                    abandoned = true;
+                } else if reconnect_cooldown <= 0.0 {
+                    reconnect_cooldown = RECONNECT_INTERVAL;
+
+                    match network.reconnect(&start, &history) {
+                        Ok(true) => {
+                            disconnected = false;
+                            disconnected_time = 0.0;
+                            incoming = None;
+                            // Anything we were waiting on was settled while replaying the game.
+                            awaiting_ack = false;
+                            awaiting_checksum = false;
+                            in_flight = None;
+                            requests.settle();
+                            illegal_move_received = None;
+
+                            if !is_server {
+                                // The server replays the whole game, so start over from the initial
+                                // position and let the moves come in like normal.
+                                if let Some(move_log) = move_log.as_mut() {
+                                    if let Err(e) = move_log.restart() {
+                                        log::error!("could not write the move log: {}", e);
+                                    }
+                                }
+                                board = new_board(start.fen.as_deref());
+                                move_selector.moves =
+                                    crazyhouse::legal_moves(&mut board, pockets.as_ref());
+                                move_selector.selected_square = None;
+                                history.clear();
+                                our_turn = board.white_move != start.is_white;
+                            }
+                        }
+                        Ok(false) => {}
+                        Err(e) => log::error!("reconnect failed: {}", e),
+                    }
+                }
+            } else if awaiting_ack && matches!(incoming, Some(Packet::Resign | Packet::Flag)) {
+                // They resigned, left or ran out of time before our move reached them, which ends
+                // the game without an answer to it. Next frame it's handled like on our turn.
+                log::info!("the game ended before the opponent answered our move");
+                awaiting_ack = false;
+                in_flight = None;
+            } else if awaiting_ack {
+                // A draw offer made before our move reached them lapsed with it, see below.
+                if matches!(incoming, Some(Packet::DrawOffer)) {
+                    log::info!("the opponent's draw offer crossed our move and lapsed");
+                    incoming = None;
+                }
+                let received = take_ack(&mut incoming);
+
+                ack_wait += rl.get_frame_time();
+                if received.is_none() && !disconnected && ack_wait > ACK_TIMEOUT {
+                    log::error!(
+                        "no ack for our move in {} seconds, reconnecting",
+                        ACK_TIMEOUT
+                    );
+                    // Closing our end makes the opponent notice too, so both sides resync.
+                    if let Err(e) = network.close() {
+                        log::error!("could not close the connection: {}", e);
+                    }
+                    disconnected = true;
+                }
+
+                if let Some(ack) = received {
+                    log::debug!("received {ack:?}");
+                    awaiting_ack = false;
+                    in_flight = None;
+                    awaiting_checksum = ack.ok && network.exchanges_checksums();
+
+                    if !ack.ok {
+                        log::error!("opponent rejected our move, undoing it");
+                        board.undo_move();
+                        if let Some(m) = history.pop() {
+                            let from = (7 - m.from.1 as u32) * 8 + m.from.0 as u32;
+                            let to = (7 - m.to.1 as u32) * 8 + m.to.0 as u32;
+                            rejected_flash = Some(((from, to), REJECTED_FLASH_TIME));
+                        }
+                        move_selector.moves = crazyhouse::legal_moves(&mut board, pockets.as_ref());
+                        our_turn = !our_turn;
+                    } else if ack.end_state != end_state(&game_state) {
+                        // Our own view of the board is what counts, but it hints at a desync.
+                        log::error!(
+                            "the opponent thinks the game is {:?}, we see {:?}",
+                            ack.end_state,
+                            end_state(&game_state)
+                        );
+                    }
+                }
+            } else if awaiting_checksum {
+                awaiting_checksum = !check_board(&mut incoming, &board, &mut desynced);
+            } else if desynced {
+                // Playing on would only build on a position the two sides disagree about.
+            } else if requests.takeback.requested {
+                let received = take_ack(&mut incoming);
+
+                if let Some(ack) = received {
+                    requests.takeback.requested = false;
+
+                    if ack.ok {
+                        log::info!("opponent accepted the takeback");
+                        take_back(&mut board, &mut history);
+                        move_selector.moves = crazyhouse::legal_moves(&mut board, pockets.as_ref());
+                        move_selector.selected_square = None;
+                        last_opponent_move = None;
+                    } else {
+                        log::info!("opponent declined the takeback");
+                    }
+                }
+            } else if requests.adjourn.requested {
+                let received = take_ack(&mut incoming);
+
+                if let Some(ack) = received {
+                    requests.adjourn.requested = false;
+
+                    if ack.ok {
+                        log::info!("opponent agreed to adjourn");
+                        save_adjourned(rng.seed(), &start, &history);
+                        adjourned = true;
+                    } else {
+                        log::info!("opponent declined to adjourn");
+                    }
+                }
+            } else if requests.draw.requested {
+                // Playing on or resigning instead of answering turns the offer down, whatever
+                // they sent is handled like any other packet once the offer is out of the way.
+                if incoming.is_some() && !matches!(incoming, Some(Packet::Ack(_))) {
+                    log::info!("opponent played on, the draw offer lapsed");
+                    requests.draw.requested = false;
+                }
+                let received = take_ack(&mut incoming);
+
+                if let Some(ack) = received {
+                    requests.draw.requested = false;
+
+                    if ack.ok {
+                        log::info!("opponent accepted the draw");
+                        match rules::draw_claim(&board.board) {
+                            Some(claim) => draw_claimed = Some(claim),
+                            None => draw_agreed = true,
+                        }
+                    } else {
+                        log::info!("opponent declined the draw");
+                    }
+                }
+            } else if requests.takeback.offered {
+                if let Some(accepted) = answer(rl, typing) {
+                    requests.takeback.offered = false;
+
+                    let sent = network.send(Packet::Ack(Ack {
+                        ok: accepted,
+                        end_state: None,
+                    }));
+                    if let Err(e) = sent {
+                        connection_lost(e, &mut disconnected, &mut broken);
+                    } else if accepted {
+                        take_back(&mut board, &mut history);
+                        move_selector.moves = crazyhouse::legal_moves(&mut board, pockets.as_ref());
+                        last_opponent_move = None;
+                    }
+                }
+            } else if requests.adjourn.offered {
+                if let Some(accepted) = answer(rl, typing) {
+                    requests.adjourn.offered = false;
+
+                    let sent = network.send(Packet::Ack(Ack {
+                        ok: accepted,
+                        end_state: None,
+                    }));
+                    if let Err(e) = sent {
+                        connection_lost(e, &mut disconnected, &mut broken);
+                    } else if accepted {
+                        save_adjourned(rng.seed(), &start, &history);
+                        adjourned = true;
+                    }
+                }
+            } else if requests.rematch.requested {
+                // Both asking at once is as good as both agreeing, neither waits for an ack then.
+                let asked_too = matches!(incoming, Some(Packet::RematchRequest));
+                let received = if asked_too {
+                    incoming = None;
+                    None
+                } else {
+                    take_ack(&mut incoming)
+                };
+
+                if asked_too || received.as_ref().is_some_and(|ack| ack.ok) {
+                    log::info!("opponent agreed to a rematch");
+                    requests.rematch.requested = false;
+                    rematch = true;
+                } else if received.is_some() {
+                    log::info!("opponent declined the rematch");
+                    requests.rematch.requested = false;
+                }
+            } else if requests.rematch.offered {
+                // The next game of a match was agreed to when the match was.
+                let answered = match match_break {
+                    Some(_) => Some(true),
+                    None => answer(rl, typing),
+                };
+                if let Some(accepted) = answered {
+                    requests.rematch.offered = false;
+
+                    let sent = network.send(Packet::Ack(Ack {
+                        ok: accepted,
+                        end_state: None,
+                    }));
+                    if let Err(e) = sent {
+                        connection_lost(e, &mut disconnected, &mut broken);
+                    } else if accepted {
+                        rematch = true;
+                    }
+                }
+            } else if match_goes_on || (!opponent_left && timed_out.is_none()) {
+                match incoming.take() {
+                    Some(Packet::TakebackRequest) => {
+                        log::info!("opponent asked for a takeback");
+                        requests.takeback.offered = true;
+                    }
+                    Some(Packet::AdjournRequest) => {
+                        log::info!("opponent asked to adjourn");
+                        requests.adjourn.offered = true;
+                    }
+                    Some(Packet::RematchRequest)
+                        if game_state != GameState::InProgress || match_break.is_some() =>
+                    {
+                        log::info!("opponent asked for a rematch");
+                        requests.rematch.offered = true;
+                    }
+                    // Their game ended a move before ours, the request waits until ours does.
+                    Some(Packet::RematchRequest) => incoming = Some(Packet::RematchRequest),
+                    Some(Packet::Resign) => {
+                        log::info!("opponent resigned");
+                        opponent_left = true;
+                    }
+                    Some(Packet::Flag) => {
+                        log::info!("the opponent's time ran out");
+                        if let Some(clock) = clock.as_mut() {
+                            clock.flag(our_color(&start).opposite());
+                        }
+                    }
+                    Some(Packet::DrawOffer) => {
+                        // Offers come on their own, never along with a move. One in a position
+                        // where the draw can be claimed is a claim, and there's nothing to
+                        // accept.
+                        if let Some(claim) = rules::draw_claim(&board.board) {
+                            log::info!("opponent claimed a draw");
+                            let sent = network.send(Packet::Ack(Ack {
+                                ok: true,
+                                end_state: Some(chess_networking::GameState::Draw),
+                            }));
+                            if let Err(e) = sent {
+                                connection_lost(e, &mut disconnected, &mut broken);
+                            }
+                            draw_claimed = Some(claim);
+                        } else {
+                            log::info!("opponent offered a draw");
+                            requests.draw.offered = true;
+                        }
+                    }
+                    Some(Packet::Move(m)) => {
+                        log::debug!("received {m:?}");
+                        let move_str = move_string(&board, &m);
+
+                        let is_legal_move = move_selector.moves.iter().any(|s| *s == move_str);
+
+                        if is_legal_move {
+                            let (from, to) = move_squares(&move_str);
+                            let special = MoveKind::special(board.position(), from, to);
+                            let kind = play_move_sounds(
+                                &sounds,
+                                &mut board,
+                                &move_str,
+                                opponent_move_effect,
+                            );
+                            log::info!("opponent played {move_str}, {kind}");
+                            // The rook or the taken pawn not moving along would only show up as
+                            // a checksum mismatch later on, this says which move did it.
+                            if let Some(special) = special.filter(|&special| special != kind) {
+                                log::error!(
+                                    "{move_str} should be {special} but came out as {kind}"
+                                );
+                            }
+                            last_opponent_move = Some(move_str.clone());
+                            crazyhouse::make_move(&mut board, move_str);
+                            move_selector.moves =
+                                crazyhouse::legal_moves(&mut board, pockets.as_ref());
+                            move_selector.selected_square = None;
+                            game_state =
+                                current_game_state(&mut board, &move_selector.moves, variant);
+                            our_turn = !our_turn;
+                            history.push(m);
+                            illegal_move_received = None;
+                        } else {
+                            // The negative ack lets them try again, the board stays as it was.
+                            log::error!("opponent sent an illegal move {}", move_str);
+                            illegal_move_received = Some(move_str);
+                        }
+
+                        let sent = network.send(Packet::Ack(Ack {
+                            ok: is_legal_move,
+                            end_state: end_state(&game_state),
+                        }));
+                        let sent = sent.and_then(|_| {
+                            if is_legal_move {
+                                network.send(Packet::Checksum(board.checksum()))
+                            } else {
+                                Ok(())
+                            }
+                        });
+                        if let Err(e) = sent {
+                            connection_lost(e, &mut disconnected, &mut broken);
+                        }
+                    }
+                    Some(packet) => {
+                        log::error!("ignoring an unexpected {} from the opponent", packet.name())
+                    }
+                    None => {}
+                }
+            }
+        }
+
+        // Like for spectators, a move of ours goes in once the opponent accepted it.
+        let logged = match move_log.as_mut().filter(|_| !awaiting_ack) {
+            Some(log) => log.update(&history, clock.as_ref().map(Clock::remaining)),
+            None => Ok(()),
+        };
+        if let Err(e) = logged {
+            log::error!("could not write the move log, stopping it: {}", e);
+            move_log = None;
+        }
+
+        // Moves both players agreed on go out to anyone watching.
+        if let Some(network) = network.as_mut() {
+            if !disconnected && !awaiting_ack {
+                if let Err(e) = network.update_spectators(&start, &history) {
+                    log::error!("could not update spectators: {}", e);
+                }
+            }
+        }
+
+        if let Some(spectator) = spectator.as_mut().filter(|_| !spectating_ended) {
+            let mut events = Vec::new();
+            loop {
+                match spectator.receive() {
+                    Ok(Some(event)) => events.push(event),
+                    Ok(None) => break,
+                    Err(e) => {
+                        log::info!("stopped spectating: {}", e);
+                        spectating_ended = true;
+                        break;
+                    }
+                }
+            }
+
+            // Catching up on a game in progress shouldn't play every move's sound at once.
+            let single = events.len() == 1;
+            let received = !events.is_empty();
+            for event in events {
+                match event {
+                    SpectatorEvent::Move(m) => {
+                        let move_str = move_string(&board, &m);
+                        if !crazyhouse::legal_moves(&mut board, pockets.as_ref())
+                            .contains(&move_str)
+                        {
+                            log::error!("the host sent an illegal move {}", move_str);
+                            continue;
+                        }
+                        if single {
+                            play_move_sounds(&sounds, &mut board, &move_str, Effect::Move);
+                        }
+                        crazyhouse::make_move(&mut board, move_str);
+                        history.push(m);
+                    }
+                    SpectatorEvent::Takeback => {
+                        if history.pop().is_some() {
+                            board.undo_move();
+                        }
+                    }
+                }
+            }
+            if received {
+                move_selector.moves = crazyhouse::legal_moves(&mut board, pockets.as_ref());
+                game_state = current_game_state(&mut board, &move_selector.moves, variant);
+            }
+        }
+
+        let game_ended = opponent_left
+            || abandoned
+            || resigned
+            || draw_agreed
+            || draw_claimed.is_some()
+            || timed_out.is_some();
+
+        if let Some(engine) = engine.as_mut().filter(|_| mode == GameMode::Computer) {
+            if !our_turn && !game_ended {
+                engine.think(&board);
+                if let Some(m) = engine.poll(&mut board, &mut rng) {
+                    let kind = play_move_sounds(&sounds, &mut board, &m, opponent_move_effect);
+                    log::info!("computer played {m}, {kind}");
+                    last_opponent_move = Some(m.clone());
+                    history.push(outgoing_move(&m));
+                    crazyhouse::make_move(&mut board, m);
+                    move_selector.moves = crazyhouse::legal_moves(&mut board, pockets.as_ref());
+                    game_state = current_game_state(&mut board, &move_selector.moves, variant);
+                    our_turn = true;
+                }
+            }
+        }
+        if our_turn && !game_ended && !desynced && broken.is_none() {
+            if review.is_some()
+                && review_snaps_back
+                && rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT)
+            {
+                review = None;
+                move_selector.selected_square = None;
+            }
+
+            let waiting = requests.waiting() || adjourned || settings_menu.open || pause_menu.open;
+            let selected = if disconnected || waiting || review.is_some() {
+                None
+            } else if let Some(text) = typed.take() {
+                let found = pgn::find_move(&mut board, &text, pockets.as_ref());
+                match &found {
+                    Some(_) => move_entry.close(),
+                    None => move_entry.reject(format!("{} isn't a legal move", text)),
+                }
+                found
+            } else {
+                let pocket = pockets.as_ref().and_then(|start| {
+                    let mouse = coords::mouse_position(rl);
+                    let side = board.current_side();
+                    let held = start.after(&board.board);
+                    PocketsPanel::piece_at(pockets_x, &held, side, mouse, flipped)
+                });
+                move_selector.on_update(rl, flipped, pocket)
+            };
+
+            if let Some(m) = selected {
+                let (from, to) = move_squares(&m);
+                let kind = play_move_sounds(&sounds, &mut board, &m, Effect::Move);
+
+                log::info!("playing {m}, {kind}");
+
+                let outgoing = outgoing_move(&m);
+                history.push(outgoing.clone());
+                crazyhouse::make_move(&mut board, m);
+                move_selector.moves = crazyhouse::legal_moves(&mut board, pockets.as_ref());
+
+                // Without a network both sides are ours, so the turn never passes.
+                if let Some(network) = network.as_mut() {
+                    if let Err(e) = network.send(Packet::Move(outgoing)) {
+                        connection_lost(e, &mut disconnected, &mut broken);
+                    }
+
+                    awaiting_ack = true;
+                    ack_wait = 0.0;
+                    in_flight = Some(((from, to), IN_FLIGHT_TIMEOUT));
+                    our_turn = !our_turn;
+
+                    // The opponent takes the move as the answer to their offer.
+                    if requests.draw.offered {
+                        log::info!("played on, the draw offer lapsed");
+                        requests.draw.offered = false;
+                    }
+                }
+            }
+        } else if preview_opponent && !opponent_left && review.is_none() {
+            move_selector.preview(rl, flipped);
+        }
+        if let Some(text) = typed {
+            move_entry.reject(format!("Can't play {} right now", text));
+        }
+
+        // Both players get the menu, whoever's turn it would have been. Watching or replaying a
+        // game leaves nothing to restart.
+        let board_over = game_state == GameState::Checkmate || game_state == GameState::Draw;
+        let can_restart = mode != GameMode::Replay && mode != GameMode::Spectate;
+        if board_over && can_restart && !game_ended && !desynced {
+            match Menu::update(rl, coords::board_area()) {
+                // Over the network both sides have to agree to play again, or the boards
+                // would no longer match.
+                Some(true) => match network.as_mut() {
+                    Some(_) if requests.rematch.pending() || disconnected => {}
+                    Some(network) => match network.send(Packet::RematchRequest) {
+                        Ok(()) => {
+                            log::info!("asked opponent for a rematch");
+                            requests.rematch.requested = true;
+                        }
+                        Err(e) => {
+                            connection_lost(e, &mut disconnected, &mut broken);
+                        }
+                    },
+                    None => restart = true,
+                },
+                Some(false) => break,
+                None => {}
+            }
+        }
+
+        // Between the games of a match there's a short break for the scoreboard, after which
+        // the next game is asked for without the menu.
+        if let Some(remaining) = match_break.as_mut() {
+            *remaining -= rl.get_frame_time();
+            let asking = requests.rematch.pending() || disconnected || desynced;
+            if let (true, false, Some(network)) = (*remaining <= 0.0, asking, network.as_mut()) {
+                match network.send(Packet::RematchRequest) {
+                    Ok(()) => {
+                        log::info!("asked opponent for the next game of the match");
+                        requests.rematch.requested = true;
+                    }
+                    Err(e) => {
+                        connection_lost(e, &mut disconnected, &mut broken);
+                    }
+                }
+            }
+        }
+
+        // Without an opponent to ask, a takeback just undoes the last ply, even after the game
+        // ended.
+        let toolbar_enabled = if network.is_some() {
+            game_state == GameState::InProgress && !game_ended
+        } else {
+            !history.is_empty() && !pause_menu.open
+        };
+        let claimable = rules::draw_claim(&board.board)
+            .filter(|_| game_state == GameState::InProgress && !game_ended);
+        let action = Toolbar::update(
+            rl,
+            mode,
+            claimable.is_some(),
+            requests.draw.offered,
+            toolbar_enabled,
+        );
+        let takeback_pressed =
+            shortcut(rl, typing, KeyboardKey::KEY_T) || action == Some(ToolbarAction::Takeback);
+
+        if network.is_none() && action == Some(ToolbarAction::ClaimDraw) {
+            draw_claimed = claimable;
+        }
+
+        if network.is_none() && takeback_pressed && !history.is_empty() {
+            // Against the computer that's our last move along with its reply, unless it's still
+            // thinking about one.
+            let plies = if mode == GameMode::Computer && board.white_move {
+                2.min(history.len())
+            } else {
+                1
+            };
+            for _ in 0..plies {
+                board.undo_move();
+                history.pop();
+            }
+            if let Some(engine) = engine.as_mut() {
+                engine.cancel();
+            }
+            move_selector.moves = crazyhouse::legal_moves(&mut board, pockets.as_ref());
+            move_selector.selected_square = None;
+            move_selector.promotion_move = None;
+            move_selector.promotion_prompt = None;
+            game_over_handled = false;
+        }
+
+        // Takebacks, adjournments and draw claims can only be asked for on our own turn. The
+        // opponent is idle then, so their answer can't cross paths with a move of theirs, and a
+        // takeback always undoes two plies.
+        //
+        // A draw is offered on the opponent's turn instead, once our move is settled, and stands
+        // until they answer it or play a move. An offer that crosses their move lapses on both
+        // ends, they drop it while waiting for the ack and we take the move as the answer.
+        if let Some(network) = network.as_mut() {
+            let open = !requests.waiting()
+                && !requests.draw.offered
+                && !adjourned
+                && !disconnected
+                && !desynced
+                && !game_ended
+                && game_state == GameState::InProgress;
+            let can_request = our_turn && open;
+            let can_offer_draw = !our_turn && !awaiting_ack && !awaiting_checksum && open;
+
+            if requests.draw.offered {
+                let accepted = match action {
+                    Some(ToolbarAction::AcceptDraw) => Some(true),
+                    Some(ToolbarAction::DeclineDraw) => Some(false),
+                    _ => answer(rl, typing),
+                };
+                if let Some(accepted) = accepted {
+                    requests.draw.offered = false;
+
+                    let sent = network.send(Packet::Ack(Ack {
+                        ok: accepted,
+                        end_state: accepted.then_some(chess_networking::GameState::Draw),
+                    }));
+                    if let Err(e) = sent {
+                        connection_lost(e, &mut disconnected, &mut broken);
+                    } else if accepted {
+                        log::info!("accepted the draw");
+                        draw_agreed = true;
+                    } else {
+                        log::info!("declined the draw");
+                    }
+                }
+            }
+
+            if takeback_pressed {
+                if move_selector.promotion_prompt.is_some() {
+                    log::info!("finish the promotion before asking for a takeback");
+                } else if can_request && history.len() >= 2 {
+                    match network.send(Packet::TakebackRequest) {
+                        Ok(()) => {
+                            log::info!("asked opponent for a takeback");
+                            requests.takeback.requested = true;
+                            move_selector.selected_square = None;
+                        }
+                        Err(e) => {
+                            connection_lost(e, &mut disconnected, &mut broken);
+                        }
+                    }
+                }
+            } else if shortcut(rl, typing, KeyboardKey::KEY_P) {
+                if move_selector.promotion_prompt.is_some() {
+                    log::info!("finish the promotion before asking to adjourn");
+                } else if can_request {
+                    match network.send(Packet::AdjournRequest) {
+                        Ok(()) => {
+                            log::info!("asked opponent to adjourn");
+                            requests.adjourn.requested = true;
+                            move_selector.selected_square = None;
+                        }
+                        Err(e) => {
+                            connection_lost(e, &mut disconnected, &mut broken);
+                        }
+                    }
+                }
+            } else if (action == Some(ToolbarAction::OfferDraw) && can_offer_draw)
+                || (action == Some(ToolbarAction::ClaimDraw) && can_request)
+            {
+                // A claim goes out as an offer, which the opponent accepts on seeing the same
+                // repetition or fifty moves.
+                match network.send(Packet::DrawOffer) {
+                    Ok(()) => {
+                        log::info!("offered a draw");
+                        requests.draw.requested = true;
+                        move_selector.selected_square = None;
+                    }
+                    Err(e) => {
+                        connection_lost(e, &mut disconnected, &mut broken);
+                    }
+                }
+            } else if action == Some(ToolbarAction::Resign) && !game_ended && !disconnected {
+                // This works on their turn too, the opponent stops waiting for an answer to their
+                // move once a resignation comes in.
+                send_forfeit(network.as_mut());
+                resigned = true;
+            }
+        }
+
+        if timed_out.is_some() || draw_claimed.is_some() {
+            // Only a game without a peer can simply start over, over the network only quitting
+            // does anything.
+            match Menu::update(rl, coords::board_area()) {
+                Some(true) if network.is_none() => restart = true,
+                Some(false) => break,
+                _ => {}
+            }
+        }
+
+        if mode == GameMode::Analysis && shortcut(rl, typing, KeyboardKey::KEY_R) {
+            restart = true;
+        }
+        // Pasting a FEN while analysing sets the board up from there, and R goes back to it.
+        let pasting = rl.is_key_down(KeyboardKey::KEY_LEFT_CONTROL)
+            || rl.is_key_down(KeyboardKey::KEY_RIGHT_CONTROL);
+        if mode == GameMode::Analysis && pasting && shortcut(rl, typing, KeyboardKey::KEY_V) {
+            match rl.get_clipboard_text().map(|text| text.trim().to_string()) {
+                Ok(text) => match fen::parse(&text) {
+                    // The pockets and the rules were set up for the variant we started with.
+                    Ok(_)
+                        if Variant::from_fen(Some(&text)) != variant
+                            || Pockets::from_fen(&text).is_some() != pockets.is_some() =>
+                    {
+                        log::error!("the pasted position is from another variant");
+                    }
+                    Ok(_) => {
+                        log::info!("analysing {}", text);
+                        start.fen = Some(text);
+                        restart = true;
+                    }
+                    Err(e) => log::error!("could not paste a position: {}", e),
+                },
+                Err(e) => log::error!("could not paste: {}", e),
+            }
+        }
+
+        if rematch {
+            start.is_white = !start.is_white;
+            flipped = start.is_white;
+            restart = true;
+            // However the last game of a match ended, the next one starts afresh.
+            resigned = false;
+            opponent_left = false;
+            draw_agreed = false;
+            requests.draw = Request::default();
+            match_break = None;
+        }
+
+        if restart {
+            board = new_board(start.fen.as_deref());
+            move_selector.moves = crazyhouse::legal_moves(&mut board, pockets.as_ref());
+            move_selector.selected_square = None;
+            move_selector.promotion_move = None;
+            move_selector.promotion_prompt = None;
+            last_opponent_move = None;
+            history.clear();
+            clock = new_clock(&start);
+            move_log = new_move_log(&start, &history);
+            game_over_handled = false;
+            draw_claimed = None;
+            annotations.clear();
+            if network.is_some() {
+                our_turn = board.white_move != start.is_white;
+            }
+            if let Some(engine) = engine.as_mut() {
+                engine.cancel();
+            }
+            sounds.play(Effect::GameStart);
+        }
+
+        if opponent_left || abandoned || resigned || draw_agreed {
+            // There's nobody left to restart against, so only quitting does anything.
+            if let Some(false) = Menu::update(rl, coords::board_area()) {
+                break;
+            }
+        }
+
+        let game_over = game_state != GameState::InProgress || game_ended;
+        if game_over && !game_over_handled {
+            sounds.play(Effect::GameEnd);
+            let result = match game_state {
+                GameState::Checkmate if board.white_move => "0-1",
+                GameState::Checkmate => "1-0",
+                GameState::Draw => "1/2-1/2",
+                _ if draw_agreed || draw_claimed.is_some() => "1/2-1/2",
+                _ if resigned && our_color(&start) == ChessColor::Black => "1-0",
+                _ if resigned => "0-1",
+                _ if timed_out == Some(ChessColor::White) => "0-1",
+                _ if timed_out == Some(ChessColor::Black) => "1-0",
+                // The opponent resigned or disconnected, so we won.
+                _ if opponent_left && our_color(&start) == ChessColor::Black => "0-1",
+                _ if opponent_left => "1-0",
+                _ => "*",
+            };
+            game_result = Some(result);
+            // A game that was abandoned or broke off ends the match with it.
+            let playing_on = !abandoned && broken.is_none() && !desynced;
+            if let Some(score) = match_score.as_mut().filter(|score| !score.decided()) {
+                score.record(result, !start.is_white);
+                log::info!("match score {}", score.score());
+                if !score.decided() && playing_on {
+                    match_break = Some(MATCH_BREAK);
+                }
+            }
+            if let Some(path) = pgn_archive {
+                let (white, black) =
+                    player_names(mode, name.as_deref(), opponent.as_deref(), &start);
+                let pgn = pgn::export(&history, start.fen.as_deref(), white, black, result);
+                archive_game(path, &pgn);
+            }
+            game_over_handled = true;
+        }
+
+        let latest = board.board.len() - 1;
+        // The wheel steps through the game as well, as long as it's over the board and not one
+        // of the panels next to it.
+        let wheel = rl.get_mouse_wheel_move();
+        let over_board = coords::board_area().check_collision_point_rec(coords::mouse_position(rl));
+        if rl.is_key_pressed(KeyboardKey::KEY_LEFT) || (wheel > 0.0 && over_board) {
+            review = Some(review.unwrap_or(latest).saturating_sub(1));
+            move_selector.selected_square = None;
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_RIGHT) || (wheel < 0.0 && over_board) {
+            review = review.map(|i| i + 1);
+        }
+        if review.is_some() && mode != GameMode::Replay && LiveButton::update(rl) {
+            review = None;
+        }
+        // Catching up with the game, or the game moving back past us (restart, takeback), ends
+        // the review.
+        if review.is_some_and(|i| i >= latest) {
+            review = None;
+        }
+
+        if shortcut(rl, typing, KeyboardKey::KEY_B) {
+            blindfold = !blindfold;
+        }
+        if shortcut(rl, typing, KeyboardKey::KEY_Q) {
+            move_selector.auto_queen = !move_selector.auto_queen;
+            let state = if move_selector.auto_queen {
+                "on"
+            } else {
+                "off"
+            };
+            log::info!("auto-queen {state}");
+        }
+        if shortcut(rl, typing, KeyboardKey::KEY_A) {
+            show_threats = !show_threats;
+        }
+        if shortcut(rl, typing, KeyboardKey::KEY_L) {
+            learning = !learning;
+        }
+        if shortcut(rl, typing, KeyboardKey::KEY_F) {
+            flipped = !flipped;
+            flip_fade = FLIP_FADE_TIME;
+            auto_flip = false;
+            move_selector.selected_square = None;
+        }
+        // Only a game at a shared board stops for a pause, anywhere else the opponent's clock
+        // would keep going.
+        let paused = match mode {
+            GameMode::Local => pause_menu.update(rl),
+            _ => None,
+        };
+        if paused == Some(PauseChoice::Quit) {
+            break;
+        }
+        let ctrl = rl.is_key_down(KeyboardKey::KEY_LEFT_CONTROL)
+            || rl.is_key_down(KeyboardKey::KEY_RIGHT_CONTROL);
+        let save = shortcut(rl, typing, KeyboardKey::KEY_S) && ctrl && mode != GameMode::Spectate;
+        if save || paused == Some(PauseChoice::Save) {
+            let (white, black) = player_names(mode, name.as_deref(), opponent.as_deref(), &start);
+            let game = savegame::SavedGame {
+                mode: match mode {
+                    GameMode::Computer => "vs-ai",
+                    GameMode::Analysis => "analysis",
+                    GameMode::Network => "network",
+                    _ => "local",
+                }
+                .to_string(),
+                seed: rng.seed(),
+                is_white: start.is_white,
+                fen: start.fen.clone(),
+                position: fen::to_fen(&board, pockets.as_ref()),
+                history: history.clone(),
+                clock: clock.as_ref().map(|clock| {
+                    let time = start.time.unwrap_or(0);
+                    (time, start.inc.unwrap_or(0), clock.remaining())
+                }),
+                white: white.to_string(),
+                black: black.to_string(),
+            };
+            let path = load_path.unwrap_or(savegame::DEFAULT_PATH);
+            match savegame::save(std::path::Path::new(path), &game) {
+                Ok(()) => log::info!("saved the game to {}, load it with --load {}", path, path),
+                Err(e) => log::error!("could not save the game to {}: {}", path, e),
+            }
+        } else if shortcut(rl, typing, KeyboardKey::KEY_S) {
+            settings_menu.open = !settings_menu.open;
+        }
+        if shortcut(rl, typing, KeyboardKey::KEY_E) && ctrl {
+            // What's on screen is the frame drawn last, from before the keys went down.
+            let seconds = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            let path = format!("position-{}.png", seconds);
+            rl.take_screenshot(thread, &path);
+            log::info!("saved a picture of the board to {}", path);
+        }
+        if shortcut(rl, typing, KeyboardKey::KEY_C) && ctrl {
+            // With Shift it's the game so far instead, with no result yet if it's still going.
+            let shift = rl.is_key_down(KeyboardKey::KEY_LEFT_SHIFT)
+                || rl.is_key_down(KeyboardKey::KEY_RIGHT_SHIFT);
+            let (what, text) = if shift {
+                let (white, black) =
+                    player_names(mode, name.as_deref(), opponent.as_deref(), &start);
+                let result = game_result.filter(|_| game_over_handled).unwrap_or("*");
+                let pgn = pgn::export(&history, start.fen.as_deref(), white, black, result);
+                ("the game", pgn)
+            } else {
+                ("the position", fen::to_fen(&board, pockets.as_ref()))
+            };
+            match rl.set_clipboard_text(&text) {
+                Ok(()) => log::info!("copied {} to the clipboard", what),
+                Err(e) => log::error!("could not copy {}: {}", what, e),
+            }
+        }
+        if !settings_menu.open && !pause_menu.open {
+            annotations.update(rl, flipped);
+        }
+        let scale = config.window_scale;
+        if settings_menu.update(rl, config) {
+            sounds.configure(config, no_sound);
+            theme.apply(rl, thread, config);
+            if config.window_scale != scale {
+                coords::fit_window(rl, config.window_scale);
+            }
+        }
+        if shortcut(rl, typing, KeyboardKey::KEY_M) {
+            config.sound = !config.sound;
+            sounds.configure(config, no_sound);
+            log::info!("sound {}", if config.sound { "on" } else { "off" });
+            if let Err(e) = config.save() {
+                log::error!("could not save the settings to {}: {}", config::PATH, e);
+            }
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_F3) {
+            show_debug = !show_debug;
+        }
+
+        if shortcut(rl, typing, KeyboardKey::KEY_H) && hints_allowed && analyser.is_some() {
+            show_hint = !show_hint;
+        }
+
+        // Watching, replaying and a game that's over, never while it's still being played.
+        let candidates_allowed = matches!(mode, GameMode::Replay | GameMode::Spectate)
+            || game_ended
+            || game_state != GameState::InProgress;
+        if shortcut(rl, typing, KeyboardKey::KEY_TAB) && candidates_allowed {
+            show_candidates = !show_candidates;
+        }
+        if show_candidates && candidates_allowed {
+            candidates.update(&board, review.unwrap_or(latest));
+        }
+
+        // Nothing to analyse for when neither the hint nor the bar are shown.
+        let analysing = show_hint || eval_bar.is_some();
+        if let Some(analyser) = analyser.as_mut().filter(|_| analysing) {
+            // Every new position restarts the analysis, dropping whatever was left of the old one.
+            if analysed != Some(board.checksum()) {
+                analysed = Some(board.checksum());
+                engine_hint = None;
+                analyser.cancel();
+                analyser.think(&board);
+            }
+            if let Some(m) = analyser.poll(&mut board, &mut rng) {
+                engine_hint = Some(move_squares(&m));
+                if let (Some(bar), Some(score)) = (&mut eval_bar, analyser.score()) {
+                    let score = if board.white_move { score } else { -score };
+                    bar.set_engine_score(&board, score);
+                }
+            }
+        }
+
+        if let Some(bar) = &mut eval_bar {
+            bar.update(&board);
+        }
+        if let Some(list) = &mut move_list {
+            let area = HistoryPanel::area(
+                WINDOW_WIDTH + coords::board_margin() + eval_bar_width + captures_width,
+            );
+            list.update(rl, area, &history, start.fen.as_deref());
+        }
+
+        if let Some((_, remaining)) = &mut rejected_flash {
+            *remaining -= rl.get_frame_time();
+            if *remaining <= 0.0 {
+                rejected_flash = None;
+            }
+        }
+
+        if let Some((_, remaining)) = &mut in_flight {
+            *remaining -= rl.get_frame_time();
+            if *remaining <= 0.0 || disconnected {
+                in_flight = None;
+            }
+        }
+
+        if auto_flip && flipped != (board.current_side() == ChessColor::Black) {
+            flipped = !flipped;
+            flip_fade = FLIP_FADE_TIME;
+        }
+        flip_fade = (flip_fade - rl.get_frame_time()).max(0.0);
+
+        let timer_paused = move_selector.promotion_prompt.is_some()
+            || pause_menu.open
+            || game_over
+            || disconnected
+            || adjourned
+            || spectating_ended
+            || mode == GameMode::Replay;
+        move_timer.update(rl, &board, timer_paused);
+        if let Some(clock) = &mut clock {
+            clock.update(
+                rl,
+                &board,
+                game_over
+                    || pause_menu.open
+                    || disconnected
+                    || adjourned
+                    || desynced
+                    || broken.is_some()
+                    || spectating_ended,
+            );
+            // Only our own clock is heard, or both at a shared board.
+            let ours = |side| match mode {
+                GameMode::Network => side == our_color(&start),
+                GameMode::Computer => side == ChessColor::White,
+                GameMode::Spectate | GameMode::Replay => false,
+                GameMode::Analysis | GameMode::Local => true,
+            };
+            if clock.running_low().is_some_and(ours) {
+                sounds.play(Effect::LowTime);
+            } else if clock.ticked().is_some_and(ours) {
+                sounds.play(Effect::Tick);
+            }
+            // The opponent's clock only stops at zero for ours, see `Clock::judging`.
+            if let Some(network) = network.as_mut().filter(|_| clock.ran_out().is_some()) {
+                if let Err(e) = network.send(Packet::Flag) {
+                    connection_lost(e, &mut disconnected, &mut broken);
+                }
+            }
+        }
+
+        let square_pixels = RECT_WIDTH as f32 * coords::zoom(rl);
+        theme.update(rl, thread, square_pixels);
+        let textures = theme.pieces();
+
+        let dragged = move_selector.dragged(rl);
+        let from_pocket = move_selector.dragged_from_pocket(rl);
+        let mouse = coords::mouse_position(rl);
+        // A dropped piece is already where it's going.
+        let dropped = rl.is_mouse_button_released(MouseButton::MOUSE_BUTTON_LEFT);
+        animation.update(rl, &board, dropped);
+
+        // Holding V peeks at the pieces while blindfolded.
+        let peeking = !typing && rl.is_key_down(KeyboardKey::KEY_V);
+        let hide_pieces = blindfold && !peeking;
+
+        let camera = coords::camera(rl);
+        let board_area = coords::board_area();
+        let mut d = rl.begin_drawing(thread);
+        d.clear_background(Color::get_color(COLOR_BACKGROUND));
+        let mut d = d.begin_mode2D(camera);
+
+        draw_board(&mut d, board_shadow, &theme, config, flipped);
+        // The move that led to the position on screen, whoever made it. Like the pieces it's
+        // hidden when blindfolded.
+        let shown_ply = review.unwrap_or(board.board.len() - 1);
+        let last_move = shown_ply.checked_sub(1).and_then(|i| history.get(i));
+        if let Some(m) = last_move.filter(|_| !hide_pieces) {
+            let (from, to) = network_move_squares(m);
+            for square in [from, to] {
+                let (x, y) = coords::square_position(square, flipped);
+                d.draw_rectangle(
+                    x,
+                    y,
+                    RECT_WIDTH,
+                    RECT_WIDTH,
+                    Color::get_color(COLOR_LAST_MOVE),
+                );
+            }
+        }
+        if toolbar_height > 0 {
+            Toolbar::draw(
+                &mut d,
+                mode,
+                claimable.is_some(),
+                requests.draw.offered,
+                toolbar_enabled,
+            );
+        }
+        if let Some(bar) = &eval_bar {
+            bar.draw(&mut d, WINDOW_WIDTH + coords::board_margin());
+        }
+        if show_captures {
+            CapturesPanel::draw(
+                &mut d,
+                WINDOW_WIDTH + coords::board_margin() + eval_bar_width,
+                &board.board[..=shown_ply],
+                textures,
+                flipped,
+            );
+        }
+        if let Some(list) = &move_list {
+            let area = HistoryPanel::area(
+                WINDOW_WIDTH + coords::board_margin() + eval_bar_width + captures_width,
+            );
+            list.draw(&mut d, area);
+        }
+        if let Some(start) = &pockets {
+            let held = start.after(&board.board[..=shown_ply]);
+            PocketsPanel::draw(&mut d, pockets_x, &held, textures, flipped);
+        }
+        let end_reason = if opponent_left {
+            Some(rules::EndReason::OpponentResigned)
+        } else if abandoned {
+            Some(rules::EndReason::Disconnection)
+        } else if resigned {
+            Some(rules::EndReason::Resigned)
+        } else if let Some(claim) = draw_claimed {
+            Some(rules::EndReason::Claimed(claim))
+        } else if draw_agreed {
+            Some(rules::EndReason::Agreement)
+        } else if let Some(side) = timed_out {
+            Some(rules::EndReason::Timeout(side))
+        } else if review.is_some() {
+            // Reviewing goes on past the end of the game, most of all when replaying one.
+            None
+        } else {
+            match game_state {
+                GameState::Checkmate => Some(win_reason(&board, variant)),
+                GameState::Draw => Some(draw_reason(&board, &move_selector.moves)),
+                GameState::InProgress => None,
+            }
+        };
+        match end_reason {
+            Some(reason) => Menu::draw(
+                &mut d,
+                &board,
+                textures,
+                reason.describe(),
+                board_area,
+                flipped,
+            ),
+            None => {
+                if let Some(s) = move_selector.selected_square {
+                    hightlight_current_piece(&mut d, &board, s, flipped);
+                }
+                if let Some(i) = review {
+                    if !hide_pieces {
+                        draw_pieces(&mut d, &board.board[i], textures, flipped, &[]);
+                    }
+                    let text = format!("Reviewing position {} of {}", i, board.board.len() - 1);
+                    draw_announcement(&mut d, &text);
+                    // A frame around the board so an old position can't be taken for the game.
+                    d.draw_rectangle_lines_ex(
+                        board_area,
+                        REVIEW_FRAME_WIDTH,
+                        Color::get_color(COLOR_REVIEW_FRAME),
+                    );
+                    if mode != GameMode::Replay {
+                        LiveButton::draw(&mut d);
+                    }
+                } else if hide_pieces {
+                    // Blindfold mode only keeps the squares, and the legal move hints would give
+                    // away what's standing where so those go too. The last move is read out
+                    // instead, from the move list when there is one.
+                    let latest = move_list.as_ref().and_then(HistoryPanel::last_move);
+                    if let Some(m) = latest {
+                        draw_announcement(&mut d, &format!("Last move {}", m));
+                    } else if let Some(m) = &last_opponent_move {
+                        draw_announcement(&mut d, &format!("Opponent played {}", m));
+                    }
+                } else {
+                    if show_threats {
+                        draw_threats(&mut d, &board, flipped);
+                    }
+                    if board.is_in_check() {
+                        let king = rules::king_square(board.position(), board.current_side());
+                        if let Some((x, y)) = king {
+                            let (x, y) = coords::square_position((y * 8 + x) as u32, flipped);
+                            d.draw_rectangle(
+                                x,
+                                y,
+                                RECT_WIDTH,
+                                RECT_WIDTH,
+                                Color::get_color(COLOR_CHECK),
+                            );
+                        }
+                    }
+                    let mut hidden = animation.squares();
+                    hidden.extend(dragged);
+                    draw_pieces(&mut d, board.position(), textures, flipped, &hidden);
+                    animation.draw(&mut d, textures, flipped);
+
+                    if let Some(s) = move_selector.selected_square {
+                        highlight_movable_squares(&mut d, &move_selector.moves, s, flipped);
+                        if learning {
+                            let moves = &move_selector.moves;
+                            learning::draw(&mut d, board.position(), moves, s, mouse, flipped);
+                        }
+                    }
+                    if let Some(piece) = dragged.and_then(|s| board.piece_on(s)) {
+                        draw_piece_centered(&mut d, textures, piece, mouse, Color::WHITE);
+                    }
+                    let side = board.current_side();
+                    let held = from_pocket.map(|c| Piece::from(crazyhouse::placed(c, side)));
+                    if let Some(Ok(Some(piece))) = held {
+                        draw_piece_centered(&mut d, textures, piece, mouse, Color::WHITE);
+                    }
+                }
+
+                match &clock {
+                    Some(clock) => clock.draw(&mut d, board.current_side(), flipped),
+                    None => move_timer.draw(&mut d, board.current_side(), flipped),
+                }
+                let names = player_names(mode, name.as_deref(), opponent.as_deref(), &start);
+                NamePlates::draw(&mut d, names, board.current_side(), flipped);
+
+                if let Some(p) = &move_selector.promotion_prompt {
+                    p.draw(&mut d, textures, board.current_side());
+                }
+            }
+        };
+
+        if end_reason.is_none() {
+            annotations.draw(&mut d, flipped);
+        }
+
+        if let Some((from, to)) = engine_hint.filter(|_| show_hint) {
+            if !hide_pieces && review.is_none() && game_state == GameState::InProgress {
+                draw_arrow(
+                    &mut d,
+                    from,
+                    to,
+                    flipped,
+                    Color::get_color(COLOR_ENGINE_HINT),
+                );
+            }
+        }
+
+        if show_candidates && candidates_allowed && !hide_pieces {
+            candidates.draw(&mut d, flipped);
+        }
+
+        if let Some(((from, to), _)) = in_flight {
+            if !hide_pieces && review.is_none() {
+                draw_arrow(&mut d, from, to, flipped, Color::get_color(COLOR_IN_FLIGHT));
+            }
+        }
+
+        if let Some(((from, to), _)) = rejected_flash {
+            for square in [from, to] {
+                let (x, y) = coords::square_position(square, flipped);
+                d.draw_rectangle(
+                    x,
+                    y,
+                    RECT_WIDTH,
+                    RECT_WIDTH,
+                    Color::get_color(COLOR_REJECTED),
+                );
+            }
+            draw_announcement(&mut d, "Move rejected by opponent");
+        }
+
+        if let Some(m) = &illegal_move_received {
+            draw_announcement(&mut d, &format!("Opponent sent an illegal move {}", m));
+        }
+        if let Some(notice) = variant_notice.as_ref().filter(|_| history.is_empty()) {
+            draw_footnote(&mut d, notice);
+        } else if let Some(status) = tls_in_use.as_ref().and_then(Tls::status) {
+            draw_footnote(&mut d, &status);
+        }
+        move_entry.draw(&mut d);
+        if let Some(chat) = &chat {
+            chat.draw(&mut d);
+        }
+
+        if flip_fade > 0.0 {
+            let alpha = flip_fade / FLIP_FADE_TIME;
+            d.draw_rectangle_rec(board_area, Color::get_color(COLOR_BACKGROUND).fade(alpha));
+        }
+
+        if spectating_ended && game_state == GameState::InProgress {
+            draw_overlay(&mut d, "The host closed the game");
+        }
+
+        if let Some(e) = &broken {
+            draw_overlay(&mut d, &e.to_string());
+        } else if desynced {
+            draw_overlay(&mut d, "Desync detected, game stopped");
+        } else if disconnected && !abandoned && game_state == GameState::InProgress {
+            if is_server {
+                draw_overlay(&mut d, "Waiting for opponent to reconnect...");
+            } else {
+                draw_overlay(&mut d, "Connection lost, reconnecting...");
+            }
+        }
+
+        if requests.takeback.offered {
+            draw_overlay(&mut d, "Allow a takeback? (Y/N)");
+        } else if requests.takeback.requested {
+            draw_announcement(&mut d, "Waiting for the opponent to allow the takeback...");
+        }
+
+        // The offer stands while the game goes on, so neither side gets the board covered.
+        if requests.draw.offered {
+            draw_announcement(&mut d, "Opponent offers a draw (Y/N)");
+        } else if requests.draw.requested {
+            draw_announcement(&mut d, "Draw offered, waiting for an answer...");
+        }
+
+        if adjourned {
+            let id = adjourn::game_id(rng.seed());
+            draw_overlay(
+                &mut d,
+                &format!("Game adjourned, resume with --resume {}", id),
+            );
+        } else if requests.adjourn.offered {
+            draw_overlay(&mut d, "Adjourn the game? (Y/N)");
+        } else if requests.adjourn.requested {
+            draw_announcement(&mut d, "Waiting for the opponent to agree to adjourn...");
+        }
+
+        let between_games = end_reason.is_some() && review.is_none();
+        if let Some(score) = match_score.as_ref().filter(|_| between_games) {
+            let next_in = match_break.unwrap_or(0.0);
+            score.draw(&mut d, board_area, "You", "Opponent", next_in);
+        }
+
+        if requests.rematch.offered && match_break.is_none() {
+            draw_overlay(&mut d, "Play a rematch with colors swapped? (Y/N)");
+        } else if requests.rematch.requested && match_break.is_some() {
+            draw_announcement(&mut d, "Waiting for the opponent to start the next game...");
+        } else if requests.rematch.requested {
+            draw_announcement(&mut d, "Waiting for the opponent to accept the rematch...");
+        }
+
+        settings_menu.draw(&mut d, config);
+        pause_menu.draw(&mut d);
+
+        if show_debug {
+            let text = format!("legal moves: {}", move_selector.moves.len());
+            d.draw_text(&text, 10, WINDOW_HEIGHT - 30, 20, Color::RED);
+            d.draw_text(
+                &fen::to_fen(&board, pockets.as_ref()),
+                10,
+                WINDOW_HEIGHT - 55,
+                20,
+                Color::RED,
+            );
+        }
+    }
+
+    rl.set_exit_key(Some(KeyboardKey::KEY_ESCAPE));
+
+    // Quitting from the menu only happens once the game is over, so a game still in progress
+    // here means the window was closed mid-game.
+    if let Some(network) = network.as_mut() {
+        if !opponent_left
+            && !disconnected
+            && !desynced
+            && broken.is_none()
+            && !adjourned
+            && !resigned
+            && !draw_agreed
+            && draw_claimed.is_none()
+            && clock.as_ref().is_none_or(|clock| clock.flagged().is_none())
+            && current_game_state(&mut board, &move_selector.moves, variant)
+                == GameState::InProgress
+        {
+            send_forfeit(network.as_mut());
+        }
+
+        if let Err(e) = network.close() {
+            log::error!("could not close the connection: {}", e);
+        }
+    }
+    after_game(rl)
+}
+
+/// Runs a lobby without a window until it fails, returning the exit code.
+fn run_lobby(args: &[String]) -> i32 {
+    let address = args.get(2).filter(|a| !a.starts_with('-')).map_or_else(
+        || format!("0.0.0.0:{}", matchmaking::DEFAULT_LOBBY_PORT),
+        |address| with_port(address, matchmaking::DEFAULT_LOBBY_PORT),
+    );
+    log::info!("running a lobby on {}", address);
+    match std::net::TcpListener::bind(&address).and_then(matchmaking::serve) {
+        Ok(()) => 0,
+        Err(e) => {
+            log::error!("the lobby on {} stopped: {}", address, e);
+            1
+        }
+    }
+}
+
+/// Runs `connect` on a thread of its own and shows `text` over an empty board until it's done,
+/// so the window keeps responding while waiting on the network. `None` if the window was closed
+/// first.
+fn lobby<T: Send + 'static>(
+    rl: &mut RaylibHandle,
+    thread: &RaylibThread,
+    config: &Config,
+    theme: &Theme,
+    board_shadow: bool,
+    text: &str,
+    connect: impl FnOnce() -> std::io::Result<T> + Send + 'static,
+) -> Option<std::io::Result<T>> {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        // Nobody is listening anymore if the window was closed in the meantime.
+        let _ = sender.send(connect());
+    });
+
+    while !rl.window_should_close() {
+        if let Ok(result) = receiver.try_recv() {
+            return Some(result);
+        }
+
+        let camera = coords::camera(rl);
+        let mut d = rl.begin_drawing(thread);
+        d.clear_background(Color::get_color(COLOR_BACKGROUND));
+        let mut d = d.begin_mode2D(camera);
+        draw_board(&mut d, board_shadow, theme, config, false);
+        draw_overlay(&mut d, text);
+    }
+
+    None
+}
+
+/// What to play over, `--transport ws` for WebSockets and plain TCP otherwise.
+fn transport(args: &[String]) -> Transport {
+    let Some(name) = flag_value(args, "--transport") else {
+        return Transport::Tcp;
+    };
+    Transport::parse(name).unwrap_or_else(|| {
+        log::error!("unknown transport {}, expected tcp or ws, using tcp", name);
+        Transport::Tcp
+    })
+}
+
+/// TLS for the connection with `--tls`, as the server or as someone connecting to one.
+fn tls(args: &[String], is_server: bool) -> std::io::Result<Option<Tls>> {
+    if !args.iter().any(|a| a == "--tls") {
+        return Ok(None);
+    }
+    if is_server {
+        Tls::server().map(Some)
+    } else {
+        Tls::client(flag_value(args, "--tls-fingerprint")).map(Some)
+    }
+}
+
+/// Shows `text` over the board until a key or mouse button is pressed, or the window is closed.
+fn show_error(
+    rl: &mut RaylibHandle,
+    thread: &RaylibThread,
+    config: &Config,
+    theme: &Theme,
+    board_shadow: bool,
+    text: &str,
+) {
+    while !rl.window_should_close() {
+        if rl.get_key_pressed().is_some()
+            || rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT)
+        {
+            break;
+        }
+
+        let camera = coords::camera(rl);
+        let mut d = rl.begin_drawing(thread);
+        d.clear_background(Color::get_color(COLOR_BACKGROUND));
+        let mut d = d.begin_mode2D(camera);
+        draw_board(&mut d, board_shadow, theme, config, false);
+        draw_overlay(&mut d, text);
+    }
+}
+
+/// The external UCI engine `--engine` names, which plays the computer's side or suggests moves
+/// while analysing. `None` in any other mode or if it doesn't start.
+fn uci_engine(args: &[String], mode: GameMode) -> Option<uci::UciEngine> {
+    let path = flag_value(args, "--engine")
+        .filter(|_| mode == GameMode::Computer || mode == GameMode::Analysis)?;
+    let movetime = match flag_value(args, "--movetime").map(|ms| ms.parse()) {
+        Some(Ok(ms)) => ms,
+        Some(Err(_)) => {
+            log::error!("--movetime expects milliseconds, using one second");
+            1000
+        }
+        None => 1000,
+    };
+    match uci::UciEngine::start(path, movetime) {
+        Ok(engine) => Some(engine),
+        Err(e) => {
+            log::error!("could not start the engine {}: {}", path, e);
+            None
+        }
+    }
+}
+
+/// The built-in engine at the level of `--difficulty`, searching `--depth` plies if given.
+fn built_in_engine(args: &[String]) -> Engine {
+    let difficulty = match flag_value(args, "--difficulty") {
+        Some(level) => level
+            .parse()
+            .ok()
+            .and_then(Difficulty::from_level)
+            .unwrap_or_else(|| {
+                log::error!("--difficulty expects a level from 1 to 5, using the default");
+                Difficulty::DEFAULT
+            }),
+        None => Difficulty::DEFAULT,
+    };
+    let depth = flag_value(args, "--depth").and_then(|depth| match depth.parse() {
+        Ok(depth) if depth > 0 => Some(depth),
+        _ => {
+            log::error!("--depth expects a positive number of plies, ignoring it");
+            None
+        }
+    });
+
+    log::info!("playing the computer at level {}", difficulty.level());
+    Engine::new(difficulty, depth)
+}
+
+/// Back to the main menu once a game is over, unless the window was closed.
+fn after_game(rl: &RaylibHandle) -> Scene {
+    if rl.window_should_close() {
+        Scene::Quit
+    } else {
+        Scene::MainMenu
+    }
+}
+
+/// The value following `flag`, if it's there.
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    let idx = args.iter().position(|a| a == flag)?;
+    args.get(idx + 1).map(|a| a.as_str())
+}
+
+/// Where to host or join a game. It's given after the mode or the flag picking it, otherwise
+/// it's every interface for hosting and the local machine for joining.
+fn game_address(args: &[String], config: &Config, is_server: bool) -> String {
+    flag_value(args, if is_server { "--listen" } else { "--connect" })
+        .or_else(|| {
+            args.get(2)
+                .filter(|_| command(args).is_some())
+                .map(|a| a.as_str())
+        })
+        .filter(|a| !a.starts_with('-'))
+        .map(with_default_port)
+        .unwrap_or_else(|| match &config.address {
+            Some(address) if !is_server => with_default_port(address),
+            _ => {
+                let host = if is_server { "0.0.0.0" } else { "127.0.0.1" };
+                format!("{}:{}", host, DEFAULT_PORT)
+            }
+        })
+}
+
+/// Adds the default port to an address that doesn't have one.
+fn with_default_port(address: &str) -> String {
+    with_port(address, DEFAULT_PORT)
+}
+
+/// Adds `port` to an address that doesn't have one.
+fn with_port(address: &str, port: u16) -> String {
+    if address.contains(':') {
+        address.to_string()
+    } else {
+        format!("{}:{}", address, port)
+    }
+}
+
+/// A key press meant as a shortcut, letters go into the move box while it's open.
+fn shortcut(rl: &RaylibHandle, typing: bool, key: KeyboardKey) -> bool {
+    !typing && rl.is_key_pressed(key)
+}
+
+/// Answer to a question put to the player, Y for yes and N for no.
+fn answer(rl: &RaylibHandle, typing: bool) -> Option<bool> {
+    if shortcut(rl, typing, KeyboardKey::KEY_Y) {
+        Some(true)
+    } else if shortcut(rl, typing, KeyboardKey::KEY_N) {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// The side we play in a network game. Each side's `Start` has its own `is_white`, and we're
+/// black when it's set, see `our_turn`.
+fn our_color(start: &Start) -> ChessColor {
+    if start.is_white {
+        ChessColor::Black
+    } else {
+        ChessColor::White
+    }
+}
+
+/// White's and black's names as far as we know them, `?` for the ones we don't. `opponent` is
+/// the name the other player sent over the network.
+fn player_names<'a>(
+    mode: GameMode,
+    name: Option<&'a str>,
+    opponent: Option<&'a str>,
+    start: &'a Start,
+) -> (&'a str, &'a str) {
+    let (ours, theirs) = (name.unwrap_or("?"), opponent.unwrap_or("?"));
+    match mode {
+        GameMode::Computer => (ours, "Computer"),
+        GameMode::Network if our_color(start) == ChessColor::Black => (theirs, ours),
+        GameMode::Network => (ours, theirs),
+        // A spectator only hears the host's name, and the host plays black.
+        GameMode::Spectate => ("?", start.name.as_deref().unwrap_or("?")),
+        // Both sides of a game at one board are played by whoever sits at it.
+        _ => (ours, ours),
+    }
+}
+
+/// Puts the game aside on disk once both sides agreed to adjourn it.
+fn save_adjourned(seed: u64, start: &Start, history: &[Move]) {
+    match adjourn::save(seed, start, history) {
+        Ok(id) => log::info!("adjourned game {}, resume it with --resume {}", id, id),
+        Err(e) => log::error!("could not save the adjourned game: {}", e),
+    }
+}
+
+/// Logs a failed network call and decides what comes of it. A peer that broke the protocol is
+/// given up on, anything else is worth trying to reconnect.
+fn connection_lost(e: std::io::Error, disconnected: &mut bool, broken: &mut Option<NetworkError>) {
+    log::error!("connection lost: {}", e);
+    let e = NetworkError::from(e);
+    if e.is_malformed() {
+        *broken = Some(e);
+    } else {
+        *disconnected = true;
+    }
+}
+
+/// The opponent's answer to something we sent, if that's the packet waiting to be handled.
+fn take_ack(incoming: &mut Option<Packet>) -> Option<Ack> {
+    match incoming.take() {
+        Some(Packet::Ack(ack)) => Some(ack),
+        packet => {
+            *incoming = packet;
+            None
+        }
+    }
+}
+
+/// Compares `board` with the opponent's checksum of it after our move, if that's the packet
+/// waiting to be handled, setting `desynced` when they differ. Returns whether it was.
+fn check_board(incoming: &mut Option<Packet>, board: &ChessBoard, desynced: &mut bool) -> bool {
+    let checksum = match incoming.take() {
+        Some(Packet::Checksum(checksum)) => checksum,
+        packet => {
+            *incoming = packet;
+            return false;
+        }
+    };
+    if checksum != board.checksum() {
+        log::error!("desync: the opponent's board differs from ours after our move");
+        *desynced = true;
+    }
+    true
+}
+
+/// Sends our checksum of the position and waits for the opponent's, while the connection is
+/// still blocking. They get as long as for an ack.
+fn exchange_checksum(network: &mut dyn ChessProtocol, ours: u64) -> std::io::Result<u64> {
+    network.send(Packet::Checksum(ours))?;
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs_f32(ACK_TIMEOUT);
+    loop {
+        match network.poll()? {
+            Some(Packet::Checksum(checksum)) => return Ok(checksum),
+            Some(packet) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("expected a checksum, got a {}", packet.name()),
+                ))
+            }
+            None if std::time::Instant::now() >= deadline => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    "the opponent didn't send a checksum in time",
+                ))
+            }
+            // Only part of it has arrived, or a read timed out, give the rest a moment.
+            None => std::thread::sleep(std::time::Duration::from_millis(1)),
+        }
+    }
+}
+
+/// Best effort attempt at telling the opponent we left, the connection might already be gone.
+fn send_forfeit(network: &mut dyn ChessProtocol) {
+    let result = network
+        .set_blocking(true)
+        .and_then(|_| network.set_write_timeout(Some(std::time::Duration::from_millis(500))))
+        .and_then(|_| network.send(Packet::Resign));
+
+    match result {
+        Ok(()) => log::info!("sent forfeit to opponent"),
+        Err(e) => log::error!("could not send forfeit: {}", e),
+    }
+}
+
+/// The starting position of `--variant name`, from `fen` as given by `--fen` if at all. `None`
+/// keeps to `fen`, after logging why.
+fn variant_start(name: &str, fen: Option<&str>, rng: &mut Rng) -> Option<String> {
+    match name {
+        // Chess960 shuffles the pieces on the back rank, the same way for both sides.
+        "chess960" if fen.is_none() => {
+            let number = rng.below(960);
+            log::info!("chess960 starting position {}", number);
+            Some(fen::chess960(number))
+        }
+        "chess960" => {
+            log::error!("--variant chess960 ignored, starting from --fen");
+            None
+        }
+        // The pockets come along with the position, which is how the peer finds out.
+        "crazyhouse" => Some(fen::crazyhouse(fen.unwrap_or(fen::STARTING_POSITION))),
+        // The rest only win differently, which the name after the position tells the peer.
+        name => match Variant::from_name(name) {
+            Some(variant) => Some(variant.tag(fen.unwrap_or(fen::STARTING_POSITION))),
+            None => {
+                log::error!("unknown variant {}, playing standard chess", name);
+                None
+            }
+        },
+    }
+}
+
+/// What to tell a player who asked for `requested` with `--variant` when the game that was set up
+/// starts from `fen`, which is the host's choice in a network game. `None` when they match.
+fn variant_notice(requested: Option<&str>, fen: Option<&str>) -> Option<String> {
+    let requested = requested?;
+    let played = if fen.and_then(Pockets::from_fen).is_some() {
+        "crazyhouse"
+    } else if new_board(fen)
+        .board
+        .first()
+        .and_then(chess960::home_files)
+        .is_some()
+    {
+        "chess960"
+    } else {
+        Variant::from_fen(fen).name()
+    };
+    (requested != played).then(|| {
+        format!(
+            "Playing {} instead of {}, the host's choice",
+            played, requested
+        )
+    })
+}
+
+/// A board set up from `fen`, or the standard starting position without one.
+fn new_board(fen: Option<&str>) -> ChessBoard {
+    let fen = fen.unwrap_or(fen::STARTING_POSITION);
+    let (position, white_move) = fen::parse(fen).unwrap_or_else(|e| {
+        log::error!("{}, using the standard starting position", e);
+        fen::parse(fen::STARTING_POSITION).unwrap()
+    });
+
+    let mut board = ChessBoard::new();
+    board.board = vec![position];
+    board.white_move = white_move;
+    board
+}
+
+/// The move string our chess library uses for a move coming from the network.
+fn move_string(board: &ChessBoard, m: &Move) -> String {
+    let from_square = ((7 - m.from.1) * 8 + m.from.0) as u32;
+    let to_squqare = ((7 - m.to.1) * 8 + m.to.0) as u32;
+
+    // A crazyhouse drop goes nowhere, with the piece where a promotion's would be and nothing
+    // for a pawn.
+    if from_square == to_squqare {
+        let piece = match &m.promotion {
+            Some(PromotionPiece::Queen) => 'q',
+            Some(PromotionPiece::Rook) => 'r',
+            Some(PromotionPiece::Bishop) => 'b',
+            Some(PromotionPiece::Knight) => 'n',
+            None => 'p',
+        };
+        return crazyhouse::drop_move(from_square, piece);
+    }
+    let moving_piece = board.piece_on(from_square); // Why no implicit upcasting rust?
+    let target_piece = board.piece_on(to_squqare);
+
+    // Some clients castle by moving the king onto its own rook, our library wants the square
+    // the king ends up on. Chess960 castling from anywhere else stays that way.
+    let to_file = match (moving_piece, target_piece) {
+        (Some(king), Some(rook))
+            if king.t == PieceType::King
+                && rook.t == PieceType::Rook
+                && king.color == rook.color
+                && m.from.0 == 4 =>
+        {
+            if m.to.0 > m.from.0 {
+                6
+            } else {
+                2
+            }
+        }
+        _ => m.to.0,
+    };
+
+    let mut move_str = String::new();
+    move_str.push(('a' as u8 + m.from.0 as u8) as char);
+    move_str.push(('1' as u8 + m.from.1 as u8) as char);
+    move_str.push(('a' as u8 + to_file as u8) as char);
+    move_str.push(('1' as u8 + m.to.1 as u8) as char);
+
+    // An empty from-square can't match any legal move, so such a move simply gets rejected.
+    let is_pawn = moving_piece.is_some_and(|p| p.t == PieceType::Pawn);
+
+    if let Some(promotion_piece) = &m.promotion {
+        move_str.push(match promotion_piece {
+            PromotionPiece::Queen => 'q',
+            PromotionPiece::Rook => 'r',
+            PromotionPiece::Bishop => 'b',
+            PromotionPiece::Knight => 'n',
+        });
+    } else if is_pawn && (m.to.1 == 0 || m.to.1 == 7) {
+        // Some clients leave the piece out, promoting to a queen is what they almost always mean.
+        log::info!("promotion without a piece, assuming a queen");
+        move_str.push('q');
+    }
+
+    // Our chess library needs a 'e' appended if the move is en passant.
+    if is_pawn && target_piece.is_none() {
+        let diff = (from_square as i32 - to_squqare as i32).abs();
+        if diff != 8 && diff != 16 {
+            move_str.push('e');
+        }
+    }
+
+    move_str
+}
+
+/// Appends a finished game to the PGN file at `path`, creating it if needed.
+fn archive_game(path: &str, pgn: &str) {
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path);
+
+    // One write per game, so two games finishing at once don't end up interleaved.
+    match file.and_then(|mut f| f.write_all(pgn.as_bytes())) {
+        Ok(()) => log::info!("saved game to {}", path),
+        Err(e) => log::error!("could not save game to {}: {}", path, e),
+    }
+}
+
+/// Plays the sounds for a move that's about to be made, `quiet` being the one for a plain move,
+/// and returns what kind of move it is.
+fn play_move_sounds(sounds: &Sounds, board: &mut ChessBoard, m: &str, quiet: Effect) -> MoveKind {
+    let white = board.white_move;
+    let before = *board.position();
+    crazyhouse::make_move(board, m.to_string());
+    let kind = MoveKind::between(&before, board.position(), white);
+    board.undo_move();
+
+    match kind {
+        MoveKind::Quiet => sounds.play(quiet),
+        MoveKind::Capture => sounds.play(Effect::Capture),
+        MoveKind::EnPassant => sounds.play(Effect::EnPassant),
+        MoveKind::Castle => sounds.play(Effect::Castle),
+        MoveKind::Promotion => sounds.play(Effect::Promote),
+        MoveKind::PromotionCapture => {
+            sounds.play(Effect::Capture);
+            sounds.play(Effect::Promote);
+        }
+        MoveKind::Check => sounds.play(Effect::Check),
+    }
+    kind
+}
+
+/// The network form of a move string from our chess library.
+fn outgoing_move(m: &str) -> Move {
+    let (from, to) = move_squares(m);
+    Move {
+        from: (from as u8 & 7, 7 - from as u8 / 8),
+        to: (to as u8 & 7, 7 - to as u8 / 8),
+        promotion: if is_promotion(m) {
+            Some(match m.chars().nth(4).unwrap() {
+                'q' => PromotionPiece::Queen,
+                'r' => PromotionPiece::Rook,
+                'b' => PromotionPiece::Bishop,
+                'n' => PromotionPiece::Knight,
+                _ => unreachable!(),
+            })
+        } else {
+            None
+        },
+        forfeit: false,
+        offer_draw: false,
+    }
+}
+
+/// Undoes our last move and the opponent's reply, which leaves the turn where it was.
+fn take_back(board: &mut ChessBoard, history: &mut Vec<Move>) {
+    for _ in 0..2 {
+        board.undo_move();
+        history.pop();
+    }
+}
+
+struct Menu;
+
+impl Menu {
+    const BUTTON_WIDTH: f32 = 300.0;
+    const BUTTON_HEIGHT: f32 = 100.0;
+    const BUTTON_PAD: f32 = 50.0;
+    const BUTTON_DIFF: f32 = Self::BUTTON_HEIGHT - Self::BUTTON_PAD / 2.0;
+
+    /// The restart and quit buttons, centered on `area`. Both `update` and `draw` go through
+    /// this so the clickable regions are always the drawn ones.
+    fn buttons(area: Rectangle) -> [Rectangle; 2] {
+        let x = area.x + area.width / 2.0 - Self::BUTTON_WIDTH / 2.0;
+        let y = area.y + area.height / 2.0;
+
+        [-1.0, 1.0].map(|mul| {
+            Rectangle::new(
+                x,
+                y + mul * Self::BUTTON_DIFF,
+                Self::BUTTON_WIDTH,
+                Self::BUTTON_HEIGHT,
+            )
+        })
+    }
+
+    fn update(rl: &mut RaylibHandle, area: Rectangle) -> Option<bool> {
+        if rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT) {
+            let [restart, quit] = Self::buttons(area);
+            let mouse = coords::mouse_position(rl);
+
+            if restart.check_collision_point_rec(mouse) {
+                return Some(true);
+            }
+            if quit.check_collision_point_rec(mouse) {
+                return Some(false);
+            }
+        }
+
+        None
+    }
+
+    fn draw(
+        d: &mut RaylibDrawHandle,
+        board: &ChessBoard,
+        textures: &[Texture2D],
+        result_text: &str,
+        area: Rectangle,
+        flipped: bool,
+    ) {
+        draw_pieces(d, board.position(), textures, flipped, &[]);
+        d.draw_rectangle_rec(area, Color::get_color(0x00_00_00_55));
+
+        // Hardcoded since the bindings don't support MeasureTextEx which also returns height.
+        let y_offset = 25.0;
+
+        for (button, label) in Self::buttons(area).into_iter().zip(["Restart", "Quit"]) {
+            d.draw_rectangle_rounded(button, 0.5, 15, Color::RAYWHITE);
+
+            let length = d.measure_text(label, 48);
+            let x_offset = (button.width - length as f32) / 2.0;
+            d.draw_text(
+                label,
+                (button.x + x_offset) as i32,
+                (button.y + y_offset) as i32,
+                48,
+                Color::BLACK,
+            );
+        }
+
+        let length = d.measure_text(result_text, 72);
+        let x = area.x + area.width / 2.0 - length as f32 / 2.0;
+        let y = area.y + area.height / 2.0;
+        d.draw_text(result_text, x as i32, y as i32 - 300, 72, Color::PURPLE);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum ToolbarAction {
+    Resign,
+    OfferDraw,
+    AcceptDraw,
+    DeclineDraw,
+    Takeback,
+    ClaimDraw,
+}
+
+/// Jumps from an earlier position back to the game going on, shown while reviewing one.
+struct LiveButton;
+
+impl LiveButton {
+    const WIDTH: f32 = 280.0;
+    const HEIGHT: f32 = 64.0;
+    const FONT_SIZE: i32 = 32;
+    const LABEL: &'static str = "Back to live";
+
+    /// Centered towards the bottom of the board, clear of the names and clocks in the corners.
+    fn rect() -> Rectangle {
+        let area = coords::board_area();
+        Rectangle::new(
+            area.x + (area.width - Self::WIDTH) / 2.0,
+            area.y + area.height - Self::HEIGHT - RECT_WIDTH as f32 / 2.0,
+            Self::WIDTH,
+            Self::HEIGHT,
+        )
+    }
+
+    fn update(rl: &RaylibHandle) -> bool {
+        rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT)
+            && Self::rect().check_collision_point_rec(coords::mouse_position(rl))
+    }
+
+    fn draw(d: &mut RaylibDrawHandle) {
+        let button = Self::rect();
+        d.draw_rectangle_rounded(button, 0.3, 8, Color::RAYWHITE);
+
+        let length = d.measure_text(Self::LABEL, Self::FONT_SIZE);
+        d.draw_text(
+            Self::LABEL,
+            (button.x + (button.width - length as f32) / 2.0) as i32,
+            (button.y + (button.height - Self::FONT_SIZE as f32) / 2.0) as i32,
+            Self::FONT_SIZE,
+            Color::BLACK,
+        );
+    }
+}
+
+/// Buttons under the board for what can't be done by moving pieces.
+struct Toolbar;
+
+impl Toolbar {
+    const HEIGHT: i32 = 64;
+    const BUTTON_WIDTH: f32 = 200.0;
+    const BUTTON_HEIGHT: f32 = 44.0;
+    const BUTTON_PAD: f32 = 16.0;
+    const FONT_SIZE: i32 = 28;
+
+    /// Resigning and offering a draw only mean something with an opponent on the other end, and
+    /// a replayed game can't be changed at all. Claiming a draw shows up once one can be claimed,
+    /// and a draw the opponent offers is answered in place of offering one.
+    fn actions(
+        mode: GameMode,
+        can_claim: bool,
+        draw_offered: bool,
+    ) -> Vec<(ToolbarAction, &'static str)> {
+        let mut actions = match mode {
+            GameMode::Network if draw_offered => {
+                return vec![
+                    (ToolbarAction::Resign, "Resign"),
+                    (ToolbarAction::AcceptDraw, "Accept draw"),
+                    (ToolbarAction::DeclineDraw, "Decline draw"),
+                    (ToolbarAction::Takeback, "Takeback"),
+                ]
+            }
+            GameMode::Network => vec![
+                (ToolbarAction::Resign, "Resign"),
+                (ToolbarAction::OfferDraw, "Offer draw"),
+                (ToolbarAction::Takeback, "Takeback"),
+            ],
+            GameMode::Analysis | GameMode::Local | GameMode::Computer => {
+                vec![(ToolbarAction::Takeback, "Takeback")]
+            }
+            GameMode::Replay | GameMode::Spectate => return Vec::new(),
+        };
+        if can_claim {
+            actions.push((ToolbarAction::ClaimDraw, "Claim draw"));
+        }
+        actions
+    }
+
+    /// The button for the `i`th action, left to right below the board's frame.
+    fn button(i: usize) -> Rectangle {
+        let y = (WINDOW_HEIGHT + coords::board_margin()) as f32
+            + (Self::HEIGHT as f32 - Self::BUTTON_HEIGHT) / 2.0;
+
+        Rectangle::new(
+            i as f32 * (Self::BUTTON_WIDTH + Self::BUTTON_PAD),
+            y,
+            Self::BUTTON_WIDTH,
+            Self::BUTTON_HEIGHT,
+        )
+    }
+
+    fn update(
+        rl: &mut RaylibHandle,
+        mode: GameMode,
+        can_claim: bool,
+        draw_offered: bool,
+        enabled: bool,
+    ) -> Option<ToolbarAction> {
+        if !enabled || !rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT) {
+            return None;
+        }
+
+        let mouse = coords::mouse_position(rl);
+        Self::actions(mode, can_claim, draw_offered)
+            .into_iter()
+            .enumerate()
+            .find(|&(i, _)| Self::button(i).check_collision_point_rec(mouse))
+            .map(|(_, (action, _))| action)
+    }
+
+    fn draw(
+        d: &mut RaylibDrawHandle,
+        mode: GameMode,
+        can_claim: bool,
+        draw_offered: bool,
+        enabled: bool,
+    ) {
+        let color = if enabled {
+            Color::RAYWHITE
+        } else {
+            Color::GRAY
+        };
+
+        let actions = Self::actions(mode, can_claim, draw_offered);
+        for (i, (_, label)) in actions.into_iter().enumerate() {
+            let button = Self::button(i);
+            d.draw_rectangle_rounded(button, 0.3, 8, color);
+
+            let length = d.measure_text(label, Self::FONT_SIZE);
+            d.draw_text(
+                label,
+                (button.x + (button.width - length as f32) / 2.0) as i32,
+                (button.y + (button.height - Self::FONT_SIZE as f32) / 2.0) as i32,
+                Self::FONT_SIZE,
+                Color::BLACK,
+            );
+        }
     }
 }
 
-struct Menu;
+struct EvalBar {
+    position: eval::Position,
+    score: i32,
+}
 
-impl Menu {
-    const BUTTON_WIDTH: f32 = 300.0;
-    const BUTTON_HEIGHT: f32 = 100.0;
-    const BUTTON_PAD: f32 = 50.0;
-    const BUTTON_X: f32 = WINDOW_WIDTH as f32 / 2.0 - Self::BUTTON_WIDTH / 2.0;
-    const BUTTON_Y: f32 = WINDOW_HEIGHT as f32 / 2.0;
-    const BUTTON_DIFF: f32 = Self::BUTTON_HEIGHT - Self::BUTTON_PAD / 2.0;
+impl EvalBar {
+    const WIDTH: i32 = 40;
+    const FONT_SIZE: i32 = 16;
+    /// Scores (in centipawns) past this fill the whole bar.
+    const MAX_SCORE: i32 = 1000;
 
-    fn update(rl: &mut RaylibHandle) -> Option<bool> {
-        if rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT) {
-            for mul in [-1, 1] {
-                let r = Rectangle::new(
-                    Self::BUTTON_X,
-                    Self::BUTTON_Y + mul as f32 * Self::BUTTON_DIFF,
-                    Self::BUTTON_WIDTH,
-                    Self::BUTTON_HEIGHT,
-                );
+    fn new() -> EvalBar {
+        EvalBar {
+            position: [['.'; 8]; 8],
+            score: 0,
+        }
+    }
 
-                if r.check_collision_point_rec(rl.get_mouse_position()) {
-                    if mul == -1 {
-                        return Some(true);
-                    } else {
-                        return Some(false);
-                    }
-                }
-            }
+    /// Falls back on the static evaluation whenever the position changed, until the engine has
+    /// something better to say about it.
+    fn update(&mut self, board: &ChessBoard) {
+        let position = &board.board[board.board.len() - 1];
+        if *position != self.position {
+            self.position = *position;
+            self.score = eval::evaluate(position);
         }
+    }
 
-        None
+    /// `score` is from White's point of view like the static evaluation.
+    fn set_engine_score(&mut self, board: &ChessBoard, score: i32) {
+        self.position = board.board[board.board.len() - 1];
+        self.score = score;
     }
 
-    fn draw(
-        d: &mut RaylibDrawHandle,
-        board: &ChessBoard,
-        textures: &[Texture2D],
-        result_text: &str,
-    ) {
-        draw_pieces(d, &board, &textures);
+    fn draw(&self, d: &mut RaylibDrawHandle, x: i32) {
+        let clamped = self.score.clamp(-Self::MAX_SCORE, Self::MAX_SCORE);
+        let white_share = 0.5 + clamped as f32 / (2.0 * Self::MAX_SCORE as f32);
+        let white_height = (white_share * WINDOW_HEIGHT as f32) as i32;
+
         d.draw_rectangle(
+            x,
             0,
-            0,
-            WINDOW_WIDTH,
+            Self::WIDTH,
             WINDOW_HEIGHT,
-            Color::get_color(0x00_00_00_55),
+            Color::get_color(COLOR_EVAL_BLACK),
+        );
+        d.draw_rectangle(
+            x,
+            0,
+            Self::WIDTH,
+            white_height,
+            Color::get_color(COLOR_EVAL_WHITE),
         );
 
-        let x = Self::BUTTON_X;
-        let y = Self::BUTTON_Y;
-        let diff = Self::BUTTON_DIFF;
+        // The score is written at the end of the bar belonging to the side that is ahead.
+        let text = if self.score.abs() >= ai::MATE - 1000 {
+            "M".to_string()
+        } else {
+            format!("{:.1}", self.score.abs() as f32 / 100.0)
+        };
+        let length = d.measure_text(&text, Self::FONT_SIZE);
+        let text_x = x + (Self::WIDTH - length) / 2;
 
-        d.draw_rectangle_rounded(
-            Rectangle::new(x, y - diff, Self::BUTTON_WIDTH, Self::BUTTON_HEIGHT),
-            0.5,
-            15,
-            Color::RAYWHITE,
-        );
+        if self.score >= 0 {
+            d.draw_text(
+                &text,
+                text_x,
+                5,
+                Self::FONT_SIZE,
+                Color::get_color(COLOR_EVAL_BLACK),
+            );
+        } else {
+            d.draw_text(
+                &text,
+                text_x,
+                WINDOW_HEIGHT - Self::FONT_SIZE - 5,
+                Self::FONT_SIZE,
+                Color::get_color(COLOR_EVAL_WHITE),
+            );
+        }
+    }
+}
 
-        d.draw_rectangle_rounded(
-            Rectangle::new(x, y + diff, Self::BUTTON_WIDTH, Self::BUTTON_HEIGHT),
-            0.5,
-            15,
-            Color::RAYWHITE,
-        );
+/// Stopwatch for how long the side to move has been thinking, restarted every turn.
+struct MoveTimer {
+    elapsed: f32,
+    ply: usize,
+}
 
-        let length = d.measure_text("Restart", 48);
-        let x_offset = (Self::BUTTON_WIDTH - length as f32) / 2.0;
-        // Hardcoded since the bindings don't support MeasureTextEx which also returns height.
-        let y_offset = 25.0;
+impl MoveTimer {
+    const FONT_SIZE: i32 = 24;
+    const PAD: i32 = 8;
 
-        d.draw_text(
-            "Restart",
-            (x + x_offset) as i32,
-            (y - diff + y_offset) as i32,
-            48,
-            Color::BLACK,
-        );
+    fn new() -> MoveTimer {
+        MoveTimer {
+            elapsed: 0.0,
+            ply: 0,
+        }
+    }
+
+    fn update(&mut self, rl: &RaylibHandle, board: &ChessBoard, paused: bool) {
+        // Every move (or undo) changes the history length, which is when the turn flips.
+        if board.board.len() != self.ply {
+            self.ply = board.board.len();
+            self.elapsed = 0.0;
+        }
+
+        if !paused {
+            self.elapsed += rl.get_frame_time();
+        }
+    }
+
+    /// Drawn in the corner on the side of the player to move.
+    fn draw(&self, d: &mut RaylibDrawHandle, side: ChessColor, flipped: bool) {
+        let seconds = self.elapsed as u32;
+        let text = format!("{}:{:02}", seconds / 60, seconds % 60);
+        let length = d.measure_text(&text, Self::FONT_SIZE);
+
+        let width = length + 2 * Self::PAD;
+        let height = Self::FONT_SIZE + 2 * Self::PAD;
+        let x = WINDOW_WIDTH - width - Self::PAD;
+        let at_bottom = (side == ChessColor::White) != flipped;
+        let y = if at_bottom {
+            WINDOW_HEIGHT - height - Self::PAD
+        } else {
+            Self::PAD
+        };
 
-        let length = d.measure_text("Quit", 48);
-        let x_offset = (Self::BUTTON_WIDTH - length as f32) / 2.0;
+        d.draw_rectangle_rounded(
+            Rectangle::new(x as f32, y as f32, width as f32, height as f32),
+            0.3,
+            8,
+            Color::get_color(0x00_00_00_99),
+        );
         d.draw_text(
-            "Quit",
-            (x + x_offset) as i32,
-            (y + diff + y_offset) as i32,
-            48,
-            Color::BLACK,
+            &text,
+            x + Self::PAD,
+            y + Self::PAD,
+            Self::FONT_SIZE,
+            Color::RAYWHITE,
         );
-
-        let length = d.measure_text(result_text, 72);
-        let x = WINDOW_WIDTH as f32 / 2.0 - length as f32 / 2.0;
-        d.draw_text(result_text, x as i32, y as i32 - 300, 72, Color::PURPLE);
     }
 }
 
@@ -327,21 +3666,50 @@ struct MoveSelector {
     moves: Vec<String>,
     promotion_prompt: Option<PromotionUI>,
     promotion_move: Option<String>,
+    /// Always promote to a queen instead of asking.
+    auto_queen: bool,
+    /// Whether the selected piece was picked up by pressing on it, it follows the cursor until
+    /// the button is let go.
+    dragging: bool,
+    /// A piece taken out of the pocket in crazyhouse, dropped wherever the button is let go or
+    /// clicked next.
+    dropping: Option<char>,
 }
 
 impl MoveSelector {
-    fn on_update(&mut self, rl: &mut RaylibHandle) -> Option<String> {
-        let x = rl.get_mouse_x();
-        let y = rl.get_mouse_y();
-        let clicked_square = ((y / RECT_WIDTH) * 8 + x / RECT_WIDTH) as u32;
+    fn has_moves_from(&self, square: u32) -> bool {
+        self.moves
+            .iter()
+            .any(|m| move_squares(m).0 == square && !crazyhouse::is_drop(m))
+    }
+
+    /// Selecting without ever moving, for looking at the moves of the side that isn't ours.
+    fn preview(&mut self, rl: &RaylibHandle, flipped: bool) {
+        if rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT) {
+            self.selected_square = coords::square_at(coords::mouse_position(rl), flipped)
+                .filter(|&square| self.has_moves_from(square));
+        }
+    }
+
+    /// `pocket` is the piece of ours in the pocket under the cursor, if any.
+    fn on_update(
+        &mut self,
+        rl: &mut RaylibHandle,
+        flipped: bool,
+        pocket: Option<char>,
+    ) -> Option<String> {
+        // Nothing can be selected once the game is over.
+        if self.moves.is_empty() {
+            self.selected_square = None;
+            self.dropping = None;
+            return None;
+        }
+
+        let mouse = coords::mouse_position(rl);
+        let (x, y) = (mouse.x, mouse.y);
 
         if let Some(m) = &self.promotion_move {
-            if let Some(c) = self
-                .promotion_prompt
-                .as_mut()
-                .unwrap()
-                .update(rl, x as f32, y as f32)
-            {
+            if let Some(c) = self.promotion_prompt.as_mut().unwrap().update(rl, x, y) {
                 let mut clone = m.clone();
                 clone.pop();
                 clone.push(c);
@@ -356,35 +3724,112 @@ impl MoveSelector {
             return None;
         }
 
+        // A move is either two clicks, on the piece and then on where it goes, or dragging the
+        // piece there. Both start out the same by pressing on the piece.
         if rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT) {
-            if self.selected_square.is_none() {
-                self.selected_square = Some(clicked_square);
+            self.dragging = false;
+
+            if let Some(piece) = pocket {
+                self.selected_square = None;
+                self.dropping = Some(piece);
+                self.dragging = true;
                 return None;
             }
 
-            let from = self.selected_square.unwrap();
-            let to = clicked_square;
-
-            let valid_move = self.moves.iter().find(|s| move_squares(s) == (from, to));
-            if valid_move.is_none() {
+            let Some(clicked_square) = coords::square_at(mouse, flipped) else {
                 self.selected_square = None;
+                self.dropping = None;
                 return None;
+            };
+            if let Some(piece) = self.dropping.take() {
+                return self.finish_drop(clicked_square, piece);
             }
 
-            let m = valid_move.unwrap().clone();
-            if is_promotion(&m) {
-                self.promotion_move = Some(m);
-                self.promotion_prompt = Some(PromotionUI::new(x as f32, y as f32));
-                return None;
+            // Clicking another piece of ours picks it instead, unless that's Chess960 castling
+            // with the king onto the rook.
+            if let Some(from) = self.selected_square {
+                let castles = self
+                    .moves
+                    .iter()
+                    .any(|m| move_squares(m) == (from, clicked_square));
+                if from != clicked_square && (castles || !self.has_moves_from(clicked_square)) {
+                    return self.finish_move(from, clicked_square, x, y);
+                }
             }
 
-            self.selected_square = None;
+            // Only pieces of the side to move have moves, so this also ignores clicks on the
+            // opponent's pieces and on empty squares.
+            if self.has_moves_from(clicked_square) {
+                self.selected_square = Some(clicked_square);
+                self.dragging = true;
+            } else {
+                self.selected_square = None;
+            }
+            return None;
+        }
 
-            return Some(m);
+        if rl.is_mouse_button_released(MouseButton::MOUSE_BUTTON_LEFT) && self.dragging {
+            self.dragging = false;
+
+            // Letting go over the pocket leaves the piece picked up for a click.
+            if let Some(piece) = self.dropping {
+                let square = coords::square_at(mouse, flipped)?;
+                self.dropping = None;
+                return self.finish_drop(square, piece);
+            }
+
+            // Letting go over the square it came from leaves the piece selected for a click.
+            let from = self.selected_square?;
+            match coords::square_at(mouse, flipped) {
+                Some(to) if to != from => return self.finish_move(from, to, x, y),
+                Some(_) => {}
+                None => self.selected_square = None,
+            }
         }
 
         None
     }
+
+    /// The square of the piece being dragged, if there is one.
+    fn dragged(&self, rl: &RaylibHandle) -> Option<u32> {
+        let held = self.dragging && rl.is_mouse_button_down(MouseButton::MOUSE_BUTTON_LEFT);
+        self.selected_square.filter(|_| held)
+    }
+
+    /// The piece being dragged out of the pocket, if there is one.
+    fn dragged_from_pocket(&self, rl: &RaylibHandle) -> Option<char> {
+        let held = self.dragging && rl.is_mouse_button_down(MouseButton::MOUSE_BUTTON_LEFT);
+        self.dropping.filter(|_| held)
+    }
+
+    /// Drops `piece` on `square` if that's a legal drop.
+    fn finish_drop(&mut self, square: u32, piece: char) -> Option<String> {
+        let m = crazyhouse::drop_move(square, piece);
+        self.moves.contains(&m).then_some(m)
+    }
+
+    /// Plays `from` to `to` if that's a legal move, asking for the promotion piece first if
+    /// needed. `x` and `y` are where to put the promotion prompt.
+    fn finish_move(&mut self, from: u32, to: u32, x: f32, y: f32) -> Option<String> {
+        let Some(valid_move) = self.moves.iter().find(|s| move_squares(s) == (from, to)) else {
+            self.selected_square = None;
+            return None;
+        };
+
+        let mut m = valid_move.clone();
+        if is_promotion(&m) && self.auto_queen {
+            m.pop();
+            m.push('q');
+        } else if is_promotion(&m) {
+            self.promotion_move = Some(m);
+            self.promotion_prompt = Some(PromotionUI::new(x, y));
+            return None;
+        }
+
+        self.selected_square = None;
+
+        Some(m)
+    }
 }
 
 struct PromotionUI {
@@ -468,8 +3913,77 @@ impl PromotionUI {
     }
 }
 
+/// The board's own game state, except that running out of legal moves always ends the game,
+/// as checkmate if the side to move is in check and as a draw (stalemate) otherwise. Winning by
+/// the rules of `variant` counts as checkmate, the side to move having lost either way.
+fn current_game_state(board: &mut ChessBoard, moves: &[String], variant: Variant) -> GameState {
+    if variant.winner(&board.board).is_some() {
+        return GameState::Checkmate;
+    }
+    let state = board.current_gamestate();
+    // Our chess library knows nothing of drops, which can still get out of what it takes for
+    // mate or stalemate, and keep mating material in hand.
+    let drops = moves.iter().any(|m| crazyhouse::is_drop(m));
+    if state != GameState::InProgress && drops && board.get_moves().is_empty() {
+        return GameState::InProgress;
+    }
+    if state == GameState::InProgress && !drops && rules::insufficient_material(board.position()) {
+        return GameState::Draw;
+    }
+    if state != GameState::InProgress || !moves.is_empty() {
+        return state;
+    }
+
+    log::error!("no legal moves left but the game is still in progress, ending it");
+    if board.is_in_check() {
+        GameState::Checkmate
+    } else {
+        GameState::Draw
+    }
+}
+
+/// How the side that just moved won, for a checkmate `current_game_state` found.
+fn win_reason(board: &ChessBoard, variant: Variant) -> rules::EndReason {
+    match (variant, variant.winner(&board.board)) {
+        (Variant::KingOfTheHill, Some(side)) => rules::EndReason::KingOfTheHill(side),
+        (Variant::ThreeCheck, Some(side)) => rules::EndReason::ThirdCheck(side),
+        _ => rules::EndReason::Checkmate,
+    }
+}
+
+/// Why the position is a draw, for one `current_game_state` found. The chess library only says
+/// that it is, so the position is looked at again.
+fn draw_reason(board: &ChessBoard, moves: &[String]) -> rules::EndReason {
+    if rules::insufficient_material(board.position()) {
+        rules::EndReason::InsufficientMaterial
+    } else if moves.is_empty() {
+        rules::EndReason::Stalemate
+    } else if let Some(claim) = rules::draw_claim(&board.board) {
+        rules::EndReason::Claimed(claim)
+    } else {
+        rules::EndReason::Draw
+    }
+}
+
+/// How the protocol's acks tell the mover that their move ended the game.
+fn end_state(state: &GameState) -> Option<chess_networking::GameState> {
+    match state {
+        GameState::Checkmate => Some(chess_networking::GameState::CheckMate),
+        GameState::Draw => Some(chess_networking::GameState::Draw),
+        GameState::InProgress => None,
+    }
+}
+
+/// Whether `m` ends in a promotion piece. Dropping a piece other than a pawn counts as well, the
+/// network sends those the same way.
 fn is_promotion(m: &str) -> bool {
-    m.len() > 4 && m.chars().nth(4).unwrap() != 'e'
+    m.len() > 4 && !matches!(m.chars().nth(4).unwrap(), 'e' | 'p')
+}
+
+/// Board squares of a move coming from the network, which counts ranks from White's side.
+fn network_move_squares(m: &Move) -> (u32, u32) {
+    let square = |(file, rank): (u8, u8)| (7 - rank as u32) * 8 + file as u32;
+    (square(m.from), square(m.to))
 }
 
 fn move_squares(s: &str) -> (u32, u32) {
@@ -484,60 +3998,172 @@ fn square(s: &str) -> u32 {
     y0 * 8 + x0
 }
 
-fn hightlight_current_piece(d: &mut impl RaylibDraw, board: &ChessBoard, square: u32) {
+fn hightlight_current_piece(
+    d: &mut impl RaylibDraw,
+    board: &ChessBoard,
+    square: u32,
+    flipped: bool,
+) {
     let color = if board.current_side() == ChessColor::White {
         Color::get_color(COLOR_WHITE_SELECTED)
     } else {
         Color::get_color(COLOR_BLACK_SELECTED)
     };
 
-    let x = (square & 7) as i32;
-    let y = (square / 8) as i32;
-
-    d.draw_rectangle(
-        x * RECT_WIDTH,
-        y * RECT_WIDTH,
-        RECT_WIDTH,
-        RECT_WIDTH,
-        color,
-    );
+    let (x, y) = coords::square_position(square, flipped);
+    d.draw_rectangle(x, y, RECT_WIDTH, RECT_WIDTH, color);
 }
 
-fn highlight_movable_squares(d: &mut impl RaylibDraw, moves: &[String], selected_square: u32) {
+fn highlight_movable_squares(
+    d: &mut impl RaylibDraw,
+    moves: &[String],
+    selected_square: u32,
+    flipped: bool,
+) {
     for m in moves {
         let (from, to) = move_squares(m);
         if from == selected_square {
-            let x = to % 8;
-            let y = to / 8;
-            let center_x = x as i32 * RECT_WIDTH + RECT_WIDTH / 2;
-            let center_y = y as i32 * RECT_WIDTH + RECT_WIDTH / 2;
+            let (x, y) = coords::square_position(to, flipped);
+            let center_x = x + RECT_WIDTH / 2;
+            let center_y = y + RECT_WIDTH / 2;
 
             d.draw_circle(center_x, center_y, 24.0, Color::get_color(COLOR_MOVABLE));
         }
     }
 }
 
-fn draw_pieces(d: &mut impl RaylibDraw, board: &ChessBoard, textures: &[Texture2D]) {
+/// Draws an arrow from the center of one square to another.
+fn draw_arrow(d: &mut impl RaylibDraw, from: u32, to: u32, flipped: bool, color: Color) {
+    let center = |square| {
+        let (x, y) = coords::square_position(square, flipped);
+        let half = RECT_WIDTH as f32 / 2.0;
+        Vector2::new(x as f32 + half, y as f32 + half)
+    };
+    let start = center(from);
+    let end = center(to);
+
+    let head_length = RECT_WIDTH as f32 * 0.35;
+    let direction = (end - start).normalized();
+    let normal = Vector2::new(-direction.y, direction.x);
+    let base = end - direction * head_length;
+
+    d.draw_line_ex(start, base, RECT_WIDTH as f32 * 0.15, color);
+
+    let left = base + normal * (head_length * 0.6);
+    let right = base - normal * (head_length * 0.6);
+    // raylib only fills triangles whose corners go counter-clockwise on screen.
+    let cross = (left - end).x * (right - end).y - (left - end).y * (right - end).x;
+    if cross < 0.0 {
+        d.draw_triangle(end, left, right, color);
+    } else {
+        d.draw_triangle(end, right, left, color);
+    }
+}
+
+/// Shades every square the side that isn't to move attacks, where the side to move shouldn't
+/// leave anything hanging.
+fn draw_threats(d: &mut impl RaylibDraw, board: &ChessBoard, flipped: bool) {
+    let opponent = board.current_side().opposite();
+
+    for square in 0..64 {
+        let (x, y) = ((square & 7) as i32, (square / 8) as i32);
+        if rules::is_attacked(board.position(), x, y, opponent) {
+            let (x, y) = coords::square_position(square, flipped);
+            d.draw_rectangle(x, y, RECT_WIDTH, RECT_WIDTH, Color::get_color(COLOR_THREAT));
+        }
+    }
+}
+
+/// Dims the whole board and shows a message in the middle of it, with whatever follows its first
+/// line in small print at the bottom.
+fn draw_overlay(d: &mut RaylibDrawHandle, text: &str) {
+    const FONT_SIZE: i32 = 48;
+
+    let (text, note) = match text.split_once('\n') {
+        Some((text, note)) => (text, Some(note)),
+        None => (text, None),
+    };
+
+    d.draw_rectangle(
+        0,
+        0,
+        WINDOW_WIDTH,
+        WINDOW_HEIGHT,
+        Color::get_color(0x00_00_00_99),
+    );
+
+    let length = d.measure_text(text, FONT_SIZE);
+    d.draw_text(
+        text,
+        (WINDOW_WIDTH - length) / 2,
+        (WINDOW_HEIGHT - FONT_SIZE) / 2,
+        FONT_SIZE,
+        Color::RAYWHITE,
+    );
+    if let Some(note) = note {
+        draw_footnote(d, note);
+    }
+}
+
+/// A line of small print along the bottom of the board, for what should stay in view without
+/// getting in the way.
+fn draw_footnote(d: &mut RaylibDrawHandle, text: &str) {
+    const FONT_SIZE: i32 = 20;
+    const PAD: i32 = 6;
+
+    let length = d.measure_text(text, FONT_SIZE);
+    let y = WINDOW_HEIGHT - FONT_SIZE - 2 * PAD;
+    d.draw_rectangle(
+        0,
+        y,
+        length + 2 * PAD,
+        FONT_SIZE + 2 * PAD,
+        Color::get_color(0x00_00_00_99),
+    );
+    d.draw_text(text, PAD, y + PAD, FONT_SIZE, Color::RAYWHITE);
+}
+
+fn draw_announcement(d: &mut RaylibDrawHandle, text: &str) {
+    const FONT_SIZE: i32 = 32;
+    const PAD: i32 = 12;
+
+    let length = d.measure_text(text, FONT_SIZE);
+    let x = (WINDOW_WIDTH - length) / 2;
+
+    d.draw_rectangle_rounded(
+        Rectangle::new(
+            (x - PAD) as f32,
+            PAD as f32,
+            (length + 2 * PAD) as f32,
+            (FONT_SIZE + 2 * PAD) as f32,
+        ),
+        0.3,
+        8,
+        Color::get_color(0x00_00_00_99),
+    );
+    d.draw_text(text, x, 2 * PAD, FONT_SIZE, Color::RAYWHITE);
+}
+
+/// Draws every piece in `position` except the ones on `hidden`.
+fn draw_pieces(
+    d: &mut impl RaylibDraw,
+    position: &eval::Position,
+    textures: &[Texture2D],
+    flipped: bool,
+    hidden: &[u32],
+) {
     for y in 0..8 {
         for x in 0..8 {
-            let curr_piece = board.board[board.board.len() - 1][y][x];
-            if curr_piece != '.' {
-                let color = !curr_piece.is_uppercase();
-                let piece_type = match curr_piece.to_ascii_lowercase() {
-                    'p' => 0,
-                    'n' => 1,
-                    'b' => 2,
-                    'r' => 3,
-                    'q' => 4,
-                    'k' => 5,
-                    _ => panic!("Invalid piece: {}", curr_piece.to_ascii_lowercase() as u8),
-                };
+            if hidden.contains(&((y * 8 + x) as u32)) {
+                continue;
+            }
 
-                let texture_index = piece_type + 6 * color as usize;
-                let texture = &textures[texture_index];
+            let curr_piece = position[y][x];
+            // Invalid characters are reported by `piece_on`, here we just skip drawing them.
+            if let Ok(Some(piece)) = Piece::from(curr_piece) {
+                let texture = &textures[piece.texture_index()];
 
-                let x = x as i32 * RECT_WIDTH;
-                let y = y as i32 * RECT_WIDTH;
+                let (x, y) = coords::square_position((y * 8 + x) as u32, flipped);
 
                 d.draw_texture_pro(
                     texture,
@@ -552,14 +4178,77 @@ fn draw_pieces(d: &mut impl RaylibDraw, board: &ChessBoard, textures: &[Texture2
     }
 }
 
-fn draw_board(d: &mut impl RaylibDraw) {
+/// A piece the size of a square centered on `center` and tinted with `tint`, for pieces that
+/// aren't on a square.
+fn draw_piece_centered(
+    d: &mut impl RaylibDraw,
+    textures: &[Texture2D],
+    piece: Piece,
+    center: Vector2,
+    tint: Color,
+) {
+    let texture = &textures[piece.texture_index()];
+    let half = RECT_WIDTH as f32 / 2.0;
+
+    d.draw_texture_pro(
+        texture,
+        Rectangle::new(0.0, 0.0, texture.width() as f32, texture.height() as f32),
+        Rectangle::new(
+            center.x - half,
+            center.y - half,
+            RECT_WIDTH as f32,
+            RECT_WIDTH as f32,
+        ),
+        Vector2::zero(),
+        0.0,
+        tint,
+    );
+}
+
+/// The squares in the colors of `theme`, along with the file and rank labels if those are turned
+/// on in `config`.
+fn draw_board(
+    d: &mut impl RaylibDraw,
+    shadow: bool,
+    theme: &Theme,
+    config: &Config,
+    flipped: bool,
+) {
+    const LABEL_SIZE: i32 = 20;
+    const LABEL_PAD: i32 = 6;
+
+    let size = 8 * RECT_WIDTH;
+    let thickness = coords::board_border();
+    let (light, dark) = (theme.light_square, theme.dark_square);
+
+    if shadow {
+        // Stacking faint rectangles that each reach a bit further out fades the shadow towards
+        // its edges.
+        for spread in 1..=BOARD_SHADOW {
+            let outset = thickness + spread;
+            d.draw_rectangle(
+                BOARD_SHADOW_OFFSET - outset,
+                BOARD_SHADOW_OFFSET - outset,
+                size + 2 * outset,
+                size + 2 * outset,
+                Color::new(0, 0, 0, 16),
+            );
+        }
+    }
+
+    // The frame is a darker version of the dark squares so it always goes with the board colors.
+    let border = Color::new(dark.r / 2, dark.g / 2, dark.b / 2, 255);
+    d.draw_rectangle(
+        -thickness,
+        -thickness,
+        size + 2 * thickness,
+        size + 2 * thickness,
+        border,
+    );
+
     for y in 0..8 {
         for x in 0..8 {
-            let color = if (x + y) % 2 == 0 {
-                Color::get_color(COLOR_EVEN)
-            } else {
-                Color::get_color(COLOR_ODD)
-            };
+            let color = if (x + y) % 2 == 0 { light } else { dark };
 
             d.draw_rectangle(
                 x * RECT_WIDTH,
@@ -568,24 +4257,37 @@ fn draw_board(d: &mut impl RaylibDraw) {
                 RECT_WIDTH,
                 color,
             );
-        }
-    }
-}
 
-fn load_textures(rl: &mut RaylibHandle, thread: &RaylibThread) -> Vec<Texture2D> {
-    let mut textures = Vec::new();
-    const NUM_PIECES: u32 = 6;
-
-    for idx in 0..NUM_PIECES * 2 {
-        let texture = match rl.load_texture(&thread, format!("assets/{}.png", idx).as_str()) {
-            Ok(texture) => texture,
-            Err(msg) => panic!("{}", msg),
-        };
+            if !config.coordinates {
+                continue;
+            }
 
-        textures.push(texture);
+            // Labels take the color of the other kind of square so they stand out on theirs.
+            let label_color = if (x + y) % 2 == 0 { dark } else { light };
+            if x == 0 {
+                let rank = if flipped { y + 1 } else { 8 - y };
+                d.draw_text(
+                    &rank.to_string(),
+                    LABEL_PAD,
+                    y * RECT_WIDTH + LABEL_PAD,
+                    LABEL_SIZE,
+                    label_color,
+                );
+            }
+            if y == 7 {
+                let file = if flipped { 7 - x } else { x };
+                let label = ((b'a' + file as u8) as char).to_string();
+                // The default font's letters are about half as wide as they're tall.
+                d.draw_text(
+                    &label,
+                    (x + 1) * RECT_WIDTH - LABEL_SIZE / 2 - LABEL_PAD,
+                    size - LABEL_SIZE - LABEL_PAD,
+                    LABEL_SIZE,
+                    label_color,
+                );
+            }
+        }
     }
-
-    textures
 }
 
 #[derive(Clone, Copy, PartialEq, Debug)]
@@ -594,6 +4296,15 @@ pub enum ChessColor {
     Black,
 }
 
+impl ChessColor {
+    pub fn opposite(self) -> ChessColor {
+        match self {
+            ChessColor::White => ChessColor::Black,
+            ChessColor::Black => ChessColor::White,
+        }
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum PieceType {
     Pawn,
@@ -611,13 +4322,28 @@ pub struct Piece {
 }
 
 trait BoardExtensions {
+    /// The current position, the last snapshot in `board`.
+    fn position(&self) -> &eval::Position;
     fn piece_on(&self, square: u32) -> Option<Piece>;
     fn current_side(&self) -> ChessColor;
+    fn is_in_check(&self) -> bool;
+    fn checksum(&self) -> u64;
 }
 
 impl BoardExtensions for ChessBoard {
+    fn position(&self) -> &eval::Position {
+        &self.board[self.board.len() - 1]
+    }
+
     fn piece_on(&self, square: u32) -> Option<Piece> {
-        Piece::from(self.board[self.board.len() - 1][square as usize / 8][square as usize & 7])
+        let c = self.board[self.board.len() - 1][square as usize / 8][square as usize & 7];
+        match Piece::from(c) {
+            Ok(piece) => piece,
+            Err(e) => {
+                log::error!("{} on square {}", e, square);
+                None
+            }
+        }
     }
 
     fn current_side(&self) -> ChessColor {
@@ -627,10 +4353,34 @@ impl BoardExtensions for ChessBoard {
             ChessColor::Black
         }
     }
+
+    fn is_in_check(&self) -> bool {
+        rules::in_check(&self.board[self.board.len() - 1], self.current_side())
+    }
+
+    /// FNV-1a hash of the position and the side to move, for comparing boards with the peer.
+    fn checksum(&self) -> u64 {
+        let side = if self.white_move { b'w' } else { b'b' };
+        let bytes = self.position().iter().flatten().map(|&c| c as u8);
+
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for byte in bytes.chain([side]) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+
+        hash
+    }
 }
 
 impl Piece {
-    fn from(s: char) -> Option<Piece> {
+    /// Parses a board character. `'.'` is an empty square, anything that isn't a piece letter is
+    /// an error since it means the board got corrupted somewhere.
+    fn from(s: char) -> Result<Option<Piece>, InvalidPiece> {
+        if s == '.' {
+            return Ok(None);
+        }
+
         let white = s.is_uppercase();
         let piece_type = match s.to_ascii_lowercase() {
             'p' => PieceType::Pawn,
@@ -639,17 +4389,146 @@ impl Piece {
             'r' => PieceType::Rook,
             'q' => PieceType::Queen,
             'k' => PieceType::King,
-            '.' => return None,
-            _ => unreachable!(),
+            _ => return Err(InvalidPiece(s)),
         };
 
-        Some(Piece {
+        Ok(Some(Piece {
             t: piece_type,
             color: if white {
                 ChessColor::White
             } else {
                 ChessColor::Black
             },
-        })
+        }))
+    }
+
+    fn texture_index(&self) -> usize {
+        self.t as usize + 6 * self.color as usize
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct InvalidPiece(char);
+
+impl std::fmt::Display for InvalidPiece {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid piece character {:?}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_piece_characters() {
+        let cases = [
+            ('P', PieceType::Pawn, ChessColor::White),
+            ('n', PieceType::Knight, ChessColor::Black),
+            ('B', PieceType::Bishop, ChessColor::White),
+            ('r', PieceType::Rook, ChessColor::Black),
+            ('Q', PieceType::Queen, ChessColor::White),
+            ('k', PieceType::King, ChessColor::Black),
+        ];
+        for (c, t, color) in cases {
+            let piece = Piece::from(c).unwrap().unwrap();
+            assert_eq!((piece.t, piece.color), (t, color), "{}", c);
+        }
+    }
+
+    #[test]
+    fn parses_an_empty_square() {
+        assert!(matches!(Piece::from('.'), Ok(None)));
+    }
+
+    #[test]
+    fn rejects_other_characters() {
+        assert!(matches!(Piece::from('x'), Err(InvalidPiece('x'))));
+    }
+
+    #[test]
+    fn a_mismatching_checksum_desyncs() {
+        let board = new_board(None);
+        let mut desynced = false;
+
+        let mut incoming = Some(Packet::Checksum(board.checksum()));
+        assert!(check_board(&mut incoming, &board, &mut desynced));
+        assert!(incoming.is_none() && !desynced);
+
+        let mut incoming = Some(Packet::Checksum(board.checksum() ^ 1));
+        assert!(check_board(&mut incoming, &board, &mut desynced));
+        assert!(desynced);
+    }
+
+    #[test]
+    fn leaves_other_packets_for_later() {
+        let board = new_board(None);
+        let mut desynced = false;
+        let mut incoming = Some(Packet::Resign);
+        assert!(!check_board(&mut incoming, &board, &mut desynced));
+        assert!(matches!(incoming, Some(Packet::Resign)));
+        assert!(!desynced);
+    }
+
+    #[test]
+    fn tells_the_joiner_about_another_variant() {
+        let three_check = Variant::ThreeCheck.tag(fen::STARTING_POSITION);
+        let crazyhouse = fen::crazyhouse(fen::STARTING_POSITION);
+        let chess960 = fen::chess960(0);
+
+        assert_eq!(variant_notice(None, Some(&three_check)), None);
+        assert_eq!(
+            variant_notice(Some("three-check"), Some(&three_check)),
+            None
+        );
+        assert_eq!(variant_notice(Some("crazyhouse"), Some(&crazyhouse)), None);
+        assert_eq!(variant_notice(Some("chess960"), Some(&chess960)), None);
+        assert_eq!(variant_notice(Some("standard"), None), None);
+        assert_eq!(
+            variant_notice(Some("crazyhouse"), Some(&three_check)).as_deref(),
+            Some("Playing three-check instead of crazyhouse, the host's choice")
+        );
+        assert_eq!(
+            variant_notice(Some("chess960"), None).as_deref(),
+            Some("Playing standard instead of chess960, the host's choice")
+        );
+        assert_eq!(
+            variant_notice(Some("king-of-the-hill"), Some(&chess960)).as_deref(),
+            Some("Playing chess960 instead of king-of-the-hill, the host's choice")
+        );
+    }
+
+    #[test]
+    fn names_both_players_over_the_network() {
+        let start = |is_white| Start {
+            is_white,
+            name: Some("Host".to_string()),
+            fen: None,
+            time: None,
+            inc: None,
+        };
+        let (client, host) = (start(false), start(true));
+        let names = |start| player_names(GameMode::Network, Some("Us"), Some("Them"), start);
+        assert_eq!(names(&client), ("Us", "Them"));
+        assert_eq!(names(&host), ("Them", "Us"));
+        let unknown = player_names(GameMode::Network, Some("Us"), None, &host);
+        assert_eq!(unknown, ("?", "Us"));
+    }
+
+    #[test]
+    fn selects_nothing_of_the_opponents() {
+        let mut board = new_board(None);
+        let move_selector = MoveSelector {
+            moves: crazyhouse::legal_moves(&mut board, None),
+            selected_square: None,
+            promotion_prompt: None,
+            promotion_move: None,
+            auto_queen: false,
+            dragging: false,
+            dropping: None,
+        };
+        // The pawns in front of the kings, black's on e7 and white's on e2.
+        assert!(!move_selector.has_moves_from(12));
+        assert!(move_selector.has_moves_from(52));
     }
 }
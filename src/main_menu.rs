@@ -0,0 +1,336 @@
+use crate::config::{self, Config};
+use crate::{coords, fen, log};
+use raylib::prelude::*;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Field {
+    Name,
+    Address,
+    GameFile,
+}
+
+/// What was picked in the main menu.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Choice {
+    Host,
+    Join,
+    Local,
+    Computer,
+    Replay,
+    Load,
+    /// Setting up a position to play from, starting with the pasted one if there is one.
+    Editor,
+    Settings,
+    Quit,
+}
+
+/// The screen shown when no mode is given on the command line, text fields for the name,
+/// address and game file above a button per way of playing. The game file is a PGN to replay
+/// or a game saved with Ctrl+S to load.
+pub struct MainMenu {
+    pub name: String,
+    pub address: String,
+    pub game_file: String,
+    /// A FEN pasted with Ctrl+V for a new game to start from, Ctrl+X drops it again.
+    pub position: Option<String>,
+    /// Field that typing goes into.
+    focus: Option<Field>,
+    /// Where in the focused field typing goes, in characters from the start.
+    cursor: usize,
+    /// Why the last choice couldn't be started, shown below the buttons.
+    pub message: Option<String>,
+}
+
+impl MainMenu {
+    const FIELDS: [Field; 3] = [Field::Name, Field::Address, Field::GameFile];
+    const CHOICES: [(Choice, &'static str); 9] = [
+        (Choice::Host, "Host game"),
+        (Choice::Join, "Join game"),
+        (Choice::Local, "Local game"),
+        (Choice::Computer, "vs AI"),
+        (Choice::Replay, "Replay"),
+        (Choice::Load, "Load game"),
+        (Choice::Editor, "Set up position"),
+        (Choice::Settings, "Settings"),
+        (Choice::Quit, "Quit"),
+    ];
+    const WIDTH: f32 = 560.0;
+    const FIELD_HEIGHT: f32 = 56.0;
+    const BUTTON_HEIGHT: f32 = 52.0;
+    const PAD: f32 = 16.0;
+    const TOP: f32 = 120.0;
+    const FONT_SIZE: i32 = 28;
+    const MAX_LENGTH: usize = 64;
+
+    /// Starts out with the name and address from the settings file.
+    pub fn new(config: &Config) -> MainMenu {
+        MainMenu {
+            name: config.name.clone().unwrap_or_default(),
+            address: config.address.clone().unwrap_or_default(),
+            game_file: String::new(),
+            position: None,
+            focus: None,
+            cursor: 0,
+            message: None,
+        }
+    }
+
+    /// Byte offset of the cursor into `text`, for editing it.
+    fn cursor_offset(text: &str, cursor: usize) -> usize {
+        text.char_indices()
+            .nth(cursor)
+            .map_or(text.len(), |(i, _)| i)
+    }
+
+    /// Moves typing to `field`, with the cursor at `cursor` or the end of the text.
+    fn focus(&mut self, field: Option<Field>, cursor: Option<usize>) {
+        self.focus = field;
+        let length = field.map_or(0, |field| self.text(field).chars().count());
+        self.cursor = cursor.unwrap_or(length).min(length);
+    }
+
+    /// The cursor position closest to `x` pixels into the text of `field`.
+    fn cursor_at(&self, rl: &RaylibHandle, field: Field, x: f32) -> usize {
+        let text = self.text(field);
+        let prefix = format!("{}: ", Self::label(field));
+        let offset = |n: usize| {
+            let end = Self::cursor_offset(text, n);
+            rl.measure_text(&format!("{}{}", prefix, &text[..end]), Self::FONT_SIZE) as f32
+        };
+        (0..=text.chars().count())
+            .min_by(|&a, &b| (offset(a) - x).abs().total_cmp(&(offset(b) - x).abs()))
+            .unwrap_or(0)
+    }
+
+    fn left() -> f32 {
+        let area = coords::board_area();
+        area.x + (area.width - Self::WIDTH) / 2.0
+    }
+
+    fn field(i: usize) -> Rectangle {
+        Rectangle::new(
+            Self::left(),
+            Self::TOP + i as f32 * (Self::FIELD_HEIGHT + Self::PAD),
+            Self::WIDTH,
+            Self::FIELD_HEIGHT,
+        )
+    }
+
+    fn button(i: usize) -> Rectangle {
+        let top = Self::TOP + Self::FIELDS.len() as f32 * (Self::FIELD_HEIGHT + Self::PAD);
+        Rectangle::new(
+            Self::left(),
+            top + Self::PAD + i as f32 * (Self::BUTTON_HEIGHT + Self::PAD),
+            Self::WIDTH,
+            Self::BUTTON_HEIGHT,
+        )
+    }
+
+    fn text(&self, field: Field) -> &String {
+        match field {
+            Field::Name => &self.name,
+            Field::Address => &self.address,
+            Field::GameFile => &self.game_file,
+        }
+    }
+
+    fn text_mut(&mut self, field: Field) -> &mut String {
+        match field {
+            Field::Name => &mut self.name,
+            Field::Address => &mut self.address,
+            Field::GameFile => &mut self.game_file,
+        }
+    }
+
+    fn label(field: Field) -> &'static str {
+        match field {
+            Field::Name => "Name",
+            Field::Address => "Address",
+            Field::GameFile => "Game file",
+        }
+    }
+
+    /// Handles typing and clicks, returning the button that was clicked if any. Typing goes in
+    /// at the cursor, which the arrow keys, Home and End move, and Backspace and Delete take out
+    /// the character before and after it. Pasting a FEN sets the position to start from, anything
+    /// else goes into the field like typing.
+    pub fn update(&mut self, rl: &mut RaylibHandle) -> Option<Choice> {
+        let ctrl = rl.is_key_down(KeyboardKey::KEY_LEFT_CONTROL)
+            || rl.is_key_down(KeyboardKey::KEY_RIGHT_CONTROL);
+        let mut pasted = String::new();
+        if ctrl && rl.is_key_pressed(KeyboardKey::KEY_V) {
+            match rl.get_clipboard_text() {
+                Ok(text) if fen::parse(text.trim()).is_ok() => {
+                    log::info!("starting from {}", text.trim());
+                    self.position = Some(text.trim().to_string());
+                    self.message = None;
+                }
+                Ok(text) => pasted = text,
+                Err(e) => log::error!("could not paste: {}", e),
+            }
+        }
+        if ctrl && rl.is_key_pressed(KeyboardKey::KEY_X) {
+            self.position = None;
+        }
+
+        if rl.is_key_pressed(KeyboardKey::KEY_TAB) {
+            let next = self
+                .focus
+                .and_then(|focus| Self::FIELDS.iter().position(|&f| f == focus))
+                .map_or(0, |i| (i + 1) % Self::FIELDS.len());
+            self.focus(Some(Self::FIELDS[next]), None);
+        }
+
+        if let Some(focus) = self.focus {
+            let key = |key| rl.is_key_pressed(key) || rl.is_key_pressed_repeat(key);
+            let length = self.text(focus).chars().count();
+            if key(KeyboardKey::KEY_LEFT) {
+                self.cursor = self.cursor.saturating_sub(1);
+            }
+            if key(KeyboardKey::KEY_RIGHT) {
+                self.cursor = (self.cursor + 1).min(length);
+            }
+            if key(KeyboardKey::KEY_HOME) {
+                self.cursor = 0;
+            }
+            if key(KeyboardKey::KEY_END) {
+                self.cursor = length;
+            }
+            let backspace = key(KeyboardKey::KEY_BACKSPACE);
+            let delete = key(KeyboardKey::KEY_DELETE);
+
+            let mut cursor = self.cursor;
+            let text = self.text_mut(focus);
+            if backspace && cursor > 0 {
+                cursor -= 1;
+                text.remove(Self::cursor_offset(text, cursor));
+            }
+            if delete && cursor < text.chars().count() {
+                text.remove(Self::cursor_offset(text, cursor));
+            }
+            let typed = std::iter::from_fn(|| rl.get_char_pressed());
+            for c in pasted.chars().chain(typed) {
+                if !c.is_control() && text.chars().count() < Self::MAX_LENGTH {
+                    text.insert(Self::cursor_offset(text, cursor), c);
+                    cursor += 1;
+                }
+            }
+            self.cursor = cursor;
+        }
+
+        if !rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT) {
+            return None;
+        }
+
+        let mouse = coords::mouse_position(rl);
+        // Clicking anywhere but a field stops typing into the one that had focus, clicking into
+        // one puts the cursor where it was clicked.
+        let clicked = (0..Self::FIELDS.len())
+            .find(|&i| Self::field(i).check_collision_point_rec(mouse))
+            .map(|i| (Self::FIELDS[i], mouse.x - Self::field(i).x - Self::PAD));
+        let cursor = clicked.map(|(field, x)| self.cursor_at(rl, field, x));
+        self.focus(clicked.map(|(field, _)| field), cursor);
+
+        (0..Self::CHOICES.len())
+            .find(|&i| Self::button(i).check_collision_point_rec(mouse))
+            .map(|i| Self::CHOICES[i].0)
+    }
+
+    /// Keeps the name and address for next time, they're what the settings file holds.
+    pub fn remember(&self, config: &mut Config) {
+        let name = Some(self.name.trim().to_string()).filter(|name| !name.is_empty());
+        let address = Some(self.address.trim().to_string()).filter(|a| !a.is_empty());
+        if config.name == name && config.address == address {
+            return;
+        }
+
+        config.name = name;
+        config.address = address;
+        if let Err(e) = config.save() {
+            log::error!("could not save the settings to {}: {}", config::PATH, e);
+        }
+    }
+
+    pub fn draw(&self, d: &mut RaylibDrawHandle) {
+        let title = "Chess";
+        let area = coords::board_area();
+        let length = d.measure_text(title, 72);
+        d.draw_text(
+            title,
+            (area.x + (area.width - length as f32) / 2.0) as i32,
+            24,
+            72,
+            Color::RAYWHITE,
+        );
+
+        let text_y = |rect: Rectangle| rect.y + (rect.height - Self::FONT_SIZE as f32) / 2.0;
+
+        for (i, &field) in Self::FIELDS.iter().enumerate() {
+            let rect = Self::field(i);
+            let focused = self.focus == Some(field);
+            let background = if focused { 0xffffffff } else { 0xc8c8c8ff };
+            d.draw_rectangle_rounded(rect, 0.3, 8, Color::get_color(background));
+
+            let text = format!("{}: {}", Self::label(field), self.text(field));
+            d.draw_text(
+                &text,
+                (rect.x + Self::PAD) as i32,
+                text_y(rect) as i32,
+                Self::FONT_SIZE,
+                Color::BLACK,
+            );
+
+            if focused {
+                let before =
+                    &self.text(field)[..Self::cursor_offset(self.text(field), self.cursor)];
+                let prefix = format!("{}: {}", Self::label(field), before);
+                let x = rect.x + Self::PAD + d.measure_text(&prefix, Self::FONT_SIZE) as f32;
+                d.draw_rectangle(
+                    x as i32,
+                    text_y(rect) as i32,
+                    2,
+                    Self::FONT_SIZE,
+                    Color::BLACK,
+                );
+            }
+        }
+
+        for (i, (_, label)) in Self::CHOICES.iter().enumerate() {
+            let rect = Self::button(i);
+            d.draw_rectangle_rounded(rect, 0.3, 8, Color::RAYWHITE);
+
+            let length = d.measure_text(label, Self::FONT_SIZE);
+            d.draw_text(
+                label,
+                (rect.x + (rect.width - length as f32) / 2.0) as i32,
+                text_y(rect) as i32,
+                Self::FONT_SIZE,
+                Color::BLACK,
+            );
+        }
+
+        if self.position.is_some() {
+            let note = "Starting from the pasted position, Ctrl+X to drop it";
+            let length = d.measure_text(note, 20);
+            d.draw_text(
+                note,
+                (area.x + (area.width - length as f32) / 2.0) as i32,
+                (Self::TOP - 24.0) as i32,
+                20,
+                Color::LIGHTGRAY,
+            );
+        }
+
+        if let Some(message) = &self.message {
+            let rect = Self::button(Self::CHOICES.len());
+            let length = d.measure_text(message, Self::FONT_SIZE);
+            d.draw_text(
+                message,
+                (area.x + (area.width - length as f32) / 2.0) as i32,
+                text_y(rect) as i32,
+                Self::FONT_SIZE,
+                Color::get_color(0xe02828ff),
+            );
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use raylib::prelude::*;
+
+/// The running score of a best-of-N match against the same opponent, who swaps colors with us
+/// after every game.
+pub struct MatchScore {
+    games: u32,
+    /// Ours first, counted in halves so a draw is one each.
+    half_points: [u32; 2],
+    played: u32,
+}
+
+/// Points the way they're written on a crosstable, `1½` or `½`.
+fn points(half_points: u32) -> String {
+    match (half_points / 2, half_points % 2) {
+        (0, 1) => "½".to_string(),
+        (whole, 1) => format!("{}½", whole),
+        (whole, _) => whole.to_string(),
+    }
+}
+
+impl MatchScore {
+    const WIDTH: f32 = 560.0;
+    const FONT_SIZE: i32 = 32;
+    const PAD: f32 = 16.0;
+
+    pub fn new(games: u32) -> MatchScore {
+        MatchScore {
+            games,
+            half_points: [0, 0],
+            played: 0,
+        }
+    }
+
+    /// Counts a finished game, `result` being its PGN result and `white` whether we had white.
+    /// A game without a result doesn't count.
+    pub fn record(&mut self, result: &str, white: bool) {
+        let ours = match (result, white) {
+            ("1-0", true) | ("0-1", false) => 2,
+            ("1-0", false) | ("0-1", true) => 0,
+            ("1/2-1/2", _) => 1,
+            _ => return,
+        };
+        self.half_points[0] += ours;
+        self.half_points[1] += 2 - ours;
+        self.played += 1;
+    }
+
+    /// Whether the match is over, every game played or one side out of reach.
+    pub fn decided(&self) -> bool {
+        self.played >= self.games || self.half_points.iter().any(|&half| half > self.games)
+    }
+
+    /// The score with ours first, as in `1½-½`.
+    pub fn score(&self) -> String {
+        format!(
+            "{}-{}",
+            points(self.half_points[0]),
+            points(self.half_points[1])
+        )
+    }
+
+    /// The scoreboard shown between games, in the bottom part of `area` below the menu.
+    /// `next_in` is how many seconds are left until the next game starts.
+    pub fn draw(
+        &self,
+        d: &mut RaylibDrawHandle,
+        area: Rectangle,
+        ours: &str,
+        theirs: &str,
+        next_in: f32,
+    ) {
+        let line_height = Self::FONT_SIZE as f32 + Self::PAD;
+        let rect = Rectangle::new(
+            area.x + (area.width - Self::WIDTH) / 2.0,
+            area.y + area.height * 0.75,
+            Self::WIDTH,
+            3.0 * line_height + Self::PAD,
+        );
+        d.draw_rectangle_rounded(rect, 0.2, 8, Color::get_color(0x00_00_00_99));
+
+        // The default font has no ½.
+        let [ours_points, theirs_points] = self.half_points.map(|half| half as f32 / 2.0);
+        let status = if !self.decided() {
+            format!(
+                "Game {} of {} in {}",
+                self.played + 1,
+                self.games,
+                next_in.ceil().max(0.0)
+            )
+        } else if self.half_points[0] > self.half_points[1] {
+            "Match won".to_string()
+        } else if self.half_points[0] < self.half_points[1] {
+            "Match lost".to_string()
+        } else {
+            "Match drawn".to_string()
+        };
+        let lines = [
+            (format!("Best of {}", self.games), Color::LIGHTGRAY),
+            (
+                format!("{} {} - {} {}", ours, ours_points, theirs_points, theirs),
+                Color::RAYWHITE,
+            ),
+            (status, Color::get_color(0xf5f580ff)),
+        ];
+        for (i, (text, color)) in lines.iter().enumerate() {
+            let length = d.measure_text(text, Self::FONT_SIZE) as f32;
+            d.draw_text(
+                text,
+                (rect.x + (rect.width - length) / 2.0) as i32,
+                (rect.y + Self::PAD + i as f32 * line_height) as i32,
+                Self::FONT_SIZE,
+                *color,
+            );
+        }
+    }
+}
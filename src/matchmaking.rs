@@ -0,0 +1,230 @@
+use crate::log;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Port a lobby listens on when its address doesn't name one.
+pub const DEFAULT_LOBBY_PORT: u16 = 8385;
+/// How long the lobby gets to answer before we give up on it.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A hosted game waiting for an opponent, as the lobby lists it.
+pub struct OpenGame {
+    pub id: u64,
+    /// Where to connect to play it, the host's address as the lobby saw it.
+    pub address: String,
+    pub name: String,
+}
+
+/// A game we host kept on the lobby's list, until this is dropped and the lobby sees the
+/// connection close.
+pub struct Listing {
+    _stream: TcpStream,
+    pub id: u64,
+}
+
+/// Connects to `lobby` and sends it `line`. The lobby protocol is a line of text each way, so
+/// the lobby server can be written in anything, `serve` is ours.
+///
+/// - `list` is answered with a `game <id> <address> <name>` line per open game and `end`.
+/// - `host <port> <name>` puts a game hosted on `port` at our address on the list, answered with
+///   `ok <id>`. It stays there for as long as the connection is kept open.
+///
+/// Anything the lobby can't do is answered with `error <reason>`.
+fn request(lobby: &str, line: &str) -> std::io::Result<(TcpStream, BufReader<TcpStream>)> {
+    let mut stream = TcpStream::connect(lobby)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    stream.write_all(format!("{}\n", line).as_bytes())?;
+    let reader = BufReader::new(stream.try_clone()?);
+    Ok((stream, reader))
+}
+
+fn invalid(message: impl Into<String>) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.into())
+}
+
+/// The next line from the lobby, with an `error` from it turned into one of ours.
+fn read_line(reader: &mut BufReader<TcpStream>) -> std::io::Result<String> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::ConnectionAborted,
+            "the lobby closed the connection",
+        ));
+    }
+
+    let line = line.trim_end().to_string();
+    match line.strip_prefix("error ") {
+        Some(reason) => Err(std::io::Error::other(format!("the lobby says {}", reason))),
+        None => Ok(line),
+    }
+}
+
+/// The games open at `lobby`.
+pub fn list(lobby: &str) -> std::io::Result<Vec<OpenGame>> {
+    let (_stream, mut reader) = request(lobby, "list")?;
+
+    let mut games = Vec::new();
+    loop {
+        let line = read_line(&mut reader)?;
+        if line == "end" {
+            return Ok(games);
+        }
+
+        let mut fields = line.splitn(4, ' ');
+        let game = match (fields.next(), fields.next(), fields.next(), fields.next()) {
+            (Some("game"), Some(id), Some(address), name) => OpenGame {
+                id: id.parse().map_err(|_| invalid("unreadable game id"))?,
+                address: address.to_string(),
+                name: name.unwrap_or("?").to_string(),
+            },
+            _ => return Err(invalid(format!("unexpected line from the lobby: {}", line))),
+        };
+        games.push(game);
+    }
+}
+
+/// Puts the game we're about to host on `port` on the lobby's list under `name`.
+pub fn host(lobby: &str, port: u16, name: &str) -> std::io::Result<Listing> {
+    // Names are the last field, but can't span lines.
+    let name = name.replace(['\n', '\r'], " ");
+    let (stream, mut reader) = request(lobby, &format!("host {} {}", port, name))?;
+
+    let line = read_line(&mut reader)?;
+    let id = line
+        .strip_prefix("ok ")
+        .and_then(|id| id.parse().ok())
+        .ok_or_else(|| invalid(format!("unexpected line from the lobby: {}", line)))?;
+    // The listing is kept by leaving the connection open, nothing more is read from it.
+    stream.set_read_timeout(None)?;
+    Ok(Listing {
+        _stream: stream,
+        id,
+    })
+}
+
+/// The games a lobby we run lists, and the id the next one gets.
+#[derive(Default)]
+struct Lobby {
+    games: Vec<OpenGame>,
+    next_id: u64,
+}
+
+/// Runs a lobby on `listener`, answering each connection on a thread of its own the way
+/// `request` describes. Only returns if the listener fails.
+pub fn serve(listener: TcpListener) -> std::io::Result<()> {
+    let lobby = Arc::new(Mutex::new(Lobby::default()));
+    loop {
+        let (stream, peer) = listener.accept()?;
+        let lobby = Arc::clone(&lobby);
+        std::thread::spawn(move || {
+            if let Err(e) = answer(stream, &lobby) {
+                log::error!("lobby request from {} failed: {}", peer, e);
+            }
+        });
+    }
+}
+
+/// Answers the one request on `stream`. A hosted game is listed until the host hangs up.
+fn answer(mut stream: TcpStream, lobby: &Mutex<Lobby>) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let line = line.trim_end();
+    let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+    let locked = || {
+        lobby
+            .lock()
+            .map_err(|_| std::io::Error::other("the lobby crashed"))
+    };
+
+    match command {
+        "list" => {
+            let mut reply = String::new();
+            for game in &locked()?.games {
+                reply.push_str(&format!(
+                    "game {} {} {}\n",
+                    game.id, game.address, game.name
+                ));
+            }
+            reply.push_str("end\n");
+            stream.write_all(reply.as_bytes())
+        }
+        "host" => {
+            let (port, name) = rest.split_once(' ').unwrap_or((rest, ""));
+            let Ok(port) = port.parse() else {
+                return stream.write_all(b"error unreadable port\n");
+            };
+            let address = SocketAddr::new(stream.peer_addr()?.ip(), port).to_string();
+            let id = {
+                let mut lobby = locked()?;
+                lobby.next_id += 1;
+                let id = lobby.next_id;
+                let name = if name.is_empty() { "?" } else { name };
+                lobby.games.push(OpenGame {
+                    id,
+                    address,
+                    name: name.to_string(),
+                });
+                id
+            };
+            log::info!("listed game {}", id);
+            let listed = stream.write_all(format!("ok {}\n", id).as_bytes());
+
+            // Nothing more comes from the host, reading only tells us when it hangs up.
+            if listed.is_ok() {
+                let _ = std::io::copy(&mut reader, &mut std::io::sink());
+            }
+            locked()?.games.retain(|game| game.id != id);
+            log::info!("unlisted game {}", id);
+            listed
+        }
+        _ => stream.write_all(format!("error unknown request {}\n", command).as_bytes()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_a_hosted_game_until_the_host_leaves() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let lobby = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || serve(listener));
+
+        let listing = host(&lobby, 8384, "Ada Lovelace").unwrap();
+        let games = list(&lobby).unwrap();
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].id, listing.id);
+        assert_eq!(games[0].address, "127.0.0.1:8384");
+        assert_eq!(games[0].name, "Ada Lovelace");
+
+        drop(listing);
+        let mut left = false;
+        for _ in 0..100 {
+            left = list(&lobby).unwrap().is_empty();
+            if left {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(left);
+    }
+
+    #[test]
+    fn refuses_what_it_cant_do() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let lobby = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || serve(listener));
+
+        let (_stream, mut reader) = request(&lobby, "join 1").unwrap();
+        let error = read_line(&mut reader).unwrap_err();
+        assert!(error.to_string().contains("unknown request join"));
+        assert!(request(&lobby, "host port")
+            .and_then(|(_stream, mut reader)| read_line(&mut reader))
+            .is_err());
+    }
+}
@@ -0,0 +1,108 @@
+use crate::{WINDOW_HEIGHT, WINDOW_WIDTH};
+use raylib::prelude::*;
+
+/// A text box for typing moves instead of clicking them, like `Nf3` or `e2e4`. Enter opens it
+/// and submits what was typed, backspacing past the start closes it again.
+pub struct MoveEntry {
+    /// What's been typed so far, `None` while the box is closed.
+    text: Option<String>,
+    /// Why the last move typed couldn't be played.
+    error: Option<String>,
+}
+
+impl MoveEntry {
+    const WIDTH: i32 = 360;
+    const FONT_SIZE: i32 = 32;
+    const PAD: i32 = 12;
+    const MAX_LENGTH: usize = 10;
+
+    pub fn new() -> MoveEntry {
+        MoveEntry {
+            text: None,
+            error: None,
+        }
+    }
+
+    /// Whether keys go into the box rather than being shortcuts.
+    pub fn is_open(&self) -> bool {
+        self.text.is_some()
+    }
+
+    /// Handles typing, returning the text once Enter is pressed on it.
+    pub fn update(&mut self, rl: &mut RaylibHandle) -> Option<String> {
+        let Some(text) = &mut self.text else {
+            if rl.is_key_pressed(KeyboardKey::KEY_ENTER) {
+                // Letters pressed as shortcuts are still queued up, they weren't meant for the box.
+                while rl.get_char_pressed().is_some() {}
+                self.text = Some(String::new());
+                self.error = None;
+            }
+            return None;
+        };
+
+        while let Some(c) = rl.get_char_pressed() {
+            if !c.is_whitespace() && !c.is_control() && text.len() < Self::MAX_LENGTH {
+                text.push(c);
+                self.error = None;
+            }
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_BACKSPACE) && text.pop().is_none() {
+            self.text = None;
+            self.error = None;
+            return None;
+        }
+
+        if !rl.is_key_pressed(KeyboardKey::KEY_ENTER) {
+            return None;
+        }
+        // Enter on an empty box just closes it.
+        let text = self.text.take().filter(|text| !text.is_empty())?;
+        self.text = Some(String::new());
+        Some(text)
+    }
+
+    /// Reports that the submitted move couldn't be played, keeping the box open for another try.
+    pub fn reject(&mut self, reason: String) {
+        self.error = Some(reason);
+    }
+
+    /// Closes the box once a typed move went through.
+    pub fn close(&mut self) {
+        self.text = None;
+        self.error = None;
+    }
+
+    /// The box at the bottom of the board with the error above it, if there is one.
+    pub fn draw(&self, d: &mut RaylibDrawHandle) {
+        let Some(text) = &self.text else {
+            return;
+        };
+
+        let height = Self::FONT_SIZE + 2 * Self::PAD;
+        let x = (WINDOW_WIDTH - Self::WIDTH) / 2;
+        let y = WINDOW_HEIGHT - height - Self::PAD;
+        d.draw_rectangle_rounded(
+            Rectangle::new(x as f32, y as f32, Self::WIDTH as f32, height as f32),
+            0.3,
+            8,
+            Color::get_color(0x00_00_00_cc),
+        );
+        d.draw_text(
+            &format!("Move: {}_", text),
+            x + Self::PAD,
+            y + Self::PAD,
+            Self::FONT_SIZE,
+            Color::RAYWHITE,
+        );
+
+        if let Some(error) = &self.error {
+            d.draw_text(
+                error,
+                x + Self::PAD,
+                y - Self::FONT_SIZE - Self::PAD,
+                Self::FONT_SIZE,
+                Color::get_color(0xe02828ff),
+            );
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use crate::eval::Position;
+use crate::{rules, ChessColor};
+
+/// What a move does, which decides how it sounds, slides and gets logged.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MoveKind {
+    Quiet,
+    Capture,
+    Castle,
+    EnPassant,
+    Promotion,
+    /// A pawn taking a piece on the last rank.
+    PromotionCapture,
+    /// An otherwise quiet move giving check. Any of the others can give check as well, but what
+    /// they do on the board counts for more.
+    Check,
+}
+
+impl MoveKind {
+    /// Castling or en passant if that's what moving from square `from` to `to` in `position`
+    /// has to be, going by the piece and where it goes, for checking the board got it right.
+    /// Chess960 castling is the king moving onto its own rook.
+    pub fn special(position: &Position, from: u32, to: u32) -> Option<MoveKind> {
+        let at = |s: u32| position[s as usize / 8][s as usize % 8];
+        let files = (from % 8).abs_diff(to % 8);
+        let onto_rook = at(to).eq_ignore_ascii_case(&'r')
+            && at(to).is_ascii_uppercase() == at(from).is_ascii_uppercase();
+        match at(from).to_ascii_lowercase() {
+            'k' if files == 2 || onto_rook => Some(MoveKind::Castle),
+            'p' if files == 1 && at(to) == '.' => Some(MoveKind::EnPassant),
+            _ => None,
+        }
+    }
+
+    /// The kind of move that led from `before` to `after`, `white` being whether white made it.
+    pub fn between(before: &Position, after: &Position, white: bool) -> MoveKind {
+        let ours = |c: char| c != '.' && c.is_ascii_uppercase() == white;
+        let theirs = |c: char| c != '.' && c.is_ascii_uppercase() != white;
+        let squares = || (0..8).flat_map(|y| (0..8).map(move |x| (y, x)));
+
+        let emptied: Vec<char> = squares()
+            .filter(|&(y, x)| ours(before[y][x]) && after[y][x] == '.')
+            .map(|(y, x)| before[y][x].to_ascii_lowercase())
+            .collect();
+        let filled: Vec<char> = squares()
+            .filter(|&(y, x)| ours(after[y][x]) && before[y][x] != after[y][x])
+            .map(|(y, x)| after[y][x].to_ascii_lowercase())
+            .collect();
+        // A piece taken en passant leaves its square empty, any other is replaced.
+        let taken: Vec<bool> = squares()
+            .filter(|&(y, x)| theirs(before[y][x]) && !theirs(after[y][x]))
+            .map(|(y, x)| after[y][x] == '.')
+            .collect();
+
+        let promotion = emptied == ['p'] && filled.first().is_some_and(|&c| c != 'p');
+        let side = if white {
+            ChessColor::Black
+        } else {
+            ChessColor::White
+        };
+        match taken.first() {
+            Some(_) if promotion => MoveKind::PromotionCapture,
+            Some(true) => MoveKind::EnPassant,
+            Some(false) => MoveKind::Capture,
+            None if promotion => MoveKind::Promotion,
+            // In Chess960 the king or the rook can end up where the other one was.
+            None if emptied.len() == 2 || filled.len() == 2 => MoveKind::Castle,
+            None if rules::in_check(after, side) => MoveKind::Check,
+            None => MoveKind::Quiet,
+        }
+    }
+}
+
+impl std::fmt::Display for MoveKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            MoveKind::Quiet => "a quiet move",
+            MoveKind::Capture => "a capture",
+            MoveKind::Castle => "castling",
+            MoveKind::EnPassant => "en passant",
+            MoveKind::Promotion => "a promotion",
+            MoveKind::PromotionCapture => "a promotion with a capture",
+            MoveKind::Check => "check",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen;
+
+    /// The kind of move between two positions given by their piece placement.
+    fn kind(before: &str, after: &str, white: bool) -> MoveKind {
+        let (before, _) = fen::parse(before).unwrap();
+        let (after, _) = fen::parse(after).unwrap();
+        MoveKind::between(&before, &after, white)
+    }
+
+    #[test]
+    fn tells_each_kind_apart() {
+        let cases = [
+            (
+                "4k3/8/8/8/8/8/4P3/4K3",
+                "4k3/8/8/8/4P3/8/8/4K3",
+                MoveKind::Quiet,
+            ),
+            (
+                "4k3/8/8/3p4/4P3/8/8/4K3",
+                "4k3/8/8/3P4/8/8/8/4K3",
+                MoveKind::Capture,
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K2R",
+                "4k3/8/8/8/8/8/8/5RK1",
+                MoveKind::Castle,
+            ),
+            (
+                "4k3/8/8/3pP3/8/8/8/4K3",
+                "4k3/8/3P4/8/8/8/8/4K3",
+                MoveKind::EnPassant,
+            ),
+            // Promoting counts for more than the check it gives.
+            (
+                "4k3/P7/8/8/8/8/8/4K3",
+                "Q3k3/8/8/8/8/8/8/4K3",
+                MoveKind::Promotion,
+            ),
+            (
+                "2k5/P7/8/8/8/8/8/4K3",
+                "N1k5/8/8/8/8/8/8/4K3",
+                MoveKind::Promotion,
+            ),
+            (
+                "1r2k3/P7/8/8/8/8/8/4K3",
+                "1Q2k3/8/8/8/8/8/8/4K3",
+                MoveKind::PromotionCapture,
+            ),
+            (
+                "4k3/8/8/8/8/8/8/R3K3",
+                "R3k3/8/8/8/8/8/8/4K3",
+                MoveKind::Check,
+            ),
+        ];
+        for (before, after, expected) in cases {
+            assert_eq!(
+                kind(before, after, true),
+                expected,
+                "{} to {}",
+                before,
+                after
+            );
+        }
+    }
+
+    #[test]
+    fn counts_chess960_castling_with_one_piece_staying_put() {
+        // The king on b1 lands on c1, the rook on a1 on d1.
+        let before = "4k3/8/8/8/8/8/8/RK6";
+        assert_eq!(kind(before, "4k3/8/8/8/8/8/8/2KR4", true), MoveKind::Castle);
+        // The king on d1 goes to c1 and the rook from a1 to where the king was.
+        let before = "4k3/8/8/8/8/8/8/R2K4";
+        assert_eq!(kind(before, "4k3/8/8/8/8/8/8/2KR4", true), MoveKind::Castle);
+    }
+
+    #[test]
+    fn spots_castling_and_en_passant_before_they_are_played() {
+        let (position, _) = fen::parse("4k3/8/8/3pP3/8/8/8/R3K2R w").unwrap();
+        let square = |name: &str| {
+            let bytes = name.as_bytes();
+            (b'8' - bytes[1]) as u32 * 8 + (bytes[0] - b'a') as u32
+        };
+        let special = |from, to| MoveKind::special(&position, square(from), square(to));
+        assert_eq!(special("e1", "g1"), Some(MoveKind::Castle));
+        assert_eq!(special("e1", "a1"), Some(MoveKind::Castle));
+        assert_eq!(special("e5", "d6"), Some(MoveKind::EnPassant));
+        assert_eq!(special("e5", "e6"), None);
+        assert_eq!(special("e1", "f1"), None);
+    }
+}
@@ -0,0 +1,139 @@
+use crate::crazyhouse::Pockets;
+use crate::network::format_move;
+use crate::{crazyhouse, log, move_string, new_board, pgn};
+use chess::ChessBoard;
+use chess_networking::Move;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Every move of a game as it's played, a line each in a file of its own, for tracking down
+/// desyncs and for tools looking at games afterwards. The file is only ever appended to, so a
+/// crash loses nothing that was already written.
+///
+/// It starts with `key value` lines like an adjourned game, followed by
+///
+/// - `move <time> <side> <san> <white clock> <black clock>`, the clocks being the seconds left
+///   or `-` without a time control,
+/// - `takeback <time> <side>` for each ply taken back,
+/// - `reconnected <time>` when the game is replayed from the start after a dropped connection,
+///
+/// with `<time>` the wall-clock time in seconds since the Unix epoch.
+pub struct MoveLog {
+    file: File,
+    /// The game as far as it's been logged, for the SAN of the next move.
+    board: ChessBoard,
+    fen: Option<String>,
+    logged: usize,
+}
+
+fn now() -> String {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    format!("{}.{:03}", time.as_secs(), time.subsec_millis())
+}
+
+impl MoveLog {
+    /// Starts the log of a game in `directory`, named after the time it started. `history` is
+    /// what was played before, as in a resumed game, which is listed but not logged again.
+    pub fn create(
+        directory: &str,
+        fen: Option<&str>,
+        history: &[Move],
+        white: &str,
+        black: &str,
+    ) -> std::io::Result<MoveLog> {
+        std::fs::create_dir_all(directory)?;
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let path = Path::new(directory).join(format!("game-{}.log", millis));
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        log::info!("logging moves to {}", path.display());
+
+        let mut move_log = MoveLog {
+            file,
+            board: new_board(fen),
+            fen: fen.map(|fen| fen.to_string()),
+            logged: 0,
+        };
+        let mut header = format!(
+            "started {}\nwhite_name {}\nblack_name {}\n",
+            now(),
+            white,
+            black
+        );
+        if let Some(fen) = fen {
+            header.push_str(&format!("fen {}\n", fen));
+        }
+        if !history.is_empty() {
+            let moves: Vec<String> = history.iter().map(format_move).collect();
+            header.push_str(&format!("moves {}\n", moves.join(" ")));
+            for m in history {
+                let m = move_string(&move_log.board, m);
+                crazyhouse::make_move(&mut move_log.board, m);
+            }
+            move_log.logged = history.len();
+        }
+        move_log.file.write_all(header.as_bytes())?;
+        Ok(move_log)
+    }
+
+    /// Logs whatever changed in `history` since the last call, `clock` being the seconds white
+    /// and black have left.
+    pub fn update(&mut self, history: &[Move], clock: Option<[f32; 2]>) -> std::io::Result<()> {
+        let mut lines = String::new();
+        while self.logged > history.len() {
+            self.board.undo_move();
+            self.logged -= 1;
+            lines.push_str(&format!("takeback {} {}\n", now(), self.side()));
+        }
+        for m in &history[self.logged..] {
+            let side = self.side();
+            let m = move_string(&self.board, m);
+            let pockets = self.fen.as_deref().and_then(Pockets::from_fen);
+            let san = pgn::san(&mut self.board, &m, pockets.as_ref());
+            crazyhouse::make_move(&mut self.board, m);
+            self.logged += 1;
+
+            let [white, black] = match clock {
+                Some(remaining) => remaining.map(|seconds| format!("{:.1}", seconds)),
+                None => ["-".to_string(), "-".to_string()],
+            };
+            lines.push_str(&format!(
+                "move {} {} {} {} {}\n",
+                now(),
+                side,
+                san,
+                white,
+                black
+            ));
+        }
+
+        if lines.is_empty() {
+            return Ok(());
+        }
+        self.file.write_all(lines.as_bytes())
+    }
+
+    /// Starts over from the initial position, for a game that's about to be replayed move by
+    /// move.
+    pub fn restart(&mut self) -> std::io::Result<()> {
+        self.board = new_board(self.fen.as_deref());
+        self.logged = 0;
+        self.file
+            .write_all(format!("reconnected {}\n", now()).as_bytes())
+    }
+
+    /// Whose move is next, which is also whose move was just taken back.
+    fn side(&self) -> &'static str {
+        if self.board.white_move {
+            "white"
+        } else {
+            "black"
+        }
+    }
+}
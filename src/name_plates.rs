@@ -0,0 +1,72 @@
+use crate::{ChessColor, WINDOW_HEIGHT};
+use raylib::prelude::*;
+
+/// Each player's name in the corner at their end of the board, across from their clock, with a
+/// swatch of their color. The board starts out turned towards us, so that's our name at the
+/// bottom and the opponent's at the top, and turning it takes the names along.
+pub struct NamePlates;
+
+impl NamePlates {
+    const FONT_SIZE: i32 = 32;
+    const PAD: i32 = 10;
+    const SWATCH_SIZE: i32 = 24;
+    /// How far down the top plate reaches, anything else in that corner goes below it.
+    pub const BOTTOM: i32 = Self::FONT_SIZE + 3 * Self::PAD;
+
+    /// `names` are white's and black's, `?` for one we don't know.
+    pub fn draw(d: &mut RaylibDrawHandle, names: (&str, &str), to_move: ChessColor, flipped: bool) {
+        for side in [ChessColor::White, ChessColor::Black] {
+            let name = match (side, names) {
+                (ChessColor::White, ("?", _)) => "White",
+                (ChessColor::Black, (_, "?")) => "Black",
+                (ChessColor::White, (name, _)) => name,
+                (ChessColor::Black, (_, name)) => name,
+            };
+
+            let length = d.measure_text(name, Self::FONT_SIZE);
+            let width = Self::SWATCH_SIZE + length + 3 * Self::PAD;
+            let height = Self::FONT_SIZE + 2 * Self::PAD;
+            let x = Self::PAD;
+            let at_bottom = (side == ChessColor::White) != flipped;
+            let y = if at_bottom {
+                WINDOW_HEIGHT - height - Self::PAD
+            } else {
+                Self::PAD
+            };
+
+            // The side to move stands out, same as its clock.
+            let background = if side == to_move {
+                Color::get_color(0x00_00_00_cc)
+            } else {
+                Color::get_color(0x00_00_00_66)
+            };
+            d.draw_rectangle_rounded(
+                Rectangle::new(x as f32, y as f32, width as f32, height as f32),
+                0.3,
+                8,
+                background,
+            );
+
+            let swatch = Rectangle::new(
+                (x + Self::PAD) as f32,
+                (y + (height - Self::SWATCH_SIZE) / 2) as f32,
+                Self::SWATCH_SIZE as f32,
+                Self::SWATCH_SIZE as f32,
+            );
+            let color = match side {
+                ChessColor::White => Color::RAYWHITE,
+                ChessColor::Black => Color::BLACK,
+            };
+            d.draw_rectangle_rec(swatch, color);
+            d.draw_rectangle_lines_ex(swatch, 2.0, Color::GRAY);
+
+            d.draw_text(
+                name,
+                x + Self::SWATCH_SIZE + 2 * Self::PAD,
+                y + Self::PAD,
+                Self::FONT_SIZE,
+                Color::RAYWHITE,
+            );
+        }
+    }
+}
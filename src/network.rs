@@ -1,147 +1,1302 @@
+use crate::log;
+use crate::tls::{Tls, TlsStream};
+use crate::websocket::WebSocketStream;
 use chess_networking::*;
 use std::io::prelude::*;
-use std::net::{TcpListener, TcpStream};
+use std::net::{IpAddr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How long a reconnecting peer gets to finish the resume handshake.
+const RESUME_TIMEOUT: Duration = Duration::from_secs(5);
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(500);
+/// How often the thread letting people in checks for new connections and whether it should stop.
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(50);
 
 pub trait ChessProtocol {
     fn set_blocking(&mut self, block: bool) -> std::io::Result<()>;
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> std::io::Result<()>;
     fn handle_setup(&mut self, desired_start: Start) -> std::io::Result<Start>;
-    fn send_move(&mut self, m: Move) -> std::io::Result<()>;
-    fn receive_move(&mut self) -> std::io::Result<Option<Move>>;
-    fn receive_ack(&mut self) -> std::io::Result<Option<Ack>>;
-    fn send_ack(&mut self, ack: Ack) -> std::io::Result<()>;
+    /// The name the opponent sent in their `Start`, known once the setup is done.
+    fn opponent_name(&self) -> Option<&str> {
+        None
+    }
+    /// Agrees on the seed for anything random right after the setup. The server's seed is the
+    /// one both sides end up with, so they make the same random choices.
+    fn share_seed(&mut self, seed: u64) -> std::io::Result<u64>;
+    /// Agrees on how many games the match is, right after the seed. The server's count is the
+    /// one played, 1 being a single game.
+    fn share_match(&mut self, games: u32) -> std::io::Result<u32>;
+    /// Whether the peer sends a `Packet::Checksum` after accepting our move.
+    fn exchanges_checksums(&self) -> bool;
+
+    /// Sends `packet`, kinds the other side can't take are dropped.
+    fn send(&mut self, packet: Packet) -> std::io::Result<()>;
+    /// The next packet from the peer, `None` until a whole one has arrived. Every kind comes
+    /// through here, so it's up to the caller to handle the ones it expects and nothing gets
+    /// read as the wrong kind.
+    fn poll(&mut self) -> std::io::Result<Option<Packet>>;
+
+    /// Tries to get a dropped connection back, returns `Ok(true)` once the game can continue.
+    ///
+    /// Resuming works like the normal setup except that the client answers the server's `Start`
+    /// with an `Ack`, after which the server replays every move of the game. Each replayed move
+    /// is acknowledged just like a regular one, so the client can simply rebuild its board from
+    /// the initial position through its normal move handling.
+    fn reconnect(&mut self, start: &Start, history: &[Move]) -> std::io::Result<bool>;
+
+    /// Lets in anyone who connected to watch and brings every spectator up to date with
+    /// `history`, the moves both players have agreed on. Only a server has spectators.
+    fn update_spectators(&mut self, _start: &Start, _history: &[Move]) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    /// Flushes anything still buffered and shuts the connection down. Closing an already closed
+    /// connection does nothing.
+    fn close(&mut self) -> std::io::Result<()>;
+}
+
+/// Parses the coordinate form written by `format_move`.
+pub fn parse_move(s: &str) -> Option<Move> {
+    if s == "resign" || s == "draw" {
+        return Some(Move {
+            from: (0, 0),
+            to: (0, 0),
+            promotion: None,
+            forfeit: s == "resign",
+            offer_draw: s == "draw",
+        });
+    }
+
+    let bytes = s.as_bytes();
+    if bytes.len() != 4 && bytes.len() != 5 {
+        return None;
+    }
+
+    let file = |b: u8| (b'a'..=b'h').contains(&b).then(|| b - b'a');
+    let rank = |b: u8| (b'1'..=b'8').contains(&b).then(|| b - b'1');
+    let promotion = match bytes.get(4) {
+        None => None,
+        Some(b'q') => Some(PromotionPiece::Queen),
+        Some(b'r') => Some(PromotionPiece::Rook),
+        Some(b'b') => Some(PromotionPiece::Bishop),
+        Some(b'n') => Some(PromotionPiece::Knight),
+        Some(_) => return None,
+    };
+
+    Some(Move {
+        from: (file(bytes[0])?, rank(bytes[1])?),
+        to: (file(bytes[2])?, rank(bytes[3])?),
+        promotion,
+        forfeit: false,
+        offer_draw: false,
+    })
+}
+
+/// Coordinate form of a move, `e2e4` or `e7e8q`, `resign` for a forfeit and `draw` for a draw
+/// offer.
+pub fn format_move(m: &Move) -> String {
+    if m.forfeit {
+        return "resign".to_string();
+    }
+    if m.offer_draw {
+        return "draw".to_string();
+    }
+
+    let mut s = String::new();
+    s.push((b'a' + m.from.0) as char);
+    s.push((b'1' + m.from.1) as char);
+    s.push((b'a' + m.to.0) as char);
+    s.push((b'1' + m.to.1) as char);
+    if let Some(promotion) = &m.promotion {
+        s.push(match promotion {
+            PromotionPiece::Queen => 'q',
+            PromotionPiece::Rook => 'r',
+            PromotionPiece::Bishop => 'b',
+            PromotionPiece::Knight => 'n',
+        });
+    }
+
+    s
+}
+
+/// Which version of our protocol we speak. It's not the bare chess-networking one: that crate
+/// only encodes `Start`, `Move` and `Ack`, and everything else a game needs is ours. This is the
+/// one place describing what goes over the wire, so bump the version along with any change to
+/// it and both sides will refuse to play rather than misread each other.
+///
+/// - Every packet is a frame, see `write_packet`: the length of the rest as a big endian `u32`
+///   of at most `MAX_FRAME_LEN`, a byte with the kind of packet, then the packet itself.
+/// - `Start`, `Move` and `Ack` are encoded by chess-networking, `Checksum` and `Seed` are big
+///   endian `u64`s, `Match` a big endian `u32`, `Chat` UTF-8 text and the rest carry nothing.
+/// - The first frame either side sends is `Packet::Hello` with this version, and neither reads
+///   anything else before the other's has checked out.
+/// - A `Start`'s FEN may have a seventh field naming the variant, see `Variant::tag`, and the
+///   Crazyhouse pockets in brackets after the piece placement, see `fen::pockets`.
+/// - A Crazyhouse drop is a `Move` from and to the same square with the piece as the promotion,
+///   none for a pawn. Chess960 castling moves the king onto its own rook.
+pub const PROTOCOL_VERSION: u16 = 1;
+
+/// Largest frame we accept. The length prefix comes from the peer, so anything bigger is
+/// treated as a protocol error instead of allocating whatever it asks for.
+const MAX_FRAME_LEN: usize = 64 * 1024;
+const HEADER_LEN: usize = 4;
+
+/// Everything the peer sends is checked before it's used, and whatever doesn't hold up comes
+/// back as an `InvalidData` error so `NetworkError` can tell it apart.
+fn protocol_error(message: impl Into<String>) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.into())
+}
+
+/// Why the connection failed, sorted by what can be done about it. Only a peer that broke the
+/// protocol isn't worth reconnecting to, it would just do the same again.
+#[derive(Debug)]
+pub enum NetworkError {
+    /// The peer went away or the connection dropped.
+    Closed,
+    /// The peer didn't answer in time.
+    TimedOut,
+    /// The peer sent something that isn't a valid packet.
+    Malformed,
+    Io(std::io::Error),
+}
+
+impl NetworkError {
+    pub fn is_malformed(&self) -> bool {
+        matches!(self, NetworkError::Malformed)
+    }
+}
+
+impl From<std::io::Error> for NetworkError {
+    fn from(e: std::io::Error) -> NetworkError {
+        match e.kind() {
+            std::io::ErrorKind::InvalidData => NetworkError::Malformed,
+            std::io::ErrorKind::ConnectionAborted
+            | std::io::ErrorKind::ConnectionReset
+            | std::io::ErrorKind::BrokenPipe
+            | std::io::ErrorKind::UnexpectedEof
+            | std::io::ErrorKind::NotConnected => NetworkError::Closed,
+            std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock => NetworkError::TimedOut,
+            _ => NetworkError::Io(e),
+        }
+    }
+}
+
+/// Short enough for an overlay, the details go to the log.
+impl std::fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NetworkError::Closed => write!(f, "Opponent closed the connection"),
+            NetworkError::TimedOut => write!(f, "Opponent stopped answering"),
+            NetworkError::Malformed => write!(f, "Opponent broke the protocol"),
+            NetworkError::Io(e) => write!(f, "Network error, {}", e.kind()),
+        }
+    }
+}
+
+/// Every packet goes over the wire as a frame: the length of the rest as a big endian `u32`, a
+/// byte saying which kind of packet it is and then the packet itself.
+fn write_packet(stream: &mut impl Write, packet: &Packet) -> std::io::Result<()> {
+    let bytes = packet.encode()?;
+    if bytes.len() > MAX_FRAME_LEN {
+        return Err(protocol_error(format!(
+            "packet of {} bytes is larger than the {} byte limit",
+            bytes.len(),
+            MAX_FRAME_LEN
+        )));
+    }
+
+    let mut frame = Vec::with_capacity(HEADER_LEN + bytes.len());
+    frame.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&bytes);
+    stream.write_all(&frame)
+}
+
+/// How long a spectator too slow to keep up may hold up a write before it's dropped.
+const SPECTATOR_WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+/// Most bytes taken off the socket in one go.
+const READ_CHUNK: usize = 4096;
+
+/// Longest chat message we send or accept, in bytes.
+pub const MAX_CHAT_LEN: usize = 200;
+
+/// Everything the two sides send each other, and what a server sends its spectators.
+#[derive(Clone)]
+pub enum Packet {
+    /// The client's wishes for the game, answered with the server's terms.
+    Start(Start),
+    /// A regular move, resigning and draw offers have kinds of their own.
+    Move(Move),
+    /// Answers a move, a draw offer or a request.
+    Ack(Ack),
+    /// Offers a draw, or claims one if the position allows it.
+    DrawOffer,
+    Resign,
+    /// Follows an `Ack` accepting a move with a checksum of the position the move led to, so the
+    /// mover can tell if the two boards have drifted apart.
+    Checksum(u64),
+    /// The server's seed, see `ChessProtocol::share_seed`.
+    Seed(u64),
+    /// Asks the opponent to take back our last move along with their reply. A spectator gets
+    /// one for each ply taken back.
+    TakebackRequest,
+    /// Asks the opponent to adjourn the game so it can be finished later.
+    AdjournRequest,
+    /// Asks the opponent for another game with the colors swapped once this one is over.
+    RematchRequest,
+    /// Opens a spectator's connection, in place of the `Start` a player opens with.
+    Spectate,
+    /// A line of chat from one player to the other.
+    Chat(String),
+    /// The number of games in the match, see `ChessProtocol::share_match`.
+    Match(u32),
+    /// Opens every connection, see `PROTOCOL_VERSION`.
+    Hello(u16),
+    /// Says our own time ran out. Each side's clock only counts for its own time, see
+    /// `Clock::judging`, so this is how the opponent learns the game was lost on time.
+    Flag,
+}
+
+impl Packet {
+    const START: u8 = 1;
+    const MOVE: u8 = 2;
+    const ACK: u8 = 3;
+    const DRAW_OFFER: u8 = 4;
+    const RESIGN: u8 = 5;
+    const CHECKSUM: u8 = 6;
+    const SEED: u8 = 7;
+    const TAKEBACK_REQUEST: u8 = 8;
+    const ADJOURN_REQUEST: u8 = 9;
+    const REMATCH_REQUEST: u8 = 10;
+    const SPECTATE: u8 = 11;
+    const CHAT: u8 = 12;
+    const MATCH: u8 = 13;
+    const FLAG: u8 = 14;
+    const HELLO: u8 = 15;
+
+    /// What kind of packet this is, for the log.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Packet::Start(_) => "start",
+            Packet::Move(_) => "move",
+            Packet::Ack(_) => "ack",
+            Packet::DrawOffer => "draw offer",
+            Packet::Resign => "resignation",
+            Packet::Checksum(_) => "checksum",
+            Packet::Seed(_) => "seed",
+            Packet::TakebackRequest => "takeback request",
+            Packet::AdjournRequest => "adjourn request",
+            Packet::RematchRequest => "rematch request",
+            Packet::Spectate => "spectate request",
+            Packet::Chat(_) => "chat",
+            Packet::Match(_) => "match length",
+            Packet::Flag => "flag",
+            Packet::Hello(_) => "hello",
+        }
+    }
+
+    fn encode(&self) -> std::io::Result<Vec<u8>> {
+        fn encoded<T>(kind: u8, packet: T) -> std::io::Result<Vec<u8>>
+        where
+            Vec<u8>: TryFrom<T>,
+        {
+            let encoded: Vec<u8> = packet
+                .try_into()
+                .map_err(|_| protocol_error("could not encode packet"))?;
+            let mut bytes = vec![kind];
+            bytes.extend_from_slice(&encoded);
+            Ok(bytes)
+        }
+        let with_u64 = |kind: u8, n: u64| {
+            let mut bytes = vec![kind];
+            bytes.extend_from_slice(&n.to_be_bytes());
+            Ok(bytes)
+        };
+
+        match self {
+            Packet::Start(start) => encoded(Self::START, start.clone()),
+            Packet::Move(m) => encoded(Self::MOVE, m.clone()),
+            Packet::Ack(ack) => encoded(Self::ACK, ack.clone()),
+            Packet::DrawOffer => Ok(vec![Self::DRAW_OFFER]),
+            Packet::Resign => Ok(vec![Self::RESIGN]),
+            Packet::Checksum(checksum) => with_u64(Self::CHECKSUM, *checksum),
+            Packet::Seed(seed) => with_u64(Self::SEED, *seed),
+            Packet::TakebackRequest => Ok(vec![Self::TAKEBACK_REQUEST]),
+            Packet::AdjournRequest => Ok(vec![Self::ADJOURN_REQUEST]),
+            Packet::RematchRequest => Ok(vec![Self::REMATCH_REQUEST]),
+            Packet::Spectate => Ok(vec![Self::SPECTATE]),
+            Packet::Chat(text) => {
+                let mut bytes = vec![Self::CHAT];
+                bytes.extend_from_slice(text.as_bytes());
+                Ok(bytes)
+            }
+            Packet::Match(games) => {
+                let mut bytes = vec![Self::MATCH];
+                bytes.extend_from_slice(&games.to_be_bytes());
+                Ok(bytes)
+            }
+            Packet::Flag => Ok(vec![Self::FLAG]),
+            Packet::Hello(version) => {
+                let mut bytes = vec![Self::HELLO];
+                bytes.extend_from_slice(&version.to_be_bytes());
+                Ok(bytes)
+            }
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> std::io::Result<Packet> {
+        fn decoded<T>(bytes: &[u8]) -> std::io::Result<T>
+        where
+            T: for<'a> TryFrom<&'a [u8]>,
+        {
+            T::try_from(bytes).map_err(|_| protocol_error("malformed packet from peer"))
+        }
+        let u64_from = |bytes: &[u8]| {
+            <[u8; 8]>::try_from(bytes)
+                .map(u64::from_be_bytes)
+                .map_err(|_| protocol_error("malformed packet from peer"))
+        };
+        let empty = |bytes: &[u8], packet: Packet| match bytes {
+            [] => Ok(packet),
+            _ => Err(protocol_error("malformed packet from peer")),
+        };
+
+        let Some((&kind, rest)) = bytes.split_first() else {
+            return Err(protocol_error("empty packet from peer"));
+        };
+        match kind {
+            Self::START => decoded(rest).map(Packet::Start),
+            // A move flagged as a forfeit or a draw offer still means the same.
+            Self::MOVE => decoded(rest).map(|m: Move| match m {
+                Move { forfeit: true, .. } => Packet::Resign,
+                Move {
+                    offer_draw: true, ..
+                } => Packet::DrawOffer,
+                m => Packet::Move(m),
+            }),
+            Self::ACK => decoded(rest).map(Packet::Ack),
+            Self::DRAW_OFFER => empty(rest, Packet::DrawOffer),
+            Self::RESIGN => empty(rest, Packet::Resign),
+            Self::CHECKSUM => u64_from(rest).map(Packet::Checksum),
+            Self::SEED => u64_from(rest).map(Packet::Seed),
+            Self::TAKEBACK_REQUEST => empty(rest, Packet::TakebackRequest),
+            Self::ADJOURN_REQUEST => empty(rest, Packet::AdjournRequest),
+            Self::REMATCH_REQUEST => empty(rest, Packet::RematchRequest),
+            Self::SPECTATE => empty(rest, Packet::Spectate),
+            Self::CHAT if rest.len() <= MAX_CHAT_LEN => {
+                let text = std::str::from_utf8(rest)
+                    .map_err(|_| protocol_error("chat message isn't UTF-8"))?;
+                Ok(Packet::Chat(text.to_string()))
+            }
+            Self::CHAT => Err(protocol_error("chat message too long")),
+            Self::MATCH => <[u8; 4]>::try_from(rest)
+                .map(|bytes| Packet::Match(u32::from_be_bytes(bytes)))
+                .map_err(|_| protocol_error("malformed packet from peer")),
+            Self::FLAG => empty(rest, Packet::Flag),
+            Self::HELLO => <[u8; 2]>::try_from(rest)
+                .map(|bytes| Packet::Hello(u16::from_be_bytes(bytes)))
+                .map_err(|_| protocol_error("malformed packet from peer")),
+            _ => Err(protocol_error(format!("unknown kind of packet {}", kind))),
+        }
+    }
+}
+
+/// How packets get to the other side, picked with `--transport`. Both ends have to use the same.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Transport {
+    Tcp,
+    /// Each frame as a binary WebSocket message, length prefix and all, which gets through HTTP
+    /// proxies and can be spoken by a browser.
+    WebSocket,
+}
+
+impl Transport {
+    pub fn parse(s: &str) -> Option<Transport> {
+        match s {
+            "tcp" => Some(Transport::Tcp),
+            "ws" => Some(Transport::WebSocket),
+            _ => None,
+        }
+    }
+
+    /// Sets up a connection a peer opened to us, with `tls` underneath the transport if given.
+    fn accept(self, stream: TcpStream, tls: Option<&Tls>) -> std::io::Result<Box<dyn Socket>> {
+        Ok(match (self, tls) {
+            (Transport::Tcp, None) => Box::new(stream),
+            (Transport::Tcp, Some(tls)) => Box::new(tls.wrap(stream)?),
+            (Transport::WebSocket, None) => Box::new(WebSocketStream::accept(stream)?),
+            (Transport::WebSocket, Some(tls)) => {
+                Box::new(WebSocketStream::accept(tls.wrap(stream)?)?)
+            }
+        })
+    }
+
+    /// Sets up a connection we opened to `address`, with `tls` underneath the transport if
+    /// given.
+    fn open(
+        self,
+        stream: TcpStream,
+        address: &str,
+        tls: Option<&Tls>,
+    ) -> std::io::Result<Box<dyn Socket>> {
+        Ok(match (self, tls) {
+            (Transport::Tcp, None) => Box::new(stream),
+            (Transport::Tcp, Some(tls)) => Box::new(tls.wrap(stream)?),
+            (Transport::WebSocket, None) => Box::new(WebSocketStream::connect(stream, address)?),
+            (Transport::WebSocket, Some(tls)) => {
+                Box::new(WebSocketStream::connect(tls.wrap(stream)?, address)?)
+            }
+        })
+    }
+}
+
+/// A connection as some `Transport` carries it.
+trait Socket: Read + Write + Send {
+    /// The TCP connection underneath, for its settings.
+    fn tcp(&self) -> &TcpStream;
+}
+
+impl Socket for TcpStream {
+    fn tcp(&self) -> &TcpStream {
+        self
+    }
+}
+
+impl<S: Socket> Socket for WebSocketStream<S> {
+    fn tcp(&self) -> &TcpStream {
+        self.get_ref().tcp()
+    }
+}
+
+impl<S: Socket> Socket for TlsStream<S> {
+    fn tcp(&self) -> &TcpStream {
+        self.get_ref().tcp()
+    }
+}
+
+/// A connection read a frame at a time, however TCP splits or joins them up. Bytes that arrived
+/// ahead of the rest of their frame, or after the end of the one asked for, wait in `received`
+/// for the next read.
+struct FramedStream {
+    stream: Box<dyn Socket>,
+    received: Vec<u8>,
+    /// Set once a frame header made no sense. There's no telling where the next frame starts
+    /// after that, so nothing more is read.
+    broken: bool,
+}
+
+impl FramedStream {
+    fn new(stream: Box<dyn Socket>) -> FramedStream {
+        FramedStream {
+            stream,
+            received: Vec::new(),
+            broken: false,
+        }
+    }
+
+    /// A new connection to a peer speaking our version of the protocol, see `PROTOCOL_VERSION`.
+    /// The stream has to block, with a timeout if the peer may never answer.
+    fn open(stream: Box<dyn Socket>) -> std::io::Result<FramedStream> {
+        let mut stream = FramedStream::new(stream);
+        stream.write_packet(&Packet::Hello(PROTOCOL_VERSION))?;
+        match stream.read_blocking()? {
+            Packet::Hello(PROTOCOL_VERSION) => Ok(stream),
+            Packet::Hello(version) => Err(protocol_error(format!(
+                "peer speaks protocol version {}, we speak {}",
+                version, PROTOCOL_VERSION
+            ))),
+            packet => Err(protocol_error(format!(
+                "peer opened with a {} instead of saying which protocol it speaks",
+                packet.name()
+            ))),
+        }
+    }
+
+    /// The socket underneath, for its settings. Reading from it directly loses frames.
+    fn get_ref(&self) -> &TcpStream {
+        self.stream.tcp()
+    }
+
+    /// The socket underneath, for a connection that's done being read from.
+    fn into_inner(self) -> Box<dyn Socket> {
+        self.stream
+    }
+
+    fn write_packet(&mut self, packet: &Packet) -> std::io::Result<()> {
+        write_packet(&mut self.stream, packet)
+    }
+
+    /// Reads whatever has arrived, unless `length` bytes are buffered already. Returns whether
+    /// they are now. Nothing having arrived yet isn't an error, while a connection closed by the
+    /// peer is so it isn't mistaken for an empty packet.
+    fn fill(&mut self, length: usize) -> std::io::Result<bool> {
+        if self.received.len() >= length {
+            return Ok(true);
+        }
+
+        let mut chunk = [0; READ_CHUNK];
+        match self.stream.read(&mut chunk) {
+            Ok(0) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::ConnectionAborted,
+                    "connection closed by peer",
+                ))
+            }
+            Ok(read) => self.received.extend_from_slice(&chunk[..read]),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::WouldBlock
+                        | std::io::ErrorKind::TimedOut
+                        | std::io::ErrorKind::Interrupted
+                ) => {}
+            Err(e) => return Err(e),
+        }
+        Ok(self.received.len() >= length)
+    }
+
+    /// Reads one packet, or `Ok(None)` if a whole frame hasn't arrived yet.
+    fn read_packet(&mut self) -> std::io::Result<Option<Packet>> {
+        if self.broken {
+            return Err(protocol_error("connection lost track of the frames"));
+        }
+        if !self.fill(HEADER_LEN)? {
+            return Ok(None);
+        }
+
+        let mut header = [0; HEADER_LEN];
+        header.copy_from_slice(&self.received[..HEADER_LEN]);
+        let length = u32::from_be_bytes(header) as usize;
+        if length > MAX_FRAME_LEN {
+            self.broken = true;
+            return Err(protocol_error(format!(
+                "peer sent a {} byte frame, the limit is {} bytes",
+                length, MAX_FRAME_LEN
+            )));
+        }
+
+        // A frame can take several reads to come in, each adding to what's buffered.
+        if !self.fill(HEADER_LEN + length)? {
+            return Ok(None);
+        }
+        let frame: Vec<u8> = self.received.drain(..HEADER_LEN + length).collect();
+        Packet::decode(&frame[HEADER_LEN..]).map(Some)
+    }
+
+    /// Blocking read used during setup and the resume handshake. With a read timeout set on the
+    /// stream, running out of time is reported as an error.
+    fn read_blocking(&mut self) -> std::io::Result<Packet> {
+        let deadline = self
+            .get_ref()
+            .read_timeout()?
+            .map(|timeout| Instant::now() + timeout);
+
+        loop {
+            if let Some(packet) = self.read_packet()? {
+                return Ok(packet);
+            }
+
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    "peer didn't answer in time",
+                ));
+            }
+
+            // Only part of a frame has arrived, give the rest a moment.
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn close(&mut self) -> std::io::Result<()> {
+        self.stream.flush()?;
+        match self.get_ref().shutdown(Shutdown::Both) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotConnected => Err(e),
+            _ => Ok(()),
+        }
+    }
 }
 
 pub struct Server {
+    stream: FramedStream,
+    /// Where the opponent connected from, only they get to take the seat back after a drop.
+    peer: IpAddr,
+    /// Everyone watching, with how many moves of the game each has been sent.
+    spectators: Vec<(Box<dyn Socket>, usize)>,
+    /// Connections that came in after the game started, see `let_in`.
+    arrivals: Receiver<Arrival>,
+    /// Tells the thread behind `arrivals` to stop listening once the game is gone.
+    stop: Arc<AtomicBool>,
+    /// What the client calls itself, from its `Start`.
+    opponent: Option<String>,
+}
+
+/// A connection made to a running game, with the transport and TLS set up and the first packet
+/// read, which says whether it's the opponent coming back or someone coming to watch.
+struct Arrival {
+    stream: FramedStream,
+    first: Packet,
+    peer: SocketAddr,
+}
+
+/// Takes the connections made to `listener` until `stop` is set, doing the handshakes on a
+/// thread of their own so a slow or idle connection never holds up the game.
+fn let_in(
     listener: TcpListener,
+    transport: Transport,
+    tls: Option<Tls>,
+    stop: Arc<AtomicBool>,
+) -> std::io::Result<Receiver<Arrival>> {
+    listener.set_nonblocking(true)?;
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        while !stop.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((stream, peer)) => {
+                    let (sender, tls) = (sender.clone(), tls.clone());
+                    std::thread::spawn(move || greet(stream, peer, transport, tls, sender));
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(ACCEPT_INTERVAL)
+                }
+                Err(e) => {
+                    log::error!("could not accept a connection: {}", e);
+                    std::thread::sleep(ACCEPT_INTERVAL);
+                }
+            }
+        }
+    });
+    Ok(receiver)
+}
+
+/// Sets up a connection `let_in` took and hands it over once its first packet is in.
+fn greet(
     stream: TcpStream,
+    peer: SocketAddr,
+    transport: Transport,
+    tls: Option<Tls>,
+    arrivals: Sender<Arrival>,
+) {
+    let arrived = stream
+        .set_nonblocking(false)
+        .and_then(|()| stream.set_read_timeout(Some(RESUME_TIMEOUT)))
+        .and_then(|()| transport.accept(stream, tls.as_ref()))
+        .and_then(|stream| {
+            let mut stream = FramedStream::open(stream)?;
+            let first = stream.read_blocking()?;
+            Ok(Arrival {
+                stream,
+                first,
+                peer,
+            })
+        });
+    match arrived {
+        Ok(arrival) => {
+            // Nobody is waiting anymore when the game ended in the meantime.
+            let _ = arrivals.send(arrival);
+        }
+        Err(e) => log::error!("rejected {}: {}", peer, e),
+    }
 }
 
 impl Server {
-    pub fn new(address: &str) -> std::io::Result<Server> {
+    pub fn new(address: &str, transport: Transport, tls: Option<Tls>) -> std::io::Result<Server> {
         let listener = TcpListener::bind(address)?;
-        let stream = listener.accept()?.0;
-        Ok(Server { listener, stream })
+        let (stream, peer) = listener.accept()?;
+        let stream = FramedStream::open(transport.accept(stream, tls.as_ref())?)?;
+        let stop = Arc::new(AtomicBool::new(false));
+        Ok(Server {
+            stream,
+            peer: peer.ip(),
+            spectators: Vec::new(),
+            arrivals: let_in(listener, transport, tls, stop.clone())?,
+            stop,
+            opponent: None,
+        })
+    }
+
+    /// The next connection made to the game, if one is ready.
+    fn next_arrival(&mut self) -> std::io::Result<Option<Arrival>> {
+        match self.arrivals.try_recv() {
+            Ok(arrival) => Ok(Some(arrival)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => {
+                Err(std::io::Error::other("stopped listening for connections"))
+            }
+        }
+    }
+
+    /// Catches a new spectator up on the game, whose request was already read off `stream`.
+    ///
+    /// Spectators get the `Start` followed by every move, and from then on each move as it's
+    /// agreed on. A takeback comes as one `Packet::TakebackRequest` per ply taken back.
+    fn add_spectator(
+        &mut self,
+        mut stream: Box<dyn Socket>,
+        start: &Start,
+        history: &[Move],
+    ) -> std::io::Result<()> {
+        stream
+            .tcp()
+            .set_write_timeout(Some(SPECTATOR_WRITE_TIMEOUT))?;
+        write_packet(&mut stream, &Packet::Start(start.clone()))?;
+        for m in history {
+            write_packet(&mut stream, &Packet::Move(m.clone()))?;
+        }
+
+        log::info!("{} spectating", stream.tcp().peer_addr()?);
+        self.spectators.push((stream, history.len()));
+        Ok(())
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Sends a spectator what changed in `history` since it was last up to date.
+fn sync_spectator(
+    stream: &mut Box<dyn Socket>,
+    sent: &mut usize,
+    history: &[Move],
+) -> std::io::Result<()> {
+    while *sent > history.len() {
+        write_packet(stream, &Packet::TakebackRequest)?;
+        *sent -= 1;
+    }
+    for m in &history[*sent..] {
+        write_packet(stream, &Packet::Move(m.clone()))?;
+        *sent += 1;
     }
+    Ok(())
 }
 
 impl ChessProtocol for Server {
     fn set_blocking(&mut self, block: bool) -> std::io::Result<()> {
-        self.stream.set_nonblocking(!block)?;
+        self.stream.get_ref().set_nonblocking(!block)?;
         Ok(())
     }
 
-    fn send_ack(&mut self, ack: Ack) -> std::io::Result<()> {
-        let bytes: Vec<u8> = ack.try_into().unwrap();
-        self.stream.write(&bytes)?;
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.stream.get_ref().set_write_timeout(timeout)
+    }
 
-        Ok(())
+    fn send(&mut self, packet: Packet) -> std::io::Result<()> {
+        self.stream.write_packet(&packet)
     }
 
-    fn receive_ack(&mut self) -> std::io::Result<Option<Ack>> {
-        let mut buf: [u8; 1024] = [0; 1024];
-        let length = match self.stream.read(&mut buf) {
-            Ok(l) => l,
-            Err(_) => return Ok(None),
-        };
+    fn poll(&mut self) -> std::io::Result<Option<Packet>> {
+        self.stream.read_packet()
+    }
+
+    fn exchanges_checksums(&self) -> bool {
+        true
+    }
+
+    fn close(&mut self) -> std::io::Result<()> {
+        self.stream.close()
+    }
 
-        let ack: Ack = buf[0..length].try_into().unwrap();
-        Ok(Some(ack))
+    fn opponent_name(&self) -> Option<&str> {
+        self.opponent.as_deref()
     }
 
     fn handle_setup(&mut self, mut desired_start: Start) -> std::io::Result<Start> {
-        let mut buf: [u8; 1024] = [0; 1024];
-        let length = self.stream.read(&mut buf)?;
-        let what_client_wants: Start = buf[0..length].try_into().unwrap();
+        let Packet::Start(what_client_wants) = self.stream.read_blocking()? else {
+            return Err(protocol_error("client didn't open with a start"));
+        };
+        if let Some(name) = &what_client_wants.name {
+            log::info!("playing against {}", name);
+        }
+        self.opponent = what_client_wants.name.clone();
+
+        // Our time control and starting position win, but if we didn't ask for one the client
+        // gets theirs.
+        if desired_start.time.is_none() {
+            desired_start.time = what_client_wants.time;
+            desired_start.inc = what_client_wants.inc;
+        }
+        if desired_start.fen.is_none() {
+            desired_start.fen = what_client_wants.fen;
+        }
 
         let mut client = desired_start.clone();
         client.is_white = !desired_start.is_white;
 
-        let bytes: Vec<u8> = client.try_into().unwrap();
-        self.stream.write(&bytes)?;
+        self.stream.write_packet(&Packet::Start(client))?;
 
         Ok(desired_start)
     }
 
-    fn send_move(&mut self, m: Move) -> std::io::Result<()> {
-        let bytes: Vec<u8> = m.try_into().unwrap();
-        self.stream.write(&bytes)?;
+    fn share_seed(&mut self, seed: u64) -> std::io::Result<u64> {
+        self.stream.write_packet(&Packet::Seed(seed))?;
+        Ok(seed)
+    }
 
-        Ok(())
+    fn share_match(&mut self, games: u32) -> std::io::Result<u32> {
+        self.stream.write_packet(&Packet::Match(games))?;
+        Ok(games)
     }
 
-    fn receive_move(&mut self) -> std::io::Result<Option<Move>> {
-        let mut buf: [u8; 1024] = [0; 1024];
-        let length = match self.stream.read(&mut buf) {
-            Ok(l) => l,
-            Err(_) => return Ok(None),
+    fn reconnect(&mut self, start: &Start, history: &[Move]) -> std::io::Result<bool> {
+        let Some(arrival) = self.next_arrival()? else {
+            return Ok(false);
         };
+        let Arrival {
+            stream,
+            first,
+            peer,
+        } = arrival;
+        let what_client_wants = match first {
+            Packet::Spectate => {
+                self.add_spectator(stream.into_inner(), start, history)?;
+                return Ok(false);
+            }
+            Packet::Start(_) if peer.ip() != self.peer => {
+                log::error!("rejected {} trying to take over the game", peer);
+                stream.get_ref().shutdown(Shutdown::Both)?;
+                return Ok(false);
+            }
+            Packet::Start(what_client_wants) => what_client_wants,
+            packet => {
+                log::error!("rejected a connection opening with a {}", packet.name());
+                stream.get_ref().shutdown(Shutdown::Both)?;
+                return Ok(false);
+            }
+        };
+        self.stream = stream;
+
+        // The open seat is the one with the opposite color to ours.
+        if what_client_wants.is_white == start.is_white {
+            log::error!("rejected a reconnecting client asking for our color");
+            self.stream.get_ref().shutdown(Shutdown::Both)?;
+            return Ok(false);
+        }
+
+        let mut client = start.clone();
+        client.is_white = !start.is_white;
+        self.stream.write_packet(&Packet::Start(client))?;
+
+        let Packet::Ack(ready) = self.stream.read_blocking()? else {
+            return Err(protocol_error("client didn't answer the start with an ack"));
+        };
+        if !ready.ok {
+            return Ok(false);
+        }
+
+        for m in history {
+            self.stream.write_packet(&Packet::Move(m.clone()))?;
+
+            let Packet::Ack(ack) = self.stream.read_blocking()? else {
+                return Err(protocol_error(
+                    "client didn't answer a replayed move with an ack",
+                ));
+            };
+            if !ack.ok {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "client rejected a replayed move",
+                ));
+            }
 
-        let m: Move = buf[0..length].try_into().unwrap();
-        Ok(Some(m))
+            // The client rebuilds its board from these same moves, so there's nothing for the
+            // checksum to catch here.
+            let Packet::Checksum(_) = self.stream.read_blocking()? else {
+                return Err(protocol_error(
+                    "client didn't follow its ack with a checksum",
+                ));
+            };
+        }
+
+        log::info!("client reconnected, replayed {} moves", history.len());
+        self.stream.get_ref().set_read_timeout(None)?;
+        self.stream.get_ref().set_nonblocking(true)?;
+        Ok(true)
+    }
+
+    fn update_spectators(&mut self, start: &Start, history: &[Move]) -> std::io::Result<()> {
+        while let Some(Arrival {
+            stream,
+            first,
+            peer,
+        }) = self.next_arrival()?
+        {
+            // The players are both connected, so anyone else can only come to watch.
+            let added = match first {
+                Packet::Spectate => self.add_spectator(stream.into_inner(), start, history),
+                _ => {
+                    let _ = stream.get_ref().shutdown(Shutdown::Both);
+                    Err(protocol_error("didn't ask to spectate"))
+                }
+            };
+            if let Err(e) = added {
+                log::error!("rejected {}: {}", peer, e);
+            }
+        }
+
+        self.spectators.retain_mut(
+            |(stream, sent)| match sync_spectator(stream, sent, history) {
+                Ok(()) => true,
+                Err(e) => {
+                    log::info!("a spectator left: {}", e);
+                    false
+                }
+            },
+        );
+        Ok(())
     }
 }
 
 pub struct Client {
-    stream: TcpStream,
+    stream: FramedStream,
+    address: String,
+    transport: Transport,
+    tls: Option<Tls>,
+    /// What the server calls itself, from its `Start`.
+    opponent: Option<String>,
 }
 
 impl Client {
-    pub fn new(address: &str) -> std::io::Result<Client> {
+    pub fn new(address: &str, transport: Transport, tls: Option<Tls>) -> std::io::Result<Client> {
         let stream = TcpStream::connect(address)?;
-        Ok(Client { stream })
+        Ok(Client {
+            stream: FramedStream::open(transport.open(stream, address, tls.as_ref())?)?,
+            address: address.to_string(),
+            transport,
+            tls,
+            opponent: None,
+        })
     }
 }
 
+fn connect(address: &str, timeout: Duration) -> std::io::Result<TcpStream> {
+    let mut last_error = std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        "address didn't resolve to anything",
+    );
+
+    for addr in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = e,
+        }
+    }
+
+    Err(last_error)
+}
+
 impl ChessProtocol for Client {
     fn set_blocking(&mut self, block: bool) -> std::io::Result<()> {
-        self.stream.set_nonblocking(!block)?;
+        self.stream.get_ref().set_nonblocking(!block)?;
         Ok(())
     }
 
-    fn send_ack(&mut self, ack: Ack) -> std::io::Result<()> {
-        let bytes: Vec<u8> = ack.try_into().unwrap();
-        self.stream.write(&bytes)?;
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.stream.get_ref().set_write_timeout(timeout)
+    }
 
-        Ok(())
+    fn send(&mut self, packet: Packet) -> std::io::Result<()> {
+        self.stream.write_packet(&packet)
     }
 
-    fn receive_ack(&mut self) -> std::io::Result<Option<Ack>> {
-        let mut buf: [u8; 1024] = [0; 1024];
-        let length = match self.stream.read(&mut buf) {
-            Ok(l) => l,
-            Err(_) => return Ok(None),
-        };
+    fn poll(&mut self) -> std::io::Result<Option<Packet>> {
+        self.stream.read_packet()
+    }
 
-        let ack: Ack = buf[0..length].try_into().unwrap();
-        Ok(Some(ack))
+    fn exchanges_checksums(&self) -> bool {
+        true
     }
 
-    fn handle_setup(&mut self, desired_start: Start) -> std::io::Result<Start> {
-        let bytes: Vec<u8> = desired_start.try_into().unwrap();
-        self.stream.write(&bytes)?;
+    fn close(&mut self) -> std::io::Result<()> {
+        self.stream.close()
+    }
 
-        let mut buf: [u8; 1024] = [0; 1024];
-        let length = self.stream.read(&mut buf)?;
-        let actual_start: Start = buf[0..length].try_into().unwrap();
+    fn opponent_name(&self) -> Option<&str> {
+        self.opponent.as_deref()
+    }
+
+    fn handle_setup(&mut self, desired_start: Start) -> std::io::Result<Start> {
+        self.stream.write_packet(&Packet::Start(desired_start))?;
+        let Packet::Start(actual_start) = self.stream.read_blocking()? else {
+            return Err(protocol_error("server didn't answer with a start"));
+        };
+        if let Some(name) = &actual_start.name {
+            log::info!("playing against {}", name);
+        }
+        self.opponent = actual_start.name.clone();
 
         Ok(actual_start)
     }
 
-    fn send_move(&mut self, m: Move) -> std::io::Result<()> {
-        let bytes: Vec<u8> = m.try_into().unwrap();
-        self.stream.write(&bytes)?;
+    fn share_seed(&mut self, _seed: u64) -> std::io::Result<u64> {
+        match self.stream.read_blocking()? {
+            Packet::Seed(seed) => Ok(seed),
+            packet => Err(protocol_error(format!(
+                "server sent a {} instead of the seed",
+                packet.name()
+            ))),
+        }
+    }
 
-        Ok(())
+    fn share_match(&mut self, _games: u32) -> std::io::Result<u32> {
+        match self.stream.read_blocking()? {
+            Packet::Match(0) => Err(protocol_error("server sent a match of no games")),
+            Packet::Match(games) => Ok(games),
+            packet => Err(protocol_error(format!(
+                "server sent a {} instead of the match length",
+                packet.name()
+            ))),
+        }
+    }
+
+    fn reconnect(&mut self, start: &Start, _history: &[Move]) -> std::io::Result<bool> {
+        let stream = match connect(&self.address, CONNECT_TIMEOUT) {
+            Ok(stream) => stream,
+            Err(e) => {
+                log::debug!("reconnect to {} failed: {}", self.address, e);
+                return Ok(false);
+            }
+        };
+
+        stream.set_read_timeout(Some(RESUME_TIMEOUT))?;
+        let stream = self
+            .transport
+            .open(stream, &self.address, self.tls.as_ref())?;
+        self.stream = FramedStream::open(stream)?;
+
+        self.stream.write_packet(&Packet::Start(start.clone()))?;
+
+        let Packet::Start(actual_start) = self.stream.read_blocking()? else {
+            return Err(protocol_error("server didn't answer with a start"));
+        };
+        if actual_start.is_white != start.is_white {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "server changed colors when resuming",
+            ));
+        }
+        // The moves about to be replayed only make sense from the position they started from.
+        if actual_start.fen != start.fen {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "server resumed from a different starting position",
+            ));
+        }
+
+        // Tells the server we're ready for the move history.
+        self.send(Packet::Ack(Ack {
+            ok: true,
+            end_state: None,
+        }))?;
+
+        log::info!("reconnected to {}", self.address);
+        self.stream.get_ref().set_read_timeout(None)?;
+        self.stream.get_ref().set_nonblocking(true)?;
+        Ok(true)
+    }
+}
+
+/// Something that happened in a game being watched.
+pub enum SpectatorEvent {
+    Move(Move),
+    /// The last ply was taken back.
+    Takeback,
+}
+
+/// A read-only connection to a game hosted by a `Server`.
+pub struct Spectator {
+    stream: FramedStream,
+}
+
+impl Spectator {
+    /// Connects to the game at `address` and returns how it started. The moves made so far
+    /// follow as regular events.
+    pub fn connect(
+        address: &str,
+        transport: Transport,
+        tls: Option<&Tls>,
+    ) -> std::io::Result<(Spectator, Start)> {
+        let stream = connect(address, CONNECT_TIMEOUT)?;
+        // The TLS handshake is the first thing waiting on the server.
+        stream.set_read_timeout(Some(RESUME_TIMEOUT))?;
+        let mut stream = FramedStream::open(transport.open(stream, address, tls)?)?;
+        stream.write_packet(&Packet::Spectate)?;
+
+        let Packet::Start(start) = stream.read_blocking()? else {
+            return Err(protocol_error("server didn't answer with a start"));
+        };
+        stream.get_ref().set_read_timeout(None)?;
+        stream.get_ref().set_nonblocking(true)?;
+
+        Ok((Spectator { stream }, start))
+    }
+
+    pub fn receive(&mut self) -> std::io::Result<Option<SpectatorEvent>> {
+        match self.stream.read_packet()? {
+            Some(Packet::Move(m)) => Ok(Some(SpectatorEvent::Move(m))),
+            Some(Packet::TakebackRequest) => Ok(Some(SpectatorEvent::Takeback)),
+            Some(packet) => Err(protocol_error(format!(
+                "server sent a spectator a {}",
+                packet.name()
+            ))),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /// Hands out the bytes it was given one chunk per read, like a socket the peer wrote to in
+    /// pieces. With nothing left a read would block.
+    struct Chunks(VecDeque<Vec<u8>>);
+
+    impl Read for Chunks {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let Some(mut chunk) = self.0.pop_front() else {
+                return Err(std::io::ErrorKind::WouldBlock.into());
+            };
+            // What doesn't fit is left for the next read.
+            if chunk.len() > buf.len() {
+                self.0.push_front(chunk.split_off(buf.len()));
+            }
+            buf[..chunk.len()].copy_from_slice(&chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    impl Write for Chunks {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Socket for Chunks {
+        fn tcp(&self) -> &TcpStream {
+            unreachable!("reading frames never touches the TCP connection")
+        }
     }
 
-    fn receive_move(&mut self) -> std::io::Result<Option<Move>> {
-        let mut buf: [u8; 1024] = [0; 1024];
-        let length = match self.stream.read(&mut buf) {
-            Ok(l) => l,
-            Err(_) => return Ok(None),
+    fn frame(packet: &Packet) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_packet(&mut bytes, packet).unwrap();
+        bytes
+    }
+
+    fn framed(chunks: Vec<Vec<u8>>) -> FramedStream {
+        FramedStream::new(Box::new(Chunks(chunks.into())))
+    }
+
+    #[test]
+    fn reads_a_frame_arriving_a_byte_at_a_time() {
+        let bytes = frame(&Packet::Seed(42));
+        let mut stream = framed(bytes.iter().map(|&b| vec![b]).collect());
+        // Every read takes one more byte, so it can't take more reads than there are bytes.
+        let packet = (0..bytes.len()).find_map(|_| stream.read_packet().unwrap());
+        assert!(matches!(packet, Some(Packet::Seed(42))));
+        assert!(stream.read_packet().unwrap().is_none());
+    }
+
+    #[test]
+    fn reads_two_frames_arriving_together() {
+        let mut bytes = frame(&Packet::Chat("hello".to_string()));
+        bytes.extend(frame(&Packet::Checksum(7)));
+        let mut stream = framed(vec![bytes]);
+        assert!(matches!(stream.read_packet(), Ok(Some(Packet::Chat(text))) if text == "hello"));
+        assert!(matches!(
+            stream.read_packet(),
+            Ok(Some(Packet::Checksum(7)))
+        ));
+        assert!(stream.read_packet().unwrap().is_none());
+    }
+
+    #[test]
+    fn gives_up_on_an_oversized_header() {
+        let header = (MAX_FRAME_LEN as u32 + 1).to_be_bytes().to_vec();
+        let mut stream = framed(vec![header, frame(&Packet::Resign)]);
+        let malformed = |result: std::io::Result<Option<Packet>>| {
+            result.is_err_and(|e| e.kind() == std::io::ErrorKind::InvalidData)
         };
+        assert!(malformed(stream.read_packet()));
+        // What follows can't be told apart from the oversized frame's body.
+        assert!(malformed(stream.read_packet()));
+    }
+
+    /// A `Start` with a name `length` characters long.
+    fn start(length: usize) -> Start {
+        Start {
+            is_white: true,
+            name: Some("x".repeat(length)),
+            fen: None,
+            time: None,
+            inc: None,
+        }
+    }
+
+    /// A `Start` packet that encodes to exactly `size` bytes, kind byte and all.
+    fn start_of_size(size: usize) -> Start {
+        let size_of = |start: &Start| Packet::Start(start.clone()).encode().unwrap().len();
+        let overhead = size_of(&start(0));
+        // However the name's length is written, it takes a few bytes at most.
+        (size.saturating_sub(overhead + 8)..=size - overhead)
+            .rev()
+            .map(start)
+            .find(|start| size_of(start) == size)
+            .unwrap()
+    }
+
+    #[test]
+    fn takes_a_start_frame_right_at_the_limit() {
+        let sent = start_of_size(MAX_FRAME_LEN);
+        let bytes = frame(&Packet::Start(sent.clone()));
+        assert_eq!(bytes.len(), HEADER_LEN + MAX_FRAME_LEN);
+        let mut stream = framed(vec![bytes.clone()]);
+        let packet = (0..bytes.len()).find_map(|_| stream.read_packet().unwrap());
+        assert!(matches!(packet, Some(Packet::Start(start)) if start.name == sent.name));
+    }
+
+    #[test]
+    fn refuses_a_start_frame_a_byte_over_the_limit() {
+        let packet = Packet::Start(start_of_size(MAX_FRAME_LEN + 1));
+        assert!(write_packet(&mut Vec::new(), &packet).is_err());
+
+        // Framed by hand, as a peer without the limit would.
+        let mut bytes = (MAX_FRAME_LEN as u32 + 1).to_be_bytes().to_vec();
+        bytes.extend(packet.encode().unwrap());
+        let mut stream = framed(vec![bytes]);
+        assert!(stream.read_packet().is_err());
+    }
+
+    #[test]
+    fn says_which_version_it_speaks() {
+        let bytes = Packet::Hello(PROTOCOL_VERSION).encode().unwrap();
+        let decoded = Packet::decode(&bytes).unwrap();
+        assert!(matches!(decoded, Packet::Hello(PROTOCOL_VERSION)));
+    }
 
-        let m: Move = buf[0..length].try_into().unwrap();
-        Ok(Some(m))
+    #[test]
+    fn sends_requests_as_packets_of_their_own() {
+        for request in [
+            Packet::TakebackRequest,
+            Packet::AdjournRequest,
+            Packet::RematchRequest,
+        ] {
+            let bytes = request.encode().unwrap();
+            assert_eq!(bytes.len(), 1, "{}", request.name());
+            let decoded = Packet::decode(&bytes).unwrap();
+            assert_eq!(decoded.name(), request.name());
+        }
+        // Anything after the kind would be a different packet the peer doesn't know.
+        assert!(Packet::decode(&[Packet::REMATCH_REQUEST, 0]).is_err());
     }
 }
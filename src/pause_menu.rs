@@ -0,0 +1,99 @@
+use crate::{coords, COLOR_BACKGROUND};
+use raylib::prelude::*;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PauseChoice {
+    Resume,
+    Save,
+    Quit,
+}
+
+/// Pause screen for a game at a shared board, opened and closed with Esc. It covers the board
+/// and everything next to it, so whoever isn't to move can't think over the position meanwhile.
+pub struct PauseMenu {
+    pub open: bool,
+}
+
+impl PauseMenu {
+    const CHOICES: [(PauseChoice, &'static str); 3] = [
+        (PauseChoice::Resume, "Resume"),
+        (PauseChoice::Save, "Save"),
+        (PauseChoice::Quit, "Quit"),
+    ];
+    const BUTTON_WIDTH: f32 = 300.0;
+    const BUTTON_HEIGHT: f32 = 80.0;
+    const BUTTON_PAD: f32 = 24.0;
+    const FONT_SIZE: i32 = 40;
+    const TITLE_SIZE: i32 = 72;
+
+    pub fn new() -> PauseMenu {
+        PauseMenu { open: false }
+    }
+
+    fn button(i: usize) -> Rectangle {
+        let area = coords::board_area();
+        let height = Self::CHOICES.len() as f32 * (Self::BUTTON_HEIGHT + Self::BUTTON_PAD);
+        let top = area.y + (area.height - height) / 2.0;
+
+        Rectangle::new(
+            area.x + (area.width - Self::BUTTON_WIDTH) / 2.0,
+            top + i as f32 * (Self::BUTTON_HEIGHT + Self::BUTTON_PAD),
+            Self::BUTTON_WIDTH,
+            Self::BUTTON_HEIGHT,
+        )
+    }
+
+    /// Opens or closes on Esc, and handles clicks while open. Resuming closes the menu, the other
+    /// choices are left to the caller.
+    pub fn update(&mut self, rl: &RaylibHandle) -> Option<PauseChoice> {
+        if rl.is_key_pressed(KeyboardKey::KEY_ESCAPE) {
+            self.open = !self.open;
+            return None;
+        }
+        if !self.open || !rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT) {
+            return None;
+        }
+
+        let mouse = coords::mouse_position(rl);
+        let (choice, _) = (0..Self::CHOICES.len())
+            .find(|&i| Self::button(i).check_collision_point_rec(mouse))
+            .map(|i| Self::CHOICES[i])?;
+        if choice == PauseChoice::Resume {
+            self.open = false;
+        }
+        Some(choice)
+    }
+
+    pub fn draw(&self, d: &mut RaylibDrawHandle) {
+        if !self.open {
+            return;
+        }
+
+        d.draw_rectangle_rec(coords::layout_area(), Color::get_color(COLOR_BACKGROUND));
+
+        let area = coords::board_area();
+        let title = "Paused";
+        let length = d.measure_text(title, Self::TITLE_SIZE);
+        d.draw_text(
+            title,
+            (area.x + (area.width - length as f32) / 2.0) as i32,
+            (Self::button(0).y - 2.0 * Self::TITLE_SIZE as f32) as i32,
+            Self::TITLE_SIZE,
+            Color::RAYWHITE,
+        );
+
+        for (i, (_, label)) in Self::CHOICES.iter().enumerate() {
+            let rect = Self::button(i);
+            d.draw_rectangle_rounded(rect, 0.3, 8, Color::RAYWHITE);
+
+            let length = d.measure_text(label, Self::FONT_SIZE);
+            d.draw_text(
+                label,
+                (rect.x + (rect.width - length as f32) / 2.0) as i32,
+                (rect.y + (rect.height - Self::FONT_SIZE as f32) / 2.0) as i32,
+                Self::FONT_SIZE,
+                Color::BLACK,
+            );
+        }
+    }
+}
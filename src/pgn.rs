@@ -0,0 +1,440 @@
+use crate::chess960;
+use crate::crazyhouse::{self, Pockets};
+use crate::network::parse_move;
+use crate::variant::Variant;
+use crate::{is_promotion, move_squares, move_string, new_board, BoardExtensions, PieceType};
+use chess::*;
+use chess_networking::Move;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub fn square_name(square: u32) -> String {
+    let file = (b'a' + (square & 7) as u8) as char;
+    let rank = (b'8' - (square / 8) as u8) as char;
+    format!("{}{}", file, rank)
+}
+
+/// Standard algebraic notation for `m`, a legal move in the current position of `board`.
+/// `pockets` are the ones a crazyhouse game started with, since a drop can stop a mate.
+pub fn san(board: &mut ChessBoard, m: &str, pockets: Option<&Pockets>) -> String {
+    let (from, to) = move_squares(m);
+    // Crazyhouse drops are written like `N@f3`.
+    if let Some(piece) = crazyhouse::dropped_piece(m) {
+        let san = format!("{}@{}", piece.to_ascii_uppercase(), square_name(to));
+        return with_check_marks(board, m, san, pockets);
+    }
+    let Some(piece) = board.piece_on(from) else {
+        return m.to_string();
+    };
+
+    let mut san = String::new();
+    let file_diff = (to & 7) as i32 - (from & 7) as i32;
+    let castles = file_diff.abs() == 2 || chess960::is_castling(board.position(), m);
+    if piece.t == PieceType::King && castles {
+        san.push_str(if file_diff > 0 { "O-O" } else { "O-O-O" });
+    } else {
+        // Our chess library marks en passant with a trailing 'e'.
+        let is_capture = board.piece_on(to).is_some() || m.ends_with('e');
+
+        if piece.t == PieceType::Pawn {
+            if is_capture {
+                san.push(square_name(from).remove(0));
+            }
+        } else {
+            san.push(match piece.t {
+                PieceType::Knight => 'N',
+                PieceType::Bishop => 'B',
+                PieceType::Rook => 'R',
+                PieceType::Queen => 'Q',
+                _ => 'K',
+            });
+
+            // Other pieces of the same kind that could also go to `to`.
+            let mut rivals: Vec<u32> = board
+                .get_moves()
+                .iter()
+                .map(|other| move_squares(other))
+                .filter(|&(other_from, other_to)| other_to == to && other_from != from)
+                .map(|(other_from, _)| other_from)
+                .filter(|&other_from| board.piece_on(other_from).is_some_and(|p| p.t == piece.t))
+                .collect();
+            rivals.dedup();
+
+            if !rivals.is_empty() {
+                let name = square_name(from);
+                if rivals.iter().all(|&r| r & 7 != from & 7) {
+                    san.push_str(&name[0..1]);
+                } else if rivals.iter().all(|&r| r / 8 != from / 8) {
+                    san.push_str(&name[1..2]);
+                } else {
+                    san.push_str(&name);
+                }
+            }
+        }
+
+        if is_capture {
+            san.push('x');
+        }
+        san.push_str(&square_name(to));
+
+        if is_promotion(m) {
+            san.push('=');
+            san.push(m.chars().nth(4).unwrap().to_ascii_uppercase());
+        }
+    }
+
+    with_check_marks(board, m, san, pockets)
+}
+
+/// `san` with `+` or `#` after it if `m` gives check or mate.
+fn with_check_marks(
+    board: &mut ChessBoard,
+    m: &str,
+    mut san: String,
+    pockets: Option<&Pockets>,
+) -> String {
+    crazyhouse::make_move(board, m.to_string());
+    let gives_check = board.is_in_check();
+    let is_mate = gives_check && crazyhouse::legal_moves(board, pockets).is_empty();
+    board.undo_move();
+
+    if is_mate {
+        san.push('#');
+    } else if gives_check {
+        san.push('+');
+    }
+
+    san
+}
+
+/// Today's date in the `YYYY.MM.DD` form PGN uses.
+fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() / 86400)
+        .unwrap_or(0) as i64;
+
+    // Days since 1970-01-01 to a civil date, from Howard Hinnant's `civil_from_days`.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+/// Every move of a game in SAN, `fen` being where it started if not from the usual position.
+pub fn san_moves(history: &[Move], fen: Option<&str>) -> Vec<String> {
+    let mut board = new_board(fen);
+    let pockets = fen.and_then(Pockets::from_fen);
+    history
+        .iter()
+        .map(|m| {
+            let m = move_string(&board, m);
+            let san = san(&mut board, &m, pockets.as_ref());
+            crazyhouse::make_move(&mut board, m);
+            san
+        })
+        .collect()
+}
+
+/// A whole game as PGN, `fen` being where it started if not from the usual position. `result` is
+/// one of `1-0`, `0-1`, `1/2-1/2` or `*`.
+pub fn export(
+    history: &[Move],
+    fen: Option<&str>,
+    white: &str,
+    black: &str,
+    result: &str,
+) -> String {
+    let mut pgn = String::new();
+    for (tag, value) in [
+        ("Event", "Casual game"),
+        ("Site", "?"),
+        ("Date", &today()),
+        ("Round", "-"),
+        ("White", white),
+        ("Black", black),
+        ("Result", result),
+    ] {
+        pgn.push_str(&format!("[{} \"{}\"]\n", tag, value.replace('"', "'")));
+    }
+    if let Some(fen) = fen {
+        let variant = match crate::fen::pockets(fen) {
+            Some(_) => Some("Crazyhouse"),
+            None => Variant::from_fen(Some(fen)).title(),
+        };
+        if let Some(variant) = variant {
+            pgn.push_str(&format!("[Variant \"{}\"]\n", variant));
+        }
+        pgn.push_str(&format!("[SetUp \"1\"]\n[FEN \"{}\"]\n", fen));
+    }
+    pgn.push('\n');
+
+    let mut tokens = Vec::new();
+    // Counting plies as if white had moved first, so black starting takes the first one.
+    let skipped = usize::from(!new_board(fen).white_move);
+    if skipped == 1 && !history.is_empty() {
+        tokens.push("1...".to_string());
+    }
+    for (ply, san) in san_moves(history, fen).into_iter().enumerate() {
+        if (ply + skipped) % 2 == 0 {
+            tokens.push(format!("{}.", (ply + skipped) / 2 + 1));
+        }
+        tokens.push(san);
+    }
+    tokens.push(result.to_string());
+
+    // PGN lines are kept under 80 characters.
+    let mut line_length = 0;
+    for token in tokens {
+        if line_length > 0 && line_length + 1 + token.len() > 79 {
+            pgn.push('\n');
+            line_length = 0;
+        } else if line_length > 0 {
+            pgn.push(' ');
+            line_length += 1;
+        }
+
+        line_length += token.len();
+        pgn.push_str(&token);
+    }
+    pgn.push_str("\n\n");
+
+    pgn
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct InvalidPgn(String);
+
+impl std::fmt::Display for InvalidPgn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid PGN: {}", self.0)
+    }
+}
+
+/// The moves of a game read from PGN.
+pub struct Game {
+    /// Where the game started, if not from the usual position.
+    pub fen: Option<String>,
+    pub history: Vec<Move>,
+}
+
+/// Reads the first game of a PGN file. Comments, variations and annotations are skipped, every
+/// move has to be legal.
+pub fn parse(pgn: &str) -> Result<Game, InvalidPgn> {
+    let mut fen = None;
+    let mut movetext = String::new();
+    for line in pgn.lines().map(str::trim) {
+        if let Some(tag) = line.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+            // A second game's tags come after the first one's moves.
+            if !movetext.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = tag.split_once(' ') {
+                if name == "FEN" {
+                    fen = Some(value.trim().trim_matches('"').to_string());
+                }
+            }
+        } else {
+            movetext.push_str(line);
+            movetext.push('\n');
+        }
+    }
+
+    if let Some(fen) = &fen {
+        crate::fen::parse(fen).map_err(|e| InvalidPgn(e.to_string()))?;
+    }
+
+    let mut board = new_board(fen.as_deref());
+    let pockets = fen.as_deref().and_then(Pockets::from_fen);
+    let mut history = Vec::new();
+    for token in tokens(&movetext) {
+        if ["1-0", "0-1", "1/2-1/2", "*"].contains(&token.as_str()) {
+            break;
+        }
+        // Move numbers may be stuck to the move after them, as in `1.e4`.
+        let token = token.rsplit_once('.').map_or(token.as_str(), |(_, m)| m);
+        if token.is_empty() {
+            continue;
+        }
+
+        let m = find_move(&mut board, token, pockets.as_ref())
+            .ok_or_else(|| InvalidPgn(format!("illegal move {}", token)))?;
+
+        // Our chess library's en passant suffix isn't part of the coordinate form.
+        let coordinates = if is_promotion(&m) { &m[..] } else { &m[..4] };
+        history.push(parse_move(coordinates).unwrap());
+        crazyhouse::make_move(&mut board, m);
+    }
+
+    Ok(Game { fen, history })
+}
+
+/// The legal move written as `text`, either in SAN like `Nf3` or in coordinates like `g1f3` and
+/// `e7e8q`. Check marks and annotations are optional. `pockets` are the ones a crazyhouse game
+/// started with, for its drops.
+pub fn find_move(board: &mut ChessBoard, text: &str, pockets: Option<&Pockets>) -> Option<String> {
+    let wanted = text.replace('0', "O");
+    let wanted = wanted.trim_end_matches(['+', '#', '!', '?']);
+    crazyhouse::legal_moves(board, pockets)
+        .into_iter()
+        .find(|m| {
+            // Our chess library's en passant suffix isn't part of the coordinate form.
+            let coordinates = if is_promotion(m) { &m[..] } else { &m[..4] };
+            coordinates.eq_ignore_ascii_case(wanted)
+                || san(board, m, pockets).trim_end_matches(['+', '#']) == wanted
+        })
+}
+
+/// The movetext split into moves, move numbers and the result, without the comments,
+/// variations and numeric annotations.
+fn tokens(movetext: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut chars = movetext.chars();
+    let mut variation_depth = 0;
+    while let Some(c) = chars.next() {
+        let ends_token = c.is_whitespace() || "{;()".contains(c);
+        if ends_token && !token.is_empty() {
+            tokens.push(std::mem::take(&mut token));
+        }
+
+        match c {
+            '{' => {
+                chars.by_ref().find(|&c| c == '}');
+            }
+            ';' => {
+                chars.by_ref().find(|&c| c == '\n');
+            }
+            '(' => variation_depth += 1,
+            ')' => variation_depth -= 1,
+            _ if ends_token => {}
+            _ if variation_depth == 0 => token.push(c),
+            _ => {}
+        }
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+
+    tokens.retain(|t| !t.starts_with('$'));
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The move `text` stands for in `board`, played if there is one.
+    fn play(board: &mut ChessBoard, text: &str) -> Option<String> {
+        let m = find_move(board, text, None)?;
+        crazyhouse::make_move(board, m.clone());
+        Some(m)
+    }
+
+    #[test]
+    fn tells_apart_pieces_going_to_the_same_square() {
+        let mut board = new_board(Some("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1"));
+        assert_eq!(find_move(&mut board, "Nbd2", None).as_deref(), Some("b1d2"));
+        assert_eq!(find_move(&mut board, "Nfd2", None).as_deref(), Some("f1d2"));
+        assert_eq!(find_move(&mut board, "Nd2", None), None);
+        assert_eq!(san(&mut board, "b1d2", None), "Nbd2");
+
+        let mut board = new_board(Some("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1"));
+        assert_eq!(find_move(&mut board, "R1a3", None).as_deref(), Some("a1a3"));
+        assert_eq!(find_move(&mut board, "R5a3", None).as_deref(), Some("a5a3"));
+        assert_eq!(san(&mut board, "a5a3", None), "R5a3");
+    }
+
+    #[test]
+    fn castles_written_with_zeros_or_letters() {
+        let mut board = new_board(Some("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1"));
+        assert_eq!(find_move(&mut board, "0-0", None).as_deref(), Some("e1g1"));
+        assert_eq!(
+            find_move(&mut board, "O-O-O", None).as_deref(),
+            Some("e1c1")
+        );
+        assert_eq!(find_move(&mut board, "e1g1", None).as_deref(), Some("e1g1"));
+    }
+
+    #[test]
+    fn promotes_to_the_piece_asked_for() {
+        let mut board = new_board(Some("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1"));
+        assert_eq!(
+            find_move(&mut board, "a8=Q", None).as_deref(),
+            Some("a7a8q")
+        );
+        assert_eq!(
+            find_move(&mut board, "a8=N", None).as_deref(),
+            Some("a7a8n")
+        );
+        assert_eq!(
+            find_move(&mut board, "axb8=R", None).as_deref(),
+            Some("a7b8r")
+        );
+        assert_eq!(
+            find_move(&mut board, "a7a8b", None).as_deref(),
+            Some("a7a8b")
+        );
+    }
+
+    #[test]
+    fn takes_en_passant_right_after_the_double_step() {
+        let mut board = new_board(Some("4k3/3p4/8/4P3/8/8/8/4K3 b - - 0 1"));
+        assert_eq!(play(&mut board, "d5").as_deref(), Some("d7d5"));
+        assert_eq!(
+            find_move(&mut board, "exd6", None).as_deref(),
+            Some("e5d6e")
+        );
+        assert_eq!(
+            find_move(&mut board, "e5d6", None).as_deref(),
+            Some("e5d6e")
+        );
+
+        play(&mut board, "Kd2").unwrap();
+        play(&mut board, "Kf7").unwrap();
+        // Only the move right after the double step can take it.
+        assert_eq!(find_move(&mut board, "exd6", None), None);
+    }
+
+    /// The moves of a parsed game in coordinates.
+    fn coordinates(game: &Game) -> Vec<String> {
+        game.history
+            .iter()
+            .map(crate::network::format_move)
+            .collect()
+    }
+
+    #[test]
+    fn reads_moves_past_comments_and_variations() {
+        let pgn = "[Event \"Casual game\"]\n[White \"?\"]\n\n\
+            1.e4 {best by test} e5 2. Nf3 (2. f4 exf4) Nc6 $1 ; the usual\n\
+            3. Bb5 a6 1-0\n\n[Event \"Next\"]\n\n1. d4 *\n";
+        let game = parse(pgn).unwrap();
+        assert_eq!(game.fen, None);
+        assert_eq!(
+            coordinates(&game),
+            ["e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "a7a6"]
+        );
+    }
+
+    #[test]
+    fn starts_from_the_fen_tag() {
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 b - - 0 1";
+        let pgn = format!("[SetUp \"1\"]\n[FEN \"{}\"]\n\n1... Kd7 2. e4 *\n", fen);
+        let game = parse(&pgn).unwrap();
+        assert_eq!(game.fen.as_deref(), Some(fen));
+        assert_eq!(coordinates(&game), ["e8d7", "e2e4"]);
+    }
+
+    #[test]
+    fn refuses_illegal_moves_and_bad_fens() {
+        assert!(parse("1. e4 e4 *").is_err());
+        assert!(parse("[FEN \"8/8/8 w - - 0 1\"]\n\n1. e4 *").is_err());
+    }
+}
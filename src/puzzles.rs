@@ -0,0 +1,115 @@
+use crate::network::parse_move;
+use crate::{fen, log, move_string, new_board};
+use std::path::Path;
+
+/// A tactics puzzle, a position and the line that solves it.
+pub struct Puzzle {
+    pub id: String,
+    pub fen: String,
+    /// The opponent's move leading into the puzzle, played once it's on the board.
+    pub setup: Option<String>,
+    /// The solution in coordinate form, starting with our move and taking turns with the
+    /// opponent's replies.
+    pub solution: Vec<String>,
+}
+
+/// Checks that every move of the line is legal in turn, from the puzzle's position.
+fn check_line(fen: &str, setup: Option<&str>, solution: &[String]) -> Result<(), String> {
+    fen::parse(fen).map_err(|e| e.to_string())?;
+    let mut board = new_board(Some(fen));
+    for m in setup.into_iter().chain(solution.iter().map(|m| m.as_str())) {
+        let parsed = parse_move(m).ok_or_else(|| format!("{} isn't a move", m))?;
+        let played = move_string(&board, &parsed);
+        if !board.get_moves().contains(&played) {
+            return Err(format!("{} isn't legal in the line", m));
+        }
+        board.make_move(played);
+    }
+    Ok(())
+}
+
+/// Reads puzzles from a CSV file with one per line, in either of two layouts:
+///
+/// - `fen,moves`, the moves solving it in coordinate form separated by spaces, ours first.
+/// - The Lichess puzzle database's `id,fen,moves,...`, where the first move is the opponent's and
+///   leads into the puzzle. Its header line and the columns after the moves are skipped.
+///
+/// Empty lines and ones starting with `#` are skipped, and so are puzzles whose line doesn't hold
+/// up, which are logged.
+pub fn load(path: &Path) -> std::io::Result<Vec<Puzzle>> {
+    let contents = std::fs::read_to_string(path)?;
+
+    let mut puzzles = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("PuzzleId,") {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let (id, fen, moves, lichess) = match fields[..] {
+            [fen, moves] => ((i + 1).to_string(), fen, moves, false),
+            [id, fen, moves, ..] => (id.to_string(), fen, moves, true),
+            _ => {
+                log::error!("{}:{}: expected fen,moves", path.display(), i + 1);
+                continue;
+            }
+        };
+
+        let mut solution: Vec<String> = moves
+            .split_whitespace()
+            .map(|m| m.to_ascii_lowercase())
+            .collect();
+        let setup = if lichess && !solution.is_empty() {
+            Some(solution.remove(0))
+        } else {
+            None
+        };
+        if solution.is_empty() {
+            log::error!("{}:{}: the puzzle has no solution", path.display(), i + 1);
+            continue;
+        }
+        if let Err(e) = check_line(fen, setup.as_deref(), &solution) {
+            log::error!("{}:{}: {}", path.display(), i + 1, e);
+            continue;
+        }
+
+        puzzles.push(Puzzle {
+            id,
+            fen: fen.to_string(),
+            setup,
+            solution,
+        });
+    }
+
+    if puzzles.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "no puzzles in the file",
+        ));
+    }
+    Ok(puzzles)
+}
+
+/// How the puzzles have gone so far.
+#[derive(Default)]
+pub struct Score {
+    pub solved: usize,
+    pub attempted: usize,
+    /// Puzzles solved in a row, up to the last one.
+    pub streak: usize,
+    pub best_streak: usize,
+}
+
+impl Score {
+    pub fn record(&mut self, solved: bool) {
+        self.attempted += 1;
+        if solved {
+            self.solved += 1;
+            self.streak += 1;
+            self.best_streak = self.best_streak.max(self.streak);
+        } else {
+            self.streak = 0;
+        }
+    }
+}
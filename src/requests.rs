@@ -0,0 +1,68 @@
+/// One kind of question the players can put to each other over the network.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct Request {
+    /// We asked and wait for the opponent's answer.
+    pub requested: bool,
+    /// The opponent asked and waits for ours.
+    pub offered: bool,
+}
+
+/// Everything asked over the network that hasn't been answered yet.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct Requests {
+    pub takeback: Request,
+    /// Putting the game aside to finish later.
+    pub adjourn: Request,
+    /// Another game once this one is over, with the colors swapped.
+    pub rematch: Request,
+    pub draw: Request,
+}
+
+impl Request {
+    /// Whether either side asked and waits for an answer.
+    pub fn pending(&self) -> bool {
+        self.requested || self.offered
+    }
+}
+
+impl Requests {
+    /// Whether we asked for something about the game in progress, which holds our moves until
+    /// the opponent answers.
+    pub fn waiting(&self) -> bool {
+        self.takeback.requested || self.adjourn.requested || self.draw.requested
+    }
+
+    /// Forgets everything about the game in progress, which a reconnect settles by replaying
+    /// it. A rematch is only asked for once the game is over, so it's kept.
+    pub fn settle(&mut self) {
+        *self = Requests {
+            rematch: self.rematch,
+            ..Requests::default()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settling_keeps_the_rematch() {
+        let asked = Request {
+            requested: true,
+            offered: true,
+        };
+        let mut requests = Requests {
+            takeback: asked,
+            adjourn: asked,
+            rematch: asked,
+            draw: asked,
+        };
+        assert!(requests.waiting());
+
+        requests.settle();
+        assert!(!requests.waiting());
+        assert_eq!(requests.draw, Request::default());
+        assert_eq!(requests.rematch, asked);
+    }
+}
@@ -0,0 +1,43 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The one source of randomness for the whole game (SplitMix64). Everything random should draw
+/// from the same instance so a game can be replayed exactly by passing its seed to `--seed`.
+pub struct Rng {
+    seed: u64,
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { seed, state: seed }
+    }
+
+    /// Seeds from the clock and process id, for when no seed was asked for.
+    pub fn from_entropy() -> Rng {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+
+        Rng::new(nanos ^ (std::process::id() as u64).rotate_left(32))
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`.
+    pub fn below(&mut self, n: u64) -> u64 {
+        assert!(n > 0);
+        self.next_u64() % n
+    }
+}
@@ -0,0 +1,236 @@
+use crate::eval::Position;
+use crate::{ChessColor, Piece, PieceType};
+
+const KNIGHT_OFFSETS: [(i32, i32); 8] = [
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+];
+const KING_OFFSETS: [(i32, i32); 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+const ROOK_DIRECTIONS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+const BISHOP_DIRECTIONS: [(i32, i32); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+fn piece_at(position: &Position, x: i32, y: i32) -> Option<Piece> {
+    if !(0..8).contains(&x) || !(0..8).contains(&y) {
+        return None;
+    }
+
+    Piece::from(position[y as usize][x as usize]).ok().flatten()
+}
+
+fn is(piece: Option<Piece>, color: ChessColor, types: &[PieceType]) -> bool {
+    piece.is_some_and(|p| p.color == color && types.contains(&p.t))
+}
+
+/// Whether `by` attacks the square at column `x`, row `y` (row 0 is the 8th rank).
+pub fn is_attacked(position: &Position, x: i32, y: i32, by: ChessColor) -> bool {
+    // White pawns attack towards row 0, so an attacking white pawn sits one row below.
+    let pawn_row = match by {
+        ChessColor::White => y + 1,
+        ChessColor::Black => y - 1,
+    };
+    for dx in [-1, 1] {
+        if is(piece_at(position, x + dx, pawn_row), by, &[PieceType::Pawn]) {
+            return true;
+        }
+    }
+
+    for (dx, dy) in KNIGHT_OFFSETS {
+        if is(piece_at(position, x + dx, y + dy), by, &[PieceType::Knight]) {
+            return true;
+        }
+    }
+
+    for (dx, dy) in KING_OFFSETS {
+        if is(piece_at(position, x + dx, y + dy), by, &[PieceType::King]) {
+            return true;
+        }
+    }
+
+    let sliders = [
+        (ROOK_DIRECTIONS, [PieceType::Rook, PieceType::Queen]),
+        (BISHOP_DIRECTIONS, [PieceType::Bishop, PieceType::Queen]),
+    ];
+    for (directions, types) in sliders {
+        for (dx, dy) in directions {
+            let (mut cx, mut cy) = (x + dx, y + dy);
+            while (0..8).contains(&cx) && (0..8).contains(&cy) {
+                if let Some(piece) = piece_at(position, cx, cy) {
+                    if is(Some(piece), by, &types) {
+                        return true;
+                    }
+                    break;
+                }
+
+                cx += dx;
+                cy += dy;
+            }
+        }
+    }
+
+    false
+}
+
+/// Column and row of `side`'s king, if it has one.
+pub fn king_square(position: &Position, side: ChessColor) -> Option<(i32, i32)> {
+    for y in 0..8 {
+        for x in 0..8 {
+            if is(piece_at(position, x, y), side, &[PieceType::King]) {
+                return Some((x, y));
+            }
+        }
+    }
+
+    None
+}
+
+pub fn in_check(position: &Position, side: ChessColor) -> bool {
+    king_square(position, side).is_some_and(|(x, y)| is_attacked(position, x, y, side.opposite()))
+}
+
+/// Whether neither side has enough material left to ever checkmate: bare kings, a single knight
+/// or bishop, or only bishops that all stand on squares of one color.
+pub fn insufficient_material(position: &Position) -> bool {
+    let mut minors = Vec::new();
+    for (y, rank) in position.iter().enumerate() {
+        for (x, &c) in rank.iter().enumerate() {
+            match c.to_ascii_lowercase() {
+                '.' | 'k' => {}
+                'n' | 'b' => minors.push((c.to_ascii_lowercase(), (x + y) % 2)),
+                _ => return false,
+            }
+        }
+    }
+
+    match minors.as_slice() {
+        [] | [_] => true,
+        [(_, color), ..] => minors
+            .iter()
+            .all(|&(c, square)| c == 'b' && square == *color),
+    }
+}
+
+/// Why a game came to an end.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EndReason {
+    Checkmate,
+    Stalemate,
+    InsufficientMaterial,
+    /// We resigned, or left the game.
+    Resigned,
+    OpponentResigned,
+    /// The side whose clock ran out.
+    Timeout(ChessColor),
+    Agreement,
+    /// A repetition or fifty-move draw, claimed by one of the players or called by the board.
+    Claimed(DrawClaim),
+    /// A draw the board called for a reason we don't know of.
+    Draw,
+    /// The connection never came back after dropping.
+    Disconnection,
+    /// The side whose king made it to the center in King of the Hill.
+    KingOfTheHill(ChessColor),
+    /// The side that gave check a third time in Three-check.
+    ThirdCheck(ChessColor),
+}
+
+impl EndReason {
+    pub fn describe(self) -> &'static str {
+        match self {
+            EndReason::Checkmate => "Checkmate",
+            EndReason::Stalemate => "Draw by stalemate",
+            EndReason::InsufficientMaterial => "Draw by insufficient material",
+            EndReason::Resigned => "You resigned",
+            EndReason::OpponentResigned => "Opponent resigned",
+            EndReason::Timeout(ChessColor::White) => "White ran out of time",
+            EndReason::Timeout(ChessColor::Black) => "Black ran out of time",
+            EndReason::Agreement => "Draw agreed",
+            EndReason::Claimed(claim) => claim.describe(),
+            EndReason::Draw => "Draw",
+            EndReason::Disconnection => "Connection lost for good",
+            EndReason::KingOfTheHill(ChessColor::White) => "White's king reached the hill",
+            EndReason::KingOfTheHill(ChessColor::Black) => "Black's king reached the hill",
+            EndReason::ThirdCheck(ChessColor::White) => "White gave the third check",
+            EndReason::ThirdCheck(ChessColor::Black) => "Black gave the third check",
+        }
+    }
+}
+
+/// A draw either player may claim instead of playing on.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DrawClaim {
+    ThreefoldRepetition,
+    FiftyMoves,
+}
+
+impl DrawClaim {
+    pub fn describe(self) -> &'static str {
+        match self {
+            DrawClaim::ThreefoldRepetition => "Draw by threefold repetition",
+            DrawClaim::FiftyMoves => "Draw by the fifty-move rule",
+        }
+    }
+}
+
+/// How many times the current position has come up with the same side to move, itself
+/// included. Castling and en passant rights aren't told apart since the history doesn't keep
+/// them.
+pub fn repetitions(history: &[Position]) -> usize {
+    let Some(current) = history.last() else {
+        return 0;
+    };
+
+    history
+        .iter()
+        .rev()
+        .step_by(2)
+        .filter(|&p| p == current)
+        .count()
+}
+
+/// Plies since the last capture or pawn move, which either drop a piece or move a pawn.
+pub fn halfmove_clock(history: &[Position]) -> usize {
+    let pawns = |position: &Position| {
+        let mut squares: Vec<(usize, usize, char)> = Vec::new();
+        for (row, rank) in position.iter().enumerate() {
+            for (file, &c) in rank.iter().enumerate() {
+                if c == 'P' || c == 'p' {
+                    squares.push((row, file, c));
+                }
+            }
+        }
+        squares
+    };
+    let pieces = |position: &Position| position.iter().flatten().filter(|&&c| c != '.').count();
+
+    history
+        .windows(2)
+        .rev()
+        .take_while(|w| pieces(&w[0]) == pieces(&w[1]) && pawns(&w[0]) == pawns(&w[1]))
+        .count()
+}
+
+/// The draw that can be claimed in the last position of `history`, if any.
+pub fn draw_claim(history: &[Position]) -> Option<DrawClaim> {
+    if repetitions(history) >= 3 {
+        Some(DrawClaim::ThreefoldRepetition)
+    } else if halfmove_clock(history) >= 100 {
+        Some(DrawClaim::FiftyMoves)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,109 @@
+use crate::network::{format_move, parse_move};
+use chess_networking::Move;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Where Ctrl+S writes the game unless it was loaded from somewhere else.
+pub const DEFAULT_PATH: &str = "saved-game.json";
+
+/// A game saved in the middle, with everything needed to carry on from the same point.
+#[derive(Serialize, Deserialize)]
+pub struct SavedGame {
+    /// Command the game was played with, `local`, `vs-ai`, `analysis` or `network`.
+    pub mode: String,
+    pub seed: u64,
+    /// `Start::is_white` of the side that saved it.
+    pub is_white: bool,
+    /// Where the game started, if not from the usual position.
+    #[serde(default)]
+    pub fen: Option<String>,
+    /// The position after the last move, what a network peer is asked to start from.
+    pub position: String,
+    /// Written the way moves go over the network, `e2e4` or `e7e8q`.
+    #[serde(rename = "moves", with = "moves")]
+    pub history: Vec<Move>,
+    /// The time control in seconds like in `Start` and the seconds left for white and black.
+    #[serde(default)]
+    pub clock: Option<(u64, u64, [f32; 2])>,
+    #[serde(default = "unknown")]
+    pub white: String,
+    #[serde(default = "unknown")]
+    pub black: String,
+}
+
+/// A player's name that wasn't saved, the way PGN writes it.
+fn unknown() -> String {
+    String::from("?")
+}
+
+mod moves {
+    use super::*;
+    use serde::de::Error;
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(history: &[Move], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(history.iter().map(format_move))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Move>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|m| {
+                parse_move(m).ok_or_else(|| D::Error::custom(format!("unreadable move {}", m)))
+            })
+            .collect()
+    }
+}
+
+/// Writes the game as JSON.
+pub fn save(path: &Path, game: &SavedGame) -> std::io::Result<()> {
+    let contents = serde_json::to_string_pretty(game)?;
+    std::fs::write(path, contents)
+}
+
+pub fn load(path: &Path) -> std::io::Result<SavedGame> {
+    let contents = std::fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&contents)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let game = SavedGame {
+            mode: "vs-ai".to_string(),
+            seed: 7,
+            is_white: true,
+            fen: None,
+            position: "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2".to_string(),
+            history: ["e2e4", "e7e5"]
+                .iter()
+                .filter_map(|m| parse_move(m))
+                .collect(),
+            clock: Some((300, 2, [290.5, 301.0])),
+            white: "Ada".to_string(),
+            black: "?".to_string(),
+        };
+        let json = serde_json::to_string(&game).unwrap();
+        assert!(json.contains(r#""moves":["e2e4","e7e5"]"#));
+
+        let loaded: SavedGame = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.mode, game.mode);
+        assert_eq!(loaded.position, game.position);
+        assert_eq!(
+            loaded.history.iter().map(format_move).collect::<Vec<_>>(),
+            ["e2e4", "e7e5"]
+        );
+        assert_eq!(loaded.clock, game.clock);
+        assert_eq!(loaded.white, "Ada");
+    }
+
+    #[test]
+    fn refuses_an_unreadable_move() {
+        let json = r#"{"mode":"local","seed":1,"is_white":false,"position":"","moves":["e9"]}"#;
+        let loaded = serde_json::from_str::<SavedGame>(json);
+        assert!(loaded.is_err());
+    }
+}
@@ -0,0 +1,283 @@
+use crate::config::{self, Config, EFFECT_VOLUMES, SCALES, THEMES, VOLUMES};
+use crate::sound::Effect;
+use crate::{coords, log, theme};
+use raylib::prelude::*;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Row {
+    Sound,
+    Volume,
+    Board,
+    Pieces,
+    Coordinates,
+    WindowSize,
+    Name,
+    Address,
+    /// Goes to the rows with the volume of each sound effect.
+    Effects,
+    /// The volume of the sound effect at that index of `Effect::ALL`.
+    Effect(usize),
+    /// Goes back from the sound effects to the rest.
+    Back,
+    Close,
+}
+
+/// Settings overlay on top of the board, each row changing one setting when clicked. Every change
+/// is written back to the settings file straight away. The name and address rows are typed into
+/// once clicked, until Enter or a click anywhere keeps what was typed.
+pub struct SettingsMenu {
+    pub open: bool,
+    /// The text row being typed into and what it says so far.
+    editing: Option<(Row, String)>,
+    /// Whether the sound effect rows are shown instead of the rest.
+    effects: bool,
+}
+
+impl SettingsMenu {
+    const ROWS: [Row; 10] = [
+        Row::Sound,
+        Row::Volume,
+        Row::Effects,
+        Row::Board,
+        Row::Pieces,
+        Row::Coordinates,
+        Row::WindowSize,
+        Row::Name,
+        Row::Address,
+        Row::Close,
+    ];
+    const ROW_WIDTH: f32 = 480.0;
+    const ROW_HEIGHT: f32 = 64.0;
+    const ROW_PAD: f32 = 16.0;
+    const FONT_SIZE: i32 = 32;
+    const MAX_LENGTH: usize = 64;
+
+    pub fn new() -> SettingsMenu {
+        SettingsMenu {
+            open: false,
+            editing: None,
+            effects: false,
+        }
+    }
+
+    /// The rows shown, top to bottom.
+    fn rows(&self) -> Vec<Row> {
+        if self.effects {
+            let effects = (0..Effect::ALL.len()).map(Row::Effect);
+            effects.chain([Row::Back]).collect()
+        } else {
+            Self::ROWS.to_vec()
+        }
+    }
+
+    /// Whether keys go into one of the rows rather than being shortcuts.
+    pub fn is_typing(&self) -> bool {
+        self.open && self.editing.is_some()
+    }
+
+    /// Where row `i` out of `count` goes.
+    fn row(i: usize, count: usize) -> Rectangle {
+        let area = coords::board_area();
+        let height = count as f32 * (Self::ROW_HEIGHT + Self::ROW_PAD);
+        let top = area.y + (area.height - height) / 2.0;
+
+        Rectangle::new(
+            area.x + (area.width - Self::ROW_WIDTH) / 2.0,
+            top + i as f32 * (Self::ROW_HEIGHT + Self::ROW_PAD),
+            Self::ROW_WIDTH,
+            Self::ROW_HEIGHT,
+        )
+    }
+
+    fn label(&self, row: Row, config: &Config) -> String {
+        // A text row shows what's typed so far while it's being typed into.
+        let text = |value: &Option<String>| match &self.editing {
+            Some((editing, typed)) if *editing == row => format!("{}_", typed),
+            _ => value.clone().unwrap_or_else(|| "-".to_string()),
+        };
+        match row {
+            Row::Sound => format!("Sound: {}", if config.sound { "on" } else { "off" }),
+            Row::Volume => format!("Volume: {}%", (config.volume * 100.0).round() as i32),
+            Row::Board => {
+                let theme = config.theme().map_or("Custom", |i| THEMES[i].0);
+                format!("Board: {}", theme)
+            }
+            Row::Pieces => format!(
+                "Pieces: {}",
+                config.piece_set.as_deref().unwrap_or("Default")
+            ),
+            Row::Coordinates => format!(
+                "Coordinates: {}",
+                if config.coordinates { "on" } else { "off" }
+            ),
+            Row::WindowSize => format!("Window size: {}%", (config.window_scale * 100.0) as i32),
+            Row::Name => format!("Name: {}", text(&config.name)),
+            Row::Address => format!("Address: {}", text(&config.address)),
+            Row::Effects => "Sound effects...".to_string(),
+            Row::Effect(i) => {
+                // The settings file's `opponent_move` reads as "Opponent move".
+                let name = Effect::ALL[i].name().replace('_', " ");
+                let (first, rest) = name.split_at(1);
+                let volume = match config.effect_volumes[i] {
+                    0.0 => "off".to_string(),
+                    volume => format!("{}%", (volume * 100.0).round() as i32),
+                };
+                format!("{}{}: {}", first.to_uppercase(), rest, volume)
+            }
+            Row::Back => "Back".to_string(),
+            Row::Close => "Close".to_string(),
+        }
+    }
+
+    /// The setting a text row types into.
+    fn text(row: Row, config: &mut Config) -> Option<&mut Option<String>> {
+        match row {
+            Row::Name => Some(&mut config.name),
+            Row::Address => Some(&mut config.address),
+            _ => None,
+        }
+    }
+
+    /// Types into the row being edited, returning whether `config` changed once it's done.
+    fn type_text(&mut self, rl: &mut RaylibHandle, config: &mut Config) -> bool {
+        let Some((row, text)) = &mut self.editing else {
+            return false;
+        };
+        while let Some(c) = rl.get_char_pressed() {
+            if !c.is_control() && text.chars().count() < Self::MAX_LENGTH {
+                text.push(c);
+            }
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_BACKSPACE)
+            || rl.is_key_pressed_repeat(KeyboardKey::KEY_BACKSPACE)
+        {
+            text.pop();
+        }
+
+        let done = rl.is_key_pressed(KeyboardKey::KEY_ENTER)
+            || rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT);
+        if !done {
+            return false;
+        }
+        let value = Some(text.trim().to_string()).filter(|text| !text.is_empty());
+        let row = *row;
+        self.editing = None;
+        match Self::text(row, config) {
+            Some(setting) if *setting != value => *setting = value,
+            _ => return false,
+        }
+        Self::save(config);
+        true
+    }
+
+    fn save(config: &Config) {
+        if let Err(e) = config.save() {
+            log::error!("could not save the settings to {}: {}", config::PATH, e);
+        }
+    }
+
+    /// Handles clicks and typing while open. Returns whether `config` changed.
+    pub fn update(&mut self, rl: &mut RaylibHandle, config: &mut Config) -> bool {
+        if !self.open {
+            self.editing = None;
+            self.effects = false;
+            return false;
+        }
+        let typed = self.type_text(rl, config);
+        if !rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT) {
+            return typed;
+        }
+
+        let mouse = coords::mouse_position(rl);
+        let rows = self.rows();
+        let Some(row) = (0..rows.len())
+            .find(|&i| Self::row(i, rows.len()).check_collision_point_rec(mouse))
+            .map(|i| rows[i])
+        else {
+            return typed;
+        };
+
+        match row {
+            Row::Sound => config.sound = !config.sound,
+            Row::Volume => {
+                let next = VOLUMES
+                    .iter()
+                    .position(|&volume| volume > config.volume)
+                    .unwrap_or(0);
+                config.volume = VOLUMES[next];
+            }
+            Row::Board => {
+                // A custom color from the file goes back to the first preset.
+                let next = config.theme().map_or(0, |i| (i + 1) % THEMES.len());
+                config.light_square = THEMES[next].1;
+                config.dark_square = THEMES[next].2;
+            }
+            Row::Pieces => {
+                // The default set comes first, then the ones in `assets/pieces`.
+                let sets = theme::piece_sets();
+                let next = match &config.piece_set {
+                    Some(set) => sets.iter().position(|s| s == set).map(|i| i + 1),
+                    None => Some(0),
+                };
+                config.piece_set = next.and_then(|i| sets.get(i)).cloned();
+            }
+            Row::Coordinates => config.coordinates = !config.coordinates,
+            Row::WindowSize => {
+                let next = SCALES
+                    .iter()
+                    .position(|&scale| scale > config.window_scale)
+                    .unwrap_or(0);
+                config.window_scale = SCALES[next];
+            }
+            Row::Name | Row::Address => {
+                // Typing starts from what's set, the click that ended typing into the other row
+                // already kept that.
+                let text = Self::text(row, config).and_then(|text| text.clone());
+                self.editing = Some((row, text.unwrap_or_default()));
+                return typed;
+            }
+            Row::Effect(i) => {
+                let volume = &mut config.effect_volumes[i];
+                let next = EFFECT_VOLUMES
+                    .iter()
+                    .position(|&v| v > *volume)
+                    .unwrap_or(0);
+                *volume = EFFECT_VOLUMES[next];
+            }
+            Row::Effects | Row::Back => {
+                self.effects = row == Row::Effects;
+                return typed;
+            }
+            Row::Close => {
+                self.open = false;
+                return typed;
+            }
+        }
+
+        Self::save(config);
+        true
+    }
+
+    pub fn draw(&self, d: &mut RaylibDrawHandle, config: &Config) {
+        if !self.open {
+            return;
+        }
+
+        d.draw_rectangle_rec(coords::board_area(), Color::get_color(0x00_00_00_99));
+        let rows = self.rows();
+        for (i, &row) in rows.iter().enumerate() {
+            let rect = Self::row(i, rows.len());
+            d.draw_rectangle_rounded(rect, 0.3, 8, Color::RAYWHITE);
+
+            let label = self.label(row, config);
+            let length = d.measure_text(&label, Self::FONT_SIZE);
+            d.draw_text(
+                &label,
+                (rect.x + (rect.width - length as f32) / 2.0) as i32,
+                (rect.y + (rect.height - Self::FONT_SIZE as f32) / 2.0) as i32,
+                Self::FONT_SIZE,
+                Color::BLACK,
+            );
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use crate::config::Config;
+use crate::log;
+use raylib::prelude::*;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Effect {
+    Move,
+    /// A plain move by the opponent, the regular move sound played lower and quieter.
+    OpponentMove,
+    Capture,
+    /// The capture sound played higher, so the pawn vanishing from beside the square isn't
+    /// missed.
+    EnPassant,
+    Castle,
+    Promote,
+    Check,
+    GameStart,
+    GameEnd,
+    /// Our clock running low.
+    LowTime,
+    /// Every second of ours once the clock is running low.
+    Tick,
+}
+
+impl Effect {
+    pub const ALL: [Effect; 11] = [
+        Effect::Move,
+        Effect::OpponentMove,
+        Effect::Capture,
+        Effect::EnPassant,
+        Effect::Castle,
+        Effect::Promote,
+        Effect::Check,
+        Effect::GameStart,
+        Effect::GameEnd,
+        Effect::LowTime,
+        Effect::Tick,
+    ];
+
+    /// How the effect is called in the settings file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Effect::Move => "move",
+            Effect::OpponentMove => "opponent_move",
+            Effect::Capture => "capture",
+            Effect::EnPassant => "en_passant",
+            Effect::Castle => "castle",
+            Effect::Promote => "promote",
+            Effect::Check => "check",
+            Effect::GameStart => "game_start",
+            Effect::GameEnd => "game_end",
+            Effect::LowTime => "low_time",
+            Effect::Tick => "tick",
+        }
+    }
+
+    /// Volume the sound file is played at before any settings, the opponent's moves are a bit
+    /// quieter than ours.
+    fn base_volume(self) -> f32 {
+        match self {
+            Effect::OpponentMove => 0.7,
+            _ => 1.0,
+        }
+    }
+
+    /// Pitch the sound file is played at, for effects sharing a file with another.
+    fn pitch(self) -> f32 {
+        match self {
+            Effect::OpponentMove => 0.85,
+            Effect::EnPassant => 1.25,
+            _ => 1.0,
+        }
+    }
+
+    fn path(self) -> &'static str {
+        match self {
+            Effect::Move | Effect::OpponentMove => "assets/move-self.mp3",
+            Effect::Capture | Effect::EnPassant => "assets/capture.mp3",
+            Effect::Castle => "assets/castle.mp3",
+            Effect::Promote => "assets/promote.mp3",
+            Effect::Check => "assets/move-check.mp3",
+            Effect::GameStart => "assets/game-start.wav",
+            Effect::GameEnd => "assets/game-end.wav",
+            Effect::LowTime => "assets/low-time.wav",
+            Effect::Tick => "assets/tick.wav",
+        }
+    }
+}
+
+/// Holds every sound effect. A missing audio device or sound file just means that effect stays
+/// silent instead of taking the whole game down.
+pub struct Sounds<'aud> {
+    sounds: Vec<Option<Sound<'aud>>>,
+    muted: bool,
+    volume: f32,
+    effect_volumes: [f32; Effect::ALL.len()],
+}
+
+impl<'aud> Sounds<'aud> {
+    /// Loads every effect, played at the volumes from `config` unless `muted`.
+    pub fn load(audio: Option<&'aud RaylibAudio>, config: &Config, muted: bool) -> Sounds<'aud> {
+        let sounds = Effect::ALL
+            .iter()
+            .map(|effect| {
+                let audio = audio?;
+                match audio.new_sound(effect.path()) {
+                    Ok(sound) => {
+                        sound.set_pitch(effect.pitch());
+                        Some(sound)
+                    }
+                    Err(e) => {
+                        log::error!("could not load {}: {}", effect.path(), e);
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        let mut sounds = Sounds {
+            sounds,
+            muted,
+            volume: 1.0,
+            effect_volumes: [1.0; Effect::ALL.len()],
+        };
+        sounds.configure(config, muted);
+        sounds
+    }
+
+    /// Picks up changed sound settings, `muted` silencing everything regardless of them.
+    pub fn configure(&mut self, config: &Config, muted: bool) {
+        self.muted = muted || !config.sound;
+        self.volume = config.volume;
+        self.effect_volumes = config.effect_volumes;
+
+        for (effect, sound) in Effect::ALL.iter().zip(&self.sounds) {
+            if let Some(sound) = sound {
+                let i = *effect as usize;
+                sound.set_volume(effect.base_volume() * self.volume * self.effect_volumes[i]);
+            }
+        }
+    }
+
+    pub fn play(&self, effect: Effect) {
+        if self.muted {
+            return;
+        }
+
+        if let Some(sound) = &self.sounds[effect as usize] {
+            sound.play();
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use crate::log;
+use crate::network::{format_move, parse_move, ChessProtocol, Packet};
+use chess_networking::*;
+use std::collections::VecDeque;
+use std::io::prelude::*;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::Duration;
+
+/// Plays against whatever is on the other end of stdin/stdout, e.g. an engine or a script.
+///
+/// Moves are exchanged one per line in coordinate form (`e2e4`, `e7e8q`), `resign` gives up the
+/// game and `draw` offers a draw. Our moves are written the same way, a move of theirs that we
+/// reject is answered with an `illegal` line, and a draw offer with `draw` or `decline`.
+/// There's nothing to acknowledge our moves, so they're always accepted, and takeback, adjourn
+/// and rematch requests are always declined.
+pub struct Stdio {
+    lines: Receiver<String>,
+    /// Answers to what we sent, handed out by `poll` ahead of anything read from stdin.
+    answers: VecDeque<Packet>,
+    /// Whether the next ack answers their draw offer.
+    draw_offered: bool,
+}
+
+impl Stdio {
+    pub fn new() -> Stdio {
+        // Reading stdin blocks, so it happens on its own thread to keep the game loop going.
+        let (sender, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Stdio {
+            lines,
+            answers: VecDeque::new(),
+            draw_offered: false,
+        }
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        let mut stdout = std::io::stdout().lock();
+        writeln!(stdout, "{}", line)?;
+        stdout.flush()
+    }
+}
+
+impl ChessProtocol for Stdio {
+    fn set_blocking(&mut self, _block: bool) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn set_write_timeout(&mut self, _timeout: Option<Duration>) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn handle_setup(&mut self, desired_start: Start) -> std::io::Result<Start> {
+        Ok(desired_start)
+    }
+
+    fn share_seed(&mut self, seed: u64) -> std::io::Result<u64> {
+        Ok(seed)
+    }
+
+    /// An engine never agrees to another game, so there's only ever the one.
+    fn share_match(&mut self, _games: u32) -> std::io::Result<u32> {
+        Ok(1)
+    }
+
+    fn send(&mut self, packet: Packet) -> std::io::Result<()> {
+        let accepted = |ok| {
+            Packet::Ack(Ack {
+                ok,
+                end_state: None,
+            })
+        };
+        match packet {
+            Packet::Move(m) => {
+                self.answers.push_back(accepted(true));
+                self.write_line(&format_move(&m))
+            }
+            Packet::Resign => self.write_line("resign"),
+            Packet::DrawOffer => {
+                self.answers.push_back(accepted(true));
+                self.write_line("draw")
+            }
+            Packet::Ack(ack) => {
+                let answering_offer = std::mem::take(&mut self.draw_offered);
+                match (answering_offer, ack.ok) {
+                    (true, true) => self.write_line("draw"),
+                    (true, false) => self.write_line("decline"),
+                    (false, true) => Ok(()),
+                    (false, false) => self.write_line("illegal"),
+                }
+            }
+            Packet::TakebackRequest | Packet::AdjournRequest | Packet::RematchRequest => {
+                self.answers.push_back(accepted(false));
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn poll(&mut self) -> std::io::Result<Option<Packet>> {
+        if let Some(answer) = self.answers.pop_front() {
+            return Ok(Some(answer));
+        }
+
+        loop {
+            let line = match self.lines.try_recv() {
+                Ok(line) => line,
+                Err(TryRecvError::Empty) => return Ok(None),
+                Err(TryRecvError::Disconnected) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "stdin closed",
+                    ))
+                }
+            };
+
+            let line = line.trim().to_ascii_lowercase();
+            if line.is_empty() {
+                continue;
+            }
+
+            match parse_move(&line) {
+                Some(m) if m.forfeit => return Ok(Some(Packet::Resign)),
+                Some(m) if m.offer_draw => {
+                    self.draw_offered = true;
+                    return Ok(Some(Packet::DrawOffer));
+                }
+                Some(m) => return Ok(Some(Packet::Move(m))),
+                None => log::error!("ignoring unrecognized input '{}'", line),
+            }
+        }
+    }
+
+    fn exchanges_checksums(&self) -> bool {
+        false
+    }
+
+    /// Once stdin is closed there's no getting it back.
+    fn reconnect(&mut self, _start: &Start, _history: &[Move]) -> std::io::Result<bool> {
+        Ok(false)
+    }
+
+    fn close(&mut self) -> std::io::Result<()> {
+        std::io::stdout().flush()
+    }
+}
@@ -0,0 +1,175 @@
+use crate::config::Config;
+use crate::log;
+use raylib::prelude::*;
+
+const NUM_PIECES: u32 = 6;
+const DEFAULT_PIECE_DIR: &str = "assets";
+const PIECE_SET_DIR: &str = "assets/pieces";
+
+/// How the board looks, the square colors and the piece set. Changing either in the settings
+/// swaps it out while playing, loading the new pieces straight away.
+pub struct Theme {
+    pub light_square: Color,
+    pub dark_square: Color,
+    /// The piece set from the settings last applied, which `--pieces` can override at the start.
+    configured: Option<String>,
+    pieces: PieceTextures,
+}
+
+impl Theme {
+    /// Starts out with the settings, or with `piece_set` instead of the configured set if given.
+    pub fn load(
+        rl: &mut RaylibHandle,
+        thread: &RaylibThread,
+        config: &Config,
+        piece_set: Option<&str>,
+    ) -> Theme {
+        let piece_set = piece_set.or(config.piece_set.as_deref());
+        Theme {
+            light_square: Color::get_color(config.light_square),
+            dark_square: Color::get_color(config.dark_square),
+            configured: config.piece_set.clone(),
+            pieces: PieceTextures::load(rl, thread, piece_set),
+        }
+    }
+
+    /// Takes over changed settings, reloading the pieces only when another set was picked.
+    pub fn apply(&mut self, rl: &mut RaylibHandle, thread: &RaylibThread, config: &Config) {
+        self.light_square = Color::get_color(config.light_square);
+        self.dark_square = Color::get_color(config.dark_square);
+        if self.configured != config.piece_set {
+            self.configured = config.piece_set.clone();
+            self.pieces = PieceTextures::load(rl, thread, config.piece_set.as_deref());
+        }
+    }
+
+    /// Keeps the pieces sharp for squares `square_pixels` framebuffer pixels wide.
+    pub fn update(&mut self, rl: &mut RaylibHandle, thread: &RaylibThread, square_pixels: f32) {
+        self.pieces.update(rl, thread, square_pixels);
+    }
+
+    pub fn pieces(&self) -> &[Texture2D] {
+        self.pieces.get()
+    }
+}
+
+/// Names of the complete piece sets under `assets/pieces`, in alphabetical order.
+pub fn piece_sets() -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(PIECE_SET_DIR) else {
+        return Vec::new();
+    };
+
+    let mut sets: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| piece_set_complete(&format!("{}/{}", PIECE_SET_DIR, name)))
+        .collect();
+    sets.sort();
+    sets
+}
+
+/// The 12 piece textures, at the resolution closest to what a square covers on screen. Sharper
+/// versions live in `<size>` subdirectories of a set and are loaded the first time the board is
+/// drawn large enough to need them, then kept around.
+struct PieceTextures {
+    dir: String,
+    /// Loaded resolutions, an empty list marks one the set doesn't have.
+    sets: Vec<(i32, Vec<Texture2D>)>,
+    current: usize,
+}
+
+impl PieceTextures {
+    /// Resolution of the textures directly in the set directory.
+    const BASE_SIZE: i32 = 128;
+    const SIZES: [i32; 3] = [Self::BASE_SIZE, 256, 512];
+
+    /// Loads the base textures, either from the default set or from `assets/pieces/<set>`.
+    /// A set that is missing any of them is ignored in favour of the default one.
+    fn load(
+        rl: &mut RaylibHandle,
+        thread: &RaylibThread,
+        piece_set: Option<&str>,
+    ) -> PieceTextures {
+        let dir = match piece_set {
+            Some(set) => {
+                let dir = format!("{}/{}", PIECE_SET_DIR, set);
+                if piece_set_complete(&dir) {
+                    dir
+                } else {
+                    log::error!("piece set '{}' is incomplete, using the default set", set);
+                    DEFAULT_PIECE_DIR.to_string()
+                }
+            }
+            None => DEFAULT_PIECE_DIR.to_string(),
+        };
+
+        let textures = match load_textures(rl, thread, &dir) {
+            Ok(textures) => textures,
+            Err(msg) => panic!("{}", msg),
+        };
+
+        PieceTextures {
+            dir,
+            sets: vec![(Self::BASE_SIZE, textures)],
+            current: 0,
+        }
+    }
+
+    /// Switches to the smallest resolution that covers `square_pixels` framebuffer pixels, or
+    /// the largest one the set has.
+    fn update(&mut self, rl: &mut RaylibHandle, thread: &RaylibThread, square_pixels: f32) {
+        let wanted = Self::SIZES
+            .into_iter()
+            .find(|&size| size as f32 >= square_pixels)
+            .unwrap_or(Self::SIZES[Self::SIZES.len() - 1]);
+
+        if !self.sets.iter().any(|(size, _)| *size == wanted) {
+            let dir = format!("{}/{}", self.dir, wanted);
+            let textures = if piece_set_complete(&dir) {
+                load_textures(rl, thread, &dir).unwrap_or_else(|e| {
+                    log::error!("could not load {}px pieces: {}", wanted, e);
+                    Vec::new()
+                })
+            } else {
+                log::debug!("no {}px pieces in {}", wanted, self.dir);
+                Vec::new()
+            };
+            self.sets.push((wanted, textures));
+        }
+
+        let best = self
+            .sets
+            .iter()
+            .enumerate()
+            .filter(|(_, (size, textures))| !textures.is_empty() && *size <= wanted)
+            .max_by_key(|(_, (size, _))| *size);
+        if let Some((idx, _)) = best {
+            self.current = idx;
+        }
+    }
+
+    fn get(&self) -> &[Texture2D] {
+        &self.sets[self.current].1
+    }
+}
+
+fn load_textures(
+    rl: &mut RaylibHandle,
+    thread: &RaylibThread,
+    dir: &str,
+) -> Result<Vec<Texture2D>, String> {
+    let mut textures = Vec::new();
+
+    for idx in 0..NUM_PIECES * 2 {
+        let texture = rl
+            .load_texture(thread, format!("{}/{}.png", dir, idx).as_str())
+            .map_err(|e| e.to_string())?;
+        textures.push(texture);
+    }
+
+    Ok(textures)
+}
+
+fn piece_set_complete(dir: &str) -> bool {
+    (0..NUM_PIECES * 2).all(|idx| std::path::Path::new(&format!("{}/{}.png", dir, idx)).is_file())
+}
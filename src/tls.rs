@@ -0,0 +1,279 @@
+use crate::{config, log};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName, UnixTime};
+use rustls::{
+    ClientConfig, ClientConnection, DigitallySignedStruct, ServerConfig, ServerConnection,
+    SignatureScheme, StreamOwned,
+};
+use std::fs::OpenOptions;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Where the server's certificate and key are kept under `config::dir`, so its fingerprint stays
+/// the same from one game to the next. Only the user gets to read the key.
+const CERT_FILE: &str = "tls-cert.der";
+const KEY_FILE: &str = "tls-key.der";
+/// The name the certificate is made out to. It's never checked, the fingerprint is what counts.
+const SERVER_NAME: &str = "chess-gui";
+
+fn tls_error(e: impl std::fmt::Display) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+}
+
+/// The SHA-256 of a certificate in hex, which is what `--tls-fingerprint` takes.
+fn fingerprint(cert: &CertificateDer) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, cert.as_ref());
+    digest
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// TLS for the connection between the players, turned on with `--tls` on both ends.
+///
+/// There's no certificate authority to vouch for whoever hosts a game, so the server makes a
+/// self-signed certificate the first time and the client checks it against the fingerprint the
+/// host shared with `--tls-fingerprint`. Without one any certificate is taken, which still keeps
+/// the game from being read on the way but not from someone in the middle, and its fingerprint is
+/// shown to compare with the one the server shows.
+#[derive(Clone)]
+pub enum Tls {
+    Server {
+        config: Arc<ServerConfig>,
+        /// Our certificate's, for the host to pass on to whoever joins.
+        fingerprint: String,
+    },
+    Client {
+        config: Arc<ClientConfig>,
+        verifier: Arc<Fingerprint>,
+    },
+}
+
+impl Tls {
+    /// Our side of TLS as the one hosting the game.
+    pub fn server() -> std::io::Result<Tls> {
+        let (cert, key) = server_certificate()?;
+        let fingerprint = fingerprint(&cert);
+        log::info!("TLS certificate fingerprint {}", fingerprint);
+
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .map_err(tls_error)?
+            .with_no_client_auth()
+            .with_single_cert(vec![cert], key)
+            .map_err(tls_error)?;
+        Ok(Tls::Server {
+            config: Arc::new(config),
+            fingerprint,
+        })
+    }
+
+    /// Our side of TLS connecting to someone else's game, taking only a certificate with
+    /// `expected` for a fingerprint if given.
+    pub fn client(expected: Option<&str>) -> std::io::Result<Tls> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let verifier = Arc::new(Fingerprint {
+            // Fingerprints are often written with colons between the bytes.
+            expected: expected.map(|fingerprint| fingerprint.replace(':', "").to_lowercase()),
+            seen: Mutex::new(None),
+            provider: provider.clone(),
+        });
+        let config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .map_err(tls_error)?
+            .dangerous()
+            .with_custom_certificate_verifier(verifier.clone())
+            .with_no_client_auth();
+        Ok(Tls::Client {
+            config: Arc::new(config),
+            verifier,
+        })
+    }
+
+    /// What there is to know about the keys for the players to compare: our fingerprint as the
+    /// host, or the host's and whether it was checked once connected to them.
+    pub fn status(&self) -> Option<String> {
+        match self {
+            Tls::Server { fingerprint, .. } => Some(format!("TLS, our key {}", fingerprint)),
+            Tls::Client { verifier, .. } => {
+                let seen = verifier.seen.lock().ok()?.clone()?;
+                Some(match verifier.expected {
+                    Some(_) => format!("TLS, host key {} verified", seen),
+                    None => format!("TLS unverified, compare host key {}", seen),
+                })
+            }
+        }
+    }
+
+    /// Does the handshake over `stream`, so a certificate that doesn't check out is an error
+    /// right away rather than on the first packet.
+    pub fn wrap<S: Read + Write>(&self, stream: S) -> std::io::Result<TlsStream<S>> {
+        let mut stream = match self {
+            Tls::Server { config, .. } => {
+                let connection = ServerConnection::new(config.clone()).map_err(tls_error)?;
+                TlsStream::Server(StreamOwned::new(connection, stream))
+            }
+            Tls::Client { config, .. } => {
+                let name = ServerName::try_from(SERVER_NAME).map_err(tls_error)?;
+                let connection = ClientConnection::new(config.clone(), name).map_err(tls_error)?;
+                TlsStream::Client(StreamOwned::new(connection, stream))
+            }
+        };
+
+        match &mut stream {
+            TlsStream::Server(s) => {
+                while s.conn.is_handshaking() {
+                    s.conn.complete_io(&mut s.sock)?;
+                }
+            }
+            TlsStream::Client(s) => {
+                while s.conn.is_handshaking() {
+                    s.conn.complete_io(&mut s.sock)?;
+                }
+            }
+        }
+        Ok(stream)
+    }
+}
+
+/// The certificate made on an earlier run, or a new one if there's none yet.
+fn server_certificate() -> std::io::Result<(CertificateDer<'static>, PrivateKeyDer<'static>)> {
+    let dir = config::dir();
+    let (cert_path, key_path) = (dir.join(CERT_FILE), dir.join(KEY_FILE));
+    if let (Ok(cert), Ok(key)) = (std::fs::read(&cert_path), std::fs::read(&key_path)) {
+        let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key));
+        return Ok((CertificateDer::from(cert), key));
+    }
+
+    let certified =
+        rcgen::generate_simple_self_signed(vec![SERVER_NAME.to_string()]).map_err(tls_error)?;
+    let cert = certified.cert.der().clone();
+    let key = certified.key_pair.serialize_der();
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(&cert_path, &cert)?;
+    write_private(&key_path, &key)?;
+    log::info!("made a TLS certificate, kept in {}", cert_path.display());
+
+    let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key));
+    Ok((cert, key))
+}
+
+/// Writes `contents` to a new file at `path` that only the user can read, on systems that have
+/// file modes.
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(contents)
+}
+
+/// Takes the server's certificate if its fingerprint is the one we were given. The signatures
+/// are still checked, so the server has to hold the certificate's key.
+#[derive(Debug)]
+pub struct Fingerprint {
+    expected: Option<String>,
+    /// The fingerprint of the certificate the server showed, once it did.
+    seen: Mutex<Option<String>>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for Fingerprint {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let actual = fingerprint(end_entity);
+        if let Ok(mut seen) = self.seen.lock() {
+            *seen = Some(actual.clone());
+        }
+        match &self.expected {
+            Some(expected) if *expected != actual => Err(rustls::Error::General(format!(
+                "the server's certificate has fingerprint {}, expected {}",
+                actual, expected
+            ))),
+            Some(_) => Ok(ServerCertVerified::assertion()),
+            None => {
+                log::info!(
+                    "the server's TLS certificate has fingerprint {}, nothing to check it against",
+                    actual
+                );
+                Ok(ServerCertVerified::assertion())
+            }
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        let algorithms = &self.provider.signature_verification_algorithms;
+        verify_tls12_signature(message, cert, dss, algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        let algorithms = &self.provider.signature_verification_algorithms;
+        verify_tls13_signature(message, cert, dss, algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// A connection with TLS on top of `S`, read and written like `S` itself.
+pub enum TlsStream<S: Read + Write> {
+    Server(StreamOwned<ServerConnection, S>),
+    Client(StreamOwned<ClientConnection, S>),
+}
+
+impl<S: Read + Write> TlsStream<S> {
+    pub fn get_ref(&self) -> &S {
+        match self {
+            TlsStream::Server(s) => s.get_ref(),
+            TlsStream::Client(s) => s.get_ref(),
+        }
+    }
+}
+
+impl<S: Read + Write> Read for TlsStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            TlsStream::Server(s) => s.read(buf),
+            TlsStream::Client(s) => s.read(buf),
+        }
+    }
+}
+
+impl<S: Read + Write> Write for TlsStream<S> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            TlsStream::Server(s) => s.write(buf),
+            TlsStream::Client(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            TlsStream::Server(s) => s.flush(),
+            TlsStream::Client(s) => s.flush(),
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use crate::ai::{Player, MATE};
+use crate::crazyhouse;
+use crate::log;
+use crate::rng::Rng;
+use chess::ChessBoard;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::time::{Duration, Instant};
+
+/// How long the engine gets to introduce itself before we give up on it.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// An external engine speaking UCI over its stdin and stdout.
+pub struct UciEngine {
+    child: Child,
+    stdin: ChildStdin,
+    /// Lines the engine printed, read on a thread of their own so polling never blocks.
+    lines: Receiver<String>,
+    movetime: u32,
+    searching: bool,
+    /// `bestmove` answers still to come for searches that were stopped, to be thrown away.
+    stale: usize,
+    /// The latest score the engine reported for the current search.
+    score: Option<i32>,
+}
+
+impl UciEngine {
+    /// Starts the engine at `path`, giving it `movetime` milliseconds per move.
+    pub fn start(path: &str, movetime: u32) -> std::io::Result<UciEngine> {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+
+        let (sender, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut engine = UciEngine {
+            child,
+            stdin,
+            lines,
+            movetime,
+            searching: false,
+            stale: 0,
+            score: None,
+        };
+        engine.send("uci")?;
+        engine.wait_for("uciok")?;
+        engine.send("isready")?;
+        engine.wait_for("readyok")?;
+
+        Ok(engine)
+    }
+
+    fn send(&mut self, command: &str) -> std::io::Result<()> {
+        log::debug!("uci > {}", command);
+        writeln!(self.stdin, "{}", command)?;
+        self.stdin.flush()
+    }
+
+    fn wait_for(&mut self, answer: &str) -> std::io::Result<()> {
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match self.lines.recv_timeout(left) {
+                Ok(line) if line.trim() == answer => return Ok(()),
+                Ok(line) => log::debug!("uci < {}", line),
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        format!("engine never answered {}", answer),
+                    ))
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "engine exited",
+                    ))
+                }
+            }
+        }
+    }
+}
+
+impl Player for UciEngine {
+    fn think(&mut self, board: &ChessBoard) {
+        if self.searching {
+            return;
+        }
+
+        // The FEN carries castling and en passant over, only repetitions get lost.
+        let position = format!("position fen {}", crate::fen::to_fen(board, None));
+        let go = format!("go movetime {}", self.movetime);
+        match self.send(&position).and_then(|_| self.send(&go)) {
+            Ok(()) => {
+                self.searching = true;
+                self.score = None;
+            }
+            Err(e) => log::error!("could not reach the engine: {}", e),
+        }
+    }
+
+    fn poll(&mut self, board: &mut ChessBoard, _rng: &mut Rng) -> Option<String> {
+        loop {
+            let line = match self.lines.try_recv() {
+                Ok(line) => line,
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => {
+                    if self.searching {
+                        log::error!("the engine exited without finding a move");
+                        self.searching = false;
+                    }
+                    return None;
+                }
+            };
+            log::debug!("uci < {}", line);
+
+            let mut words = line.split_whitespace();
+            match words.next() {
+                // Whatever a stopped search still reports comes before its `bestmove`.
+                Some("info") if self.stale == 0 => {
+                    if let Some(score) = parse_score(words) {
+                        self.score = Some(score);
+                    }
+                    continue;
+                }
+                Some("bestmove") => {}
+                _ => continue,
+            }
+            if self.stale > 0 {
+                self.stale -= 1;
+                continue;
+            }
+
+            self.searching = false;
+            let best = words.next().unwrap_or("(none)");
+            let m = from_uci(board, best);
+            if m.is_none() {
+                log::error!("the engine suggested {}, which isn't a legal move", best);
+            }
+            return m;
+        }
+    }
+
+    fn cancel(&mut self) {
+        if !self.searching {
+            return;
+        }
+
+        // A stopped search still answers with a move, which no longer fits the position.
+        if let Err(e) = self.send("stop") {
+            log::error!("could not reach the engine: {}", e);
+        }
+        self.searching = false;
+        self.stale += 1;
+    }
+
+    fn score(&self) -> Option<i32> {
+        self.score
+    }
+}
+
+/// The score out of the rest of an `info` line, `score cp <centipawns>` or `score mate <moves>`
+/// with negative moves when the side to move is getting mated.
+fn parse_score<'a>(mut words: impl Iterator<Item = &'a str>) -> Option<i32> {
+    words.find(|&word| word == "score")?;
+    let kind = words.next()?;
+    let value: i32 = words.next()?.parse().ok()?;
+    match kind {
+        "cp" => Some(value),
+        "mate" if value > 0 => Some(MATE - value),
+        "mate" => Some(-MATE - value),
+        _ => None,
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        // Killing it as well in case it doesn't listen, exiting shouldn't hang on the engine.
+        let _ = self.send("quit");
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// UCI's long algebraic form of one of our chess library's move strings, which differs only in
+/// marking en passant with a trailing 'e'.
+pub fn to_uci(m: &str) -> &str {
+    if m.ends_with('e') {
+        &m[..4]
+    } else {
+        m
+    }
+}
+
+/// The legal move in the current position of `board` that the UCI move `m` stands for.
+pub fn from_uci(board: &mut ChessBoard, m: &str) -> Option<String> {
+    crazyhouse::legal_moves(board, None)
+        .into_iter()
+        .find(|legal| to_uci(legal) == m)
+}
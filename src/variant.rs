@@ -0,0 +1,97 @@
+use crate::eval::Position;
+use crate::{rules, ChessColor};
+
+/// The squares a king wins on in King of the Hill, as rows and columns.
+const HILL: [(usize, usize); 4] = [(3, 3), (3, 4), (4, 3), (4, 4)];
+
+/// Variants played by the usual rules with another way to win on top. FEN has no field for them,
+/// so the variant's name goes after the move counters of the starting position, where other FEN
+/// readers stop. That's how the peer, spectators and saved games know what's being played, see
+/// `network::PROTOCOL_VERSION`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Variant {
+    Standard,
+    /// A king reaching one of the four center squares wins.
+    KingOfTheHill,
+    /// Giving check for the third time wins.
+    ThreeCheck,
+}
+
+impl Variant {
+    /// The variant `--variant` names, other than the ones that change the pieces or the board.
+    pub fn from_name(name: &str) -> Option<Variant> {
+        match name {
+            "standard" => Some(Variant::Standard),
+            "king-of-the-hill" => Some(Variant::KingOfTheHill),
+            "three-check" => Some(Variant::ThreeCheck),
+            _ => None,
+        }
+    }
+
+    /// The name `--variant` takes for it.
+    pub fn name(self) -> &'static str {
+        match self {
+            Variant::Standard => "standard",
+            Variant::KingOfTheHill => "king-of-the-hill",
+            Variant::ThreeCheck => "three-check",
+        }
+    }
+
+    /// What the PGN `Variant` tag calls it, `None` for standard chess which goes without.
+    pub fn title(self) -> Option<&'static str> {
+        match self {
+            Variant::Standard => None,
+            Variant::KingOfTheHill => Some("King of the Hill"),
+            Variant::ThreeCheck => Some("Three-check"),
+        }
+    }
+
+    /// The variant a game starting from `fen` is played as, standard without a name after the
+    /// move counters or without a FEN at all.
+    pub fn from_fen(fen: Option<&str>) -> Variant {
+        fen.and_then(|fen| fen.split_whitespace().nth(6))
+            .and_then(Variant::from_name)
+            .unwrap_or(Variant::Standard)
+    }
+
+    /// `fen` with this variant's name after the move counters, filling in any fields it leaves
+    /// out so the name ends up in its place.
+    pub fn tag(self, fen: &str) -> String {
+        let defaults = ["", "w", "-", "-", "0", "1"];
+        let mut fields: Vec<&str> = fen.split_whitespace().take(6).collect();
+        fields.extend(&defaults[fields.len().min(defaults.len())..]);
+        if self != Variant::Standard {
+            fields.push(self.name());
+        }
+        fields.join(" ")
+    }
+
+    /// The side that won by this variant's own rule over the positions of `history`, which only
+    /// the last move can have done.
+    pub fn winner(self, history: &[Position]) -> Option<ChessColor> {
+        let position = history.last()?;
+        match self {
+            Variant::Standard => None,
+            Variant::KingOfTheHill => [ChessColor::White, ChessColor::Black]
+                .into_iter()
+                .find(|&side| rules::king_square(position, side).is_some_and(on_hill)),
+            Variant::ThreeCheck => [ChessColor::White, ChessColor::Black]
+                .into_iter()
+                .find(|&side| checks_given(history, side) >= 3),
+        }
+    }
+}
+
+fn on_hill((x, y): (i32, i32)) -> bool {
+    HILL.contains(&(y as usize, x as usize))
+}
+
+/// How many times `side` gave check over `history`. A side is never in check right after its
+/// own move, so the opponent being in check means `side` just gave it.
+pub fn checks_given(history: &[Position], side: ChessColor) -> usize {
+    history
+        .iter()
+        .skip(1)
+        .filter(|position| rules::in_check(position, side.opposite()))
+        .count()
+}
@@ -0,0 +1,98 @@
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use tungstenite::{Message, WebSocket};
+
+/// A WebSocket connection read and written like a plain byte stream, so the framing on top of it
+/// works the same as over TCP. Each write goes out as one binary message, which for us is always
+/// one whole frame, and incoming messages are read back to back. `S` is the stream the messages
+/// go over, TCP or TLS on top of it.
+pub struct WebSocketStream<S = TcpStream> {
+    socket: WebSocket<S>,
+    /// What's left of the last message after a read too small to take all of it.
+    pending: Vec<u8>,
+}
+
+impl<S: Read + Write> WebSocketStream<S> {
+    /// Answers the handshake of a peer that connected to us.
+    pub fn accept(stream: S) -> std::io::Result<WebSocketStream<S>> {
+        let socket = tungstenite::accept(stream)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?;
+        Ok(WebSocketStream {
+            socket,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Does the handshake over `stream`, already connected to `address`.
+    pub fn connect(stream: S, address: &str) -> std::io::Result<WebSocketStream<S>> {
+        let (socket, _) = tungstenite::client(format!("ws://{}/", address), stream)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?;
+        Ok(WebSocketStream {
+            socket,
+            pending: Vec::new(),
+        })
+    }
+
+    pub fn get_ref(&self) -> &S {
+        self.socket.get_ref()
+    }
+}
+
+fn io_error(e: tungstenite::Error) -> std::io::Error {
+    match e {
+        tungstenite::Error::Io(e) => e,
+        tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => {
+            std::io::Error::new(
+                std::io::ErrorKind::ConnectionAborted,
+                "connection closed by peer",
+            )
+        }
+        e => std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()),
+    }
+}
+
+impl<S: Read + Write> Read for WebSocketStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.pending.is_empty() {
+            match self.socket.read() {
+                Ok(Message::Binary(data)) => self.pending.extend_from_slice(&data),
+                // tungstenite answers the close handshake, all we need to know is that it's over.
+                Ok(Message::Close(_)) | Err(tungstenite::Error::ConnectionClosed) => return Ok(0),
+                // Pings are answered by tungstenite as well.
+                Ok(Message::Ping(_) | Message::Pong(_) | Message::Frame(_)) => {}
+                Ok(Message::Text(_)) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "text message, packets go as binary ones",
+                    ))
+                }
+                Err(e) => return Err(io_error(e)),
+            }
+        }
+
+        let length = buf.len().min(self.pending.len());
+        buf[..length].copy_from_slice(&self.pending[..length]);
+        self.pending.drain(..length);
+        Ok(length)
+    }
+}
+
+impl<S: Read + Write> Write for WebSocketStream<S> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self.socket.send(Message::binary(buf.to_vec())) {
+            Ok(()) => Ok(buf.len()),
+            // The message is queued up and goes out with the next write or flush.
+            Err(tungstenite::Error::Io(e)) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                Ok(buf.len())
+            }
+            Err(e) => Err(io_error(e)),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.socket.flush() {
+            Err(tungstenite::Error::Io(e)) if e.kind() == std::io::ErrorKind::WouldBlock => Ok(()),
+            result => result.map_err(io_error),
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use std::io::Write;
+use std::net::TcpListener;
+use std::process::{Child, Command, Output, Stdio};
+use std::time::Duration;
+
+/// How many times the client tries to reach a server that may not be listening yet.
+const CONNECT_ATTEMPTS: usize = 50;
+const RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A headless game's process for `command` at `address` with `options`, with `moves` typed on
+/// its stdin.
+fn spawn(command: &str, address: &str, options: &[&str], moves: &str) -> Child {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_vhultman-chess-gui"))
+        .args([command, address, "--headless", "--name", command])
+        .args(options)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    // A client that found nobody listening may be gone already, and fails the attempt anyway.
+    let _ = child.stdin.take().unwrap().write_all(moves.as_bytes());
+    child
+}
+
+/// Plays the server's `moves` against the client's over loopback, the server with `options`, and
+/// returns what each printed once the game is over.
+fn play(options: &[&str], server_moves: &str, client_moves: &str) -> (Output, Output) {
+    // A port nothing else is using, free again once the listener is dropped.
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let address = format!("127.0.0.1:{}", port);
+
+    let server = spawn("server", &address, options, server_moves);
+    let client = (0..CONNECT_ATTEMPTS)
+        .find_map(|_| {
+            let output = spawn("client", &address, &[], client_moves)
+                .wait_with_output()
+                .unwrap();
+            if output.status.success() {
+                return Some(output);
+            }
+            std::thread::sleep(RETRY_INTERVAL);
+            None
+        })
+        .expect("the client never got through to the server");
+    let server = server.wait_with_output().unwrap();
+    assert!(server.status.success());
+    (server, client)
+}
+
+/// The last line a finished game printed, which says how it ended.
+fn result(output: &Output) -> String {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout.lines().last().unwrap_or_default().to_string()
+}
+
+#[test]
+fn plays_fools_mate_over_loopback() {
+    // The client moves for white.
+    let (server, client) = play(&[], "e5\nQh4\n", "f3\ng4\n");
+    assert_eq!(result(&client), "0-1 checkmate");
+    assert_eq!(result(&server), "0-1 checkmate");
+}
+
+#[test]
+fn drops_a_taken_pawn_in_crazyhouse() {
+    let (server, client) = play(
+        &["--variant", "crazyhouse"],
+        "d5\nQxd5\n",
+        "e4\nexd5\nP@e6\n",
+    );
+    let client = String::from_utf8_lossy(&client.stdout);
+    let server = String::from_utf8_lossy(&server.stdout);
+    assert!(client.contains("pockets [Pp]"));
+    assert!(server.contains("opponent played e6e6"));
+    assert!(!server.contains("illegal"));
+}